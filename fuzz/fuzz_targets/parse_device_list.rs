@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// The parsers are included directly since the main crate is a binary
+#[allow(dead_code)]
+#[path = "../../src/parse.rs"]
+mod parse;
+
+fuzz_target!(|data: &[u8]| {
+    let text = String::from_utf8_lossy(data);
+    let _ = parse::parse_device_list(&text);
+});
@@ -0,0 +1,20 @@
+#!/usr/bin/env bash
+# Build, lint and test every supported feature combination.
+# The tray feature is required; cli and notifications are optional.
+set -euo pipefail
+REPO_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")/.." && pwd)"
+cd "$REPO_DIR"
+
+combos=(
+  "tray"
+  "tray,cli"
+  "tray,notifications"
+  "tray,cli,notifications"
+)
+
+for features in "${combos[@]}"; do
+  echo "==> --no-default-features --features $features"
+  cargo clippy --all-targets --no-default-features --features "$features" -- -D warnings
+  cargo test --no-default-features --features "$features"
+done
+echo "All feature combinations passed"
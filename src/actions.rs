@@ -0,0 +1,253 @@
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Tray actions that can be triggered from outside the running tray, e.g. from the
+/// desktop file actions GNOME search shows (`rivalcfg-tray --action <id>`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrayAction {
+    OpenConfig,
+    RefreshBattery,
+    SwitchProfile(String),
+    /// Show the full text of an earlier error; only used by notification clicks
+    ShowError(u64),
+}
+
+const OPEN_CONFIG_ID: &str = "open-config";
+const REFRESH_BATTERY_ID: &str = "refresh-battery";
+const PROFILE_ID_PREFIX: &str = "profile-";
+const SHOW_ERROR_ID_PREFIX: &str = "show-error-";
+
+// Desktop action ids may only use [A-Za-z0-9-]; everything else becomes '-'
+fn profile_action_id(name: &str) -> String {
+    let slug: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect();
+    format!("{}{}", PROFILE_ID_PREFIX, slug)
+}
+
+impl TrayAction {
+    pub fn id(&self) -> String {
+        match self {
+            TrayAction::OpenConfig => OPEN_CONFIG_ID.to_string(),
+            TrayAction::RefreshBattery => REFRESH_BATTERY_ID.to_string(),
+            TrayAction::SwitchProfile(name) => profile_action_id(name),
+            TrayAction::ShowError(token) => format!("{}{}", SHOW_ERROR_ID_PREFIX, token),
+        }
+    }
+
+    /// Resolve an action id against the current profile names. Profiles whose
+    /// names map to the same id resolve to the first of them.
+    pub fn from_id(id: &str, profiles: &[String]) -> Option<TrayAction> {
+        match id {
+            OPEN_CONFIG_ID => Some(TrayAction::OpenConfig),
+            REFRESH_BATTERY_ID => Some(TrayAction::RefreshBattery),
+            _ if id.starts_with(SHOW_ERROR_ID_PREFIX) => id[SHOW_ERROR_ID_PREFIX.len()..]
+                .parse()
+                .ok()
+                .map(TrayAction::ShowError),
+            _ => profiles
+                .iter()
+                .find(|name| profile_action_id(name) == id)
+                .map(|name| TrayAction::SwitchProfile(name.clone())),
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            TrayAction::OpenConfig => "Open Configuration".to_string(),
+            TrayAction::RefreshBattery => "Refresh Battery".to_string(),
+            TrayAction::SwitchProfile(name) => format!("Switch to Profile {}", name),
+            TrayAction::ShowError(_) => "Show Error".to_string(),
+        }
+    }
+}
+
+/// Every action offered for `profiles`, in menu order, without duplicate ids
+pub fn all_actions(profiles: &[String]) -> Vec<TrayAction> {
+    let mut actions = vec![TrayAction::OpenConfig, TrayAction::RefreshBattery];
+    for name in profiles {
+        let action = TrayAction::SwitchProfile(name.clone());
+        if !actions.iter().any(|a| a.id() == action.id()) {
+            actions.push(action);
+        }
+    }
+    actions
+}
+
+/// The `Actions=` key and `[Desktop Action ...]` groups for `actions`
+pub fn desktop_actions_section(exec: &str, actions: &[TrayAction]) -> String {
+    let ids: Vec<String> = actions.iter().map(|a| a.id()).collect();
+    let mut out = format!("Actions={};\n", ids.join(";"));
+    for (action, id) in actions.iter().zip(&ids) {
+        // Names are single-line values
+        let name = action.label().replace('\n', " ");
+        out.push_str(&format!("\n[Desktop Action {}]\nName={}\nExec={} --action {}\n", id, name, exec, id));
+    }
+    out
+}
+
+/// The program run by the main `Exec=` line of `desktop_file`
+pub fn desktop_exec(desktop_file: &str) -> Option<&str> {
+    desktop_file
+        .lines()
+        .take_while(|line| !line.starts_with("[Desktop Action "))
+        .find_map(|line| line.strip_prefix("Exec="))?
+        .split_whitespace()
+        .next()
+}
+
+/// `base` with any existing actions replaced by the ones for `actions`
+pub fn render_desktop_file(base: &str, exec: &str, actions: &[TrayAction]) -> String {
+    let mut kept = Vec::new();
+    let mut in_action_group = false;
+    for line in base.lines() {
+        if line.starts_with('[') {
+            in_action_group = line.starts_with("[Desktop Action ");
+        }
+        if in_action_group || line.starts_with("Actions=") {
+            continue;
+        }
+        kept.push(line);
+    }
+    while kept.last().is_some_and(|l| l.trim().is_empty()) {
+        kept.pop();
+    }
+    let mut out = kept.join("\n");
+    out.push('\n');
+    out.push_str(&desktop_actions_section(exec, actions));
+    out
+}
+
+/// Write `content` to `path` unless it already holds exactly that. Returns whether
+/// the file was written.
+pub fn write_if_changed(path: &Path, content: &str) -> std::io::Result<bool> {
+    if std::fs::read_to_string(path).is_ok_and(|current| current == content) {
+        return Ok(false);
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, content)?;
+    Ok(true)
+}
+
+/// Socket a running tray listens on for forwarded `--action` ids
+pub fn action_socket_path(runtime_dir: &Path) -> PathBuf {
+    runtime_dir.join("actions.sock")
+}
+
+/// `rivalcfg-tray` under `base`, created with mode 0700 (or tightened to it) so other
+/// users can't reach the action socket. Fails if it exists but isn't a directory.
+pub fn private_dir_in(base: &Path) -> std::io::Result<PathBuf> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let dir = base.join("rivalcfg-tray");
+    std::fs::DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+    // symlink_metadata so a planted symlink isn't followed
+    let meta = std::fs::symlink_metadata(&dir)?;
+    if !meta.is_dir() {
+        return Err(std::io::Error::other(format!("{} is not a directory", dir.display())));
+    }
+    if meta.permissions().mode() & 0o077 != 0 {
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(dir)
+}
+
+/// Hand `id` to the tray listening on `socket`
+pub fn forward_action(socket: &Path, id: &str) -> std::io::Result<()> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.write_all(id.as_bytes())
+}
+
+/// Receives action ids forwarded by other `rivalcfg-tray --action` invocations
+#[derive(Debug)]
+pub struct ActionListener {
+    listener: UnixListener,
+    path: PathBuf,
+    // accepted connections whose sender hasn't closed yet, with what arrived so far
+    pending: Vec<PendingAction>,
+}
+
+// Longest action id read from one connection; the rest is ignored
+const MAX_ACTION_ID_LEN: usize = 256;
+// How long a sender may keep a connection open without finishing its id
+const ACTION_READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+struct PendingAction {
+    stream: UnixStream,
+    received: Vec<u8>,
+    accepted: Instant,
+}
+
+impl ActionListener {
+    /// Listen on `socket`, replacing a stale socket file left by an earlier run
+    pub fn bind(socket: &Path) -> std::io::Result<Self> {
+        if socket.exists() && UnixStream::connect(socket).is_err() {
+            std::fs::remove_file(socket)?;
+        }
+        let listener = UnixListener::bind(socket)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            path: socket.to_path_buf(),
+            pending: Vec::new(),
+        })
+    }
+
+    /// Ids whose sender finished since the last call; never blocks. A sender writes
+    /// the id and closes, so a connection still open is kept for a later call until
+    /// ACTION_READ_TIMEOUT runs out.
+    pub fn try_recv(&mut self) -> Vec<String> {
+        self.try_recv_at(Instant::now())
+    }
+
+    /// try_recv as of `now`
+    pub fn try_recv_at(&mut self, now: Instant) -> Vec<String> {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_ok() {
+                self.pending.push(PendingAction {
+                    stream,
+                    received: Vec::new(),
+                    accepted: now,
+                });
+            }
+        }
+        let mut ids = Vec::new();
+        self.pending.retain_mut(|p| {
+            let mut chunk = [0u8; MAX_ACTION_ID_LEN];
+            loop {
+                match p.stream.read(&mut chunk) {
+                    Ok(n) if n > 0 && p.received.len() < MAX_ACTION_ID_LEN => {
+                        p.received.extend_from_slice(&chunk[..n]);
+                    }
+                    // closed by the sender, or as much as will be used has arrived
+                    Ok(_) => {
+                        p.received.truncate(MAX_ACTION_ID_LEN);
+                        let id = String::from_utf8_lossy(&p.received);
+                        if !id.trim().is_empty() {
+                            ids.push(id.trim().to_string());
+                        }
+                        return false;
+                    }
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        return now.saturating_duration_since(p.accepted) < ACTION_READ_TIMEOUT;
+                    }
+                    Err(_) => return false,
+                }
+            }
+        });
+        ids
+    }
+}
+
+impl Drop for ActionListener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
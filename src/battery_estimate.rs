@@ -0,0 +1,74 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Fewest discharging samples an estimate is based on
+pub const MIN_ESTIMATE_SAMPLES: usize = 6;
+/// Estimates beyond this are treated as meaningless (e.g. a nearly flat series)
+pub const MAX_ESTIMATE: Duration = Duration::from_secs(7 * 24 * 3600);
+// Samples older than this no longer describe the current drain
+const SAMPLE_WINDOW: Duration = Duration::from_secs(3 * 3600);
+
+/// Time left until the battery is empty, from `(time, level)` samples in time
+/// order. Only the trailing run without a rise in level is used, so readings from
+/// before or during a charge don't count. Returns None with fewer than
+/// `MIN_ESTIMATE_SAMPLES` usable samples, when the level isn't falling, or when
+/// the estimate exceeds `MAX_ESTIMATE`.
+pub fn estimate_remaining(samples: &[(Instant, u8)]) -> Option<Duration> {
+    let start = samples
+        .windows(2)
+        .rposition(|w| w[1].1 > w[0].1)
+        .map_or(0, |i| i + 1);
+    let samples = &samples[start..];
+    if samples.len() < MIN_ESTIMATE_SAMPLES {
+        return None;
+    }
+    // Least squares fit of level over time, which smooths out ±1% jitter
+    let t0 = samples[0].0;
+    let points: Vec<(f64, f64)> = samples
+        .iter()
+        .map(|(t, level)| (t.saturating_duration_since(t0).as_secs_f64(), *level as f64))
+        .collect();
+    let n = points.len() as f64;
+    let mean_t = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_l = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let var_t: f64 = points.iter().map(|p| (p.0 - mean_t).powi(2)).sum();
+    if var_t == 0.0 {
+        return None;
+    }
+    let slope = points.iter().map(|p| (p.0 - mean_t) * (p.1 - mean_l)).sum::<f64>() / var_t;
+    if slope >= 0.0 {
+        return None;
+    }
+    let last_level = samples.last()?.1 as f64;
+    let secs = last_level / -slope;
+    (secs <= MAX_ESTIMATE.as_secs_f64()).then(|| Duration::from_secs_f64(secs))
+}
+
+/// Recent discharging readings kept for `estimate_remaining`
+#[derive(Debug, Default)]
+pub struct BatterySamples {
+    samples: VecDeque<(Instant, u8)>,
+}
+
+impl BatterySamples {
+    /// Record a discharging reading, dropping samples older than the window
+    pub fn push(&mut self, now: Instant, level: u8) {
+        while self
+            .samples
+            .front()
+            .is_some_and(|(t, _)| now.saturating_duration_since(*t) > SAMPLE_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((now, level));
+    }
+
+    /// Forget everything, e.g. while charging or disconnected
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn estimate(&mut self) -> Option<Duration> {
+        estimate_remaining(self.samples.make_contiguous())
+    }
+}
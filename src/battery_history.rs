@@ -0,0 +1,48 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Size at which the history file is rotated. One rotated file is kept, so the
+/// history takes at most about twice this on disk.
+pub const MAX_HISTORY_BYTES: u64 = 1024 * 1024;
+
+pub const HISTORY_HEADER: &str = "timestamp,level,charging\n";
+
+/// `~/.local/state/rivalcfg-tray/battery-history.csv`
+pub fn history_path() -> Option<PathBuf> {
+    Some(dirs::state_dir()?.join("rivalcfg-tray").join("battery-history.csv"))
+}
+
+/// One CSV row: Unix timestamp in seconds, level in percent, and whether charging
+pub fn history_row(timestamp: u64, level: u8, charging: bool) -> String {
+    format!("{},{},{}\n", timestamp, level, charging)
+}
+
+// battery-history.csv -> battery-history.csv.1
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".1");
+    PathBuf::from(name)
+}
+
+/// Append `row` to the history at `path`, first moving the file to `<path>.1`
+/// (replacing the previous one) if the row would take it past `max_bytes`. New
+/// files start with a header line.
+pub fn append_with_rotation(path: &Path, row: &[u8], max_bytes: u64) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    if size > 0 && size + row.len() as u64 > max_bytes {
+        std::fs::rename(path, rotated_path(path))?;
+    }
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+    if file.metadata()?.len() == 0 {
+        file.write_all(HISTORY_HEADER.as_bytes())?;
+    }
+    file.write_all(row)
+}
+
+/// Write callback for the history's `FileWriter`
+pub fn append_history(path: &Path, row: &[u8]) -> std::io::Result<()> {
+    append_with_rotation(path, row, MAX_HISTORY_BYTES)
+}
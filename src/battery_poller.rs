@@ -0,0 +1,63 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::cmd::{BATTERY_RETRY_DELAYS, ChargeStatus, CommandRunner, RivalError, get_battery_level_retrying};
+
+/// Reads the battery through `runner`, reusing the last successful reading while
+/// it is younger than `poll_interval` so bursts of icon refreshes (colour changes,
+/// renames, ...) don't each spawn rivalcfg. Failed reads are never cached; only
+/// `poll_retrying` retries them (see get_battery_level_retrying).
+pub struct CachedBatteryPoller {
+    runner: Arc<dyn CommandRunner>,
+    last_state: Mutex<Option<(Instant, (u8, ChargeStatus))>>,
+    poll_interval: Duration,
+}
+
+impl CachedBatteryPoller {
+    pub fn new(runner: Arc<dyn CommandRunner>, poll_interval: Duration) -> Self {
+        Self {
+            runner,
+            last_state: Mutex::new(None),
+            poll_interval,
+        }
+    }
+
+    /// A single read, safe on the GTK main loop
+    pub fn poll(&self) -> Result<(u8, ChargeStatus), RivalError> {
+        self.poll_at(Instant::now())
+    }
+
+    /// Like poll, but a failed read is retried after each of BATTERY_RETRY_DELAYS.
+    /// Sleeps, so only call it from a worker thread.
+    pub fn poll_retrying(&self) -> Result<(u8, ChargeStatus), RivalError> {
+        self.poll_at_with_delays(Instant::now(), &BATTERY_RETRY_DELAYS)
+    }
+
+    /// The cached reading if it was taken less than `poll_interval` before `now`,
+    /// otherwise a fresh one
+    pub fn poll_at(&self, now: Instant) -> Result<(u8, ChargeStatus), RivalError> {
+        self.poll_at_with_delays(now, &[])
+    }
+
+    fn poll_at_with_delays(&self, now: Instant, delays: &[Duration]) -> Result<(u8, ChargeStatus), RivalError> {
+        if let Ok(last) = self.last_state.lock()
+            && let Some((at, reading)) = *last
+            && now.saturating_duration_since(at) < self.poll_interval
+        {
+            return Ok(reading);
+        }
+        let reading = get_battery_level_retrying(self.runner.as_ref(), delays)
+            .inspect_err(|e| eprintln!("[rivalcfg-tray] Battery read failed: {}", e));
+        if let Ok(mut last) = self.last_state.lock() {
+            *last = reading.as_ref().ok().map(|r| (now, *r));
+        }
+        reading
+    }
+
+    /// Make the next poll run the command, e.g. for an explicit refresh
+    pub fn invalidate(&self) {
+        if let Ok(mut last) = self.last_state.lock() {
+            *last = None;
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use crate::parse::ChargeStatus;
+
+/// Filters out charging/discharging flapping (e.g. a charging pad with poor contact).
+/// A new charge status is only committed once it has been reported by
+/// `confirm_after` polls in a row; until then the committed status is returned.
+#[derive(Debug, Default)]
+pub struct ChargeDebouncer {
+    committed: Option<ChargeStatus>,
+    // the differing status seen last and how many polls in a row reported it
+    pending: Option<(ChargeStatus, u32)>,
+}
+
+impl ChargeDebouncer {
+    /// Feed one poll's status and get the status to display. The first status seen
+    /// (or the first after `reset`) is committed immediately.
+    pub fn observe(&mut self, status: ChargeStatus, confirm_after: u32) -> ChargeStatus {
+        let Some(committed) = self.committed else {
+            self.committed = Some(status);
+            return status;
+        };
+        if status == committed {
+            self.pending = None;
+            return committed;
+        }
+        let seen = match self.pending {
+            Some((pending, n)) if pending == status => n + 1,
+            _ => 1,
+        };
+        if seen >= confirm_after {
+            self.committed = Some(status);
+            self.pending = None;
+            status
+        } else {
+            self.pending = Some((status, seen));
+            committed
+        }
+    }
+
+    /// Forget the history, e.g. while the device is disconnected.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
@@ -1,12 +1,12 @@
 // PathBuf is not needed at top-level in this module right now
+use crate::parse;
 
 #[derive(Debug, Clone)]
 pub struct CommandOutput {
     pub stdout: String,
     pub stderr: String,
     pub success: bool,
-    // exit code if available; currently unused but kept for future diagnostics
-    #[allow(dead_code)]
+    // exit code if available; None when the program could not be started
     pub _code: Option<i32>,
 }
 
@@ -14,11 +14,36 @@ pub trait CommandRunner: Send + Sync {
     fn run(&self, program: &str, args: &[&str]) -> CommandOutput;
 }
 
-#[derive(Debug, Default)]
-pub struct RealCommandRunner {}
+#[derive(Debug)]
+pub struct RealCommandRunner {
+    // program spawned for "rivalcfg", since rivalcfg may be configured to live outside PATH
+    rivalcfg: String,
+}
+
+impl RealCommandRunner {
+    pub fn new(rivalcfg: String) -> Self {
+        Self { rivalcfg }
+    }
+}
+
+impl Default for RealCommandRunner {
+    /// Runner for the configured `rivalcfg_path`, read once here rather than on every
+    /// spawn; a changed path is picked up by runners created afterwards.
+    fn default() -> Self {
+        Self::new(crate::rivalcfg_program(&crate::load_settings().unwrap_or_default()))
+    }
+}
 
 impl CommandRunner for RealCommandRunner {
     fn run(&self, program: &str, args: &[&str]) -> CommandOutput {
+        // Every spawn site passes validated values only (see validate_spawn_arg)
+        debug_assert!(
+            args.iter().all(|a| crate::is_spawn_safe(a)),
+            "unsanitized argument for {}: {:?}",
+            program,
+            args
+        );
+        let program = if program == "rivalcfg" { self.rivalcfg.as_str() } else { program };
         let output = std::process::Command::new(program).args(args).output();
         match output {
             Ok(o) => CommandOutput {
@@ -37,13 +62,36 @@ impl CommandRunner for RealCommandRunner {
     }
 }
 
+// DPI values as integer strings ("800.0" -> "800"), keeping comma-separated stages;
+// anything that isn't a number is passed through for validation to report
+fn sensitivity_arg(sens: &str) -> String {
+    sens.split(',')
+        .map(|v| match v.trim().parse::<f64>() {
+            Ok(n) if n.is_finite() && n >= 0.0 => (n.round() as u64).to_string(),
+            _ => v.trim().to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
 /// Build arguments for `rivalcfg` from Settings. Returns only the args (no program name).
+/// Values are expected to be trimmed already (see `normalize_settings`).
 pub fn build_rivalcfg_args(s: &crate::Settings) -> Vec<String> {
+    for value in [&s.sensitivity, &s.polling_rate, &s.sleep_timer, &s.dim_timer, &s.buttons, &s.led_color]
+        .into_iter()
+        .flatten()
+    {
+        debug_assert_eq!(value.trim(), value, "Settings must be normalized before building rivalcfg args");
+        debug_assert!(crate::is_spawn_safe(value), "Settings must be validated before building rivalcfg args");
+    }
     let mut args = Vec::new();
-    if let Some(ref sens) = s.sensitivity {
+    if let Some((x, y)) = s.dual_sensitivity {
+        args.push("--sensitivity".to_string());
+        args.push(format!("{},{}", x, y));
+    } else if let Some(ref sens) = s.sensitivity {
         if !sens.is_empty() {
             args.push("--sensitivity".to_string());
-            args.push(sens.clone());
+            args.push(sensitivity_arg(sens));
         }
     }
     if let Some(ref rate) = s.polling_rate {
@@ -64,66 +112,238 @@ pub fn build_rivalcfg_args(s: &crate::Settings) -> Vec<String> {
             args.push(dim.clone());
         }
     }
+    if let Some(ref buttons) = s.buttons
+        && !buttons.is_empty()
+    {
+        args.push("--buttons".to_string());
+        args.push(buttons.clone());
+    }
+    // "default" leaves the LED alone; a custom mode needs a valid colour. Without an
+    // LED mode, colour_mode "custom" sends custom_color instead.
+    let color = match (s.led_mode.as_deref(), s.led_color.as_deref()) {
+        (Some(crate::LED_MODE_OFF), _) => Some(LED_OFF_COLOR),
+        (Some(crate::LED_MODE_CUSTOM), Some(color)) => Some(color),
+        (None, _) if s.colour_mode.as_deref() == Some("custom") => s.custom_color.as_deref(),
+        _ => None,
+    };
+    if let Some(color) = color.filter(|c| crate::validate_hex_color(c).is_ok()) {
+        args.push("--color".to_string());
+        args.push(color.to_string());
+    }
+    // Pass-through for options the GUI has no dedicated field for, after the known ones
+    if let Some(ref extra) = s.extra_args {
+        debug_assert!(extra.iter().all(|a| crate::is_spawn_safe(a)), "extra args must be validated");
+        args.extend(extra.iter().cloned());
+    }
     args
 }
 
-pub fn get_battery_status(stdout: &str) -> Option<bool> {
-    if stdout.contains("Discharging") {
-        Some(false)
-    } else if stdout.contains("Charging") {
-        Some(true)
-    } else {
-        None
+// rivalcfg has no "off" keyword; black turns the LEDs off on every supported model
+const LED_OFF_COLOR: &str = "#000000";
+
+pub use crate::parse::ChargeStatus;
+
+/// Why a rivalcfg query produced no result
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RivalError {
+    /// rivalcfg exited with an error; carries its stderr
+    CommandFailed(String),
+    /// rivalcfg could not be started at all (missing, not executable, ...)
+    SpawnFailed(String),
+    /// rivalcfg ran but no supported device is plugged in
+    NoDevice,
+    /// the device has no battery to read (a wired-only mouse)
+    NoBattery,
+    /// rivalcfg's output wasn't understood; carries the output
+    ParseFailed(String),
+}
+
+impl std::fmt::Display for RivalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RivalError::CommandFailed(stderr) => write!(f, "rivalcfg command failed: {}", stderr.trim()),
+            RivalError::SpawnFailed(stderr) => write!(f, "could not run rivalcfg: {}", stderr.trim()),
+            RivalError::NoDevice => write!(f, "no supported device found"),
+            RivalError::NoBattery => write!(f, "the device has no battery"),
+            RivalError::ParseFailed(output) => write!(f, "could not parse rivalcfg output: {}", output.trim()),
+        }
     }
 }
 
-// get_battery_status is public already; no re-export needed here
+impl std::error::Error for RivalError {}
 
-pub fn get_battery_level_with_runner(runner: &dyn CommandRunner) -> Option<(u8, bool)> {
+// rivalcfg reports a missing mouse in its error output rather than with a distinct exit code
+fn reports_no_device(out: &CommandOutput) -> bool {
+    let text = format!("{}\n{}", out.stdout, out.stderr).to_ascii_lowercase();
+    ["no supported device", "no device found", "device not found"]
+        .iter()
+        .any(|marker| text.contains(marker))
+}
+
+pub fn get_battery_level_with_runner(runner: &dyn CommandRunner) -> Result<(u8, ChargeStatus), RivalError> {
     eprintln!("[rivalcfg-tray] Attempting to run rivalcfg --battery-level");
     let out = runner.run("rivalcfg", &["--battery-level"]);
+    if reports_no_device(&out) {
+        return Err(RivalError::NoDevice);
+    }
+    if parse::reports_no_battery(&format!("{}\n{}", out.stdout, out.stderr)) {
+        return Err(RivalError::NoBattery);
+    }
     if !out.success {
         eprintln!("[rivalcfg-tray] rivalcfg command failed:\nstdout: {}\nstderr: {}", out.stdout, out.stderr);
-        return None;
+        if out._code.is_none() {
+            return Err(RivalError::SpawnFailed(out.stderr));
+        }
+        return Err(RivalError::CommandFailed(out.stderr));
     }
     eprintln!("[rivalcfg-tray] rivalcfg output: {}", out.stdout);
-    let charging_status = get_battery_status(&out.stdout)?;
-    let second_last_word = out.stdout.split_whitespace().rev().nth(1)?;
-    let trimmed = second_last_word.trim_end_matches('%');
-    let percent = trimmed.parse::<u8>().ok()?;
-    Some((percent, charging_status))
+    let reading = parse::parse_battery(&out.stdout).ok_or_else(|| RivalError::ParseFailed(out.stdout.clone()))?;
+    Ok((reading.percent, reading.status))
+}
+
+/// Waits between battery read attempts; the receiver sometimes drops a single
+/// query right after waking up, so a failed read is tried again shortly after
+pub const BATTERY_RETRY_DELAYS: [std::time::Duration; 2] =
+    [std::time::Duration::from_millis(100), std::time::Duration::from_millis(250)];
+
+/// get_battery_level_with_runner, retried after each of `delays` while rivalcfg
+/// exits with an error. Anything else (rivalcfg missing, no device, no battery,
+/// unparsable output) won't change on a retry and is returned at once.
+pub fn get_battery_level_retrying(
+    runner: &dyn CommandRunner,
+    delays: &[std::time::Duration],
+) -> Result<(u8, ChargeStatus), RivalError> {
+    let mut result = get_battery_level_with_runner(runner);
+    for delay in delays {
+        if !matches!(result, Err(RivalError::CommandFailed(_))) {
+            break;
+        }
+        eprintln!("[rivalcfg-tray] Battery read failed, retrying in {}ms", delay.as_millis());
+        std::thread::sleep(*delay);
+        result = get_battery_level_with_runner(runner);
+    }
+    result
 }
 
-pub fn get_battery_level() -> Option<(u8, bool)> {
+pub fn get_battery_level() -> Option<(u8, ChargeStatus)> {
     let runner = RealCommandRunner::default();
-    get_battery_level_with_runner(&runner)
+    get_battery_level_retrying(&runner, &BATTERY_RETRY_DELAYS)
+        .inspect_err(|e| eprintln!("[rivalcfg-tray] Battery read failed: {}", e))
+        .ok()
 }
 
-pub fn get_mouse_name_with_runner(runner: &dyn CommandRunner) -> Option<String> {
+/// What `rivalcfg --help` reports about the connected device
+pub fn get_device_capabilities_with_runner(runner: &dyn CommandRunner) -> Result<parse::HelpCapabilities, RivalError> {
     let out = runner.run("rivalcfg", &["--help"]);
+    if reports_no_device(&out) {
+        return Err(RivalError::NoDevice);
+    }
     if !out.success {
         eprintln!("[rivalcfg-tray] rivalcfg command failed:\nstdout: {}\nstderr: {}", out.stdout, out.stderr);
-        return None;
+        return Err(RivalError::CommandFailed(out.stderr));
     }
 
-    let stdout = out.stdout;
-    // Find the line ending with "Options:"
-    let options_line = stdout.lines().find(|line| line.ends_with("Options:"));
-    if options_line.is_none() {
+    // The device name is the heading of the "<name> Options:" section, which is
+    // missing when no device is connected
+    let caps = parse::parse_help_capabilities(&out.stdout);
+    if caps.device_name.is_none() {
         eprintln!("[rivalcfg-tray] Warning: Could not find 'Options:' line in rivalcfg output");
-        return None;
+        return Err(RivalError::NoDevice);
     }
-    eprintln!("[rivalcfg-tray] Found 'Options:' line in rivalcfg output: {}", options_line.unwrap());
-    // Extract mouse name from the output (trim "Options:" from the end of the line.)
-    let mouse_name = options_line.unwrap().trim_end_matches("Options:").trim().to_string();
+    Ok(caps)
+}
+
+pub fn get_mouse_name_with_runner(runner: &dyn CommandRunner) -> Result<String, RivalError> {
+    let mouse_name = get_device_capabilities_with_runner(runner)?.device_name.unwrap_or_default();
     eprintln!("[rivalcfg-tray] rivalcfg Mouse: {}", mouse_name);
+    Ok(mouse_name)
+}
+
+/// Firmware version of the connected mouse from `rivalcfg --firmware-version`.
+/// None when the device doesn't report one or the command fails.
+pub fn get_firmware_version_with_runner(runner: &dyn CommandRunner) -> Option<String> {
+    let out = runner.run("rivalcfg", &["--firmware-version"]);
+    if !out.success {
+        eprintln!("[rivalcfg-tray] rivalcfg --firmware-version failed: {}", out.stderr.trim());
+        return None;
+    }
+    parse::parse_firmware_version(&out.stdout)
+}
+
+/// Set only the LED colour (`rivalcfg --color <hex>`), e.g. for a live preview.
+/// Nothing is saved; callers validate `color` first.
+pub fn apply_color_only(runner: &dyn CommandRunner, color: &str) -> CommandOutput {
+    debug_assert!(crate::is_spawn_safe(color), "colour must be validated before it is applied");
+    runner.run("rivalcfg", &["--color", color])
+}
+
+/// Set only the sensitivity (`rivalcfg --sensitivity <dpi>`), e.g. from a tray preset.
+/// Nothing is saved.
+pub fn set_sensitivity(runner: &dyn CommandRunner, value: u32) -> CommandOutput {
+    runner.run("rivalcfg", &["--sensitivity", &value.to_string()])
+}
+
+/// Push all saved settings to the device in one merged `rivalcfg` call.
+/// Returns None when the settings carry nothing to apply.
+pub fn apply_settings(runner: &dyn CommandRunner, s: &crate::Settings) -> Option<CommandOutput> {
+    let args = build_rivalcfg_args(s);
+    if args.is_empty() {
+        return None;
+    }
+    let slices = args.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+    Some(runner.run("rivalcfg", &slices))
+}
+
+/// Apply `s` as the config window's Apply button does. Settings with nothing to
+/// apply succeed without running rivalcfg; a failed run returns its stderr.
+pub fn apply_settings_with_runner(s: &crate::Settings, runner: &dyn CommandRunner) -> Result<(), String> {
+    match apply_settings(runner, s) {
+        Some(out) if !out.success => Err(out.stderr),
+        _ => Ok(()),
+    }
+}
+
+/// Reset the device to its factory settings (`rivalcfg -r`).
+pub fn reset_device_with_runner(runner: &dyn CommandRunner) -> CommandOutput {
+    eprintln!("[rivalcfg-tray] Resetting device settings with rivalcfg -r");
+    runner.run("rivalcfg", &["-r"])
+}
 
-    Some(mouse_name)
+/// A device known to rivalcfg and whether it is the one currently plugged in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub name: String,
+    pub usb_id: String,
+    pub connected: bool,
+}
+
+/// Every device from `rivalcfg --list`. rivalcfg only reports which device it is
+/// talking to through the `--help` heading, so entries with that name are marked
+/// as connected.
+pub fn get_all_device_info_with_runner(runner: &dyn CommandRunner) -> Vec<DeviceInfo> {
+    let out = runner.run("rivalcfg", &["--list"]);
+    if !out.success {
+        eprintln!("[rivalcfg-tray] rivalcfg --list failed: {}", out.stderr);
+        return Vec::new();
+    }
+    let connected_name = get_mouse_name_with_runner(runner)
+        .inspect_err(|e| eprintln!("[rivalcfg-tray] Could not tell which device is connected: {}", e))
+        .ok();
+    parse::parse_device_list(&out.stdout)
+        .into_iter()
+        .map(|d| DeviceInfo {
+            connected: connected_name.as_deref().is_some_and(|n| n.eq_ignore_ascii_case(&d.name)),
+            name: d.name,
+            usb_id: d.usb_id,
+        })
+        .collect()
 }
 
-pub fn get_mouse_name() -> Option<String> {
+pub fn get_device_capabilities() -> Option<parse::HelpCapabilities> {
     let runner = RealCommandRunner::default();
-    get_mouse_name_with_runner(&runner)
+    get_device_capabilities_with_runner(&runner)
+        .inspect_err(|e| eprintln!("[rivalcfg-tray] Device probe failed: {}", e))
+        .ok()
 }
 
 // Tests were moved into `src/tests.rs` so this module is intentionally empty.
\ No newline at end of file
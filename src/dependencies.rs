@@ -0,0 +1,141 @@
+use crate::cmd::CommandRunner;
+
+/// Whether an external tool answered `--version`, and what it reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tool {
+    Missing,
+    // version is None when the output had no recognisable version number
+    Available { version: Option<String> },
+}
+
+impl Tool {
+    pub fn is_available(&self) -> bool {
+        matches!(self, Tool::Available { .. })
+    }
+}
+
+/// The external tools found at startup. Both are assumed present until probed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppCapabilities {
+    pub rivalcfg: Tool,
+    pub rsvg_convert: Tool,
+}
+
+impl Default for AppCapabilities {
+    fn default() -> Self {
+        Self {
+            rivalcfg: Tool::Available { version: None },
+            rsvg_convert: Tool::Available { version: None },
+        }
+    }
+}
+
+impl AppCapabilities {
+    /// Run `--version` of every tool through `runner`
+    pub fn probe(runner: &dyn CommandRunner) -> Self {
+        Self {
+            rivalcfg: probe_tool(runner, "rivalcfg"),
+            rsvg_convert: probe_tool(runner, "rsvg-convert"),
+        }
+    }
+
+    /// Names of the tools that were not found, in the order the dialog lists them
+    pub fn missing(&self) -> Vec<&'static str> {
+        [("rivalcfg", &self.rivalcfg), ("rsvg-convert", &self.rsvg_convert)]
+            .into_iter()
+            .filter(|(_, tool)| !tool.is_available())
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Battery reads need rivalcfg; without it polling is skipped
+    pub fn can_poll_battery(&self) -> bool {
+        self.rivalcfg.is_available()
+    }
+}
+
+fn probe_tool(runner: &dyn CommandRunner, program: &str) -> Tool {
+    let out = runner.run(program, &["--version"]);
+    if !out.success {
+        eprintln!("[rivalcfg-tray] {} --version failed: {}", program, out.stderr.trim());
+        return Tool::Missing;
+    }
+    Tool::Available {
+        version: parse_tool_version(&out.stdout),
+    }
+}
+
+/// The version number in `--version` output such as "rivalcfg 4.13.0" or
+/// "rsvg-convert version 2.58.0": the first word that starts with a digit and
+/// contains a dot.
+pub fn parse_tool_version(stdout: &str) -> Option<String> {
+    stdout
+        .split_whitespace()
+        .map(|word| word.trim_start_matches('v').trim_end_matches([',', ')']))
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()) && word.contains('.'))
+        .map(str::to_string)
+}
+
+/// Distribution families with a known package for rsvg-convert
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Distro {
+    Debian,
+    Fedora,
+    Arch,
+    Suse,
+    Other,
+}
+
+/// The family of the running distribution from the contents of /etc/os-release,
+/// going by ID first and ID_LIKE second.
+pub fn distro_from_os_release(os_release: &str) -> Distro {
+    let field = |key: &str| {
+        os_release
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+            .map(|v| v.trim().trim_matches('"').to_ascii_lowercase())
+            .unwrap_or_default()
+    };
+    let ids = format!("{} {}", field("ID"), field("ID_LIKE"));
+    ids.split_whitespace()
+        .find_map(|id| match id {
+            "debian" | "ubuntu" => Some(Distro::Debian),
+            "fedora" | "rhel" | "centos" => Some(Distro::Fedora),
+            "arch" => Some(Distro::Arch),
+            "suse" | "opensuse" | "opensuse-tumbleweed" | "opensuse-leap" => Some(Distro::Suse),
+            _ => None,
+        })
+        .unwrap_or(Distro::Other)
+}
+
+/// How to install `program` on `distro`
+pub fn install_hint(program: &str, distro: Distro) -> String {
+    match (program, distro) {
+        // rivalcfg is a Python tool and rarely packaged
+        ("rivalcfg", _) => "pipx install rivalcfg, then sudo rivalcfg --update-udev".to_string(),
+        ("rsvg-convert", Distro::Debian) => "sudo apt install librsvg2-bin".to_string(),
+        ("rsvg-convert", Distro::Fedora) => "sudo dnf install librsvg2-tools".to_string(),
+        ("rsvg-convert", Distro::Arch) => "sudo pacman -S librsvg".to_string(),
+        ("rsvg-convert", Distro::Suse) => "sudo zypper install rsvg-convert".to_string(),
+        ("rsvg-convert", Distro::Other) => "install librsvg (rsvg-convert) with your package manager".to_string(),
+        (other, _) => format!("install {} with your package manager", other),
+    }
+}
+
+// What each tool is needed for, shown next to it in the dialog
+fn tool_purpose(program: &str) -> &'static str {
+    match program {
+        "rivalcfg" => "configures the mouse and reads its battery",
+        "rsvg-convert" => "renders the tray icons; bundled low-resolution icons are used without it",
+        _ => "",
+    }
+}
+
+/// Body of the startup dialog: every missing tool with its purpose and install hint
+pub fn missing_tools_message(caps: &AppCapabilities, distro: Distro) -> String {
+    caps.missing()
+        .into_iter()
+        .map(|program| format!("• {} — {}\n   {}", program, tool_purpose(program), install_hint(program, distro)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
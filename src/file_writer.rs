@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::time::{Duration, Instant};
+
+// Consecutive failures before a target is suspended
+const FAILURE_THRESHOLD: u32 = 3;
+const SUSPEND_FOR: Duration = Duration::from_secs(15 * 60);
+// Pending writes allowed before new ones are dropped instead of queued
+const QUEUE_CAPACITY: usize = 16;
+
+#[derive(Debug, Default)]
+struct TargetState {
+    failures: u32,
+    suspended_until: Option<Instant>,
+}
+
+/// What happened after recording a failed write.
+#[derive(Debug, PartialEq, Eq)]
+pub enum FailureOutcome {
+    // keep trying on the next write
+    Retry,
+    // the target just got suspended; warn once
+    Suspended,
+}
+
+/// Per-target write failure bookkeeping. Time is passed in so it can be tested.
+#[derive(Debug)]
+pub struct FailureTracker {
+    targets: HashMap<PathBuf, TargetState>,
+    threshold: u32,
+    suspend_for: Duration,
+}
+
+impl Default for FailureTracker {
+    fn default() -> Self {
+        Self::new(FAILURE_THRESHOLD, SUSPEND_FOR)
+    }
+}
+
+impl FailureTracker {
+    pub fn new(threshold: u32, suspend_for: Duration) -> Self {
+        Self {
+            targets: HashMap::new(),
+            threshold,
+            suspend_for,
+        }
+    }
+
+    /// True while `target` is suspended and writes to it should be skipped.
+    pub fn should_skip(&self, target: &Path, now: Instant) -> bool {
+        self.targets
+            .get(target)
+            .and_then(|t| t.suspended_until)
+            .is_some_and(|until| now < until)
+    }
+
+    pub fn record_failure(&mut self, target: &Path, now: Instant) -> FailureOutcome {
+        let state = self.targets.entry(target.to_path_buf()).or_default();
+        state.failures += 1;
+        if state.failures >= self.threshold && state.suspended_until.is_none_or(|until| now >= until) {
+            state.suspended_until = Some(now + self.suspend_for);
+            state.failures = 0;
+            return FailureOutcome::Suspended;
+        }
+        FailureOutcome::Retry
+    }
+
+    /// Clears any failure state. Returns true if the target had been failing.
+    pub fn record_success(&mut self, target: &Path) -> bool {
+        self.targets.remove(target).is_some()
+    }
+}
+
+struct WriteRequest {
+    target: PathBuf,
+    data: Vec<u8>,
+}
+
+/// Hands periodic file outputs to a single worker thread so slow or full
+/// filesystems never block the GTK main loop.
+pub struct FileWriter {
+    tx: SyncSender<WriteRequest>,
+}
+
+impl FileWriter {
+    /// `on_suspend` is called once each time a target gets suspended, with the
+    /// target and how long writes to it are skipped.
+    pub fn spawn<S>(on_suspend: S) -> Self
+    where
+        S: Fn(&Path, Duration) + Send + 'static,
+    {
+        Self::spawn_with(QUEUE_CAPACITY, FailureTracker::default(), write_file, on_suspend)
+    }
+
+    pub fn spawn_with<F, S>(capacity: usize, mut tracker: FailureTracker, write: F, on_suspend: S) -> Self
+    where
+        F: Fn(&Path, &[u8]) -> std::io::Result<()> + Send + 'static,
+        S: Fn(&Path, Duration) + Send + 'static,
+    {
+        let (tx, rx) = mpsc::sync_channel::<WriteRequest>(capacity);
+        std::thread::spawn(move || {
+            for req in rx {
+                if tracker.should_skip(&req.target, Instant::now()) {
+                    continue;
+                }
+                match write(&req.target, &req.data) {
+                    Ok(()) => {
+                        if tracker.record_success(&req.target) {
+                            eprintln!("[rivalcfg-tray] Writes to {} recovered", req.target.display());
+                        }
+                    }
+                    Err(e) => {
+                        if tracker.record_failure(&req.target, Instant::now()) == FailureOutcome::Suspended {
+                            eprintln!(
+                                "[rivalcfg-tray] Warning: Repeated failures writing {} ({}), suspending writes for {} minutes",
+                                req.target.display(),
+                                e,
+                                tracker.suspend_for.as_secs() / 60
+                            );
+                            on_suspend(&req.target, tracker.suspend_for);
+                        }
+                    }
+                }
+            }
+        });
+        Self { tx }
+    }
+
+    /// Queue a write without blocking. Returns false if the write was dropped
+    /// because the worker is backed up (or gone).
+    pub fn submit(&self, target: PathBuf, data: Vec<u8>) -> bool {
+        match self.tx.try_send(WriteRequest { target, data }) {
+            Ok(()) => true,
+            Err(TrySendError::Full(req)) => {
+                eprintln!("[rivalcfg-tray] Warning: File writer is backed up, dropping write to {}", req.target.display());
+                false
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+fn write_file(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, data)
+}
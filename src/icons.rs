@@ -0,0 +1,91 @@
+use std::path::{Path, PathBuf};
+
+/// Finds icon files by name. Abstracted the same way as `CommandRunner` so the
+/// icon selection logic can be tested without any icons installed.
+pub trait IconResolver: Send + Sync {
+    fn resolve(&self, name: &str) -> Option<PathBuf>;
+}
+
+/// Searches the override dir, icon theme, XDG data dirs and install paths (see `find_icon`).
+#[derive(Debug, Default)]
+pub struct RealIconResolver {}
+
+impl IconResolver for RealIconResolver {
+    fn resolve(&self, name: &str) -> Option<PathBuf> {
+        crate::find_icon(name)
+    }
+}
+
+/// Resolve `name`, falling back to the development path `icons/<name>`.
+pub fn resolve_or_default(resolver: &dyn IconResolver, name: &str) -> PathBuf {
+    resolver
+        .resolve(name)
+        .unwrap_or_else(|| PathBuf::from(format!("icons/{}", name)))
+}
+
+/// The tray's own SVGs, compiled into the binary so a bare executable still has
+/// icons. Installed files and icon themes take precedence (see `locate_icon`).
+pub const BUNDLED_ICONS: [(&str, &[u8]); 11] = [
+    ("battery-0.svg", include_bytes!("../icons/battery-0.svg")),
+    ("battery-25.svg", include_bytes!("../icons/battery-25.svg")),
+    ("battery-50.svg", include_bytes!("../icons/battery-50.svg")),
+    ("battery-75.svg", include_bytes!("../icons/battery-75.svg")),
+    ("battery-100.svg", include_bytes!("../icons/battery-100.svg")),
+    ("battery-warn.svg", include_bytes!("../icons/battery-warn.svg")),
+    ("battery-full.svg", include_bytes!("../icons/battery-full.svg")),
+    ("battery-charging.svg", include_bytes!("../icons/battery-charging.svg")),
+    ("charging.svg", include_bytes!("../icons/charging.svg")),
+    ("mouse-disconnected.svg", include_bytes!("../icons/mouse-disconnected.svg")),
+    ("mouse.svg", include_bytes!("../icons/mouse.svg")),
+];
+
+pub fn bundled_icon(name: &str) -> Option<&'static [u8]> {
+    BUNDLED_ICONS.iter().find(|(n, _)| *n == name).map(|(_, bytes)| *bytes)
+}
+
+/// Size of the pre-rendered PNGs in `FALLBACK_PNGS`
+pub const FALLBACK_PNG_SIZE: u32 = 64;
+
+/// Pre-rendered black PNGs of every battery bucket, plain and with the charging
+/// bolt, for systems without rsvg-convert. Regenerate them when the SVGs change.
+pub const FALLBACK_PNGS: [(&str, &[u8]); 15] = [
+    ("battery-0.png", include_bytes!("../icons/fallback/battery-0.png")),
+    ("battery-0-charging.png", include_bytes!("../icons/fallback/battery-0-charging.png")),
+    ("battery-warn.png", include_bytes!("../icons/fallback/battery-warn.png")),
+    ("battery-warn-charging.png", include_bytes!("../icons/fallback/battery-warn-charging.png")),
+    ("battery-25.png", include_bytes!("../icons/fallback/battery-25.png")),
+    ("battery-25-charging.png", include_bytes!("../icons/fallback/battery-25-charging.png")),
+    ("battery-50.png", include_bytes!("../icons/fallback/battery-50.png")),
+    ("battery-50-charging.png", include_bytes!("../icons/fallback/battery-50-charging.png")),
+    ("battery-75.png", include_bytes!("../icons/fallback/battery-75.png")),
+    ("battery-75-charging.png", include_bytes!("../icons/fallback/battery-75-charging.png")),
+    ("battery-100.png", include_bytes!("../icons/fallback/battery-100.png")),
+    ("battery-100-charging.png", include_bytes!("../icons/fallback/battery-100-charging.png")),
+    ("battery-full.png", include_bytes!("../icons/fallback/battery-full.png")),
+    ("mouse-disconnected.png", include_bytes!("../icons/fallback/mouse-disconnected.png")),
+    ("mouse.png", include_bytes!("../icons/fallback/mouse.png")),
+];
+
+pub fn fallback_png(name: &str) -> Option<&'static [u8]> {
+    FALLBACK_PNGS.iter().find(|(n, _)| *n == name).map(|(_, bytes)| *bytes)
+}
+
+/// Write the bundled copy of `name` into `dir` (rewriting it if it differs) and
+/// return its path. The SVG-to-PNG step and the compositing helpers work on files.
+pub fn bundled_icon_path(name: &str, dir: &Path) -> Option<PathBuf> {
+    write_bundled(name, bundled_icon(name)?, dir)
+}
+
+/// Like `bundled_icon_path`, for the pre-rendered PNGs
+pub fn fallback_png_path(name: &str, dir: &Path) -> Option<PathBuf> {
+    write_bundled(name, fallback_png(name)?, dir)
+}
+
+fn write_bundled(name: &str, bytes: &[u8], dir: &Path) -> Option<PathBuf> {
+    let path = dir.join(name);
+    if std::fs::read(&path).ok().as_deref() != Some(bytes) {
+        std::fs::create_dir_all(dir).ok()?;
+        std::fs::write(&path, bytes).ok()?;
+    }
+    Some(path)
+}
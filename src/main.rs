@@ -2,7 +2,8 @@ use std::env;
 use std::collections::HashMap;
 use std::sync::{Mutex, LazyLock};
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::{Instant, SystemTime};
 use std::io::Write;
 
 // settings includes
@@ -14,677 +15,4186 @@ use std::fs;
 // Global cache for PNG conversions
 static PNG_CACHE: LazyLock<Mutex<HashMap<String, (String, SystemTime)>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
 
-// Track last known battery state to avoid unnecessary updates
-static LAST_BATTERY_STATE: LazyLock<Mutex<Option<(u8, bool)>>> = LazyLock::new(|| Mutex::new(None));
+// Battery poll loop heartbeat, checked periodically to recover a stalled timer
+static POLL_WATCHDOG: LazyLock<Mutex<PollWatchdog>> =
+    LazyLock::new(|| Mutex::new(PollWatchdog::new(POLL_INTERVAL, Instant::now())));
 
-#[derive(Serialize, Deserialize, Default, Debug, Clone)]
-struct Settings {
-    sensitivity: Option<String>,
-    polling_rate: Option<String>,
-    sleep_timer: Option<String>,
-    dim_timer: Option<String>,
-    // icon colour mode: "light", "dark", or "custom" (custom may store a hex color in custom_color)
-    colour_mode: Option<String>,
-    custom_color: Option<String>,
-}
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(300);
+
+// Single worker for periodic file outputs (state file, ...)
+static FILE_WRITER: LazyLock<FileWriter> = LazyLock::new(|| FileWriter::spawn(notify_writes_suspended));
+
+// Appends battery history rows; a full or missing disk only suspends the history
+static HISTORY_WRITER: LazyLock<FileWriter> = LazyLock::new(|| {
+    FileWriter::spawn_with(16, FailureTracker::default(), append_history, notify_writes_suspended)
+});
 
-fn settings_file_path() -> Option<PathBuf> {
-    // Use XDG config directory if available, otherwise fallback to home/.config
-    let base = dirs::config_dir()?;
-    let dir = base.join("rivalcfg-tray");
-    Some(dir.join("settings.json"))
+// Warn once per suspension; called on the writer thread
+fn notify_writes_suspended(target: &std::path::Path, suspend_for: Duration) {
+    let event = NotifyEvent::WritesSuspended {
+        target: escape_for_display(&target.display().to_string()),
+        minutes: suspend_for.as_secs() / 60,
+    };
+    send_notification(&tray_notifier(), event);
 }
-mod cmd;
-use crate::cmd::{
-    CommandRunner,
-    RealCommandRunner,
-    build_rivalcfg_args,
-    get_battery_level,
-    get_mouse_name,
-};
 
-fn load_settings() -> Option<Settings> {
-    let path = settings_file_path()?;
-    if !path.exists() {
-        return Some(Settings::default());
+// Battery reads for icon refreshes; readings are reused for the shortest poll interval
+static BATTERY_POLLER: LazyLock<CachedBatteryPoller> = LazyLock::new(|| {
+    CachedBatteryPoller::new(
+        Arc::new(RealCommandRunner::default()),
+        Duration::from_secs(MIN_POLL_INTERVAL_SECS.into()),
+    )
+});
+
+// Recent discharging readings for the time remaining estimate
+static BATTERY_SAMPLES: LazyLock<Mutex<BatterySamples>> = LazyLock::new(|| Mutex::new(BatterySamples::default()));
+
+// Whether the tray currently shows the low-battery attention icon
+static ATTENTION_ACTIVE: LazyLock<Mutex<bool>> = LazyLock::new(|| Mutex::new(false));
+
+const DEFAULT_ATTENTION_THRESHOLD: u8 = 10;
+// Extra percentage points above the threshold needed to leave the attention state
+const ATTENTION_HYSTERESIS: u8 = 5;
+const ATTENTION_COLOR: &str = "#e01b24";
+
+// Whether a "Re-apply current settings" run is still waiting on rivalcfg
+static APPLY_IN_FLIGHT: LazyLock<Mutex<bool>> = LazyLock::new(|| Mutex::new(false));
+
+// Whether a periodic battery read is still running on its worker thread
+static POLL_IN_FLIGHT: AtomicBool = AtomicBool::new(false);
+
+// Whether the config window's Apply button is still waiting on rivalcfg; see ApplyGuard
+static APPLY_BUTTON_BUSY: AtomicBool = AtomicBool::new(false);
+
+// Holds a busy flag for as long as it lives, so every way out of the Apply handler
+// (including failed validation) releases it
+struct ApplyGuard(&'static AtomicBool);
+
+impl ApplyGuard {
+    // None while another apply holds `flag`
+    fn acquire(flag: &'static AtomicBool) -> Option<Self> {
+        flag.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .ok()
+            .map(|_| ApplyGuard(flag))
     }
-    let data = fs::read_to_string(&path).ok()?;
-    let s: Settings = serde_json::from_str(&data).ok()?;
-    Some(s)
 }
 
-fn save_settings(s: &Settings) -> Result<(), anyhow::Error> {
-    if let Some(path) = settings_file_path() {
-        if let Some(dir) = path.parent() {
-            std::fs::create_dir_all(dir)?;
-        }
-        let data = serde_json::to_string_pretty(s)?;
-        fs::write(&path, data)?;
+impl Drop for ApplyGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
     }
-    Ok(())
 }
 
-// Validation helpers used by the config dialog and tests
-fn validate_sensitivity(s: &str) -> Result<(), String> {
-    if s.is_empty() {
-        return Ok(());
+// How long the config window waits after the last edit before auto-applying
+const AUTO_APPLY_DELAY: Duration = Duration::from_millis(800);
+
+// Collapses a burst of config window edits into one apply, `delay` after the last one
+#[derive(Debug)]
+struct ApplyDebounce {
+    delay: Duration,
+    last_change: Option<Instant>,
+}
+
+impl ApplyDebounce {
+    fn new(delay: Duration) -> Self {
+        ApplyDebounce { delay, last_change: None }
     }
-    match s.parse::<u32>() {
-        Ok(v) if v >= 100 && v <= 16000 => Ok(()),
-        _ => Err("Sensitivity must be a number between 100 and 16000".to_string()),
+
+    fn changed(&mut self, now: Instant) {
+        self.last_change = Some(now);
     }
-}
 
-fn validate_polling_rate(s: &str) -> Result<(), String> {
-    if s.is_empty() {
-        return Ok(());
+    fn pending(&self) -> bool {
+        self.last_change.is_some()
     }
-    match s {
-        "125" | "250" | "500" | "1000" => Ok(()),
-        _ => Err("Polling rate must be one of: 125, 250, 500, 1000".to_string()),
+
+    // True once per burst, when `delay` has passed since its last change
+    fn take_due(&mut self, now: Instant) -> bool {
+        match self.last_change {
+            Some(at) if now.saturating_duration_since(at) >= self.delay => {
+                self.last_change = None;
+                true
+            }
+            _ => false,
+        }
     }
 }
 
-fn validate_timer(s: &str, name: &str) -> Result<(), String> {
-    if s.is_empty() {
-        return Ok(());
+// Whether the current low battery spell has already been announced
+static LOW_BATTERY_NOTIFIED: LazyLock<Mutex<bool>> = LazyLock::new(|| Mutex::new(false));
+
+const DEFAULT_LOW_BATTERY_THRESHOLD: u8 = 15;
+
+// Edge-trigger state of the "fully charged" notification
+static CHARGE_COMPLETE: LazyLock<Mutex<ChargeCompleteTracker>> =
+    LazyLock::new(|| Mutex::new(ChargeCompleteTracker::default()));
+
+// Set when profiles were added so the tray submenu gets rebuilt
+static PROFILES_CHANGED: LazyLock<Mutex<bool>> = LazyLock::new(|| Mutex::new(false));
+
+// Why the tray should be redrawn now rather than at the next poll
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TrayRefresh {
+    SettingsApplied,
+}
+
+// Installed by main, which re-reads the battery and redraws the tray for every request
+static TRAY_REFRESH: LazyLock<Mutex<Option<std::sync::mpsc::Sender<TrayRefresh>>>> =
+    LazyLock::new(|| Mutex::new(None));
+
+fn tray_refresh_sender() -> Option<std::sync::mpsc::Sender<TrayRefresh>> {
+    TRAY_REFRESH.lock().ok().and_then(|tx| tx.clone())
+}
+
+// Apply `s` and, once rivalcfg succeeded, ask the main loop to refresh the tray so
+// it doesn't show a reading from before the apply until the next poll
+fn apply_settings_and_refresh(
+    s: &Settings,
+    runner: &dyn CommandRunner,
+    refresh: Option<&std::sync::mpsc::Sender<TrayRefresh>>,
+) -> Result<(), String> {
+    let result = apply_settings_with_runner(s, runner);
+    if result.is_ok()
+        && let Some(tx) = refresh
+    {
+        let _ = tx.send(TrayRefresh::SettingsApplied);
     }
-    match s.parse::<u32>() {
-        Ok(_) => Ok(()),
-        Err(_) => Err(format!("{} must be an integer value (minutes)", name)),
+    result
+}
+
+// Temp files created by this process (rendered PNGs); the registry owns and deletes them
+static TEMP_FILES: LazyLock<Mutex<TempFileRegistry>> =
+    LazyLock::new(|| Mutex::new(TempFileRegistry::new(MAX_LIVE_TEMP_FILES)));
+
+// PNG the tray icon was last set from; cleanup keeps it
+static CURRENT_ICON: LazyLock<Mutex<Option<String>>> = LazyLock::new(|| Mutex::new(None));
+
+// Raw device name from rivalcfg, cached at startup for tooltips
+static MOUSE_NAME: LazyLock<Mutex<Option<String>>> = LazyLock::new(|| Mutex::new(None));
+
+use crate::parse::HelpCapabilities;
+
+// What rivalcfg --help said about the device at the last successful probe
+static DEVICE_CAPABILITIES: LazyLock<Mutex<Option<HelpCapabilities>>> = LazyLock::new(|| Mutex::new(None));
+
+const DEFAULT_STARTUP_GRACE_SECS: u32 = 10;
+
+// Cached raw device name, or the generic name until a probe found one
+fn current_mouse_name() -> String {
+    MOUSE_NAME
+        .lock()
+        .ok()
+        .and_then(|n| n.clone())
+        .unwrap_or_else(|| DEFAULT_DEVICE_NAME.to_string())
+}
+
+// Ask rivalcfg for the device name. A failed probe keeps any earlier result so an
+// early, not-yet-ready session doesn't replace a good name with the fallback.
+fn probe_device_name() {
+    if let Some(caps) = get_device_capabilities()
+        && let Some(name) = caps.device_name.clone()
+    {
+        eprintln!("[rivalcfg-tray] Detected device: {}", name);
+        if let Ok(mut cached) = MOUSE_NAME.lock() {
+            *cached = Some(name);
+        }
+        let wired = lacks_battery(&caps);
+        if wired {
+            eprintln!("[rivalcfg-tray] Device has no battery, hiding the battery display");
+        }
+        NO_BATTERY.store(wired, Ordering::SeqCst);
+        if let Ok(mut cached) = DEVICE_CAPABILITIES.lock() {
+            *cached = Some(caps);
+        }
     }
 }
 
-// Helpers to convert between hex color strings and gdk::RGBA
-fn rgba_from_hex(hex: &str) -> Option<gtk::gdk::RGBA> {
-    let h = hex.trim().trim_start_matches('#');
-    if h.len() == 6 {
-        let r = u8::from_str_radix(&h[0..2], 16).ok()?;
-        let g = u8::from_str_radix(&h[2..4], 16).ok()?;
-        let b = u8::from_str_radix(&h[4..6], 16).ok()?;
-        return Some(gtk::gdk::RGBA::new(
-            r as f64 / 255.0,
-            g as f64 / 255.0,
-            b as f64 / 255.0,
-            1.0,
-        ));
+// Track last known battery state to avoid unnecessary updates (None forces a redraw)
+static LAST_BATTERY_STATE: LazyLock<Mutex<Option<BatteryState>>> = LazyLock::new(|| Mutex::new(None));
+
+// Forget the last drawn state so the next render redraws the icon even if the
+// reading is unchanged, e.g. after a setting that changes how the icon looks
+fn force_icon_refresh() {
+    if let Ok(mut last) = LAST_BATTERY_STATE.lock() {
+        *last = None;
     }
-    if h.len() == 3 {
-        let rs = &h[0..1];
-        let gs = &h[1..2];
-        let bs = &h[2..3];
-        let r = u8::from_str_radix(&format!("{}{}", rs, rs), 16).ok()?;
-        let g = u8::from_str_radix(&format!("{}{}", gs, gs), 16).ok()?;
-        let b = u8::from_str_radix(&format!("{}{}", bs, bs), 16).ok()?;
-        return Some(gtk::gdk::RGBA::new(
-            r as f64 / 255.0,
-            g as f64 / 255.0,
-            b as f64 / 255.0,
-            1.0,
-        ));
+}
+
+// Whether going from `old` to `new` settings changes how the tray icon looks
+fn icon_settings_changed(old: &Settings, new: &Settings) -> bool {
+    old.colour_mode != new.colour_mode
+        || old.custom_color != new.custom_color
+        || old.icon_theme != new.icon_theme
+        || old.icon_pack != new.icon_pack
+        || old.monochrome_icons != new.monochrome_icons
+        || old.icon_size != new.icon_size
+        || old.show_label != new.show_label
+        || old.attention_threshold != new.attention_threshold
+        || old.warning_level != new.warning_level
+        || old.critical_level != new.critical_level
+        || old.battery_hysteresis != new.battery_hysteresis
+        || old.devices != new.devices
+}
+
+// Icon bucket last shown for the battery level; see select_bucket
+static LAST_BATTERY_BUCKET: LazyLock<Mutex<Option<BatteryBucket>>> = LazyLock::new(|| Mutex::new(None));
+
+// Points a level must move past a bucket boundary before the icon follows it
+const DEFAULT_BATTERY_HYSTERESIS: u8 = 2;
+
+// Battery icon buckets, lowest first; each one is drawn with its own battery-*.svg
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum BatteryBucket {
+    Empty,
+    Warn,
+    Quarter,
+    Half,
+    ThreeQuarters,
+    Full,
+}
+
+const DEFAULT_WARNING_LEVEL: u8 = 24;
+const DEFAULT_CRITICAL_LEVEL: u8 = 9;
+
+// Levels at or below which the tray shows the warning and the empty battery icon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IconThresholds {
+    warning: u8,
+    critical: u8,
+}
+
+impl Default for IconThresholds {
+    fn default() -> Self {
+        IconThresholds { warning: DEFAULT_WARNING_LEVEL, critical: DEFAULT_CRITICAL_LEVEL }
     }
-    None
 }
 
-fn hex_from_rgba(rgba: &gtk::gdk::RGBA) -> String {
-    let r = (rgba.red() * 255.0).round().clamp(0.0, 255.0) as u8;
-    let g = (rgba.green() * 255.0).round().clamp(0.0, 255.0) as u8;
-    let b = (rgba.blue() * 255.0).round().clamp(0.0, 255.0) as u8;
-    format!("#{:02x}{:02x}{:02x}", r, g, b)
+// The configured thresholds; invalid pairs (see validate_icon_thresholds) fall back
+// to the defaults
+fn icon_thresholds(s: &Settings) -> IconThresholds {
+    let t = IconThresholds {
+        warning: s.warning_level.unwrap_or(DEFAULT_WARNING_LEVEL),
+        critical: s.critical_level.unwrap_or(DEFAULT_CRITICAL_LEVEL),
+    };
+    if validate_icon_thresholds(t).is_ok() { t } else { IconThresholds::default() }
 }
 
-// Function to cleanup temp files
-fn cleanup_temp_files() {
-    // Cleanup from both temp and runtime directories
-    let mut dirs_to_clean = vec![std::env::temp_dir()];
-    if let Ok(runtime_dir) = std::env::var("XDG_RUNTIME_DIR") {
-        dirs_to_clean.push(PathBuf::from(runtime_dir).join("rivalcfg-tray"));
-    }
-
-    for temp_dir in dirs_to_clean {
-        // Remove all rivalcfg*.svg files
-        if let Ok(entries) = std::fs::read_dir(&temp_dir) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if let Some(fname) = path.file_name().and_then(|f| f.to_str()) {
-                    if fname.starts_with("rivalcfg") && fname.ends_with(".svg") {
-                        if let Err(e) = std::fs::remove_file(&path) {
-                            eprintln!("[rivalcfg-tray] Warning: Failed to cleanup temp SVG file {}: {}", path.display(), e);
-                        } else {
-                            eprintln!("[rivalcfg-tray] Cleaned up temp SVG file: {}", path.display());
-                        }
-                    }
-                }
-            }
+impl BatteryBucket {
+    // Same boundaries as battery_icon_name
+    fn from_level(level: u8, t: IconThresholds) -> Self {
+        if level <= t.critical {
+            return BatteryBucket::Empty;
+        }
+        if level <= t.warning {
+            return BatteryBucket::Warn;
+        }
+        match level {
+            91.. => BatteryBucket::Full,
+            75..=90 => BatteryBucket::ThreeQuarters,
+            50..=74 => BatteryBucket::Half,
+            _ => BatteryBucket::Quarter,
         }
     }
 
-    // Clean up PNG cache entries and their files
-    if let Ok(mut cache) = PNG_CACHE.lock() {
-        let mut to_remove = Vec::new();
-        for (svg_path, (png_path, _)) in cache.iter() {
-            if !std::path::Path::new(png_path).exists() {
-                to_remove.push(svg_path.clone());
-            } else {
-                // Try to remove the temp file
-                if let Err(e) = std::fs::remove_file(png_path) {
-                    eprintln!("[rivalcfg-tray] Warning: Failed to cleanup temp PNG file {}: {}", png_path, e);
-                } else {
-                    eprintln!("[rivalcfg-tray] Cleaned up temp PNG file: {}", png_path);
-                    to_remove.push(svg_path.clone());
-                }
-            }
-        }
-        for key in to_remove {
-            cache.remove(&key);
+    // A level inside the bucket, used to draw its icon
+    fn icon_level(self, t: IconThresholds) -> u8 {
+        match self {
+            BatteryBucket::Full => 100,
+            BatteryBucket::ThreeQuarters => 75.max(t.warning + 1),
+            BatteryBucket::Half => 50.max(t.warning + 1),
+            BatteryBucket::Quarter => 25.max(t.warning + 1),
+            BatteryBucket::Warn => t.critical + 1,
+            BatteryBucket::Empty => 0,
         }
     }
 }
 
-fn generate_tray_icon(tray_icon: &TrayIcon) -> Option<(u8, bool)> {
-    let (level, charging) = get_battery_level().unwrap_or((0, false));
-    
-    // Check if battery state has changed
-    if let Ok(mut last_state) = LAST_BATTERY_STATE.lock() {
-        if let Some((last_level, last_charging)) = *last_state {
-            if last_level == level && last_charging == charging {
-                eprintln!("[rivalcfg-tray] Battery state unchanged ({}%, charging: {}), skipping icon update", level, charging);
-                return Some((level, charging));
-            }
+// Bucket to display for `level`. The previous bucket is kept until the level is at
+// least `hysteresis` points past the boundary, so a reading wobbling between e.g.
+// 74% and 75% doesn't flip the icon back and forth.
+fn select_bucket(prev: Option<BatteryBucket>, level: u8, hysteresis: u8, t: IconThresholds) -> BatteryBucket {
+    let raw = BatteryBucket::from_level(level, t);
+    match prev {
+        Some(prev) if raw > prev => prev.max(BatteryBucket::from_level(level.saturating_sub(hysteresis), t)),
+        Some(prev) if raw < prev => prev.min(BatteryBucket::from_level(level.saturating_add(hysteresis), t)),
+        _ => raw,
+    }
+}
+
+// `state` as the icon shows it: the level snapped to the displayed bucket
+fn displayed_battery_state(state: BatteryState, bucket: Option<BatteryBucket>, t: IconThresholds) -> BatteryState {
+    match (state, bucket) {
+        (BatteryState::Connected { status, .. }, Some(bucket)) => {
+            BatteryState::Connected { level: bucket.icon_level(t), status }
         }
-        *last_state = Some((level, charging));
+        _ => state,
     }
-    
-    let icon_path = if charging {
-        let charging_svg = find_icon("charging.svg")
-            .unwrap_or_else(|| PathBuf::from("icons/charging.svg"));
-        composite_battery_charging_svg(&battery_icon_path(level), &charging_svg)
-            .unwrap_or(battery_icon_path(level))
-    } else {
-        battery_icon_path(level)
-    };
-    // Retry up to 5 times with exponential backoff if conversion fails
-    let mut tries = 0;
-    let png_path = loop {
-        if let Some(p) = svg_to_png_temp(&icon_path) {
-            break Some(p);
+}
+
+// Consecutive battery reads that failed; see debounce_disconnect
+static POLL_FAILURES: LazyLock<Mutex<u32>> = LazyLock::new(|| Mutex::new(0));
+
+const DEFAULT_DISCONNECT_AFTER: u32 = 2;
+
+// Charging state shown by the tray, debounced against flapping
+static CHARGE_DEBOUNCE: LazyLock<Mutex<ChargeDebouncer>> = LazyLock::new(|| Mutex::new(ChargeDebouncer::default()));
+
+const DEFAULT_CHARGE_DEBOUNCE_POLLS: u32 = 2;
+
+// Run a reading's charge status through `debouncer`. A switch to discharging below
+// the low battery threshold is confirmed after at most two polls, so debouncing
+// never holds back a low battery warning by more than one poll interval.
+fn debounce_charge_state(
+    debouncer: &mut ChargeDebouncer,
+    state: BatteryState,
+    confirm_after: u32,
+    low_battery_threshold: u8,
+) -> BatteryState {
+    match state {
+        BatteryState::Connected { level, status } => {
+            let urgent = status == ChargeStatus::Discharging && level < low_battery_threshold;
+            let confirm_after = if urgent { confirm_after.min(2) } else { confirm_after };
+            BatteryState::Connected { level, status: debouncer.observe(status, confirm_after) }
+        }
+        BatteryState::Disconnected | BatteryState::NoBattery => {
+            debouncer.reset();
+            state
         }
+    }
+}
 
-        // No PNG produced this iteration
-        if tries < 5 {
-            tries += 1;
-            let delay_ms = 100_u64 << tries; // Exponential backoff: 200ms, 400ms, 800ms, 1600ms, 3200ms
-            eprintln!("[rivalcfg-tray] SVG conversion failed (attempt {}), retrying in {}ms", tries, delay_ms);
-            std::thread::sleep(std::time::Duration::from_millis(delay_ms));
-            continue;
-        } else {
-            eprintln!("[rivalcfg-tray] Failed to convert SVG after {} attempts, giving up", tries + 1);
-            break None;
+// What to display for a fresh `reading`. A failed read only shows Disconnected once
+// `limit` reads in a row have failed; until then the last connected state stays up.
+// The bool is true when a reading succeeds after Disconnected was shown.
+fn debounce_disconnect(
+    failures: &mut u32,
+    reading: BatteryState,
+    last: Option<BatteryState>,
+    limit: u32,
+) -> (BatteryState, bool) {
+    match reading {
+        BatteryState::Connected { .. } => {
+            let reconnected = *failures >= limit.max(1);
+            *failures = 0;
+            (reading, reconnected)
         }
-    };
-    if let Some(png_path) = png_path {
-        std::io::stderr().flush().ok();
-        
-        // Load the PNG file as a TrayIconImage
-        if let Ok(icon_data) = std::fs::read(&png_path) {
-            // Load PNG and convert to RGBA for tray-icon
-            if let Ok(img) = image::load_from_memory(&icon_data) {
-                let rgba = img.to_rgba8();
-                let (width, height) = rgba.dimensions();
-                if let Ok(icon_image) = TrayIconImage::from_rgba(rgba.into_raw(), width, height) {
-                    if let Err(e) = tray_icon.set_icon(Some(icon_image)) {
-                        eprintln!("[rivalcfg-tray] Failed to set tray icon: {}", e);
-                    } else {
-                        eprintln!("[rivalcfg-tray] Set tray icon from: {}", png_path);
-                    }
-                } else {
-                    eprintln!("[rivalcfg-tray] Warning: Failed to create tray icon from PNG: {}", png_path);
-                }
-            } else {
-                eprintln!("[rivalcfg-tray] Warning: Failed to load PNG as image: {}", png_path);
+        // a definite answer from the device, not a failed read
+        BatteryState::NoBattery => {
+            *failures = 0;
+            (reading, false)
+        }
+        BatteryState::Disconnected => {
+            *failures = failures.saturating_add(1);
+            match last {
+                Some(last @ BatteryState::Connected { .. }) if *failures < limit => (last, false),
+                _ => (BatteryState::Disconnected, false),
             }
-        } else {
-            eprintln!("[rivalcfg-tray] Warning: Failed to read PNG file: {}", png_path);
         }
-    } else {
-        eprintln!(
-            "[rivalcfg-tray] Warning: Failed to convert SVG to PNG for icon: {} after retries",
-            icon_path.display()
-        );
-        use std::io::Write;
-        std::io::stderr().flush().ok();
     }
-    Some((level, charging))
 }
 
-// use std::io::Stdout;
-const DARK_MODE_COLOR: &str = "#ffffff";
-
-fn svg_to_png_temp(svg_path: &PathBuf) -> Option<String> {
-    use std::process::Command;
+// What the tray currently knows about the mouse battery
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BatteryState {
+    Connected { level: u8, status: ChargeStatus },
+    // rivalcfg could not read the battery (mouse off, dongle unplugged, ...)
+    Disconnected,
+    // the mouse is wired-only and has no battery to show
+    NoBattery,
+}
 
-    // Check cache first and determine if recoloring is required. We support three
-    // cases: custom color, dark mode (predefined color), or no recolor.
-    let svg_path_str = svg_path.to_string_lossy().to_string();
-    let svg_modified = std::fs::metadata(svg_path).ok()?.modified().ok()?;
-    let mut cache_key = svg_path_str.clone();
-    let mut color_for_recolor: Option<String> = None;
-    if let Some(s) = load_settings() {
-        if let Some(ref clr) = s.custom_color {
-            color_for_recolor = Some(clr.clone());
-            cache_key = format!("{}::{}", svg_path_str, clr);
-        } else if s.colour_mode.as_deref() == Some("dark") {
-            color_for_recolor = Some(DARK_MODE_COLOR.to_string());
-            cache_key = format!("{}::{}", svg_path_str, DARK_MODE_COLOR);
+impl BatteryState {
+    fn from_reading(reading: Option<(u8, ChargeStatus)>) -> Self {
+        match reading {
+            Some((level, status)) => BatteryState::Connected { level, status },
+            None => BatteryState::Disconnected,
         }
     }
 
-    if let Ok(cache) = PNG_CACHE.lock() {
-        if let Some((cached_png_path, cached_time)) = cache.get(&cache_key) {
-            if std::path::Path::new(cached_png_path).exists() && *cached_time >= svg_modified {
-                eprintln!("[rivalcfg-tray] Using cached PNG: {}", cached_png_path);
-                return Some(cached_png_path.clone());
-            }
+    fn from_result(reading: Result<(u8, ChargeStatus), RivalError>) -> Self {
+        match reading {
+            Ok((level, status)) => BatteryState::Connected { level, status },
+            Err(RivalError::NoBattery) => BatteryState::NoBattery,
+            Err(_) => BatteryState::Disconnected,
         }
     }
+}
 
-    // Use XDG runtime dir or fallback to temp dir for COSMIC compatibility
-    // Using a runtime directory helps COSMIC's status-area applet find icons more reliably
-    let runtime_dir = std::env::var("XDG_RUNTIME_DIR")
-        .ok()
-        .and_then(|d| {
-            let path = PathBuf::from(d).join("rivalcfg-tray");
-            std::fs::create_dir_all(&path).ok()?;
-            Some(path)
-        })
-        .unwrap_or_else(|| std::env::temp_dir());
+// Set while the connected mouse is known to have no battery; the poll loop then
+// stops running rivalcfg until a probe or an explicit refresh finds one again
+static NO_BATTERY: AtomicBool = AtomicBool::new(false);
 
-    // Create a temp file with a unique name
-    let temp_file = match tempfile::Builder::new()
-        .prefix("rivalcfg-tray-")
-        .suffix(".png")
-        .tempfile_in(&runtime_dir) {
-            Ok(file) => file,
-            Err(e) => {
-                eprintln!("[rivalcfg-tray] Failed to create temp file: {}", e);
-                return None;
-            }
-    };
+// Set at startup when GTK lays windows out right-to-left; the battery icon is then
+// mirrored so it drains towards the reading edge
+static ICONS_RTL: AtomicBool = AtomicBool::new(false);
 
-    let temp_path = temp_file.path().to_path_buf();
+// External tools found by the startup check (check_dependencies)
+static APP_CAPABILITIES: LazyLock<Mutex<AppCapabilities>> = LazyLock::new(|| Mutex::new(AppCapabilities::default()));
 
-    // If we have an effective recolor color (custom or dark), create a recolored SVG
-    // and convert that instead
-    let mut svg_to_convert = svg_path.clone();
-    if let Some(color) = color_for_recolor {
-        if let Some(tmp_svg) = recolor_svg_to_temp(svg_path, &color) {
-            eprintln!("[rivalcfg-tray] Using recolored SVG: {}", tmp_svg.display());
-            svg_to_convert = tmp_svg.clone();
-        }
-    }
+// False once the startup check found no rivalcfg; battery polling and applying
+// saved settings are skipped then
+fn rivalcfg_available() -> bool {
+    APP_CAPABILITIES.lock().map(|c| c.can_poll_battery()).unwrap_or(true)
+}
 
-    eprintln!("[rivalcfg-tray] Converting SVG to PNG: {} -> {}", svg_to_convert.display(), temp_path.display());
+// Whether --help shows a device without --battery-level. A help text with no
+// options at all wasn't understood and says nothing either way.
+fn lacks_battery(caps: &HelpCapabilities) -> bool {
+    caps.device_name.is_some() && !caps.options.is_empty() && !caps.supports("--battery-level")
+}
 
-    // Convert SVG to PNG
-    let output = Command::new("rsvg-convert")
-        .arg("-w")
-        .arg("64")
-        .arg("-h")
-        .arg("64")
-        .arg("-o")
-        .arg(&temp_path)
-        .arg(&svg_to_convert)
-        .output()
-        .ok()?;
+// Remember whether the mouse has a battery from the state just shown
+fn note_battery_support(state: &BatteryState) {
+    match state {
+        BatteryState::NoBattery => NO_BATTERY.store(true, Ordering::SeqCst),
+        BatteryState::Connected { .. } => NO_BATTERY.store(false, Ordering::SeqCst),
+        BatteryState::Disconnected => {}
+    }
+}
 
-    if !output.status.success() {
-        eprintln!(
-            "[rivalcfg-tray] rsvg-convert failed:\nstdout: {}\nstderr: {}",
-            String::from_utf8_lossy(&output.stdout),
-            String::from_utf8_lossy(&output.stderr)
-        );
-        return None;
+// Store `current` as the last seen state. Returns true when it differs from the
+// previous state (or no state was recorded), i.e. when the icon needs redrawing.
+fn record_battery_state(last: &mut Option<BatteryState>, current: BatteryState) -> bool {
+    if *last == Some(current) {
+        return false;
     }
+    *last = Some(current);
+    true
+}
 
-    if !temp_path.exists() {
-        eprintln!("[rivalcfg-tray] PNG file was not created: {}", temp_path.display());
-        return None;
+// Last successful battery reading, persisted so the tray has something to show
+// before the first poll after a restart.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct PersistedBattery {
+    level: u8,
+    status: ChargeStatus,
+    // raw rivalcfg name, and the name shown for it (see device_display_name); absent
+    // in files written before they were added
+    #[serde(default)]
+    device: String,
+    #[serde(default)]
+    nickname: String,
+}
+
+fn battery_state_file_path() -> Option<PathBuf> {
+    let base = dirs::data_dir()?;
+    Some(base.join("rivalcfg-tray").join("state.json"))
+}
+
+// JSON for the state file, or None for states that shouldn't overwrite it
+fn serialize_battery_state(state: &BatteryState, raw_name: &str, settings: &Settings) -> Option<String> {
+    // Only real readings are worth restoring
+    let BatteryState::Connected { level, status } = *state else {
+        return None;
+    };
+    serde_json::to_string(&PersistedBattery {
+        level,
+        status,
+        device: raw_name.to_string(),
+        nickname: device_display_name(raw_name, settings),
+    })
+    .ok()
+}
+
+fn load_battery_state_from(path: &std::path::Path) -> Option<BatteryState> {
+    let data = fs::read_to_string(path).ok()?;
+    let p: PersistedBattery = serde_json::from_str(&data).ok()?;
+    Some(BatteryState::Connected { level: p.level, status: p.status })
+}
+
+// Queue a history row for a successful reading when the history is turned on
+fn record_battery_history(settings: &Settings, state: &BatteryState, now: SystemTime) {
+    let BatteryState::Connected { level, status } = *state else {
+        return;
+    };
+    if settings.battery_history != Some(true) {
+        return;
+    }
+    let Some(path) = history_path() else {
+        return;
+    };
+    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let charging = matches!(status, ChargeStatus::Charging);
+    HISTORY_WRITER.submit(path, history_row(timestamp, level, charging).into_bytes());
+}
+
+// Queued on the background file writer so a slow disk never stalls the poll loop
+fn save_battery_state(state: &BatteryState, raw_name: &str, settings: &Settings) {
+    if let Some(path) = battery_state_file_path()
+        && let Some(data) = serialize_battery_state(state, raw_name, settings)
+    {
+        FILE_WRITER.submit(path, data.into_bytes());
+    }
+}
+
+fn load_battery_state() -> Option<BatteryState> {
+    load_battery_state_from(&battery_state_file_path()?)
+}
+
+// Attention state machine: enter below `threshold` while discharging, leave once the
+// mouse charges or climbs to `threshold + ATTENTION_HYSTERESIS`, so a reading that
+// wobbles around the threshold doesn't flap the icon.
+fn next_attention_state(active: bool, state: &BatteryState, threshold: u8) -> bool {
+    let BatteryState::Connected { level, status } = *state else {
+        return false;
+    };
+    if threshold == 0 || matches!(status, ChargeStatus::Charging | ChargeStatus::Charged) {
+        return false;
+    }
+    if active {
+        level < threshold.saturating_add(ATTENTION_HYSTERESIS)
+    } else {
+        level < threshold
+    }
+}
+
+// Level to announce when a discharging mouse drops below `threshold`. `notified`
+// remembers that the current drop was announced and is only re-armed once a
+// reading is back at or above the threshold or the mouse is charging, so each
+// discharge cycle notifies once.
+fn low_battery_notification(notified: &mut bool, state: &BatteryState, threshold: u8) -> Option<u8> {
+    let BatteryState::Connected { level, status } = *state else {
+        return None;
+    };
+    if level >= threshold || matches!(status, ChargeStatus::Charging | ChargeStatus::Charged) {
+        *notified = false;
+        return None;
+    }
+    if *notified || status != ChargeStatus::Discharging {
+        return None;
+    }
+    *notified = true;
+    Some(level)
+}
+
+// Charge session bookkeeping for charge_complete_notification
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct ChargeCompleteTracker {
+    // the previous reading was off the charger, so a new session starts now
+    was_unplugged: bool,
+    // when the current session was seen to start (None if it began before we looked)
+    charging_since: Option<Instant>,
+    // a below-full charging reading was seen in this session
+    in_progress: bool,
+    // this session's completion was already handled
+    notified: bool,
+}
+
+// A charge session that just completed
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ChargeComplete {
+    // how long the charge took, when its start was seen
+    took: Option<Duration>,
+}
+
+// Edge trigger for the "fully charged" notification, fed one reading per poll.
+// Fires once per charge session, when the mouse reports Charged or 100% while on
+// the charger after having been seen charging below full; a mouse that is already
+// full when first seen doesn't fire. Unplugging ends the session.
+fn charge_complete_notification(
+    tracker: &mut ChargeCompleteTracker,
+    state: &BatteryState,
+    now: Instant,
+) -> Option<ChargeComplete> {
+    let BatteryState::Connected { level, status } = *state else {
+        return None;
+    };
+    match status {
+        ChargeStatus::Discharging => {
+            *tracker = ChargeCompleteTracker { was_unplugged: true, ..Default::default() };
+            None
+        }
+        ChargeStatus::Unknown => None,
+        ChargeStatus::Charging | ChargeStatus::Charged => {
+            if std::mem::take(&mut tracker.was_unplugged) {
+                tracker.charging_since = Some(now);
+            }
+            let complete = status == ChargeStatus::Charged || level >= 100;
+            if !complete {
+                tracker.in_progress = true;
+                return None;
+            }
+            if tracker.notified {
+                return None;
+            }
+            tracker.notified = true;
+            if !tracker.in_progress {
+                return None;
+            }
+            Some(ChargeComplete {
+                took: tracker.charging_since.map(|t| now.saturating_duration_since(t)),
+            })
+        }
+    }
+}
+
+// Send a "<name>: Fully Charged" notification when `state` completes a charge
+// session. Returns true if a notification was sent.
+fn maybe_notify_charge_complete(
+    notifier: &dyn Notifier,
+    tracker: &mut ChargeCompleteTracker,
+    mouse_name: &str,
+    state: &BatteryState,
+    now: Instant,
+) -> bool {
+    let Some(done) = charge_complete_notification(tracker, state, now) else {
+        return false;
+    };
+    send_notification(notifier, NotifyEvent::ChargeComplete {
+        device: escape_for_display(mouse_name),
+        took: done.took,
+    });
+    true
+}
+
+// Send a critical "<name>: Low Battery (X%)" notification when `state` crosses
+// below `threshold`. Returns true if a notification was sent.
+fn maybe_notify_low_battery(
+    notifier: &dyn Notifier,
+    notified: &mut bool,
+    mouse_name: &str,
+    state: &BatteryState,
+    threshold: u8,
+) -> bool {
+    let Some(level) = low_battery_notification(notified, state, threshold) else {
+        return false;
+    };
+    send_notification(notifier, NotifyEvent::LowBattery {
+        device: escape_for_display(mouse_name),
+        level,
+    });
+    true
+}
+
+fn send_notification(notifier: &dyn Notifier, event: NotifyEvent) {
+    let notification = notification_for(&event);
+    debug_assert!(
+        is_spawn_safe(&notification.summary) && notification.body.as_deref().is_none_or(is_spawn_safe)
+    );
+    notifier.notify(notification);
+}
+
+// Full error texts behind "Settings not applied" notifications, by click token.
+// Tokens only live as long as this process.
+static NOTIFIED_ERRORS: LazyLock<Mutex<HashMap<u64, String>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+const MAX_NOTIFIED_ERRORS: usize = 16;
+
+// Keep `error` for a later notification click and return its token
+fn remember_error(errors: &mut HashMap<u64, String>, error: &str) -> u64 {
+    let token = errors.keys().max().map_or(1, |t| t + 1);
+    errors.insert(token, error.to_string());
+    while errors.len() > MAX_NOTIFIED_ERRORS {
+        if let Some(oldest) = errors.keys().min().copied() {
+            errors.remove(&oldest);
+        }
+    }
+    token
+}
+
+#[cfg(feature = "notifications")]
+fn tray_notifier() -> impl Notifier {
+    crate::notify::NotifySendNotifier::new(private_runtime_dir().map(|dir| action_socket_path(&dir)))
+}
+
+#[cfg(not(feature = "notifications"))]
+fn tray_notifier() -> impl Notifier {
+    crate::notify::DisabledNotifier
+}
+
+#[cfg(not(feature = "tray"))]
+compile_error!("rivalcfg-tray is a tray application; build it with the \"tray\" feature");
+
+// Optional features compiled into this build, listed in the config window
+fn compiled_features() -> Vec<&'static str> {
+    let mut features = vec!["tray"];
+    if cfg!(feature = "cli") {
+        features.push("cli");
+    }
+    if cfg!(feature = "notifications") {
+        features.push("notifications");
+    }
+    features
+}
+
+// Output of `program args` for the diagnostics report, or why it failed
+fn diagnostics_command(runner: &dyn CommandRunner, program: &str, args: &[&str]) -> String {
+    let out = runner.run(program, args);
+    if out.success {
+        out.stdout.trim().to_string()
+    } else {
+        format!("failed: {}", out.stderr.trim())
+    }
+}
+
+// Plain-text report of the environment for bug reports ("Copy Diagnostics")
+fn collect_diagnostics(runner: &dyn CommandRunner) -> String {
+    let mut report = format!(
+        "rivalcfg-tray {} (features: {})\n",
+        env!("CARGO_PKG_VERSION"),
+        compiled_features().join(", ")
+    );
+    report.push_str(&format!("rivalcfg --version: {}\n", diagnostics_command(runner, "rivalcfg", &["--version"])));
+    let mouse = match get_mouse_name_with_runner(runner) {
+        Ok(name) => name,
+        Err(e) => format!("not detected ({})", e),
+    };
+    report.push_str(&format!("Mouse: {}\n", mouse));
+    report.push_str(&format!(
+        "rivalcfg --battery-level: {}\n",
+        diagnostics_command(runner, "rivalcfg", &["--battery-level"])
+    ));
+    let restarts = POLL_WATCHDOG.lock().map(|w| w.restarts()).unwrap_or(0);
+    report.push_str(&format!("Poll watchdog restarts: {}\n", restarts));
+    let icon_dir = find_icon("battery-100.svg").and_then(|p| p.parent().map(|d| d.display().to_string()));
+    report.push_str(&format!("Icon directory: {}\n", icon_dir.as_deref().unwrap_or("not found")));
+    let rsvg = runner.run("rsvg-convert", &["--version"]);
+    report.push_str(&format!(
+        "rsvg-convert: {}\n",
+        if rsvg.success { rsvg.stdout.trim() } else { "not available" }
+    ));
+    match confy::get_configuration_file_path(CONFY_APP_NAME, None) {
+        Ok(path) => {
+            let contents = fs::read_to_string(&path).unwrap_or_else(|e| format!("(unreadable: {})", e));
+            report.push_str(&format!("Settings file: {}\n{}\n", path.display(), contents.trim_end()));
+        }
+        Err(e) => report.push_str(&format!("Settings file: {}\n", e)),
+    }
+    report
+}
+
+// `rivalcfg <args>` as it would be typed in a shell; arguments with spaces or
+// shell metacharacters are single-quoted
+fn format_command_string(args: &[String]) -> String {
+    let quote = |arg: &String| {
+        let plain = !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ',' | '/' | ':' | '=' | '+' | '@'));
+        if plain {
+            arg.clone()
+        } else {
+            format!("'{}'", arg.replace('\'', "'\\''"))
+        }
+    };
+    std::iter::once("rivalcfg".to_string())
+        .chain(args.iter().map(quote))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Markup for the config window's status line after an Apply: green with `summary`
+// (see applied_settings_summary) on success, the error in red otherwise
+fn apply_status_markup(result: &Result<(), String>, summary: &str) -> String {
+    match result {
+        Ok(()) if summary.is_empty() => "<span foreground=\"green\">Settings applied successfully.</span>".to_string(),
+        Ok(()) => format!(
+            "<span foreground=\"green\">Settings applied successfully: {}</span>",
+            glib::markup_escape_text(summary)
+        ),
+        Err(e) => format!("<span foreground=\"red\">Error: {}</span>", glib::markup_escape_text(e.trim())),
+    }
+}
+
+// What Apply sends to rivalcfg for `s`, e.g. "DPI 800, 1000Hz, sleep 10m"; taken
+// from the actual arguments so it never lists a value that wasn't applied
+fn applied_settings_summary(s: &Settings) -> String {
+    let args = build_rivalcfg_args(s);
+    let mut parts = Vec::new();
+    let mut rest = args.as_slice();
+    while let [flag, value, tail @ ..] = rest {
+        let part = match flag.as_str() {
+            "--sensitivity" if s.dual_sensitivity.is_some() => format!("DPI {} (X,Y)", value),
+            "--sensitivity" => format!("DPI {}", value.replace(',', "/")),
+            "--polling-rate" => format!("{}Hz", value),
+            "--sleep-timer" => format!("sleep {}m", value),
+            "--dim-timer" => format!("dim {}s", value),
+            "--buttons" => "button mapping".to_string(),
+            "--color" if s.led_mode.as_deref() == Some(LED_MODE_OFF) => "LED off".to_string(),
+            "--color" => format!("LED {}", value),
+            // extra arguments are shown as typed
+            _ => break,
+        };
+        parts.push(part);
+        rest = tail;
+    }
+    if !rest.is_empty() {
+        parts.push(rest.join(" "));
+    }
+    parts.join(", ")
+}
+
+fn firmware_label_text(version: Option<&str>) -> String {
+    format!("Firmware: {}", version.unwrap_or("N/A"))
+}
+
+fn battery_menu_text(state: &BatteryState) -> String {
+    match state {
+        BatteryState::Connected { level, .. } => format!("Battery: {}%", level),
+        BatteryState::Disconnected => "Battery: not connected".to_string(),
+        BatteryState::NoBattery => "Battery: none (wired)".to_string(),
+    }
+}
+
+// Feed one reading to the estimator and return the current estimate. Only
+// discharging readings count; charging or losing the mouse starts over.
+fn record_battery_sample(samples: &mut BatterySamples, state: &BatteryState, now: Instant) -> Option<Duration> {
+    match *state {
+        BatteryState::Connected { level, status: ChargeStatus::Discharging } => {
+            samples.push(now, level);
+            samples.estimate()
+        }
+        BatteryState::Connected { status: ChargeStatus::Unknown, .. } => samples.estimate(),
+        _ => {
+            samples.clear();
+            None
+        }
+    }
+}
+
+// Menu text for the estimate, e.g. "≈ 6h 20m remaining"
+fn remaining_menu_text(estimate: Option<Duration>) -> Option<String> {
+    estimate.map(|d| format!("≈ {} remaining", format_charge_duration(d)))
+}
+
+// Panel label beside the icon: "Mouse disconnected" once debounce_disconnect gave up
+// on the device, whether or not the label is enabled, otherwise e.g. "75%" when it
+// is. The number is padded to three digits with figure spaces so the label keeps
+// the width of "100%" (tray-icon sets no AppIndicator guide string) and doesn't
+// jiggle.
+fn tray_label_text(state: &BatteryState, show: bool) -> Option<String> {
+    match state {
+        BatteryState::Disconnected => Some("Mouse disconnected".to_string()),
+        _ if !show => None,
+        BatteryState::Connected { level, .. } => {
+            let digits = level.to_string();
+            Some(format!("{}{}%", "\u{2007}".repeat(3usize.saturating_sub(digits.len())), digits))
+        }
+        BatteryState::NoBattery => None,
+    }
+}
+
+// Tray tooltip text, e.g. "Rival 3 — 75% (Charging)"
+fn format_indicator_title(mouse_name: &str, level: u8, charging: bool) -> String {
+    if charging {
+        format!("{} — {}% (Charging)", mouse_name, level)
+    } else {
+        format!("{} — {}%", mouse_name, level)
+    }
+}
+
+fn tray_tooltip_text(mouse_name: &str, state: &BatteryState) -> String {
+    match state {
+        BatteryState::Connected { level, status: ChargeStatus::Charged } => {
+            format!("{} — {}% (Fully charged)", mouse_name, level)
+        }
+        BatteryState::Connected { level, status } => {
+            format_indicator_title(mouse_name, *level, *status == ChargeStatus::Charging)
+        }
+        BatteryState::Disconnected => format!("{} — not connected", mouse_name),
+        BatteryState::NoBattery => mouse_name.to_string(),
+    }
+}
+
+fn status_menu_text(state: &BatteryState) -> String {
+    match state {
+        BatteryState::Connected { status: ChargeStatus::Charged, .. } => "Status: Fully charged".to_string(),
+        BatteryState::Connected { status: ChargeStatus::Charging, .. } => "Status: Charging".to_string(),
+        BatteryState::Connected { status: ChargeStatus::Discharging, .. } => "Status: Discharging".to_string(),
+        BatteryState::Connected { status: ChargeStatus::Unknown, .. } => "Status: Unknown".to_string(),
+        BatteryState::Disconnected => "Status: Disconnected".to_string(),
+        BatteryState::NoBattery => "Status: Wired".to_string(),
+    }
+}
+
+// Current on-disk settings schema version, written on every save
+const SETTINGS_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+struct Settings {
+    // schema version; absent in files written before versioning was introduced
+    version: Option<u32>,
+    sensitivity: Option<String>,
+    // separate X and Y DPI for devices that take "--sensitivity X,Y"; replaces sensitivity
+    dual_sensitivity: Option<(u32, u32)>,
+    polling_rate: Option<String>,
+    sleep_timer: Option<String>,
+    dim_timer: Option<String>,
+    // icon colour mode: "light", "dark", or "custom" (custom may store a hex color in custom_color)
+    colour_mode: Option<String>,
+    custom_color: Option<String>,
+    // rendered tray icon size in pixels, one of ICON_SIZES (default 64); other values use the nearest
+    icon_size: Option<u32>,
+    // user-chosen display names, keyed on the raw device name reported by rivalcfg
+    nicknames: Option<HashMap<String, String>>,
+    // rivalcfg button mapping expression passed to --buttons, e.g. "buttons(button1=button1; ...)"
+    buttons: Option<String>,
+    // battery percentage below which the tray switches to the red attention icon (0 disables)
+    attention_threshold: Option<u8>,
+    // apply config window edits without pressing Apply (default off)
+    auto_apply: Option<bool>,
+    // push the saved device options to the mouse when the tray starts (default on)
+    auto_apply_on_startup: Option<bool>,
+    // keep a user desktop file listing the tray's actions, see sync_desktop_actions (default off)
+    desktop_actions: Option<bool>,
+    // battery percentage at or below which the warning icon is shown (default 24)
+    warning_level: Option<u8>,
+    // battery percentage at or below which the empty battery icon is shown (default 9)
+    critical_level: Option<u8>,
+    // render single-colour (monochrome) icons; None means automatic (on under GNOME)
+    #[serde(alias = "symbolic_icons")]
+    monochrome_icons: Option<bool>,
+    // tray icon variants: "color", "monochrome-light" or "monochrome-dark"; overrides monochrome_icons
+    icon_theme: Option<String>,
+    // icon pack directory name under icons/packs; None or "default" uses the built-in icons
+    icon_pack: Option<String>,
+    // battery percentage below which a desktop notification is sent (0 disables)
+    low_battery_threshold: Option<u8>,
+    // named presets of the device options, see profile_from_settings
+    profiles: Option<HashMap<String, Settings>>,
+    // seconds to wait at startup for the tray host before probing the device (0 disables)
+    startup_grace: Option<u32>,
+    // mouse LED mode: "default" (leave it alone), "off", or "custom" (uses led_color)
+    led_mode: Option<String>,
+    led_color: Option<String>,
+    // fixed battery poll interval in seconds; None adapts it to the battery (see poll_bands)
+    poll_interval: Option<u32>,
+    // battery levels and intervals of the adaptive polling, see next_poll_interval
+    poll_bands: Option<PollBands>,
+    // low battery desktop notifications on/off (default on)
+    notifications: Option<bool>,
+    // per-device overrides of the monitoring options, keyed on the raw device name
+    devices: Option<HashMap<String, DeviceSettings>>,
+    // points past a bucket boundary before the battery icon changes (default 2)
+    battery_hysteresis: Option<u8>,
+    // consecutive failed battery reads before the tray shows Disconnected (default 2)
+    disconnect_after: Option<u32>,
+    // polls in a row a new charging state must be seen before it is shown (default 2)
+    charge_debounce_polls: Option<u32>,
+    // extra rivalcfg arguments appended verbatim after the ones built from settings
+    extra_args: Option<Vec<String>>,
+    // "fully charged" desktop notification on/off (default on)
+    charge_complete_notifications: Option<bool>,
+    // append each poll to ~/.local/state/rivalcfg-tray/battery-history.csv (default off)
+    battery_history: Option<bool>,
+    // which informational items the tray menu shows
+    menu: Option<MenuSettings>,
+    // show the battery percentage as text next to the tray icon (default off)
+    show_label: Option<bool>,
+    // rivalcfg executable to run instead of the one on PATH (e.g. installed with pipx)
+    rivalcfg_path: Option<String>,
+}
+
+// Adaptive poll intervals by battery level; unset fields use the defaults below
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+struct PollBands {
+    // read every high_secs above this percentage (default 50)
+    high_level: Option<u8>,
+    // read every low_secs below this percentage, and while charging (default 15)
+    low_level: Option<u8>,
+    high_secs: Option<u32>,
+    mid_secs: Option<u32>,
+    low_secs: Option<u32>,
+}
+
+const DEFAULT_POLL_HIGH_LEVEL: u8 = 50;
+const DEFAULT_POLL_LOW_LEVEL: u8 = 15;
+const DEFAULT_POLL_HIGH_SECS: u32 = 120;
+const DEFAULT_POLL_MID_SECS: u32 = 60;
+const DEFAULT_POLL_LOW_SECS: u32 = 15;
+
+// Informational tray menu items to show; None uses the default (everything but
+// the device name)
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+struct MenuSettings {
+    device_name: Option<bool>,
+    battery_percent: Option<bool>,
+    time_estimate: Option<bool>,
+    status: Option<bool>,
+}
+
+// Non-clickable items at the top of the tray menu, in menu order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InfoItem {
+    DeviceName,
+    BatteryPercent,
+    TimeEstimate,
+    Status,
+}
+
+// The informational items `settings` asks for, in menu order
+fn menu_info_items(settings: &Settings) -> Vec<InfoItem> {
+    let m = settings.menu.clone().unwrap_or_default();
+    [
+        (InfoItem::DeviceName, m.device_name.unwrap_or(false)),
+        (InfoItem::BatteryPercent, m.battery_percent.unwrap_or(true)),
+        (InfoItem::TimeEstimate, m.time_estimate.unwrap_or(true)),
+        (InfoItem::Status, m.status.unwrap_or(true)),
+    ]
+    .into_iter()
+    .filter_map(|(item, shown)| shown.then_some(item))
+    .collect()
+}
+
+// The informational items to show for `state`: a mouse without a battery only
+// keeps its name
+fn shown_info_items(settings: &Settings, state: &BatteryState) -> Vec<InfoItem> {
+    let mut items = menu_info_items(settings);
+    if *state == BatteryState::NoBattery {
+        items.retain(|item| *item == InfoItem::DeviceName);
+    }
+    items
+}
+
+// Monitoring options a device can override; None falls back to the global setting
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+struct DeviceSettings {
+    poll_interval: Option<u32>,
+    attention_threshold: Option<u8>,
+    low_battery_threshold: Option<u8>,
+    notifications: Option<bool>,
+}
+
+// Monitoring options in effect for one device
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct EffectiveDeviceSettings {
+    poll_interval: Duration,
+    // neither the device nor the global settings fix an interval, so the poll adapts
+    // to the reading (see poll_interval_for) and poll_interval is only the fallback
+    adaptive_poll: bool,
+    attention_threshold: u8,
+    low_battery_threshold: u8,
+    notifications: bool,
+}
+
+// Poll interval bounds (seconds)
+const MIN_POLL_INTERVAL_SECS: u32 = 5;
+const MAX_POLL_INTERVAL_SECS: u32 = 3600;
+
+// Resolve each monitoring option as: the device override, else the global setting,
+// else the built-in default. Used by the poll scheduler, the icon and the notifier.
+fn effective_device_settings(s: &Settings, raw_name: &str) -> EffectiveDeviceSettings {
+    let device = s.devices.as_ref().and_then(|d| d.get(raw_name));
+    let configured_poll = device.and_then(|d| d.poll_interval).or(s.poll_interval);
+    let poll_secs = configured_poll
+        .unwrap_or(POLL_INTERVAL.as_secs() as u32)
+        .clamp(MIN_POLL_INTERVAL_SECS, MAX_POLL_INTERVAL_SECS);
+    EffectiveDeviceSettings {
+        poll_interval: Duration::from_secs(poll_secs.into()),
+        adaptive_poll: configured_poll.is_none(),
+        attention_threshold: device
+            .and_then(|d| d.attention_threshold)
+            .or(s.attention_threshold)
+            .unwrap_or(DEFAULT_ATTENTION_THRESHOLD),
+        low_battery_threshold: device
+            .and_then(|d| d.low_battery_threshold)
+            .or(s.low_battery_threshold)
+            .unwrap_or(DEFAULT_LOW_BATTERY_THRESHOLD),
+        notifications: device.and_then(|d| d.notifications).or(s.notifications).unwrap_or(true),
+    }
+}
+
+fn current_device_settings() -> EffectiveDeviceSettings {
+    effective_device_settings(&load_settings().unwrap_or_default(), &current_mouse_name())
+}
+
+// How long to wait before the next battery read: rarely while the battery is well
+// charged, often when it runs low or is charging
+fn next_poll_interval(level: u8, charging: bool, bands: &PollBands) -> Duration {
+    let high_level = bands.high_level.unwrap_or(DEFAULT_POLL_HIGH_LEVEL);
+    let low_level = bands.low_level.unwrap_or(DEFAULT_POLL_LOW_LEVEL);
+    let secs = if charging || level < low_level {
+        bands.low_secs.unwrap_or(DEFAULT_POLL_LOW_SECS)
+    } else if level > high_level {
+        bands.high_secs.unwrap_or(DEFAULT_POLL_HIGH_SECS)
+    } else {
+        bands.mid_secs.unwrap_or(DEFAULT_POLL_MID_SECS)
+    };
+    Duration::from_secs(secs.clamp(MIN_POLL_INTERVAL_SECS, MAX_POLL_INTERVAL_SECS).into())
+}
+
+// The poll interval while the tray shows `state`: a configured interval (the device's
+// or the global one) as is, otherwise next_poll_interval for the last reading
+fn poll_interval_for(s: &Settings, raw_name: &str, state: Option<&BatteryState>) -> Duration {
+    let device = effective_device_settings(s, raw_name);
+    match state {
+        Some(BatteryState::Connected { level, status }) if device.adaptive_poll => {
+            next_poll_interval(*level, *status == ChargeStatus::Charging, &s.poll_bands.unwrap_or_default())
+        }
+        // a configured interval, or nothing to adapt to yet
+        _ => device.poll_interval,
+    }
+}
+
+// The interval to move the poll timer to when `s` and `state` no longer match the
+// one it was `scheduled` with, or None to keep the running timer
+fn rescheduled_poll_interval(
+    scheduled: Duration,
+    s: &Settings,
+    raw_name: &str,
+    state: Option<&BatteryState>,
+) -> Option<Duration> {
+    let wanted = poll_interval_for(s, raw_name, state);
+    (wanted != scheduled).then_some(wanted)
+}
+
+// Accepted Settings::led_mode values
+const LED_MODE_DEFAULT: &str = "default";
+const LED_MODE_OFF: &str = "off";
+const LED_MODE_CUSTOM: &str = "custom";
+
+const DEFAULT_DEVICE_NAME: &str = "SteelSeries Mouse";
+
+// Name to show for a device: its nickname if one is set, otherwise the raw
+// rivalcfg name with whitespace normalised.
+fn device_display_name(raw_name: &str, settings: &Settings) -> String {
+    if let Some(nick) = settings.nicknames.as_ref().and_then(|n| n.get(raw_name)) {
+        let nick = nick.trim();
+        if !nick.is_empty() {
+            return nick.to_string();
+        }
+    }
+    let cleaned = raw_name.split_whitespace().collect::<Vec<_>>().join(" ");
+    if cleaned.is_empty() {
+        DEFAULT_DEVICE_NAME.to_string()
+    } else {
+        cleaned
+    }
+}
+
+// Set or clear (when blank) the nickname for a raw device name
+fn set_device_nickname(settings: &mut Settings, raw_name: &str, nickname: &str) {
+    let nicknames = settings.nicknames.get_or_insert_with(HashMap::new);
+    let nickname = nickname.trim();
+    if nickname.is_empty() {
+        nicknames.remove(raw_name);
+    } else {
+        nicknames.insert(raw_name.to_string(), nickname.to_string());
+    }
+    if nicknames.is_empty() {
+        settings.nicknames = None;
+    }
+}
+
+// confy application name; settings live in ~/.config/rivalcfg-tray/default-config.toml
+const CONFY_APP_NAME: &str = "rivalcfg-tray";
+
+// Settings file written before the move to confy, next to the confy file
+const LEGACY_SETTINGS_FILE: &str = "settings.json";
+mod actions;
+mod battery_estimate;
+mod battery_history;
+mod battery_poller;
+mod charge_debounce;
+mod cmd;
+mod dependencies;
+mod file_writer;
+mod icons;
+mod notify;
+// Parsers not yet used by the UI are still covered by the corpus tests and fuzz targets
+mod parse;
+mod service;
+mod startup;
+mod svgcolor;
+mod temp_registry;
+mod version;
+mod watchdog;
+use crate::version::crate_version;
+use crate::charge_debounce::ChargeDebouncer;
+use crate::actions::{
+    ActionListener, TrayAction, action_socket_path, all_actions, desktop_exec, forward_action, private_dir_in,
+    render_desktop_file, write_if_changed,
+};
+use crate::battery_estimate::BatterySamples;
+use crate::battery_poller::CachedBatteryPoller;
+use crate::battery_history::{append_history, history_path, history_row};
+use crate::dependencies::{AppCapabilities, distro_from_os_release, missing_tools_message};
+use crate::file_writer::{FailureTracker, FileWriter};
+use crate::notify::{ClickTarget, NotifyEvent, Notifier, format_charge_duration, notification_for, resolve_click};
+use crate::icons::{FALLBACK_PNG_SIZE, IconResolver, RealIconResolver, bundled_icon_path, resolve_or_default};
+use crate::watchdog::PollWatchdog;
+use crate::service::{remove_service_unit, render_service_unit, service_unit_path};
+use crate::startup::{GateAction, SessionBusChecker, StartupGate};
+use crate::svgcolor::recolor_svg_to_temp;
+use crate::temp_registry::{MAX_LIVE_TEMP_FILES, TempFileGuard, TempFileRegistry};
+use crate::cmd::{
+    ChargeStatus,
+    apply_color_only,
+    apply_settings,
+    apply_settings_with_runner,
+    CommandOutput,
+    CommandRunner,
+    DeviceInfo,
+    get_all_device_info_with_runner,
+    RealCommandRunner,
+    RivalError,
+    build_rivalcfg_args,
+    get_battery_level,
+    get_device_capabilities,
+    get_firmware_version_with_runner,
+    get_mouse_name_with_runner,
+    reset_device_with_runner,
+    set_sensitivity,
+};
+
+// The saved settings, read through confy. The raw values go through migrate, so
+// older schemas and single bad values don't cost the rest of the file.
+fn load_settings() -> Option<Settings> {
+    let path = confy::get_configuration_file_path(CONFY_APP_NAME, None)
+        .inspect_err(|e| eprintln!("[rivalcfg-tray] Warning: No settings location: {}", e))
+        .ok()?;
+    if !path.exists() {
+        import_legacy_settings(&path);
+    }
+    Some(load_settings_from(&path))
+}
+
+// Load settings from the confy (TOML) file at `path`. A missing file yields
+// defaults; an unreadable or malformed file is logged, backed up next to the
+// original as `<name>.bak` and also yields defaults so the tray can still start.
+fn load_settings_from(path: &std::path::Path) -> Settings {
+    if !path.exists() {
+        return Settings::default();
+    }
+    // a generic Value rather than Settings, so migrate sees the file as written
+    match confy::load_path::<serde_json::Value>(path) {
+        Ok(raw) => settings_from_raw(path, raw),
+        Err(e) => {
+            eprintln!(
+                "[rivalcfg-tray] Warning: Settings file {} could not be read ({}), using defaults",
+                path.display(),
+                e
+            );
+            back_up_settings(path);
+            Settings::default()
+        }
+    }
+}
+
+// Copy settings.json from before confy into the confy file at `path`, once. The
+// old file is left in place.
+fn import_legacy_settings(path: &std::path::Path) {
+    let legacy = path.with_file_name(LEGACY_SETTINGS_FILE);
+    if !legacy.exists() {
+        return;
+    }
+    let settings = load_legacy_settings_from(&legacy);
+    match save_settings_to(&settings, path) {
+        Ok(()) => eprintln!("[rivalcfg-tray] Moved settings from {} to {}", legacy.display(), path.display()),
+        Err(e) => eprintln!("[rivalcfg-tray] Warning: Failed to move settings to {}: {}", path.display(), e),
+    }
+}
+
+// load_settings_from for the JSON settings file used before confy
+fn load_legacy_settings_from(path: &std::path::Path) -> Settings {
+    let data = match fs::read_to_string(path) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("[rivalcfg-tray] Warning: Failed to read settings {}: {}", path.display(), e);
+            return Settings::default();
+        }
+    };
+    match serde_json::from_str::<serde_json::Value>(&data) {
+        Ok(raw) => settings_from_raw(path, raw),
+        Err(e) => {
+            eprintln!(
+                "[rivalcfg-tray] Warning: Settings file {} is not valid JSON ({}), using defaults",
+                path.display(),
+                e
+            );
+            back_up_settings(path);
+            Settings::default()
+        }
+    }
+}
+
+// Migrate the raw contents of the settings file at `path`, backing the file up when
+// some of its values had to be dropped
+fn settings_from_raw(path: &std::path::Path, raw: serde_json::Value) -> Settings {
+    let (settings, dropped) = migrate_checked(raw);
+    // the next save won't have the dropped values; keep the original around
+    if !dropped.is_empty() {
+        eprintln!(
+            "[rivalcfg-tray] Warning: Settings file {} has invalid values for {}",
+            path.display(),
+            dropped.join(", ")
+        );
+        back_up_settings(path);
+    }
+    settings
+}
+
+fn back_up_settings(path: &std::path::Path) {
+    let backup = settings_backup_path(path);
+    eprintln!("[rivalcfg-tray] Backing up {} to {}", path.display(), backup.display());
+    if let Err(e) = fs::copy(path, &backup) {
+        eprintln!("[rivalcfg-tray] Warning: Failed to back up settings to {}: {}", backup.display(), e);
+    }
+}
+
+fn settings_backup_path(path: &std::path::Path) -> PathBuf {
+    let mut name = path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+// Upgrade a raw settings JSON value from any older schema to the current `Settings` shape.
+// Unknown or unparseable values fall back to defaults rather than failing the whole load.
+fn migrate(raw: serde_json::Value) -> Settings {
+    migrate_checked(raw).0
+}
+
+// migrate, plus the keys whose values had to be dropped. Runs every migration step
+// after the file's version, then fills the remaining gaps with defaults (all Settings
+// fields are optional).
+fn migrate_checked(mut raw: serde_json::Value) -> (Settings, Vec<String>) {
+    use serde_json::Value;
+
+    // files without a "version" field predate versioning (v0)
+    let from_version = raw
+        .get("version")
+        .and_then(Value::as_u64)
+        .and_then(|v| u32::try_from(v).ok())
+        .unwrap_or(0);
+
+    if from_version < 1 && let Some(obj) = raw.as_object_mut() {
+        // v0 stored the icon colour as a boolean `colour_switch`
+        if let Some(switch) = obj.remove("colour_switch")
+            && switch.as_bool() == Some(true)
+            && !obj.contains_key("colour_mode")
+        {
+            obj.insert("colour_mode".to_string(), Value::String("custom".to_string()));
+        }
+    }
+    // v0 stored numeric fields as JSON numbers instead of strings, and hand-edited
+    // files of any version still do
+    if let Some(obj) = raw.as_object_mut() {
+        for key in ["sensitivity", "polling_rate", "sleep_timer", "dim_timer"] {
+            if let Some(Value::Number(n)) = obj.get(key) {
+                let as_str = n.to_string();
+                obj.insert(key.to_string(), Value::String(as_str));
+            }
+        }
+    }
+
+    // Stamp the current version before deserializing so a bogus value can't fail the load
+    if let Some(obj) = raw.as_object_mut() {
+        obj.insert("version".to_string(), Value::from(SETTINGS_VERSION));
+    }
+    let (mut s, dropped) = match serde_json::from_value::<Settings>(raw.clone()) {
+        Ok(s) => (s, Vec::new()),
+        Err(_) => settings_without_invalid_values(raw),
+    };
+    s.version = Some(SETTINGS_VERSION);
+    (strip_unsafe_spawn_values(normalize_settings(s)), dropped)
+}
+
+// Deserialize `raw` one key at a time and leave out every value that doesn't fit its
+// field, so one bad entry doesn't reset the whole file. Returns the dropped keys.
+fn settings_without_invalid_values(raw: serde_json::Value) -> (Settings, Vec<String>) {
+    use serde_json::Value;
+
+    let Value::Object(obj) = raw else {
+        eprintln!("[rivalcfg-tray] Warning: Settings are not a JSON object, using defaults");
+        return (Settings::default(), vec!["(whole file)".to_string()]);
+    };
+    let mut kept = serde_json::Map::new();
+    let mut dropped = Vec::new();
+    for (key, value) in obj {
+        let single = Value::Object(std::iter::once((key.clone(), value.clone())).collect());
+        match serde_json::from_value::<Settings>(single) {
+            Ok(_) => {
+                kept.insert(key, value);
+            }
+            Err(e) => {
+                eprintln!("[rivalcfg-tray] Warning: Ignoring invalid setting \"{}\": {}", key, e);
+                dropped.push(key);
+            }
+        }
+    }
+    dropped.sort();
+    (serde_json::from_value(Value::Object(kept)).unwrap_or_default(), dropped)
+}
+
+// Trimmed value, or None for empty/whitespace-only strings
+fn normalize_field(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+// Boundary clean-up for Settings built from files or widgets: trim every string
+// field and turn whitespace-only values into None, so later code (validators and
+// build_rivalcfg_args alike) only ever sees trimmed values.
+fn normalize_settings(s: Settings) -> Settings {
+    Settings {
+        sensitivity: normalize_field(s.sensitivity),
+        polling_rate: normalize_field(s.polling_rate),
+        sleep_timer: normalize_field(s.sleep_timer),
+        dim_timer: normalize_field(s.dim_timer),
+        colour_mode: normalize_field(s.colour_mode),
+        custom_color: normalize_field(s.custom_color),
+        buttons: normalize_field(s.buttons),
+        led_mode: normalize_field(s.led_mode),
+        led_color: normalize_field(s.led_color),
+        rivalcfg_path: normalize_field(s.rivalcfg_path),
+        extra_args: s.extra_args.filter(|args| !args.is_empty()),
+        nicknames: s.nicknames.map(|nicks| {
+            nicks
+                .into_iter()
+                .filter_map(|(raw, nick)| Some((raw, normalize_field(Some(nick))?)))
+                .collect()
+        }),
+        profiles: s.profiles.map(|profiles| {
+            profiles
+                .into_iter()
+                .map(|(name, p)| (name.trim().to_string(), normalize_settings(p)))
+                .filter(|(name, _)| !name.is_empty())
+                .collect()
+        }),
+        ..s
+    }
+}
+
+fn save_settings(s: &Settings) -> Result<(), anyhow::Error> {
+    let path = confy::get_configuration_file_path(CONFY_APP_NAME, None)?;
+    save_settings_to(s, &path)
+}
+
+// Write `s` to `path` with confy, atomically: confy stores to a temp file in the
+// same directory that is then renamed over `path`, so a crash or full disk
+// mid-write leaves the previous settings intact instead of a truncated file.
+fn save_settings_to(s: &Settings, path: &std::path::Path) -> Result<(), anyhow::Error> {
+    let dir = path.parent().unwrap_or_else(|| std::path::Path::new("."));
+    std::fs::create_dir_all(dir)?;
+    let mut versioned = s.clone();
+    versioned.version = Some(SETTINGS_VERSION);
+    let tmp = tempfile::NamedTempFile::new_in(dir)?;
+    confy::store_path(tmp.path(), versioned)?;
+    tmp.persist(path)?;
+    Ok(())
+}
+
+// Write `s` to `path` as pretty-printed JSON, in the settings file format
+fn export_settings_to_path(s: &Settings, path: &std::path::Path) -> Result<(), anyhow::Error> {
+    let mut versioned = s.clone();
+    versioned.version = Some(SETTINGS_VERSION);
+    fs::write(path, serde_json::to_string_pretty(&versioned)?)?;
+    Ok(())
+}
+
+// Read settings exported by export_settings_to_path (or an old settings.json).
+// Older formats are migrated; invalid files and invalid values are rejected.
+fn import_settings_from_path(path: &std::path::Path) -> Result<Settings, anyhow::Error> {
+    let raw: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let settings = normalize_settings(migrate(raw));
+    let errors = validate_settings(&settings);
+    if !errors.is_empty() {
+        let messages: Vec<String> = errors.into_iter().map(|e| e.message).collect();
+        anyhow::bail!("{}", messages.join("; "));
+    }
+    Ok(settings)
+}
+
+// Device options captured by a profile. Icon, nickname and notification preferences
+// stay global and are not switched with profiles.
+fn profile_from_settings(s: &Settings) -> Settings {
+    Settings {
+        sensitivity: s.sensitivity.clone(),
+        dual_sensitivity: s.dual_sensitivity,
+        polling_rate: s.polling_rate.clone(),
+        sleep_timer: s.sleep_timer.clone(),
+        dim_timer: s.dim_timer.clone(),
+        buttons: s.buttons.clone(),
+        led_mode: s.led_mode.clone(),
+        led_color: s.led_color.clone(),
+        extra_args: s.extra_args.clone(),
+        ..Default::default()
+    }
+}
+
+// `active` with the device options replaced by those of `profile`
+fn with_profile_applied(active: &Settings, profile: &Settings) -> Settings {
+    Settings {
+        sensitivity: profile.sensitivity.clone(),
+        dual_sensitivity: profile.dual_sensitivity,
+        polling_rate: profile.polling_rate.clone(),
+        sleep_timer: profile.sleep_timer.clone(),
+        dim_timer: profile.dim_timer.clone(),
+        buttons: profile.buttons.clone(),
+        led_mode: profile.led_mode.clone(),
+        led_color: profile.led_color.clone(),
+        extra_args: profile.extra_args.clone(),
+        ..active.clone()
+    }
+}
+
+fn store_profile(store: &mut Settings, name: &str, profile: &Settings) -> Result<(), String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Profile name must not be empty".to_string());
+    }
+    store
+        .profiles
+        .get_or_insert_with(HashMap::new)
+        .insert(name.to_string(), profile_from_settings(profile));
+    Ok(())
+}
+
+// Profile names in a stable (sorted) order for menus
+fn profile_names(store: &Settings) -> Vec<String> {
+    let mut names: Vec<String> = store.profiles.iter().flat_map(|p| p.keys().cloned()).collect();
+    names.sort();
+    names
+}
+
+fn save_profile(name: &str, profile: &Settings) -> Result<(), anyhow::Error> {
+    let mut store = load_settings().unwrap_or_default();
+    store_profile(&mut store, name, profile).map_err(|e| anyhow::anyhow!(e))?;
+    save_settings(&store)
+}
+
+fn load_profile(name: &str) -> Option<Settings> {
+    load_settings()?.profiles?.remove(name)
+}
+
+fn list_profiles() -> Vec<String> {
+    load_settings().map(|s| profile_names(&s)).unwrap_or_default()
+}
+
+// Validation helpers used by the config dialog and tests
+const MAX_SENSITIVITY_STAGES: usize = 5;
+
+// Length caps for user-controlled strings that reach a spawned command's argv
+const MAX_SPAWN_ARG_LEN: usize = 256;
+const MAX_BUTTONS_LEN: usize = 1024;
+const MAX_PROGRAM_PATH_LEN: usize = 4096;
+const MAX_NICKNAME_LEN: usize = 64;
+
+// Shared check for every user-controlled string that ends up in a spawned process:
+// argv can't carry NUL bytes, and other control characters (line breaks, escapes)
+// make spawn errors and logs misleading, so both are rejected, as is overlong input.
+fn validate_spawn_arg(s: &str, name: &str, max_len: usize) -> Result<(), String> {
+    if s.contains('\0') {
+        return Err(format!("{} must not contain NUL bytes", name));
+    }
+    if let Some(c) = s.chars().find(|c| c.is_control()) {
+        return Err(format!(
+            "{} must not contain control characters such as line breaks (found '{}')",
+            name,
+            c.escape_default()
+        ));
+    }
+    let len = s.chars().count();
+    if len > max_len {
+        return Err(format!("{} is too long ({} characters, at most {})", name, len, max_len));
+    }
+    Ok(())
+}
+
+// What spawn sites debug-assert about every argument they pass on
+fn is_spawn_safe(s: &str) -> bool {
+    !s.chars().any(char::is_control)
+}
+
+// Show non-printable characters as escapes (e.g. "\n") in logs and notifications
+fn escape_for_display(s: &str) -> String {
+    s.chars()
+        .map(|c| if c.is_control() { c.escape_default().to_string() } else { c.to_string() })
+        .collect()
+}
+
+// Settings fields that reach spawned commands, with their labels and length caps
+fn spawn_fields(s: &mut Settings) -> [(&'static str, usize, &mut Option<String>); 8] {
+    [
+        ("Sensitivity", MAX_SPAWN_ARG_LEN, &mut s.sensitivity),
+        ("Polling rate", MAX_SPAWN_ARG_LEN, &mut s.polling_rate),
+        ("Sleep Timer", MAX_SPAWN_ARG_LEN, &mut s.sleep_timer),
+        ("Dim Timer", MAX_SPAWN_ARG_LEN, &mut s.dim_timer),
+        ("Button mapping", MAX_BUTTONS_LEN, &mut s.buttons),
+        ("LED colour", MAX_SPAWN_ARG_LEN, &mut s.led_color),
+        ("Icon colour", MAX_SPAWN_ARG_LEN, &mut s.custom_color),
+        ("rivalcfg path", MAX_PROGRAM_PATH_LEN, &mut s.rivalcfg_path),
+    ]
+}
+
+// Most extra arguments accepted in the "Advanced arguments" entry
+const MAX_EXTRA_ARGS: usize = 32;
+
+// Extra rivalcfg arguments are passed on verbatim, so each one must be non-empty
+// and pass the spawn checks.
+fn validate_extra_args(args: &[String]) -> Result<(), String> {
+    if args.len() > MAX_EXTRA_ARGS {
+        return Err(format!("Advanced arguments accept at most {} arguments", MAX_EXTRA_ARGS));
+    }
+    for arg in args {
+        if arg.trim().is_empty() {
+            return Err("Advanced arguments must not contain empty arguments".to_string());
+        }
+        validate_spawn_arg(arg, "Advanced argument", MAX_SPAWN_ARG_LEN)?;
+    }
+    Ok(())
+}
+
+// Program spawned for rivalcfg commands: the configured `rivalcfg_path`, or
+// "rivalcfg" looked up on PATH
+fn rivalcfg_program(settings: &Settings) -> String {
+    settings.rivalcfg_path.clone().unwrap_or_else(|| "rivalcfg".to_string())
+}
+
+// Startup check for a configured rivalcfg_path: it must be an executable file
+fn check_rivalcfg_path(path: &std::path::Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let meta = fs::metadata(path).map_err(|e| format!("{} is not usable: {}", path.display(), e))?;
+    if !meta.is_file() {
+        return Err(format!("{} is not a file", path.display()));
+    }
+    if meta.permissions().mode() & 0o111 == 0 {
+        return Err(format!("{} is not executable", path.display()));
+    }
+    Ok(())
+}
+
+// Drop values loaded from disk that fail validate_spawn_arg (e.g. a hand-edited
+// settings file), so nothing unsanitized is ever passed to a spawn.
+fn strip_unsafe_spawn_values(mut s: Settings) -> Settings {
+    for (name, max_len, value) in spawn_fields(&mut s) {
+        if let Some(v) = value.as_deref()
+            && let Err(msg) = validate_spawn_arg(v, name, max_len)
+        {
+            eprintln!("[rivalcfg-tray] Warning: Ignoring saved value: {}", msg);
+            *value = None;
+        }
+    }
+    if let Some(args) = s.extra_args.as_deref()
+        && let Err(msg) = validate_extra_args(args)
+    {
+        eprintln!("[rivalcfg-tray] Warning: Ignoring saved extra arguments: {}", msg);
+        s.extra_args = None;
+    }
+    if let Some(nicks) = s.nicknames.as_mut() {
+        nicks.retain(|raw, nick| match validate_spawn_arg(nick, "Nickname", MAX_NICKNAME_LEN) {
+            Ok(()) => true,
+            Err(msg) => {
+                eprintln!("[rivalcfg-tray] Warning: Ignoring nickname for {}: {}", escape_for_display(raw), msg);
+                false
+            }
+        });
+    }
+    if let Some(profiles) = s.profiles.take() {
+        s.profiles = Some(
+            profiles
+                .into_iter()
+                .map(|(name, p)| (name, strip_unsafe_spawn_values(p)))
+                .collect(),
+        );
+    }
+    s
+}
+
+// Accepts a single DPI value or a comma-separated list of DPI stages (e.g. "400,800,1600")
+fn validate_sensitivity(s: &str) -> Result<(), String> {
+    // Whitespace is dropped by normalize_settings, so judge the trimmed value
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(());
+    }
+    validate_spawn_arg(s, "Sensitivity", MAX_SPAWN_ARG_LEN)?;
+    let entries: Vec<&str> = s.split(',').map(|e| e.trim()).collect();
+    if entries.len() > MAX_SENSITIVITY_STAGES {
+        return Err(format!("Sensitivity accepts at most {} DPI values", MAX_SENSITIVITY_STAGES));
+    }
+    let mut seen: Vec<u32> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        match entry.parse::<u32>() {
+            Ok(v) if (100..=16000).contains(&v) => {
+                if seen.contains(&v) {
+                    return Err(format!("Sensitivity value {} is listed more than once", v));
+                }
+                seen.push(v);
+            }
+            _ => return Err("Sensitivity must be a number between 100 and 16000".to_string()),
+        }
+    }
+    Ok(())
+}
+
+// Both axes of a per-axis sensitivity, each in the same range as a single DPI value
+fn validate_dual_sensitivity((x, y): (u32, u32)) -> Result<(), String> {
+    for (axis, value) in [("X", x), ("Y", y)] {
+        if !(100..=16000).contains(&value) {
+            return Err(format!("{} DPI must be between 100 and 16000", axis));
+        }
+    }
+    Ok(())
+}
+
+// First DPI value of a saved sensitivity, which may list several stages
+fn first_sensitivity_stage(s: &str) -> Option<u32> {
+    s.split(',').next()?.trim().parse().ok()
+}
+
+// Sensitivity to save from the config window's spin button. A multi-stage list from
+// a profile or the settings file is kept while its first stage is left unchanged, since
+// the spin button only shows one value. None while nothing is saved and the spin button
+// was never edited, so the device keeps its own DPI.
+fn sensitivity_from_spin(dpi: i32, edited: bool, saved: Option<&str>) -> Option<String> {
+    match saved {
+        Some(saved) if saved.contains(',') && first_sensitivity_stage(saved) == Some(dpi as u32) => {
+            Some(saved.to_string())
+        }
+        None if !edited => None,
+        _ => Some(dpi.to_string()),
+    }
+}
+
+fn validate_polling_rate(s: &str) -> Result<(), String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(());
+    }
+    validate_spawn_arg(s, "Polling rate", MAX_SPAWN_ARG_LEN)?;
+    match s {
+        "125" | "250" | "500" | "1000" => Ok(()),
+        _ => Err("Polling rate must be one of: 125, 250, 500, 1000".to_string()),
+    }
+}
+
+// Ranges rivalcfg accepts for the timers (0 disables); larger values fail with an
+// unhelpful error from the device layer
+const SLEEP_TIMER_MAX_MINUTES: u32 = 1200;
+// Dim timer limit when the device's own isn't known (the Rival 650's)
+const DEFAULT_MAX_DIM_TIMER_SECS: u32 = 60;
+
+fn validate_timer(s: &str, name: &str, unit: &str, min: u32, max: u32) -> Result<(), String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(());
+    }
+    validate_spawn_arg(s, name, MAX_SPAWN_ARG_LEN)?;
+    match s.parse::<u32>() {
+        Ok(v) if (min..=max).contains(&v) => Ok(()),
+        Ok(_) => Err(format!("{} must be between {} and {} {}", name, min, max, unit)),
+        Err(_) => Err(format!("{} must be an integer value ({})", name, unit)),
+    }
+}
+
+fn validate_dim_timer(s: &str, max: u32) -> Result<(), String> {
+    validate_timer(s, "Dim Timer", "seconds", 0, max)
+}
+
+// The connected device's dim timer limit from the cached --help probe
+fn max_dim_timer() -> u32 {
+    DEVICE_CAPABILITIES
+        .lock()
+        .ok()
+        .and_then(|c| c.as_ref().and_then(|c| c.max_dim_timer))
+        .unwrap_or(DEFAULT_MAX_DIM_TIMER_SECS)
+}
+
+// Whether the cached --help probe found a device with per-axis sensitivity
+fn device_supports_dual_sensitivity() -> bool {
+    DEVICE_CAPABILITIES
+        .lock()
+        .ok()
+        .and_then(|c| c.as_ref().map(HelpCapabilities::supports_dual_sensitivity))
+        .unwrap_or(false)
+}
+
+// Only checks that (), [] and {} are balanced and properly nested; rivalcfg
+// reports anything more specific when the mapping is applied.
+fn validate_buttons(s: &str) -> Result<(), String> {
+    validate_spawn_arg(s, "Button mapping", MAX_BUTTONS_LEN)?;
+    let mut stack = Vec::new();
+    for c in s.chars() {
+        match c {
+            '(' | '[' | '{' => stack.push(c),
+            ')' | ']' | '}' => {
+                let expected = match c {
+                    ')' => '(',
+                    ']' => '[',
+                    _ => '{',
+                };
+                if stack.pop() != Some(expected) {
+                    return Err(format!("Button mapping has an unexpected '{}'", c));
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(open) = stack.pop() {
+        return Err(format!("Button mapping has an unclosed '{}'", open));
+    }
+    Ok(())
+}
+
+// Strict form for colours passed to `rivalcfg --color`: "#RGB" or "#RRGGBB"
+fn validate_hex_color(s: &str) -> Result<(), String> {
+    validate_spawn_arg(s, "Colour", MAX_SPAWN_ARG_LEN)?;
+    match s.strip_prefix('#') {
+        Some(h) if (h.len() == 3 || h.len() == 6) && h.chars().all(|c| c.is_ascii_hexdigit()) => Ok(()),
+        _ => Err(format!("'{}' is not a valid hex colour (expected #RRGGBB or #RGB)", escape_for_display(s))),
+    }
+}
+
+// One problem found by validate_settings
+#[derive(Debug, Clone, PartialEq)]
+struct SettingsValidationError {
+    field: &'static str,
+    message: String,
+}
+
+// Run every field validator over `s` and collect all problems, in form order,
+// instead of stopping at the first one.
+fn validate_settings(s: &Settings) -> Vec<SettingsValidationError> {
+    let checks: [(&'static str, Option<Result<(), String>>); 8] = [
+        ("sensitivity", s.sensitivity.as_deref().map(validate_sensitivity)),
+        ("dual_sensitivity", s.dual_sensitivity.map(validate_dual_sensitivity)),
+        ("polling_rate", s.polling_rate.as_deref().map(validate_polling_rate)),
+        (
+            "sleep_timer",
+            s.sleep_timer.as_deref().map(|t| validate_timer(t, "Sleep Timer", "minutes", 0, SLEEP_TIMER_MAX_MINUTES)),
+        ),
+        (
+            "dim_timer",
+            s.dim_timer.as_deref().map(|t| validate_dim_timer(t, max_dim_timer())),
+        ),
+        ("buttons", s.buttons.as_deref().map(validate_buttons)),
+        ("custom_color", s.custom_color.as_deref().map(validate_hex_color)),
+        // the LED colour is only sent to the mouse in custom mode, and only in the
+        // strict form build_rivalcfg_args accepts
+        (
+            "led_color",
+            s.led_color
+                .as_deref()
+                .filter(|_| s.led_mode.as_deref() == Some(LED_MODE_CUSTOM))
+                .map(validate_hex_color),
+        ),
+    ];
+    let mut errors: Vec<SettingsValidationError> = checks
+        .into_iter()
+        .filter_map(|(field, result)| match result {
+            Some(Err(message)) => Some(SettingsValidationError { field, message }),
+            _ => None,
+        })
+        .collect();
+    if let Some(Err(message)) = s.extra_args.as_deref().map(validate_extra_args) {
+        errors.push(SettingsValidationError { field: "extra_args", message });
+    }
+    if let Some(Err(message)) = s
+        .rivalcfg_path
+        .as_deref()
+        .map(|p| validate_spawn_arg(p, "rivalcfg path", MAX_PROGRAM_PATH_LEN))
+    {
+        errors.push(SettingsValidationError { field: "rivalcfg_path", message });
+    }
+    if let Some(Err(message)) = s.poll_interval.map(validate_poll_interval) {
+        errors.push(SettingsValidationError { field: "poll_interval", message });
+    }
+    if let Some(Err(message)) = s
+        .devices
+        .iter()
+        .flat_map(|d| d.values())
+        .filter_map(|d| d.poll_interval)
+        .map(validate_poll_interval)
+        .find(Result::is_err)
+    {
+        errors.push(SettingsValidationError { field: "devices", message });
+    }
+    if let Some(Err(message)) = s.poll_bands.as_ref().map(validate_poll_bands) {
+        errors.push(SettingsValidationError { field: "poll_bands", message });
+    }
+    if (s.warning_level.is_some() || s.critical_level.is_some())
+        && let Err(message) = validate_icon_thresholds(IconThresholds {
+            warning: s.warning_level.unwrap_or(DEFAULT_WARNING_LEVEL),
+            critical: s.critical_level.unwrap_or(DEFAULT_CRITICAL_LEVEL),
+        })
+    {
+        errors.push(SettingsValidationError { field: "warning_level", message });
+    }
+    // nicknames end up in notification text
+    let mut nicknames: Vec<&String> = s.nicknames.iter().flat_map(|n| n.values()).collect();
+    nicknames.sort();
+    for nick in nicknames {
+        if let Err(message) = validate_spawn_arg(nick, "Nickname", MAX_NICKNAME_LEN) {
+            errors.push(SettingsValidationError { field: "nicknames", message });
+        }
+    }
+    errors
+}
+
+// Out-of-range intervals in a hand-edited file are clamped when used; the form reports them
+fn validate_poll_interval(secs: u32) -> Result<(), String> {
+    if (MIN_POLL_INTERVAL_SECS..=MAX_POLL_INTERVAL_SECS).contains(&secs) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Poll interval must be between {} and {} seconds",
+            MIN_POLL_INTERVAL_SECS, MAX_POLL_INTERVAL_SECS
+        ))
+    }
+}
+
+// Band intervals share the poll interval range; the low band must sit below the high one
+fn validate_poll_bands(b: &PollBands) -> Result<(), String> {
+    for secs in [b.high_secs, b.mid_secs, b.low_secs].into_iter().flatten() {
+        validate_poll_interval(secs)?;
+    }
+    let high = b.high_level.unwrap_or(DEFAULT_POLL_HIGH_LEVEL);
+    let low = b.low_level.unwrap_or(DEFAULT_POLL_LOW_LEVEL);
+    if high > 100 || low > high {
+        return Err("Poll band levels must be percentages with the low level below the high one".to_string());
+    }
+    Ok(())
+}
+
+// Icon thresholds must be percentages with the warning level above the critical one
+fn validate_icon_thresholds(t: IconThresholds) -> Result<(), String> {
+    for (value, name) in [(t.warning, "Warning level"), (t.critical, "Critical level")] {
+        if !(1..=99).contains(&value) {
+            return Err(format!("{} must be between 1 and 99", name));
+        }
+    }
+    if t.warning <= t.critical {
+        return Err("Warning level must be higher than the critical level".to_string());
+    }
+    Ok(())
+}
+
+// Helpers to convert between hex color strings and gdk::RGBA
+fn rgba_from_hex(hex: &str) -> Option<gtk::gdk::RGBA> {
+    let h = hex.trim().trim_start_matches('#');
+    // Byte slicing below is only safe on ASCII input
+    if !h.is_ascii() {
+        return None;
+    }
+    if h.len() == 6 {
+        let r = u8::from_str_radix(&h[0..2], 16).ok()?;
+        let g = u8::from_str_radix(&h[2..4], 16).ok()?;
+        let b = u8::from_str_radix(&h[4..6], 16).ok()?;
+        return Some(gtk::gdk::RGBA::new(
+            r as f64 / 255.0,
+            g as f64 / 255.0,
+            b as f64 / 255.0,
+            1.0,
+        ));
+    }
+    if h.len() == 3 {
+        let rs = &h[0..1];
+        let gs = &h[1..2];
+        let bs = &h[2..3];
+        let r = u8::from_str_radix(&format!("{}{}", rs, rs), 16).ok()?;
+        let g = u8::from_str_radix(&format!("{}{}", gs, gs), 16).ok()?;
+        let b = u8::from_str_radix(&format!("{}{}", bs, bs), 16).ok()?;
+        return Some(gtk::gdk::RGBA::new(
+            r as f64 / 255.0,
+            g as f64 / 255.0,
+            b as f64 / 255.0,
+            1.0,
+        ));
+    }
+    None
+}
+
+fn hex_from_rgba(rgba: &gtk::gdk::RGBA) -> String {
+    let r = (rgba.red() * 255.0).round().clamp(0.0, 255.0) as u8;
+    let g = (rgba.green() * 255.0).round().clamp(0.0, 255.0) as u8;
+    let b = (rgba.blue() * 255.0).round().clamp(0.0, 255.0) as u8;
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+// Hand a temp file over to the TEMP_FILES registry, which deletes it on eviction or cleanup
+fn keep_temp_file(file: tempfile::NamedTempFile) -> Option<PathBuf> {
+    let current = CURRENT_ICON.lock().ok().and_then(|c| c.clone());
+    let mut files = TEMP_FILES.lock().ok()?;
+    Some(files.insert(TempFileGuard::new(file), current.as_deref().map(std::path::Path::new)))
+}
+
+// Delete the registered temp files, except `keep` (the PNG on display), which stays registered
+fn remove_registered_temp_files(keep: Option<&str>) {
+    if let Ok(mut files) = TEMP_FILES.lock() {
+        files.retain_only(keep.map(std::path::Path::new));
+    }
+}
+
+// Remove rivalcfg-tray-*.png files left in `dirs` by earlier runs (e.g. after a crash)
+fn remove_stale_temp_pngs(dirs: &[PathBuf]) {
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for path in entries.flatten().map(|e| e.path()) {
+            if let Some(fname) = path.file_name().and_then(|f| f.to_str())
+                && fname.starts_with("rivalcfg-tray-")
+                && fname.ends_with(".png")
+            {
+                match std::fs::remove_file(&path) {
+                    Ok(()) => eprintln!("[rivalcfg-tray] Removed stale temp PNG: {}", path.display()),
+                    Err(e) => eprintln!("[rivalcfg-tray] Warning: Failed to remove stale temp PNG {}: {}", path.display(), e),
+                }
+            }
+        }
+    }
+}
+
+// Final cleanup when quitting: nothing is on display any more
+fn cleanup_on_exit() {
+    if let Ok(mut current) = CURRENT_ICON.lock() {
+        *current = None;
+    }
+    cleanup_temp_files();
+}
+
+// Delete the rendered PNGs through the registry, which owns all of them, except the
+// one on display
+fn cleanup_temp_files() {
+    let current = CURRENT_ICON.lock().ok().and_then(|c| c.clone());
+    remove_registered_temp_files(current.as_deref());
+    if let Ok(mut cache) = PNG_CACHE.lock() {
+        forget_missing_pngs(&mut cache);
+    }
+}
+
+// Drop the cache entries whose PNG no longer exists on disk
+fn forget_missing_pngs(cache: &mut HashMap<String, (String, SystemTime)>) {
+    cache.retain(|_, (png_path, _)| std::path::Path::new(png_path).exists());
+}
+
+// True when the PNG last handed to the tray no longer exists on disk
+fn current_icon_missing() -> bool {
+    CURRENT_ICON
+        .lock()
+        .ok()
+        .and_then(|c| c.clone())
+        .is_some_and(|png| !std::path::Path::new(&png).exists())
+}
+
+fn generate_tray_icon(tray_icon: &TrayIcon) -> BatteryState {
+    generate_tray_icon_with_resolver(tray_icon, &RealIconResolver::default())
+}
+
+fn generate_tray_icon_with_resolver(tray_icon: &TrayIcon, resolver: &dyn IconResolver) -> BatteryState {
+    let settings = load_settings().unwrap_or_default();
+    apply_battery_reading(tray_icon, battery_reading(|| BATTERY_POLLER.poll()), resolver, &settings)
+}
+
+// Read the battery on a worker thread, retrying failed reads, then update the icon
+// on the main loop and hand the resulting state, with the settings it was shown with,
+// to `then`
+fn generate_tray_icon_in_background(tray_icon: TrayIcon, then: impl FnOnce(BatteryState, &Settings) + 'static) {
+    use std::sync::mpsc::{self, TryRecvError};
+
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(battery_reading(|| BATTERY_POLLER.poll_retrying()));
+    });
+    let mut then = Some(then);
+    glib::timeout_add_local(Duration::from_millis(100), move || match rx.try_recv() {
+        Ok(reading) => {
+            let settings = load_settings().unwrap_or_default();
+            let state = apply_battery_reading(&tray_icon, reading, &RealIconResolver::default(), &settings);
+            if let Some(then) = then.take() {
+                then(state, &settings);
+            }
+            ControlFlow::Break
+        }
+        Err(TryRecvError::Empty) => ControlFlow::Continue,
+        Err(TryRecvError::Disconnected) => ControlFlow::Break,
+    });
+}
+
+// The raw state for the next icon update; `read` asks the mouse
+fn battery_reading(read: impl FnOnce() -> Result<(u8, ChargeStatus), RivalError>) -> BatteryState {
+    // a wired mouse has nothing to poll
+    if NO_BATTERY.load(Ordering::SeqCst) {
+        BatteryState::NoBattery
+    } else if !rivalcfg_available() {
+        BatteryState::Disconnected
+    } else {
+        BatteryState::from_result(read())
+    }
+}
+
+// Debounce a fresh reading against the ones before it and show the result
+fn apply_battery_reading(
+    tray_icon: &TrayIcon,
+    reading: BatteryState,
+    resolver: &dyn IconResolver,
+    settings: &Settings,
+) -> BatteryState {
+    let limit = settings.disconnect_after.unwrap_or(DEFAULT_DISCONNECT_AFTER);
+    let last = LAST_BATTERY_STATE.lock().ok().and_then(|s| *s);
+    let (state, reconnected) = match POLL_FAILURES.lock() {
+        Ok(mut failures) => debounce_disconnect(&mut failures, reading, last, limit),
+        Err(_) => (reading, false),
+    };
+    if reconnected {
+        eprintln!("[rivalcfg-tray] Device is back, refreshing the icon");
+        // Force the first reading after a disconnect to redraw everything
+        force_icon_refresh();
+    } else if state != reading {
+        eprintln!("[rivalcfg-tray] Battery read failed, keeping the last state for now");
+    }
+    let confirm_after = settings.charge_debounce_polls.unwrap_or(DEFAULT_CHARGE_DEBOUNCE_POLLS);
+    let low_threshold = effective_device_settings(settings, &current_mouse_name()).low_battery_threshold;
+    let state = match CHARGE_DEBOUNCE.lock() {
+        Ok(mut debouncer) => debounce_charge_state(&mut debouncer, state, confirm_after, low_threshold),
+        Err(_) => state,
+    };
+    update_tray_icon_with_resolver(tray_icon, state, resolver, settings)
+}
+
+// Render `state` into the tray icon and tooltip, skipping the icon work if it is unchanged
+fn update_tray_icon(tray_icon: &TrayIcon, state: BatteryState) -> BatteryState {
+    let settings = load_settings().unwrap_or_default();
+    update_tray_icon_with_resolver(tray_icon, state, &RealIconResolver::default(), &settings)
+}
+
+// update_tray_icon with the settings loaded once by the caller, for the whole update
+fn update_tray_icon_with_resolver(
+    tray_icon: &TrayIcon,
+    state: BatteryState,
+    resolver: &dyn IconResolver,
+    settings: &Settings,
+) -> BatteryState {
+    // Keep the tooltip current even when the icon itself doesn't change (e.g. after a rename)
+    let raw_name = current_mouse_name();
+    let display_name = device_display_name(&raw_name, settings);
+    let _ = tray_icon.set_tooltip(Some(tray_tooltip_text(&display_name, &state)));
+    note_battery_support(&state);
+    tray_icon.set_title(tray_label_text(&state, settings.show_label.unwrap_or(false)));
+    rebuild_menu_if_changed(tray_icon, &state, settings);
+    update_info_items(&display_name, &state);
+
+    if let Ok(mut samples) = BATTERY_SAMPLES.lock() {
+        set_remaining_item(remaining_menu_text(record_battery_sample(&mut samples, &state, Instant::now())));
+    }
+
+    let device = effective_device_settings(settings, &raw_name);
+    if device.notifications
+        && let Ok(mut notified) = LOW_BATTERY_NOTIFIED.lock()
+    {
+        maybe_notify_low_battery(
+            &tray_notifier(),
+            &mut notified,
+            &display_name,
+            &state,
+            device.low_battery_threshold,
+        );
+    }
+    let charge_notify = device.notifications && settings.charge_complete_notifications.unwrap_or(true);
+    if let Ok(mut tracker) = CHARGE_COMPLETE.lock() {
+        if charge_notify {
+            maybe_notify_charge_complete(&tray_notifier(), &mut tracker, &display_name, &state, Instant::now());
+        } else {
+            // keep following sessions so re-enabling doesn't fire for an old one
+            charge_complete_notification(&mut tracker, &state, Instant::now());
+        }
+    }
+
+    // Track the attention state on every reading so the hysteresis sees the full history
+    let threshold = device.attention_threshold;
+    let (was_attention, attention) = match ATTENTION_ACTIVE.lock() {
+        Ok(mut active) => {
+            let was = *active;
+            *active = next_attention_state(*active, &state, threshold);
+            (was, *active)
+        }
+        Err(_) => (false, false),
+    };
+
+    // A new icon size needs a fresh render even if the battery state is unchanged
+    let icon_size = effective_icon_size(settings.icon_size);
+    let rendered_size = RENDERED_ICON_SIZE.swap(icon_size, Ordering::SeqCst);
+    if rendered_size != 0 && rendered_size != icon_size {
+        eprintln!("[rivalcfg-tray] Icon size changed from {} to {}, redrawing", rendered_size, icon_size);
+        invalidate_png_cache();
+        force_icon_refresh();
+    }
+    // Pick the icon bucket, with hysteresis against the one shown last
+    let hysteresis = settings.battery_hysteresis.unwrap_or(DEFAULT_BATTERY_HYSTERESIS);
+    let thresholds = icon_thresholds(settings);
+    let (prev_bucket, bucket) = match LAST_BATTERY_BUCKET.lock() {
+        Ok(mut last) => {
+            let prev = *last;
+            *last = match state {
+                BatteryState::Connected { level, .. } => Some(select_bucket(prev, level, hysteresis, thresholds)),
+                BatteryState::Disconnected | BatteryState::NoBattery => None,
+            };
+            (prev, *last)
+        }
+        Err(_) => (None, None),
+    };
+    let displayed = displayed_battery_state(state, bucket, thresholds);
+
+    // Check if battery state has changed
+    if let Ok(mut last_state) = LAST_BATTERY_STATE.lock() {
+        let previous = last_state.map(|s| displayed_battery_state(s, prev_bucket, thresholds));
+        if !record_battery_state(&mut last_state, state) {
+            eprintln!("[rivalcfg-tray] Battery state unchanged ({:?}), skipping icon update", state);
+            return state;
+        }
+        if previous == Some(displayed) && was_attention == attention {
+            save_battery_state(&state, &raw_name, settings);
+            eprintln!("[rivalcfg-tray] Battery icon unchanged ({:?}), skipping icon update", state);
+            return state;
+        }
+    }
+    save_battery_state(&state, &raw_name, settings);
+
+    let theme = icon_theme(settings, std::env::var("XDG_CURRENT_DESKTOP").ok().as_deref());
+    let monochrome = theme != IconTheme::Color;
+    
+    let icon_path = select_icon_path(resolver, &displayed, monochrome, thresholds);
+    // the attention colour wins over the one from settings
+    let recolor = icon_color_override(theme, attention, settings).or_else(|| icon_recolor_color(settings));
+    // Retry up to 5 times with exponential backoff if conversion fails, unless
+    // rsvg-convert is missing altogether
+    let mut tries = 0;
+    let png_path = loop {
+        if CONVERTER_MISSING.load(Ordering::SeqCst) {
+            break None;
+        }
+        match svg_to_png_temp(&icon_path, icon_size, recolor.as_deref()) {
+            Ok(p) => break Some(p),
+            Err(SvgConvertError::ConverterMissing) => {
+                CONVERTER_MISSING.store(true, Ordering::SeqCst);
+                eprintln!(
+                    "[rivalcfg-tray] rsvg-convert is not available, using the bundled {}px PNG icons from now on",
+                    FALLBACK_PNG_SIZE
+                );
+                break None;
+            }
+            Err(SvgConvertError::Failed) => {}
+        }
+
+        // No PNG produced this iteration
+        if tries < 5 {
+            tries += 1;
+            let delay_ms = 100_u64 << tries; // Exponential backoff: 200ms, 400ms, 800ms, 1600ms, 3200ms
+            eprintln!("[rivalcfg-tray] SVG conversion failed (attempt {}), retrying in {}ms", tries, delay_ms);
+            std::thread::sleep(std::time::Duration::from_millis(delay_ms));
+            continue;
+        } else {
+            eprintln!("[rivalcfg-tray] Failed to convert SVG after {} attempts, giving up", tries + 1);
+            break None;
+        }
+    };
+    // Without a converted PNG, show the pre-rendered one tinted to the icon colour
+    let (png_path, tint) = match png_path {
+        Some(p) => (Some(p), None),
+        None => {
+            let tint = recolor.as_deref().and_then(rgb_from_hex);
+            let fallback = fallback_png_path(&displayed, thresholds, &icon_runtime_dir().join("bundled-icons"));
+            (fallback.and_then(|p| p.to_str().map(str::to_string)), tint)
+        }
+    };
+    if let Some(png_path) = png_path {
+        std::io::stderr().flush().ok();
+        
+        // Load the PNG file as a TrayIconImage
+        if let Ok(icon_data) = std::fs::read(&png_path) {
+            // Load PNG and convert to RGBA for tray-icon
+            if let Ok(img) = image::load_from_memory(&icon_data) {
+                let mut rgba = img.to_rgba8();
+                if let Some(rgb) = tint {
+                    tint_icon_pixels(&mut rgba, rgb);
+                }
+                mirror_icon_for_direction(&mut rgba, ICONS_RTL.load(Ordering::SeqCst));
+                let (width, height) = rgba.dimensions();
+                if let Ok(icon_image) = TrayIconImage::from_rgba(rgba.into_raw(), width, height) {
+                    if let Err(e) = tray_icon.set_icon(Some(icon_image)) {
+                        eprintln!("[rivalcfg-tray] Failed to set tray icon: {}", e);
+                    } else {
+                        eprintln!("[rivalcfg-tray] Set tray icon from: {}", png_path);
+                        if let Ok(mut current) = CURRENT_ICON.lock() {
+                            *current = Some(png_path.clone());
+                        }
+                    }
+                } else {
+                    eprintln!("[rivalcfg-tray] Warning: Failed to create tray icon from PNG: {}", png_path);
+                }
+            } else {
+                eprintln!("[rivalcfg-tray] Warning: Failed to load PNG as image: {}", png_path);
+            }
+        } else {
+            eprintln!("[rivalcfg-tray] Warning: Failed to read PNG file: {}", png_path);
+        }
+    } else {
+        eprintln!(
+            "[rivalcfg-tray] Warning: Failed to convert SVG to PNG for icon: {} after retries",
+            icon_path.display()
+        );
+        use std::io::Write;
+        std::io::stderr().flush().ok();
+    }
+    state
+}
+
+// Bundled PNG for `state` (level snapped to its bucket), written into `dir`
+fn fallback_png_path(state: &BatteryState, t: IconThresholds, dir: &std::path::Path) -> Option<PathBuf> {
+    crate::icons::fallback_png_path(&fallback_png_name(state, t), dir)
+}
+
+// FALLBACK_PNGS entry for `state`: the battery SVG's name with the bolt baked in
+// as "-charging" while charging
+fn fallback_png_name(state: &BatteryState, t: IconThresholds) -> String {
+    match *state {
+        BatteryState::Disconnected => "mouse-disconnected.png".to_string(),
+        BatteryState::NoBattery => "mouse.png".to_string(),
+        BatteryState::Connected { status: ChargeStatus::Charged, .. } => "battery-full.png".to_string(),
+        BatteryState::Connected { level, status } => {
+            let stem = battery_icon_name(level, t).trim_end_matches(".svg");
+            if status == ChargeStatus::Charging {
+                format!("{}-charging.png", stem)
+            } else {
+                format!("{}.png", stem)
+            }
+        }
+    }
+}
+
+// "#rrggbb" as RGB bytes
+fn rgb_from_hex(hex: &str) -> Option<[u8; 3]> {
+    let h = hex.trim().strip_prefix('#')?;
+    if h.len() != 6 || !h.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&h[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+// Recolour the fallback PNGs, which are drawn in black: grey pixels (the icon and its
+// antialiased edges) take `rgb` and keep their alpha, coloured ones like the yellow
+// charging bolt are left alone
+fn tint_icon_pixels(img: &mut image::RgbaImage, rgb: [u8; 3]) {
+    for px in img.pixels_mut() {
+        let [r, g, b, a] = px.0;
+        if r == g && g == b {
+            px.0 = [rgb[0], rgb[1], rgb[2], a];
+        }
+    }
+}
+
+// Flip the icon left-to-right for RTL layouts; the battery icons carry no text
+fn mirror_icon_for_direction(img: &mut image::RgbaImage, rtl: bool) {
+    if rtl {
+        image::imageops::flip_horizontal_in_place(img);
+    }
+}
+
+// Justification for wrapped label text so lines start at the reading edge. Unlike
+// halign, gtk::Justification is absolute and doesn't follow the text direction.
+fn start_justification(direction: gtk::TextDirection) -> gtk::Justification {
+    match direction {
+        gtk::TextDirection::Rtl => gtk::Justification::Right,
+        _ => gtk::Justification::Left,
+    }
+}
+
+// use std::io::Stdout;
+const DARK_MODE_COLOR: &str = "#ffffff";
+const LIGHT_MODE_COLOR: &str = "#000000";
+
+// Whether the desktop's GTK theme is dark, for the "auto" colour mode; kept current
+// by watch_theme_changes
+static THEME_PREFERS_DARK: AtomicBool = AtomicBool::new(false);
+
+// Colour the icon SVGs should be recoloured to for the saved settings, if any:
+// custom colour first, then the dark/light colour switch.
+fn icon_recolor_color(s: &Settings) -> Option<String> {
+    if let Some(ref clr) = s.custom_color {
+        return Some(clr.clone());
+    }
+    match s.colour_mode.as_deref() {
+        Some("dark") => Some(DARK_MODE_COLOR.to_string()),
+        Some("light") => Some(LIGHT_MODE_COLOR.to_string()),
+        // light icons on a dark theme's panel and vice versa
+        Some("auto") if THEME_PREFERS_DARK.load(Ordering::SeqCst) => Some(DARK_MODE_COLOR.to_string()),
+        Some("auto") => Some(LIGHT_MODE_COLOR.to_string()),
+        _ => None,
+    }
+}
+
+// A theme counts as dark when the app-wide preference is set or its name has a
+// dark variant suffix, e.g. "Adwaita-dark" or "Yaru:dark"
+fn theme_is_dark(prefer_dark: bool, theme_name: &str) -> bool {
+    let name = theme_name.to_ascii_lowercase();
+    prefer_dark || name.ends_with("-dark") || name.ends_with(":dark")
+}
+
+// Cache keys of PNGs rendered in some colour ("<svg>::<colour>@<size>"); uncoloured
+// renders don't depend on the theme and are kept
+fn recoloured_cache_keys(cache: &HashMap<String, (String, SystemTime)>) -> Vec<String> {
+    let mut keys: Vec<String> = cache.keys().filter(|k| k.contains("::")).cloned().collect();
+    keys.sort();
+    keys
+}
+
+// Drop the recoloured PNGs (and their files) after a theme change
+fn purge_recoloured_pngs() {
+    if let Ok(mut cache) = PNG_CACHE.lock() {
+        let mut files = TEMP_FILES.lock().ok();
+        for key in recoloured_cache_keys(&cache) {
+            let Some((png_path, _)) = cache.remove(&key) else {
+                continue;
+            };
+            match files.as_mut() {
+                Some(files) if files.contains(std::path::Path::new(&png_path)) => {
+                    files.remove(std::path::Path::new(&png_path))
+                }
+                _ => {
+                    let _ = std::fs::remove_file(&png_path);
+                }
+            }
+        }
+    }
+}
+
+// Follow light/dark theme switches while running. The icon is redrawn right away
+// rather than on the next battery change.
+fn watch_theme_changes(tray_icon: &TrayIcon) {
+    use gtk::prelude::*;
+
+    let Some(gtk_settings) = gtk::Settings::default() else {
+        return;
+    };
+    let current_dark = |gs: &gtk::Settings| {
+        let name = gs.gtk_theme_name().map(|n| n.to_string()).unwrap_or_default();
+        theme_is_dark(gs.is_gtk_application_prefer_dark_theme(), &name)
+    };
+    THEME_PREFERS_DARK.store(current_dark(&gtk_settings), Ordering::SeqCst);
+    for property in ["gtk-application-prefer-dark-theme", "gtk-theme-name"] {
+        let tray_icon = tray_icon.clone();
+        gtk_settings.connect_notify_local(Some(property), move |gs, _| {
+            let dark = current_dark(gs);
+            if THEME_PREFERS_DARK.swap(dark, Ordering::SeqCst) == dark {
+                return;
+            }
+            eprintln!("[rivalcfg-tray] Desktop theme changed (dark: {}), redrawing the icon", dark);
+            redraw_in_new_colour(&tray_icon);
+        });
+    }
+}
+
+// Drop all cached PNGs (and their files), e.g. after the icon size changed
+fn invalidate_png_cache() {
+    if let Ok(mut cache) = PNG_CACHE.lock() {
+        let mut files = TEMP_FILES.lock().ok();
+        for (_, (png_path, _)) in cache.drain() {
+            match files.as_mut() {
+                Some(files) if files.contains(std::path::Path::new(&png_path)) => {
+                    files.remove(std::path::Path::new(&png_path))
+                }
+                _ => {
+                    let _ = std::fs::remove_file(&png_path);
+                }
+            }
+        }
+    }
+}
+
+// Tray icon render sizes (pixels): the standard panel sizes, up to HiDPI 256x256
+const ICON_SIZES: [u32; 8] = [16, 22, 24, 32, 48, 64, 128, 256];
+const DEFAULT_ICON_SIZE: u32 = 64;
+
+// Size the tray icon was last rendered at (0 before the first render), so a changed
+// icon_size setting drops the PNGs of the old size and redraws
+static RENDERED_ICON_SIZE: AtomicU32 = AtomicU32::new(0);
+
+// Resolve the icon render size from settings: the default, or the supported size
+// nearest to the requested one (the larger on a tie) so a typo in the settings file
+// can't produce a huge PNG.
+fn effective_icon_size(requested: Option<u32>) -> u32 {
+    let requested = requested.unwrap_or(DEFAULT_ICON_SIZE);
+    ICON_SIZES
+        .into_iter()
+        .min_by_key(|&size| (size.abs_diff(requested), u32::MAX - size))
+        .unwrap_or(DEFAULT_ICON_SIZE)
+}
+
+// Use XDG runtime dir or fallback to temp dir for COSMIC compatibility
+// Using a runtime directory helps COSMIC's status-area applet find icons more reliably
+fn icon_runtime_dir() -> PathBuf {
+    private_runtime_dir().unwrap_or_else(std::env::temp_dir)
+}
+
+// Per-user 0700 directory under XDG_RUNTIME_DIR, or the user's cache dir without one.
+// Never the shared temp dir: the action socket lives here.
+fn private_runtime_dir() -> Option<PathBuf> {
+    let bases = [std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from), dirs::cache_dir()];
+    bases.into_iter().flatten().find_map(|base| match private_dir_in(&base) {
+        Ok(dir) => Some(dir),
+        Err(e) => {
+            eprintln!("[rivalcfg-tray] Warning: Can't use {} for runtime files: {}", base.display(), e);
+            None
+        }
+    })
+}
+
+// PNG_CACHE key for `svg_path` rendered in `color` at `size`: "<svg>::<colour>@<size>",
+// or "<svg>@<size>" uncoloured. The SVG's mtime never changes for installed icons,
+// so the colour and size must be part of the key for a change to ever show.
+fn png_cache_key(svg_path: &str, color: Option<&str>, size: u32) -> String {
+    match color {
+        Some(color) => format!("{}::{}@{}", svg_path, color, size),
+        None => format!("{}@{}", svg_path, size),
+    }
+}
+
+// Why an SVG could not be turned into a PNG
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SvgConvertError {
+    // rsvg-convert could not be started; retrying won't help
+    ConverterMissing,
+    // this conversion failed (bad SVG, rsvg-convert error, temp file trouble)
+    Failed,
+}
+
+// Set once rsvg-convert turned out to be missing; icons then come from FALLBACK_PNGS
+static CONVERTER_MISSING: AtomicBool = AtomicBool::new(false);
+
+// Render `svg_path` to a PNG of `icon_size` pixels, recoloured to `recolor` when the
+// caller picked a colour (the attention red, or the one from settings)
+fn svg_to_png_temp(
+    svg_path: &std::path::Path,
+    icon_size: u32,
+    recolor: Option<&str>,
+) -> Result<String, SvgConvertError> {
+    use std::process::Command;
+
+    // Check cache first and determine if recoloring is required. We support four
+    // cases: custom color, dark or light mode (predefined colors), or no recolor.
+    let svg_path_str = svg_path.to_string_lossy().to_string();
+    let svg_modified = std::fs::metadata(svg_path)
+        .and_then(|m| m.modified())
+        .map_err(|_| SvgConvertError::Failed)?;
+    let color_for_recolor = recolor.map(str::to_string);
+    let cache_key = png_cache_key(&svg_path_str, color_for_recolor.as_deref(), icon_size);
+
+    if let Ok(cache) = PNG_CACHE.lock() {
+        if let Some((cached_png_path, cached_time)) = cache.get(&cache_key) {
+            if std::path::Path::new(cached_png_path).exists() && *cached_time >= svg_modified {
+                eprintln!("[rivalcfg-tray] Using cached PNG: {}", cached_png_path);
+                if let Ok(mut files) = TEMP_FILES.lock() {
+                    files.touch(std::path::Path::new(cached_png_path));
+                }
+                return Ok(cached_png_path.clone());
+            }
+        }
+    }
+
+    let runtime_dir = icon_runtime_dir();
+
+    // Create a temp file with a unique name
+    let temp_file = match tempfile::Builder::new()
+        .prefix("rivalcfg-tray-")
+        .suffix(".png")
+        .tempfile_in(&runtime_dir) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("[rivalcfg-tray] Failed to create temp file: {}", e);
+                return Err(SvgConvertError::Failed);
+            }
+    };
+
+    let temp_path = temp_file.path().to_path_buf();
+
+    // If we have an effective recolor color (custom or dark), create a recolored SVG
+    // and convert that instead
+    let recolored = color_for_recolor
+        .and_then(|color| recolor_svg_to_temp(svg_path, &color, &PRESERVED_ICON_COLORS, &runtime_dir));
+    if let Some(ref tmp_svg) = recolored {
+        eprintln!("[rivalcfg-tray] Using recolored SVG: {}", tmp_svg.display());
+    }
+    let svg_to_convert = recolored.clone().unwrap_or_else(|| svg_path.to_path_buf());
+
+    eprintln!("[rivalcfg-tray] Converting SVG to PNG: {} -> {}", svg_to_convert.display(), temp_path.display());
+
+    // Convert SVG to PNG
+    let size_arg = icon_size.to_string();
+    let output = Command::new("rsvg-convert")
+        .arg("-w")
+        .arg(&size_arg)
+        .arg("-h")
+        .arg(&size_arg)
+        .arg("-o")
+        .arg(&temp_path)
+        .arg(&svg_to_convert)
+        .output();
+    // the recoloured copy is only rsvg-convert's input, so it never outlives the call
+    if let Some(ref tmp_svg) = recolored {
+        let _ = std::fs::remove_file(tmp_svg);
+    }
+    let output = output
+        .map_err(|e| {
+            eprintln!("[rivalcfg-tray] Could not run rsvg-convert: {}", e);
+            SvgConvertError::ConverterMissing
+        })?;
+
+    if !output.status.success() {
+        eprintln!(
+            "[rivalcfg-tray] rsvg-convert failed:\nstdout: {}\nstderr: {}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        return Err(SvgConvertError::Failed);
+    }
+
+    if !temp_path.exists() {
+        eprintln!("[rivalcfg-tray] PNG file was not created: {}", temp_path.display());
+        return Err(SvgConvertError::Failed);
+    }
+
+    eprintln!("[rivalcfg-tray] Successfully created PNG: {}", temp_path.display());
+    
+    // Keep the file past this scope; the registry owns it from here
+    let temp_path = keep_temp_file(temp_file).ok_or(SvgConvertError::Failed)?;
+    let png_path_str = temp_path.to_str().ok_or(SvgConvertError::Failed)?.to_string();
+    
+    // Update cache
+    if let Ok(mut cache) = PNG_CACHE.lock() {
+        cache.insert(cache_key, (png_path_str.clone(), svg_modified));
+    }
+    
+    Ok(png_path_str)
+}
+
+// Monochrome mode: panels such as GNOME's top bar expect single-colour icons that
+// match the panel foreground. The icon is still rendered to a PNG, just in one
+// colour. Auto-detected from XDG_CURRENT_DESKTOP (a colon-separated list, e.g.
+// "ubuntu:GNOME") unless settings force it on or off.
+fn monochrome_mode_enabled(setting: Option<bool>, current_desktop: Option<&str>) -> bool {
+    setting.unwrap_or_else(|| {
+        current_desktop.is_some_and(|d| d.split(':').any(|de| de.eq_ignore_ascii_case("GNOME")))
+    })
+}
+
+// Colours a custom icon colour leaves alone: the charging bolt's yellow
+const PRESERVED_ICON_COLORS: [&str; 1] = ["#ffff00"];
+
+// Which icon variants the tray draws: the coloured SVGs, or monochrome ones that
+// are light (for dark panels) or dark (for light panels)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IconTheme {
+    Color,
+    MonochromeLight,
+    MonochromeDark,
+}
+
+impl IconTheme {
+    fn from_setting(value: &str) -> Option<Self> {
+        match value.trim() {
+            "color" => Some(IconTheme::Color),
+            // the "symbolic-" names are from before the rename
+            "monochrome-light" | "symbolic-light" => Some(IconTheme::MonochromeLight),
+            "monochrome-dark" | "symbolic-dark" => Some(IconTheme::MonochromeDark),
+            _ => None,
+        }
+    }
+
+    // Colour monochrome icons are tinted to when no custom icon colour is set
+    fn foreground(self) -> Option<&'static str> {
+        match self {
+            IconTheme::Color => None,
+            IconTheme::MonochromeLight => Some(DARK_MODE_COLOR),
+            IconTheme::MonochromeDark => Some(LIGHT_MODE_COLOR),
+        }
+    }
+}
+
+// The icon theme from settings. Without one (or with an unknown value) the older
+// monochrome_icons switch decides, and the colour switch picks light or dark.
+fn icon_theme(s: &Settings, current_desktop: Option<&str>) -> IconTheme {
+    if let Some(theme) = s.icon_theme.as_deref().and_then(IconTheme::from_setting) {
+        return theme;
+    }
+    if !monochrome_mode_enabled(s.monochrome_icons, current_desktop) {
+        IconTheme::Color
+    } else if icon_recolor_color(s).as_deref() == Some(LIGHT_MODE_COLOR) {
+        IconTheme::MonochromeDark
+    } else {
+        IconTheme::MonochromeLight
+    }
+}
+
+// Colour the rendered icon is forced to: the attention red first, then for monochrome
+// themes the custom icon colour or the theme's foreground. None leaves coloured
+// icons to the colour switch (see icon_recolor_color).
+fn icon_color_override(theme: IconTheme, attention: bool, s: &Settings) -> Option<String> {
+    if attention {
+        return Some(ATTENTION_COLOR.to_string());
+    }
+    let foreground = theme.foreground()?;
+    Some(s.custom_color.clone().unwrap_or_else(|| foreground.to_string()))
+}
+
+use tray_icon::{MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent, menu::{CheckMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu, MenuEvent}};
+use tray_icon::Icon as TrayIconImage;
+use glib::ControlFlow;
+use std::path::PathBuf;
+// use std::process::Command; (moved to RealCommandRunner)
+use std::time::Duration;
+
+// command-runner related helpers are located in `cmd` module
+
+// Environment variable naming a directory that is searched for icons before anything else
+const ICON_DIR_ENV: &str = "RIVALCFG_TRAY_ICON_DIR";
+
+// Icon directories from the XDG base directory spec: $XDG_DATA_HOME (default
+// ~/.local/share) first, then each entry of $XDG_DATA_DIRS (default
+// /usr/local/share:/usr/share).
+fn xdg_icon_dirs(data_home: Option<&str>, data_dirs: Option<&str>, home: Option<PathBuf>) -> Vec<PathBuf> {
+    let mut bases = Vec::new();
+    match data_home.filter(|d| !d.is_empty()) {
+        Some(d) => bases.push(PathBuf::from(d)),
+        None => bases.extend(home.map(|h| h.join(".local").join("share"))),
+    }
+    let data_dirs = data_dirs.filter(|d| !d.is_empty()).unwrap_or("/usr/local/share:/usr/share");
+    bases.extend(data_dirs.split(':').filter(|d| !d.is_empty()).map(PathBuf::from));
+
+    let mut dirs = Vec::new();
+    for base in bases {
+        dirs.push(base.join("icons").join("hicolor").join("scalable").join("apps"));
+        dirs.push(base.join("icons").join("rivalcfgtray"));
+    }
+    dirs
+}
+
+// Where locate_icon found an icon, reported in the debug log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IconSource {
+    // the icon pack selected in settings
+    Pack,
+    // $RIVALCFG_TRAY_ICON_DIR
+    OverrideDir,
+    // the default GTK icon theme
+    IconTheme,
+    // $XDG_DATA_HOME / $XDG_DATA_DIRS icon directories
+    XdgDataDir,
+    // hard-coded install and development paths
+    Fallback,
+    // the copy compiled into the binary
+    Bundled,
+}
+
+fn xdg_icon_dirs_from_env() -> Vec<PathBuf> {
+    xdg_icon_dirs(
+        std::env::var("XDG_DATA_HOME").ok().as_deref(),
+        std::env::var("XDG_DATA_DIRS").ok().as_deref(),
+        dirs::home_dir(),
+    )
+}
+
+// First of `dirs` containing `name`
+fn find_icon_in_dirs(name: &str, dirs: &[PathBuf]) -> Option<PathBuf> {
+    dirs.iter().map(|d| d.join(name)).find(|p| p.exists())
+}
+
+// Ask the default GTK icon theme for `name` (file extension stripped). Only possible
+// on the main thread once GTK is initialised.
+fn icon_theme_lookup(name: &str) -> Option<PathBuf> {
+    use gtk::prelude::*;
+
+    if !gtk::is_initialized_main_thread() {
+        return None;
+    }
+    let icon_name = name.strip_suffix(".svg").unwrap_or(name);
+    let theme = gtk::IconTheme::default()?;
+    let info = theme.lookup_icon(icon_name, DEFAULT_ICON_SIZE as i32, gtk::IconLookupFlags::FORCE_SVG)?;
+    info.filename().filter(|p| p.exists())
+}
+
+fn find_icon(name: &str) -> Option<PathBuf> {
+    let (path, source) = locate_icon(name)?;
+    eprintln!("[rivalcfg-tray] Found icon at: {} ({:?})", path.display(), source);
+    Some(path)
+}
+
+// Hard-coded install and development locations for `name`, in search order
+fn fallback_icon_paths(name: &str) -> Vec<PathBuf> {
+    let mut possible_paths = Vec::new();
+    possible_paths.extend([
+        // Standard freedesktop.org icon theme directories (where PKGBUILD installs icons)
+        PathBuf::from(format!("/usr/share/icons/hicolor/scalable/apps/{}", name)),
+        PathBuf::from(format!("/usr/share/icons/hicolor/symbolic/apps/{}", name)),
+        // Check size-specific directories (16x16, 22x22, 24x24, 32x32, 48x48, 64x64, 128x128, 256x256)
+        PathBuf::from(format!("/usr/share/icons/hicolor/16x16/apps/{}", name)),
+        PathBuf::from(format!("/usr/share/icons/hicolor/22x22/apps/{}", name)),
+        PathBuf::from(format!("/usr/share/icons/hicolor/24x24/apps/{}", name)),
+        PathBuf::from(format!("/usr/share/icons/hicolor/32x32/apps/{}", name)),
+        PathBuf::from(format!("/usr/share/icons/hicolor/48x48/apps/{}", name)),
+        PathBuf::from(format!("/usr/share/icons/hicolor/64x64/apps/{}", name)),
+        PathBuf::from(format!("/usr/share/icons/hicolor/128x128/apps/{}", name)),
+        PathBuf::from(format!("/usr/share/icons/hicolor/256x256/apps/{}", name)),
+        // Current directory (for development/testing)
+        PathBuf::from(format!("icons/{}", name)),
+        // Executable directory relative
+        PathBuf::from(format!("bin/icons/{}", name)),
+        // Flatpak directories
+        PathBuf::from(format!("/app/bin/icons/{}", name)),
+        PathBuf::from(format!("/app/share/icons/rivalcfgtray/{}", name)),
+        PathBuf::from(format!("/app/share/icons/hicolor/scalable/apps/{}", name)),
+        // System-wide installation (legacy path)
+        PathBuf::from(format!("/usr/share/rivalcfgtray/icons/{}", name)),
+    ]);
+    
+    // Also try relative to the executable
+    if let Ok(exe) = std::env::current_exe() {
+        if let Some(exe_dir) = exe.parent() {
+            possible_paths.push(exe_dir.join("icons").join(name));
+            // Try one directory up
+            if let Some(parent) = exe_dir.parent() {
+                possible_paths.push(parent.join("icons").join(name));
+                possible_paths.push(parent.join("share").join("icons").join("rivalcfgtray").join(name));
+            }
+        }
+    }
+    
+    // Try relative to the current working directory with more parent directories
+    let mut current = std::env::current_dir().ok();
+    while let Some(dir) = current {
+        possible_paths.push(dir.join("icons").join(name));
+        current = dir.parent().map(|p| p.to_path_buf());
+    };
+    possible_paths
+}
+
+// Every file locate_icon may use for `name`, in search order (the GTK icon theme
+// is only consulted once GTK is running and isn't listed)
+fn icon_candidate_paths(name: &str) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Ok(dir) = std::env::var(ICON_DIR_ENV)
+        && !dir.is_empty()
+    {
+        paths.push(PathBuf::from(dir).join(name));
+    }
+    paths.extend(xdg_icon_dirs_from_env().iter().map(|d| d.join(name)));
+    paths.extend(fallback_icon_paths(name));
+    paths
+}
+
+// Name of the built-in icon set; `icon_pack` values of None or this use no pack
+const DEFAULT_ICON_PACK: &str = "default";
+
+// Directories holding icon packs, one subdirectory per pack (e.g.
+// `icons/packs/<name>/battery-50.svg`), in search order
+fn icon_pack_roots() -> Vec<PathBuf> {
+    let mut roots = Vec::new();
+    if let Ok(dir) = std::env::var(ICON_DIR_ENV)
+        && !dir.is_empty()
+    {
+        roots.push(PathBuf::from(dir).join("packs"));
+    }
+    roots.extend(
+        xdg_icon_dirs_from_env()
+            .into_iter()
+            .filter(|d| d.ends_with("rivalcfgtray"))
+            .map(|d| d.join("packs")),
+    );
+    roots.extend([
+        PathBuf::from("icons/packs"),
+        PathBuf::from("/app/share/icons/rivalcfgtray/packs"),
+        PathBuf::from("/usr/share/rivalcfgtray/icons/packs"),
+    ]);
+    if let Ok(exe) = std::env::current_exe()
+        && let Some(exe_dir) = exe.parent()
+    {
+        roots.push(exe_dir.join("icons").join("packs"));
+    }
+    roots
+}
+
+// A pack name is a single directory name, never a path
+fn is_valid_pack_name(pack: &str) -> bool {
+    !pack.is_empty() && pack != "." && pack != ".." && !pack.contains(['/', '\\'])
+}
+
+// Names of the packs found under `roots`, sorted and without duplicates
+fn discover_icon_packs(roots: &[PathBuf]) -> Vec<String> {
+    let mut packs: Vec<String> = roots
+        .iter()
+        .filter_map(|root| std::fs::read_dir(root).ok())
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| is_valid_pack_name(name) && name != DEFAULT_ICON_PACK)
+        .collect();
+    packs.sort();
+    packs.dedup();
+    packs
+}
+
+// `name` from `pack` in the first of `roots` that has it. Icons a pack doesn't
+// provide return None so the caller falls back per icon.
+fn find_icon_in_pack(name: &str, pack: &str, roots: &[PathBuf]) -> Option<PathBuf> {
+    if !is_valid_pack_name(pack) || pack == DEFAULT_ICON_PACK {
+        return None;
+    }
+    let dirs: Vec<PathBuf> = roots.iter().map(|r| r.join(pack)).collect();
+    find_icon_in_dirs(name, &dirs)
+}
+
+// The pack selected in settings, if any
+fn active_icon_pack() -> Option<String> {
+    load_settings()?
+        .icon_pack
+        .filter(|p| is_valid_pack_name(p) && p != DEFAULT_ICON_PACK)
+}
+
+// Icons the tray looks up, for `--list-icons`
+const KNOWN_ICON_NAMES: [&str; 7] = [
+    "battery-100.svg",
+    "battery-75.svg",
+    "battery-50.svg",
+    "battery-25.svg",
+    "battery-warn.svg",
+    "battery-0.svg",
+    "charging.svg",
+];
+
+// Report for `--list-icons`: each icon name, then every candidate path marked
+// [FOUND] or [MISSING]
+fn list_icon_search_report(names: &[&str]) -> String {
+    icon_search_report_with(names, icon_candidate_paths)
+}
+
+fn icon_search_report_with(names: &[&str], candidates: impl Fn(&str) -> Vec<PathBuf>) -> String {
+    let mut out = String::new();
+    for name in names {
+        out.push_str(&format!("{}:\n", name));
+        for path in candidates(name) {
+            let mark = if path.exists() { "[FOUND]" } else { "[MISSING]" };
+            out.push_str(&format!("  {} {}\n", mark, path.display()));
+        }
+    }
+    out
+}
+
+// Look `name` up in order: the active icon pack, the override dir, the GTK icon
+// theme, the XDG data dirs, the hard-coded install/development paths, and finally
+// the bundled copy.
+fn locate_icon(name: &str) -> Option<(PathBuf, IconSource)> {
+    let override_dir = std::env::var(ICON_DIR_ENV).ok().filter(|d| !d.is_empty()).map(PathBuf::from);
+    locate_icon_in(name, override_dir.as_deref(), &xdg_icon_dirs_from_env())
+}
+
+// locate_icon with the override dir and XDG icon directories given by the caller
+fn locate_icon_in(
+    name: &str,
+    override_dir: Option<&std::path::Path>,
+    xdg_dirs: &[PathBuf],
+) -> Option<(PathBuf, IconSource)> {
+    if let Some(pack) = active_icon_pack()
+        && let Some(path) = find_icon_in_pack(name, &pack, &icon_pack_roots())
+    {
+        return Some((path, IconSource::Pack));
+    }
+    if let Some(dir) = override_dir
+        && let Some(path) = find_icon_in_dirs(name, &[dir.to_path_buf()])
+    {
+        return Some((path, IconSource::OverrideDir));
+    }
+    if let Some(path) = icon_theme_lookup(name) {
+        return Some((path, IconSource::IconTheme));
+    }
+    if let Some(path) = find_icon_in_dirs(name, xdg_dirs) {
+        return Some((path, IconSource::XdgDataDir));
+    }
+
+    let possible_paths = fallback_icon_paths(name);
+
+    for path in &possible_paths {
+        if path.exists() {
+            return Some((path.clone(), IconSource::Fallback));
+        }
+    }
+    if let Some(path) = bundled_icon_path(name, &icon_runtime_dir().join("bundled-icons")) {
+        return Some((path, IconSource::Bundled));
+    }
+    eprintln!("[rivalcfg-tray] Warning: Could not find icon '{}' in any of these locations:", name);
+    for path in &possible_paths {
+        eprintln!("[rivalcfg-tray]   - {}", path.display());
+    }
+    None
+}
+
+// SVG to show for `state`. Charging icons get the bolt composited on top, except in
+// monochrome mode where the overlay doesn't survive flattening to one colour and the
+// outline icon is used instead.
+fn select_icon_path(resolver: &dyn IconResolver, state: &BatteryState, monochrome: bool, t: IconThresholds) -> PathBuf {
+    match *state {
+        BatteryState::Disconnected => resolve_or_default(resolver, "mouse-disconnected.svg"),
+        BatteryState::NoBattery => resolve_or_default(resolver, "mouse.svg"),
+        BatteryState::Connected { status: ChargeStatus::Charged, .. } => resolve_or_default(resolver, "battery-full.svg"),
+        BatteryState::Connected { status: ChargeStatus::Charging, .. } if monochrome => {
+            resolve_or_default(resolver, "battery-charging.svg")
+        }
+        BatteryState::Connected { level, status: ChargeStatus::Charging } => {
+            let battery_svg = battery_icon_path_with_resolver(resolver, level, t);
+            let charging_svg = resolve_or_default(resolver, "charging.svg");
+            composite_battery_charging_svg(&battery_svg, &charging_svg).unwrap_or(battery_svg)
+        }
+        BatteryState::Connected { level, .. } => battery_icon_path_with_resolver(resolver, level, t),
+    }
+}
+
+fn battery_icon_path_with_resolver(resolver: &dyn IconResolver, level: u8, t: IconThresholds) -> PathBuf {
+    // Always use the base battery SVG names; recoloring (for dark/custom) is
+    // performed later in the SVG->PNG pipeline based on settings.
+    resolve_or_default(resolver, battery_icon_name(level, t))
+}
+
+// Battery SVG file name for `level`; pure so the bucket boundaries can be tested
+fn battery_icon_name(level: u8, t: IconThresholds) -> &'static str {
+    if level <= t.critical {
+        "battery-0.svg"
+    } else if level <= t.warning {
+        "battery-warn.svg"
+    } else if level > 90 {
+        "battery-100.svg"
+    } else if level > 74 {
+        "battery-75.svg"
+    } else if level > 49 {
+        "battery-50.svg"
+    } else {
+        "battery-25.svg"
+    }
+}
+
+// Find the next `<svg` start tag at or after `from`; returns (tag start, tag end
+// just past its '>', self-closing?). Quoted attribute values may contain '>'.
+// Tag names match case-insensitively (`<SVG`).
+fn find_svg_start_tag(src: &str, from: usize) -> Option<(usize, usize, bool)> {
+    // ASCII lowercasing keeps byte offsets, so positions in `lower` index `src`
+    let lower = src.to_ascii_lowercase();
+    let mut search = from;
+    loop {
+        let start = search + lower[search..].find("<svg")?;
+        let after = start + "<svg".len();
+        // skip e.g. <svgfoo>
+        if !src[after..].starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/') {
+            search = after;
+            continue;
+        }
+        let mut quote: Option<char> = None;
+        for (i, c) in src[after..].char_indices() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '>') => {
+                    let end = after + i + 1;
+                    return Some((start, end, src[..end - 1].ends_with('/')));
+                }
+                _ => {}
+            }
+        }
+        return None;
+    }
+}
+
+// Everything between the root `<svg ...>` start tag and its matching `</svg>`,
+// skipping any XML prolog, comments or doctype before it. Nested <svg> elements
+// are balanced, so only the root's closing tag ends the content.
+fn svg_inner_content(src: &str) -> Option<&str> {
+    let (_, content_start, self_closing) = find_svg_start_tag(src, 0)?;
+    if self_closing {
+        return Some("");
+    }
+    let lower = src.to_ascii_lowercase();
+    let mut depth = 1;
+    let mut pos = content_start;
+    loop {
+        let close = pos + lower[pos..].find("</svg")?;
+        match find_svg_start_tag(src, pos) {
+            // a nested <svg> opens before the next close
+            Some((open, end, nested_self_closing)) if open < close => {
+                if !nested_self_closing {
+                    depth += 1;
+                }
+                pos = end;
+            }
+            _ => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&src[content_start..close]);
+                }
+                pos = close + "</svg".len();
+            }
+        }
+    }
+}
+
+// `battery` with `overlay` added as the last children of its root <svg>: before the
+// last closing tag (any casing or whitespace, e.g. `</SVG >`), or, for a
+// self-closing root, in a closing tag added for it. None if there is no root <svg>
+// or it is never closed.
+fn insert_svg_overlay(battery: &str, overlay: &str) -> Option<String> {
+    let (_, root_end, self_closing) = find_svg_start_tag(battery, 0)?;
+    if self_closing {
+        let tag = battery[..root_end - "/>".len()].trim_end();
+        return Some(format!("{}>{}\n</svg>{}", tag, overlay, &battery[root_end..]));
+    }
+    let close = battery.to_ascii_lowercase().rfind("</svg")?;
+    if close < root_end {
+        return None;
+    }
+    Some(format!("{}{}\n{}", &battery[..close], overlay, &battery[close..]))
+}
+
+fn composite_battery_charging_svg(
+    battery_svg: &PathBuf,
+    charging_svg: &PathBuf,
+) -> Option<PathBuf> {
+    use std::fs;
+    use std::io::Write;
+
+    let battery_content = fs::read_to_string(battery_svg).ok()?;
+    let charging_src = fs::read_to_string(charging_svg).ok()?;
+    // Keep every child element of the charging SVG's root <svg>, whatever it starts with
+    let charging_content = svg_inner_content(&charging_src)?;
+
+    // Simple SVG overlay by inserting charging SVG into battery SVG
+    let composite_svg = insert_svg_overlay(&battery_content, charging_content)?;
+
+    let mut tmp_path = env::temp_dir();
+    let file_stem = battery_svg
+        .file_stem()
+        .and_then(std::ffi::OsStr::to_str)
+        .unwrap_or("icon");
+    tmp_path.push(format!("{}_charging.svg", file_stem));
+
+    let mut file = fs::File::create(&tmp_path).ok()?;
+    file.write_all(composite_svg.as_bytes()).ok()?;
+
+    Some(tmp_path)
+}
+
+// Command line options understood by the tray
+#[derive(Debug, Default, PartialEq)]
+struct CliOptions {
+    // debug: lay out all windows right-to-left regardless of locale
+    force_rtl: bool,
+    // print the version and exit
+    version: bool,
+    // print where each icon is looked for and exit
+    list_icons: bool,
+    // write / remove the systemd user unit and exit
+    install_service: bool,
+    uninstall_service: bool,
+    // print the battery status for scripts/status bars and exit
+    #[cfg(feature = "cli")]
+    battery: bool,
+    // with --battery: print it as JSON
+    #[cfg(feature = "cli")]
+    json: bool,
+    // run a tray action (see actions.rs), in the running tray if there is one
+    action: Option<String>,
+}
+
+fn parse_cli_args<I: IntoIterator<Item = String>>(args: I) -> CliOptions {
+    let mut opts = CliOptions::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--action" => match args.next() {
+                Some(id) => opts.action = Some(id),
+                None => eprintln!("[rivalcfg-tray] Warning: --action needs an action id"),
+            },
+            "--force-rtl" => opts.force_rtl = true,
+            "--version" | "-V" => opts.version = true,
+            "--list-icons" => opts.list_icons = true,
+            "--install-service" => opts.install_service = true,
+            "--uninstall-service" => opts.uninstall_service = true,
+            #[cfg(feature = "cli")]
+            "--battery" => opts.battery = true,
+            // --json on its own is shorthand for --battery --json
+            #[cfg(feature = "cli")]
+            "--json" => {
+                opts.battery = true;
+                opts.json = true;
+            }
+            other => eprintln!("[rivalcfg-tray] Warning: Ignoring unknown argument: {}", other),
+        }
+    }
+    opts
+}
+
+// Machine-readable battery line for `--battery`: "45 Discharging", or with `json`
+// {"percent":45,"charging":false}. None when no device reported a level.
+#[cfg(feature = "cli")]
+fn battery_status_line(level: Option<(u8, ChargeStatus)>, json: bool) -> Option<String> {
+    let (percent, status) = level?;
+    if json {
+        // written by hand to keep the documented key order
+        Some(format!(r#"{{"percent":{},"charging":{}}}"#, percent, status == ChargeStatus::Charging))
+    } else {
+        Some(format!("{} {:?}", percent, status))
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let opts = parse_cli_args(env::args().skip(1));
+    if opts.version {
+        println!("rivalcfg-tray {}", crate_version());
+        return Ok(());
+    }
+    if opts.list_icons {
+        print!("{}", list_icon_search_report(&KNOWN_ICON_NAMES));
+        return Ok(());
+    }
+    if opts.install_service {
+        return install_service();
+    }
+    if opts.uninstall_service {
+        return uninstall_service();
+    }
+    if let Some(path) = load_settings().and_then(|s| s.rivalcfg_path)
+        && let Err(e) = check_rivalcfg_path(std::path::Path::new(&path))
+    {
+        eprintln!("[rivalcfg-tray] Warning: Configured rivalcfg_path {}; rivalcfg commands will fail", e);
+    }
+    // Runs before gtk::init so it works without a display
+    #[cfg(feature = "cli")]
+    if opts.battery {
+        match battery_status_line(get_battery_level(), opts.json) {
+            Some(line) => {
+                println!("{}", line);
+                return Ok(());
+            }
+            None => {
+                eprintln!("[rivalcfg-tray] No device battery level available");
+                std::process::exit(1);
+            }
+        }
+    }
+    // Forward --action to a running tray; without one, start the tray and run it there
+    let action_socket = private_runtime_dir().map(|dir| action_socket_path(&dir));
+    if let Some(ref id) = opts.action
+        && let Some(ref action_socket) = action_socket
+    {
+        match forward_action(action_socket, id) {
+            Ok(()) => return Ok(()),
+            Err(e) => eprintln!("[rivalcfg-tray] No running tray to forward '{}' to ({}), starting one", id, e),
+        }
+    }
+    gtk::init()?;
+    remove_stale_temp_pngs(&[std::env::temp_dir(), icon_runtime_dir()]);
+    if opts.force_rtl {
+        // Must be set before any widget is created
+        gtk::Widget::set_default_direction(gtk::TextDirection::Rtl);
+    }
+    ICONS_RTL.store(gtk::Widget::default_direction() == gtk::TextDirection::Rtl, Ordering::SeqCst);
+
+    // Shared command runner; first used to check that rivalcfg and rsvg-convert exist
+    let runner: Arc<dyn CommandRunner> = Arc::new(RealCommandRunner::default());
+    let caps = check_dependencies(runner.as_ref());
+    if !caps.rsvg_convert.is_available() {
+        eprintln!("[rivalcfg-tray] Using the bundled {}px PNG icons", FALLBACK_PNG_SIZE);
+        CONVERTER_MISSING.store(true, Ordering::SeqCst);
+    }
+    if let Ok(mut stored) = APP_CAPABILITIES.lock() {
+        *stored = caps;
+    }
+
+    // Get initial battery status and mouse name. Show the last persisted reading so the
+    // tray doesn't block on (or show 0% during) the first USB poll; that poll then runs
+    // in the background. Without one the tray starts out disconnected.
+    let state = if rivalcfg_available() {
+        load_battery_state().unwrap_or(BatteryState::Disconnected)
+    } else {
+        BatteryState::Disconnected
+    };
+    // Probe the device right away if the tray host is already up; after an early
+    // autostart the probe waits for it (or the grace period), see StartupGate.
+    let grace = load_settings()
+        .and_then(|s| s.startup_grace)
+        .unwrap_or(DEFAULT_STARTUP_GRACE_SECS);
+    let mut startup_gate = StartupGate::new(Duration::from_secs(grace.into()), Instant::now());
+    if startup_gate.poll(Instant::now(), &SessionBusChecker::default()) == GateAction::Probe {
+        probe_device_name();
+    }
+    let mouse_name = current_mouse_name();
+    eprintln!(
+        "[rivalcfg-tray] Starting tray for device: {} with battery state: {:?}",
+        mouse_name, state
+    );
+    
+    // Create menu using tray-icon's menu system
+    let handles = build_menu(&load_settings().unwrap_or_default(), &state)?;
+    let menu = handles.menu.clone();
+    MENU.with(|cell| cell.replace(Some(handles)));
+    
+    // Build the tray icon
+    let tray_icon = TrayIconBuilder::new()
+        .with_menu(Box::new(menu))
+        // left click opens the config window; the menu stays on right click
+        .with_menu_on_left_click(false)
+        .with_tooltip(tray_tooltip_text(
+            &device_display_name(&mouse_name, &load_settings().unwrap_or_default()),
+            &state,
+        ))
+        .build()?;
+    watch_theme_changes(&tray_icon);
+
+    // Apply any saved settings on startup, unless the user manages the mouse elsewhere
+    if let Some(s) = load_settings().filter(|s| rivalcfg_available() && applies_on_startup(s)) {
+        let args = build_rivalcfg_args(&s);
+        if !args.is_empty() {
+            eprintln!("[rivalcfg-tray] Applying saved settings on startup: {:?}", &args);
+            let slices = args.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+            let out = runner.run("rivalcfg", &slices);
+            if !out.success {
+                eprintln!("[rivalcfg-tray] Failed to apply saved settings: {}", out.stderr);
+            }
+        }
+    }
+
+    update_tray_icon(&tray_icon, state);
+    if rivalcfg_available() {
+        poll_battery_in_background(tray_icon.clone());
+    }
+    if let Ok(mut w) = POLL_WATCHDOG.lock() {
+        w.heartbeat(Instant::now());
+    }
+
+    // Actions forwarded by `rivalcfg-tray --action <id>`, plus one given to this process
+    sync_desktop_actions();
+    let mut pending_actions: Vec<String> = opts.action.clone().into_iter().collect();
+    // Without a private directory anyone could send actions, so don't listen at all
+    let mut action_listener = match action_socket.as_deref().map(ActionListener::bind) {
+        Some(Ok(listener)) => Some(listener),
+        Some(Err(e)) => {
+            eprintln!("[rivalcfg-tray] Warning: Failed to listen for forwarded actions: {}", e);
+            None
+        }
+        None => {
+            eprintln!("[rivalcfg-tray] Warning: No private runtime directory, not listening for forwarded actions");
+            None
+        }
+    };
+    let runner_for_actions = runner.clone();
+    let tray_icon_for_actions = tray_icon.clone();
+    glib::timeout_add_local(Duration::from_millis(500), move || {
+        pending_actions.extend(action_listener.iter_mut().flat_map(|l| l.try_recv()));
+        for id in pending_actions.drain(..) {
+            match TrayAction::from_id(&id, &list_profiles()) {
+                Some(action) => dispatch_action(&action, runner_for_actions.clone(), &tray_icon_for_actions),
+                None => eprintln!("[rivalcfg-tray] Warning: Unknown action '{}'", id),
+            }
+        }
+        ControlFlow::Continue
+    });
+
+    // Store references for menu event handling
+    let runner_for_ui = runner.clone();
+    let tray_icon_for_config = tray_icon.clone();
+    let tray_icon_for_dark = tray_icon.clone();
+    let tray_icon_for_light = tray_icon.clone();
+    let tray_icon_for_custom = tray_icon.clone();
+    let tray_icon_for_timer = tray_icon.clone();
+    
+    let runner_for_reapply = runner.clone();
+    let tray_icon_for_switch = tray_icon.clone();
+    let tray_icon_for_sensitivity = tray_icon.clone();
+    let tray_icon_for_refresh = tray_icon.clone();
+
+    let (refresh_tx, refresh_rx) = std::sync::mpsc::channel();
+    if let Ok(mut tx) = TRAY_REFRESH.lock() {
+        *tx = Some(refresh_tx);
+    }
+    
+    // Handle menu events using glib's idle_add. Items are looked up in MENU, which
+    // is replaced when the menu is rebuilt.
+    let menu_channel = MenuEvent::receiver();
+    let click_channel = TrayIconEvent::receiver();
+    let runner_for_click = runner.clone();
+    let tray_icon_for_click = tray_icon.clone();
+    glib::idle_add_local(move || {
+        // AppIndicator hosts show the menu on any click and send no click events
+        if let Ok(event) = click_channel.try_recv()
+            && opens_config_window(&event)
+        {
+            dispatch_action(&TrayAction::OpenConfig, runner_for_click.clone(), &tray_icon_for_click);
+        }
+        if let Ok(event) = menu_channel.try_recv() {
+            match menu_command(&event.id) {
+                Some(MenuCommand::Quit) => {
+                    cleanup_on_exit();
+                    gtk::main_quit();
+                }
+                Some(MenuCommand::Config) => {
+                    // Handle config dialog
+                    open_config_dialog(runner_for_ui.clone(), tray_icon_for_config.clone(), current_mouse_name());
+                }
+                Some(MenuCommand::ColourSwitch(on)) => handle_colour_switch(tray_icon_for_switch.clone(), on),
+                Some(MenuCommand::DarkMode) => {
+                    handle_dark_mode(tray_icon_for_dark.clone());
+                    set_colour_switch_checked(true);
+                }
+                Some(MenuCommand::LightMode) => {
+                    handle_light_mode(tray_icon_for_light.clone());
+                    set_colour_switch_checked(false);
+                }
+                Some(MenuCommand::AutoColour) => {
+                    handle_auto_colour_mode(tray_icon_for_light.clone());
+                    set_colour_switch_checked(false);
+                }
+                Some(MenuCommand::CustomColour) => {
+                    set_colour_switch_checked(false);
+                    handle_custom_colour(tray_icon_for_custom.clone());
+                }
+                Some(MenuCommand::Reapply) => reapply_current_settings(runner_for_reapply.clone()),
+                Some(MenuCommand::Profile(name)) => activate_profile(runner_for_reapply.as_ref(), &name),
+                Some(MenuCommand::CustomSensitivity) => {
+                    open_config_dialog(runner_for_ui.clone(), tray_icon_for_sensitivity.clone(), current_mouse_name());
+                }
+                Some(MenuCommand::SensitivityPreset(value)) => {
+                    select_sensitivity_preset(runner_for_reapply.as_ref(), value);
+                }
+                None => {}
+            }
+            // Settings may have been created or cleared (e.g. by the config window)
+            refresh_reapply_item();
+            // Clicking a check item toggles it; show what is actually saved instead
+            MENU.with(|cell| {
+                if let Some(handles) = cell.borrow().as_ref() {
+                    sync_sensitivity_items(&handles.sensitivity_items);
+                }
+            });
+        }
+        if let Ok(mut changed) = PROFILES_CHANGED.lock()
+            && std::mem::take(&mut *changed)
+        {
+            MENU.with(|cell| {
+                if let Some(handles) = cell.borrow_mut().as_mut() {
+                    handles.profile_items = rebuild_profiles_submenu(&handles.profiles_submenu);
+                }
+            });
+            sync_desktop_actions();
+        }
+        // Requests that arrive together need only one fresh reading
+        let requests: Vec<TrayRefresh> = refresh_rx.try_iter().collect();
+        if !requests.is_empty() {
+            eprintln!("[rivalcfg-tray] Refreshing the tray now: {:?}", requests);
+            BATTERY_POLLER.invalidate();
+            generate_tray_icon_in_background(tray_icon_for_refresh.clone(), |_, _| {});
+        }
+        ControlFlow::Continue
+    });
+
+    // Update icon every poll interval (30 seconds unless configured)
+    restart_battery_poll(tray_icon_for_timer.clone());
+
+    // Watchdog: recreate the poll timer if its heartbeat goes stale
+    glib::timeout_add_local(WATCHDOG_INTERVAL, move || {
+        let now = Instant::now();
+        let restart = match POLL_WATCHDOG.lock() {
+            Ok(mut w) => {
+                let restart = w.check(now);
+                if restart {
+                    let last_state = LAST_BATTERY_STATE.lock().ok().and_then(|s| *s);
+                    eprintln!(
+                        "[rivalcfg-tray] Warning: Battery poll loop stalled (last heartbeat {}s ago, last state {:?}), recreating it (restart #{})",
+                        w.heartbeat_age(now).as_secs(),
+                        last_state,
+                        w.restarts()
+                    );
+                }
+                restart
+            }
+            Err(_) => false,
+        };
+        if restart {
+            restart_battery_poll(tray_icon_for_timer.clone());
+        }
+        ControlFlow::Continue
+    });
+
+    // Deferred device probe and the one re-probe a minute after startup
+    let tray_icon_for_probe = tray_icon.clone();
+    glib::timeout_add_local(Duration::from_secs(1), move || {
+        match startup_gate.poll(Instant::now(), &SessionBusChecker::default()) {
+            GateAction::Wait => ControlFlow::Continue,
+            GateAction::Probe | GateAction::Reprobe => {
+                probe_device_name();
+                // Refresh the tooltip with the (possibly new) name
+                let last_state = LAST_BATTERY_STATE.lock().ok().and_then(|s| *s);
+                if let Some(state) = last_state {
+                    update_tray_icon(&tray_icon_for_probe, state);
+                }
+                ControlFlow::Continue
+            }
+            GateAction::Done => ControlFlow::Break,
+        }
+    });
+
+    // Cleanup temp files every 10 minutes
+    let tray_icon_for_cleanup = tray_icon.clone();
+    glib::timeout_add_local(Duration::from_secs(600), move || {
+        cleanup_temp_files();
+        // Something else removed the displayed PNG; render it again
+        if current_icon_missing() {
+            let last_state = LAST_BATTERY_STATE.lock().ok().and_then(|mut s| s.take());
+            if let Some(state) = last_state {
+                eprintln!("[rivalcfg-tray] Displayed icon file disappeared, re-rendering it");
+                update_tray_icon(&tray_icon_for_cleanup, state);
+            }
+        }
+        ControlFlow::Continue
+    });
+
+    gtk::main();
+    
+    // Cleanup temp files on exit
+    cleanup_on_exit();
+    Ok(())
+}
+
+fn user_service_unit_path() -> anyhow::Result<PathBuf> {
+    let config = dirs::config_dir().ok_or_else(|| anyhow::anyhow!("No user config directory"))?;
+    Ok(service_unit_path(&config))
+}
+
+// `--install-service`: write a systemd user unit for this executable. Enabling it
+// is left to the user.
+fn install_service() -> anyhow::Result<()> {
+    let exe = env::current_exe()?;
+    let path = user_service_unit_path()?;
+    if write_if_changed(&path, &render_service_unit(&exe.to_string_lossy()))? {
+        println!("Wrote {}", path.display());
+    } else {
+        println!("{} is already up to date", path.display());
     }
+    println!("To start the tray with your session, run:");
+    println!("  systemctl --user daemon-reload");
+    println!("  systemctl --user enable --now rivalcfg-tray");
+    Ok(())
+}
 
-    eprintln!("[rivalcfg-tray] Successfully created PNG: {}", temp_path.display());
-    
-    // Keep the temp file around by leaking it
-    std::mem::forget(temp_file);
-    
-    let png_path_str = temp_path.to_str()?.to_string();
-    
-    // Update cache
-    if let Ok(mut cache) = PNG_CACHE.lock() {
-        cache.insert(cache_key, (png_path_str.clone(), svg_modified));
+// `--uninstall-service`: remove the unit written by --install-service
+fn uninstall_service() -> anyhow::Result<()> {
+    let path = user_service_unit_path()?;
+    if !remove_service_unit(&path)? {
+        println!("No service installed at {}", path.display());
+        return Ok(());
     }
-    
-    Some(png_path_str)
+    println!("Removed {}", path.display());
+    println!("If it was enabled, run `systemctl --user disable --now rivalcfg-tray` and");
+    println!("`systemctl --user daemon-reload` to stop it.");
+    Ok(())
 }
 
-// Recolor an SVG by parsing its XML and replacing fill/stroke/style fill values with `color_hex`.
-// Returns a temp file PathBuf containing the modified SVG on success.
-fn recolor_svg_to_temp(original_svg: &PathBuf, color_hex: &str) -> Option<PathBuf> {
-    use std::fs;
-    use xmltree::Element;
-
-    let data = fs::read_to_string(original_svg).ok()?;
-    let mut root = Element::parse(data.as_bytes()).ok()?;
-
-    fn recurse(elem: &mut xmltree::Element, color: &str) {
-        // Check attributes fill and stroke
-        if let Some(fill) = elem.attributes.get_mut("fill") {
-            if !fill.trim().is_empty() && fill.trim().to_lowercase() != "none" && !fill.trim().starts_with("url(") {
-                *fill = color.to_string();
-            }
-        }
-        if let Some(stroke) = elem.attributes.get_mut("stroke") {
-            if !stroke.trim().is_empty() && stroke.trim().to_lowercase() != "none" && !stroke.trim().starts_with("url(") {
-                *stroke = color.to_string();
-            }
-        }
-        // Handle style attribute (e.g., "fill:#000;stroke:none")
-        if let Some(style) = elem.attributes.get_mut("style") {
-            let mut parts: Vec<String> = style.split(';').map(|s| s.to_string()).collect();
-            for p in parts.iter_mut() {
-                if let Some(colon_pos) = p.find(':') {
-                    let (k, v) = p.split_at(colon_pos);
-                    let key = k.trim();
-                    let val = v[1..].trim();
-                    if key == "fill" || key == "stroke" {
-                        if !val.is_empty() && val.to_lowercase() != "none" && !val.starts_with("url(") {
-                            *p = format!("{}:{}", key, color);
-                        }
-                    }
-                }
-            }
-            *style = parts.into_iter().filter(|s| !s.is_empty()).collect::<Vec<_>>().join(";");
+// Run a single battery poll on a worker thread and apply the result on the main loop
+fn poll_battery_in_background(tray_icon: TrayIcon) {
+    use std::sync::mpsc::{self, TryRecvError};
+
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        // an explicit refresh always asks the mouse
+        BATTERY_POLLER.invalidate();
+        let _ = tx.send(BATTERY_POLLER.poll_retrying());
+    });
+    glib::timeout_add_local(Duration::from_millis(100), move || match rx.try_recv() {
+        Ok(reading) => {
+            update_tray_icon(&tray_icon, BatteryState::from_result(reading));
+            ControlFlow::Break
+        }
+        Err(TryRecvError::Empty) => ControlFlow::Continue,
+        Err(TryRecvError::Disconnected) => ControlFlow::Break,
+    });
+}
+
+// Whether the tray pushes the saved settings to the mouse when it starts
+fn applies_on_startup(s: &Settings) -> bool {
+    s.auto_apply_on_startup.unwrap_or(true)
+}
+
+// "Re-apply Current Settings" is available when saved settings have something to
+// push and no earlier re-apply is still running.
+fn reapply_available(settings: Option<&Settings>, in_flight: bool) -> bool {
+    !in_flight && settings.is_some_and(|s| !build_rivalcfg_args(s).is_empty())
+}
+
+fn refresh_reapply_item() {
+    let in_flight = APPLY_IN_FLIGHT.lock().map(|f| *f).unwrap_or(false);
+    let enabled = reapply_available(load_settings().as_ref(), in_flight);
+    MENU.with(|cell| {
+        if let Some(handles) = cell.borrow().as_ref() {
+            handles.reapply.set_enabled(enabled);
         }
+    });
+}
+
+// Run the saved settings through rivalcfg on a worker thread; the menu item stays
+// disabled until the run finishes.
+fn reapply_current_settings(runner: Arc<dyn CommandRunner>) {
+    use gtk::prelude::*;
+    use std::sync::mpsc::{self, TryRecvError};
 
-        // Recurse into children
-        for child in elem.children.iter_mut() {
-            if let xmltree::XMLNode::Element(e) = child {
-                recurse(e, color);
+    let Some(settings) = load_settings() else {
+        return;
+    };
+    match APPLY_IN_FLIGHT.lock() {
+        Ok(mut in_flight) => {
+            if !reapply_available(Some(&settings), *in_flight) {
+                return;
             }
+            *in_flight = true;
         }
+        Err(_) => return,
     }
+    refresh_reapply_item();
+    eprintln!("[rivalcfg-tray] Re-applying saved settings: {:?}", build_rivalcfg_args(&settings));
 
-    recurse(&mut root, color_hex);
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(apply_settings(runner.as_ref(), &settings));
+    });
+    glib::timeout_add_local(Duration::from_millis(100), move || {
+        let out = match rx.try_recv() {
+            Ok(out) => out,
+            Err(TryRecvError::Empty) => return ControlFlow::Continue,
+            Err(TryRecvError::Disconnected) => None,
+        };
+        if let Ok(mut in_flight) = APPLY_IN_FLIGHT.lock() {
+            *in_flight = false;
+        }
+        refresh_reapply_item();
+        match out {
+            Some(out) if !out.success => {
+                eprintln!("[rivalcfg-tray] Failed to re-apply settings: {}", out.stderr);
+                let dialog = gtk::MessageDialog::new(
+                    None::<&gtk::Window>,
+                    gtk::DialogFlags::MODAL,
+                    gtk::MessageType::Error,
+                    gtk::ButtonsType::Ok,
+                    &format!("Error running the command: {}", out.stderr),
+                );
+                dialog.run();
+                unsafe {
+                    dialog.destroy();
+                }
+            }
+            Some(_) => eprintln!("[rivalcfg-tray] Settings re-applied"),
+            None => {}
+        }
+        ControlFlow::Break
+    });
+}
 
-    // Create a stable temporary svg path under the system temp dir so rsvg-convert can read it
-    let nanos = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .ok()
-        .map(|d| d.as_nanos())
-        .unwrap_or_else(|| 0);
-    let tmp_path = std::env::temp_dir().join(format!("rivalcfg-recolor-{}.svg", nanos));
-    // Write XML into the temp file
-    let mut buf: Vec<u8> = Vec::new();
-    if root.write(&mut buf).is_err() {
-        return None;
+// Replace the Profiles submenu entries with the saved profiles. Returns the menu
+// item id of each profile entry.
+fn rebuild_profiles_submenu(submenu: &Submenu) -> HashMap<tray_icon::menu::MenuId, String> {
+    while submenu.remove_at(0).is_some() {}
+    let mut items = HashMap::new();
+    let names = list_profiles();
+    if names.is_empty() {
+        let _ = submenu.append(&MenuItem::new("No saved profiles", false, None));
     }
-    if std::fs::write(&tmp_path, &buf).is_err() {
-        eprintln!("[rivalcfg-tray] Failed to write recolored SVG to {}", tmp_path.display());
-        return None;
+    for name in names {
+        let item = MenuItem::new(&name, true, None);
+        if submenu.append(&item).is_ok() {
+            items.insert(item.id().clone(), name);
+        }
     }
-    Some(tmp_path)
+    items
 }
-use tray_icon::{TrayIcon, TrayIconBuilder, menu::{Menu, MenuItem, PredefinedMenuItem, Submenu, MenuEvent}};
-use tray_icon::Icon as TrayIconImage;
-use glib::ControlFlow;
-use std::path::PathBuf;
-// use std::process::Command; (moved to RealCommandRunner)
-use std::time::Duration;
 
-// command-runner related helpers are located in `cmd` module
-
-fn find_icon(name: &str) -> Option<PathBuf> {
-    let mut possible_paths = vec![
-        // Standard freedesktop.org icon theme directories (where PKGBUILD installs icons)
-        PathBuf::from(format!("/usr/share/icons/hicolor/scalable/apps/{}", name)),
-        PathBuf::from(format!("/usr/share/icons/hicolor/symbolic/apps/{}", name)),
-        // Check size-specific directories (16x16, 22x22, 24x24, 32x32, 48x48, 64x64, 128x128, 256x256)
-        PathBuf::from(format!("/usr/share/icons/hicolor/16x16/apps/{}", name)),
-        PathBuf::from(format!("/usr/share/icons/hicolor/22x22/apps/{}", name)),
-        PathBuf::from(format!("/usr/share/icons/hicolor/24x24/apps/{}", name)),
-        PathBuf::from(format!("/usr/share/icons/hicolor/32x32/apps/{}", name)),
-        PathBuf::from(format!("/usr/share/icons/hicolor/48x48/apps/{}", name)),
-        PathBuf::from(format!("/usr/share/icons/hicolor/64x64/apps/{}", name)),
-        PathBuf::from(format!("/usr/share/icons/hicolor/128x128/apps/{}", name)),
-        PathBuf::from(format!("/usr/share/icons/hicolor/256x256/apps/{}", name)),
-        // Current directory (for development/testing)
-        PathBuf::from(format!("icons/{}", name)),
-        // Executable directory relative
-        PathBuf::from(format!("bin/icons/{}", name)),
-        // Flatpak directories
-        PathBuf::from(format!("/app/bin/icons/{}", name)),
-        PathBuf::from(format!("/app/share/icons/rivalcfgtray/{}", name)),
-        PathBuf::from(format!("/app/share/icons/hicolor/scalable/apps/{}", name)),
-        // System-wide installation (legacy path)
-        PathBuf::from(format!("/usr/share/rivalcfgtray/icons/{}", name)),
-    ];
-    
-    // Also try relative to the executable
-    if let Ok(exe) = std::env::current_exe() {
-        if let Some(exe_dir) = exe.parent() {
-            possible_paths.push(exe_dir.join("icons").join(name));
-            // Try one directory up
-            if let Some(parent) = exe_dir.parent() {
-                possible_paths.push(parent.join("icons").join(name));
-                possible_paths.push(parent.join("share").join("icons").join("rivalcfgtray").join(name));
+// Run an action forwarded from the command line / desktop file
+fn dispatch_action(action: &TrayAction, runner: Arc<dyn CommandRunner>, tray_icon: &TrayIcon) {
+    eprintln!("[rivalcfg-tray] Running action: {}", action.id());
+    match action {
+        TrayAction::OpenConfig => open_config_dialog(runner, tray_icon.clone(), current_mouse_name()),
+        TrayAction::RefreshBattery => poll_battery_in_background(tray_icon.clone()),
+        TrayAction::SwitchProfile(name) => activate_profile(runner.as_ref(), name),
+        TrayAction::ShowError(_) => {
+            let errors = NOTIFIED_ERRORS.lock().map(|e| e.clone()).unwrap_or_default();
+            match resolve_click(action.clone(), &errors) {
+                ClickTarget::ErrorDialog(message) => show_error_dialog(&message),
+                // the error is from before a restart; the settings are the next best thing
+                ClickTarget::Action(_) => open_config_dialog(runner, tray_icon.clone(), current_mouse_name()),
             }
         }
     }
-    
-    // Try relative to the current working directory with more parent directories
-    let mut current = std::env::current_dir().ok();
-    while let Some(dir) = current {
-        possible_paths.push(dir.join("icons").join(name));
-        current = dir.parent().map(|p| p.to_path_buf());
-    };
+}
 
-    for path in &possible_paths {
-        if path.exists() {
-            eprintln!("[rivalcfg-tray] Found icon at: {}", path.display());
-            return Some(path.clone());
-        }
+// Installed desktop entry; its actions are regenerated into the user's copy
+const DESKTOP_FILE_TEMPLATE: &str = include_str!("../rivalcfg-tray.desktop");
+
+// The packaged desktop file, from the first $XDG_DATA_DIRS entry that has one
+fn installed_desktop_file() -> Option<PathBuf> {
+    let data_dirs = std::env::var("XDG_DATA_DIRS").ok().filter(|d| !d.is_empty());
+    data_dirs
+        .as_deref()
+        .unwrap_or("/usr/local/share:/usr/share")
+        .split(':')
+        .filter(|d| !d.is_empty())
+        .map(|d| PathBuf::from(d).join("applications").join("rivalcfg-tray.desktop"))
+        .find(|p| p.exists())
+}
+
+// With "desktop_actions" on, keep ~/.local/share/applications/rivalcfg-tray.desktop
+// listing the current actions (including one per profile) so desktop search can offer
+// them. The copy starts from the installed desktop file and keeps its Exec, since it
+// shadows that file. Only rewritten when the content changes.
+fn sync_desktop_actions() {
+    if !load_settings().and_then(|s| s.desktop_actions).unwrap_or(false) {
+        return;
     }
-    eprintln!("[rivalcfg-tray] Warning: Could not find icon '{}' in any of these locations:", name);
-    for path in &possible_paths {
-        eprintln!("[rivalcfg-tray]   - {}", path.display());
+    let Some(path) = dirs::data_dir().map(|d| d.join("applications").join("rivalcfg-tray.desktop")) else {
+        return;
+    };
+    let base = [installed_desktop_file(), Some(path.clone())]
+        .into_iter()
+        .flatten()
+        .find_map(|p| std::fs::read_to_string(p).ok())
+        .unwrap_or_else(|| DESKTOP_FILE_TEMPLATE.to_string());
+    let exec = desktop_exec(&base).unwrap_or("rivalcfg-tray").to_string();
+    let content = render_desktop_file(&base, &exec, &all_actions(&list_profiles()));
+    match write_if_changed(&path, &content) {
+        Ok(true) => eprintln!("[rivalcfg-tray] Updated desktop actions in {}", path.display()),
+        Ok(false) => {}
+        Err(e) => eprintln!("[rivalcfg-tray] Warning: Failed to update {}: {}", path.display(), e),
     }
-    None
 }
 
-fn battery_icon_path(level: u8) -> PathBuf {
-    // Determine prefix based on saved settings (light/dark/custom)
-    // Always use the base battery SVG names; recoloring (for dark/custom) is
-    // performed later in the SVG->PNG pipeline based on settings.
-    let prefix = "battery-";
-
-    let name = if level > 90 {
-        format!("{}100.svg", prefix)
-    } else if level > 74 {
-        format!("{}75.svg", prefix)
-    } else if level > 49 {
-        format!("{}50.svg", prefix)
-    } else if level > 24 {
-        format!("{}25.svg", prefix)
-    } else if level > 9 {
-        format!("{}warn.svg", prefix)
-    } else {
-        format!("{}0.svg", prefix)
+// Make profile `name` the active settings and push it to the mouse
+fn activate_profile(runner: &dyn CommandRunner, name: &str) {
+    let Some(profile) = load_profile(name) else {
+        eprintln!("[rivalcfg-tray] Profile '{}' no longer exists", name);
+        return;
     };
+    let settings = with_profile_applied(&load_settings().unwrap_or_default(), &profile);
+    if let Err(e) = save_settings(&settings) {
+        eprintln!("[rivalcfg-tray] Failed to save settings: {}", e);
+    }
+    eprintln!("[rivalcfg-tray] Applying profile '{}'", name);
+    if let Some(out) = apply_settings(runner, &settings)
+        && !out.success
+    {
+        eprintln!("[rivalcfg-tray] Failed to apply profile '{}': {}", name, out.stderr);
+        // Profiles are switched from the menu or desktop search; report without a modal dialog
+        report_apply_failure(&out.stderr);
+    }
+}
 
-    find_icon(&name).unwrap_or_else(|| PathBuf::from(format!("icons/{}", name)))
+// Tell the user about a failed apply from the tray menu or desktop search with a
+// notification whose click shows the full error
+fn report_apply_failure(stderr: &str) {
+    let error = format!("Error running the command: {}", stderr);
+    let Ok(mut errors) = NOTIFIED_ERRORS.lock() else {
+        return;
+    };
+    let token = remember_error(&mut errors, &error);
+    drop(errors);
+    send_notification(&tray_notifier(), NotifyEvent::ApplyFailed {
+        error: escape_for_display(stderr.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("")),
+        token,
+    });
 }
 
-fn composite_battery_charging_svg(
-    battery_svg: &PathBuf,
-    charging_svg: &PathBuf,
-) -> Option<PathBuf> {
-    use std::fs;
-    use std::io::Write;
+// DPI values offered in the tray's Sensitivity submenu
+const SENSITIVITY_PRESETS: [u32; 4] = [400, 800, 1600, 3200];
 
-    let battery_content = fs::read_to_string(battery_svg).ok()?;
-    let mut charging_src = fs::read_to_string(charging_svg).ok()?;
-    // Strip everything before the path element
-    if let Some(pos) = charging_src.find("<path") {
-        charging_src = charging_src[pos..].to_string();
+// The preset matching the saved sensitivity, if it is a single preset value
+fn active_sensitivity_preset(settings: &Settings) -> Option<u32> {
+    let value: u32 = settings.sensitivity.as_deref()?.trim().parse().ok()?;
+    SENSITIVITY_PRESETS.contains(&value).then_some(value)
+}
+
+// Apply a preset from the tray menu and save it as the sensitivity setting.
+// Returns whether rivalcfg accepted it.
+fn select_sensitivity_preset(runner: &dyn CommandRunner, value: u32) -> bool {
+    eprintln!("[rivalcfg-tray] Setting sensitivity to {} DPI", value);
+    let out = set_sensitivity(runner, value);
+    if !out.success {
+        eprintln!("[rivalcfg-tray] Failed to set sensitivity: {}", out.stderr);
+        report_apply_failure(&out.stderr);
+        return false;
     }
-    // Strip everything after the path element
-    if let Some(pos) = charging_src.rfind("</svg>") {
-        charging_src = charging_src[..pos].to_string();
+    let mut settings = load_settings().unwrap_or_default();
+    settings.sensitivity = Some(value.to_string());
+    // a preset sets both axes
+    settings.dual_sensitivity = None;
+    if let Err(e) = save_settings(&settings) {
+        eprintln!("[rivalcfg-tray] Failed to save settings: {}", e);
     }
+    true
+}
 
-    let charging_content = charging_src;
-
-    // Simple SVG overlay by inserting charging SVG into battery SVG
-    let composite_svg = battery_content.replace("</svg>", &format!("{}\n</svg>", charging_content));
+// Tick the preset that matches the saved settings (none for custom values)
+fn sync_sensitivity_items(items: &[(CheckMenuItem, u32)]) {
+    let active = load_settings().as_ref().and_then(active_sensitivity_preset);
+    for (item, value) in items {
+        item.set_checked(active == Some(*value));
+    }
+}
 
-    let mut tmp_path = env::temp_dir();
-    let file_stem = battery_svg
-        .file_stem()
-        .and_then(std::ffi::OsStr::to_str)
-        .unwrap_or("icon");
-    tmp_path.push(format!("{}_charging.svg", file_stem));
+fn show_error_dialog(message: &str) {
+    use gtk::prelude::*;
 
-    let mut file = fs::File::create(&tmp_path).ok()?;
-    file.write_all(composite_svg.as_bytes()).ok()?;
+    let dialog = gtk::MessageDialog::new(
+        None::<&gtk::Window>,
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Error,
+        gtk::ButtonsType::Ok,
+        message,
+    );
+    dialog.run();
+    unsafe {
+        dialog.destroy();
+    }
+}
 
-    Some(tmp_path)
+thread_local! {
+    // The battery poll timer currently running on the main loop
+    static POLL_SOURCE: std::cell::RefCell<Option<glib::SourceId>> = const { std::cell::RefCell::new(None) };
+    // Handles to the current tray menu's items, see build_menu
+    static MENU: std::cell::RefCell<Option<MenuHandles>> = const { std::cell::RefCell::new(None) };
 }
 
-fn main() -> anyhow::Result<()> {
-    gtk::init()?;
+// The tray menu and the items the event loop and updaters need. Looked up through
+// MENU so they keep working after the menu is rebuilt.
+struct MenuHandles {
+    menu: Menu,
+    // informational items in the menu, in order
+    info: Vec<InfoItem>,
+    device_name: Option<MenuItem>,
+    percent: Option<MenuItem>,
+    // the "≈ ... remaining" item, which is only in the menu while there is an estimate
+    remaining: Option<(MenuItem, bool)>,
+    status: Option<MenuItem>,
+    config: MenuItem,
+    reapply: MenuItem,
+    profiles_submenu: Submenu,
+    profile_items: HashMap<tray_icon::menu::MenuId, String>,
+    sensitivity_items: Vec<(CheckMenuItem, u32)>,
+    custom_sensitivity: MenuItem,
+    colour_switch: CheckMenuItem,
+    dark_mode: MenuItem,
+    light_mode: MenuItem,
+    auto_colour: MenuItem,
+    custom_colour: MenuItem,
+    quit: MenuItem,
+}
 
-    // Get initial battery status and mouse name
-    let (level, charging) = get_battery_level().unwrap_or((0, false));
-    let mouse_name = get_mouse_name().unwrap_or_else(|| "SteelSeries Mouse".to_string());
-    eprintln!(
-        "[rivalcfg-tray] Starting tray for device: {} with battery level: {}%, charging: {}",
-        mouse_name, level, charging
-    );
-    
-    // Create menu using tray-icon's menu system
+// Build the tray menu, showing the informational items `settings` asks for
+fn build_menu(settings: &Settings, state: &BatteryState) -> anyhow::Result<MenuHandles> {
     let menu = Menu::new();
-    
-    // Battery percentage item (non-clickable)
-    let percent_text = MenuItem::new(&format!("Battery: {}%", level), false, None);
-    menu.append(&percent_text)?;
-    
-    // Status item (non-clickable)
-    let status_text = MenuItem::new(
-        &format!("Status: {}", if charging { "Charging" } else { "Discharging" }),
-        false,
-        None
-    );
-    menu.append(&status_text)?;
-    
+    let info = shown_info_items(settings, state);
+
+    // Informational items (non-clickable)
+    let mut device_name = None;
+    let mut percent = None;
+    let mut remaining = None;
+    let mut status = None;
+    for item in &info {
+        match item {
+            InfoItem::DeviceName => {
+                let text = device_display_name(&current_mouse_name(), settings);
+                let entry = MenuItem::new(text, false, None);
+                menu.append(&entry)?;
+                device_name = Some(entry);
+            }
+            InfoItem::BatteryPercent => {
+                let entry = MenuItem::new(battery_menu_text(state), false, None);
+                menu.append(&entry)?;
+                percent = Some(entry);
+            }
+            // Added once there is an estimate, see set_remaining_item
+            InfoItem::TimeEstimate => remaining = Some((MenuItem::new("", false, None), false)),
+            InfoItem::Status => {
+                let entry = MenuItem::new(status_menu_text(state), false, None);
+                menu.append(&entry)?;
+                status = Some(entry);
+            }
+        }
+    }
+
     // Config button
-    let config_button = MenuItem::new("Config", true, None);
-    menu.append(&config_button)?;
-    
+    let config = MenuItem::new("Config", true, None);
+    menu.append(&config)?;
+
+    // Push the saved settings to the mouse again, e.g. after it reconnected
+    let in_flight = APPLY_IN_FLIGHT.lock().map(|f| *f).unwrap_or(false);
+    let reapply = MenuItem::new("Re-apply Current Settings", reapply_available(Some(settings), in_flight), None);
+    menu.append(&reapply)?;
+
+    // Saved profiles; selecting one applies it
+    let profiles_submenu = Submenu::new("Profiles", true);
+    let profile_items = rebuild_profiles_submenu(&profiles_submenu);
+    menu.append(&profiles_submenu)?;
+
+    // One-click DPI presets; Custom… opens the config window
+    let sensitivity_submenu = Submenu::new("Sensitivity", true);
+    let mut sensitivity_items = Vec::new();
+    for value in SENSITIVITY_PRESETS {
+        let item = CheckMenuItem::new(format!("{} DPI", value), true, false, None);
+        sensitivity_submenu.append(&item)?;
+        sensitivity_items.push((item, value));
+    }
+    sync_sensitivity_items(&sensitivity_items);
+    sensitivity_submenu.append(&PredefinedMenuItem::separator())?;
+    let custom_sensitivity = MenuItem::new("Custom…", true, None);
+    sensitivity_submenu.append(&custom_sensitivity)?;
+    menu.append(&sensitivity_submenu)?;
+
     // Separator
     menu.append(&PredefinedMenuItem::separator())?;
-    
+
     // Icon Colour Switch submenu
     let colour_switch_submenu = Submenu::new("Icon Colour Switch", true);
-    let dark_mode_item = MenuItem::new("Dark Mode (default)", true, None);
-    let light_mode_item = MenuItem::new("Light Mode", true, None);
-    let custom_colour_item = MenuItem::new("Custom Colour...", true, None);
-    colour_switch_submenu.append(&dark_mode_item)?;
-    colour_switch_submenu.append(&light_mode_item)?;
-    colour_switch_submenu.append(&custom_colour_item)?;
+    // Quick toggle between white (on) and black (off) icons; kept in sync with the items below
+    let colour_switch = CheckMenuItem::new(
+        "White Icons",
+        true,
+        settings.colour_mode.as_deref() == Some("dark"),
+        None,
+    );
+    colour_switch_submenu.append(&colour_switch)?;
+    colour_switch_submenu.append(&PredefinedMenuItem::separator())?;
+    let dark_mode = MenuItem::new("Dark Mode (default)", true, None);
+    let light_mode = MenuItem::new("Light Mode", true, None);
+    let auto_colour = MenuItem::new("Follow System Theme", true, None);
+    let custom_colour = MenuItem::new("Custom Colour...", true, None);
+    colour_switch_submenu.append(&dark_mode)?;
+    colour_switch_submenu.append(&light_mode)?;
+    colour_switch_submenu.append(&auto_colour)?;
+    colour_switch_submenu.append(&custom_colour)?;
     menu.append(&colour_switch_submenu)?;
-    
+
     // Separator
     menu.append(&PredefinedMenuItem::separator())?;
-    
+
     // Quit button
-    let quit_button = MenuItem::new("Quit", true, None);
-    menu.append(&quit_button)?;
-    
-    // Build the tray icon
-    let tray_icon = TrayIconBuilder::new()
-        .with_menu(Box::new(menu))
-        .with_tooltip(&format!("Battery: {}%", level))
-        .build()?;
+    let quit = MenuItem::new("Quit", true, None);
+    menu.append(&quit)?;
 
-    // Create a shared command runner and apply any saved settings on startup
-    let runner: Arc<dyn CommandRunner> = Arc::new(RealCommandRunner::default());
-    if let Some(s) = load_settings() {
-        let args = build_rivalcfg_args(&s);
-        if !args.is_empty() {
-            eprintln!("[rivalcfg-tray] Applying saved settings on startup: {:?}", &args);
-            let slices = args.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
-            let out = runner.run("rivalcfg", &slices);
-            if !out.success {
-                eprintln!("[rivalcfg-tray] Failed to apply saved settings: {}", out.stderr);
-            }
+    Ok(MenuHandles {
+        menu,
+        info,
+        device_name,
+        percent,
+        remaining,
+        status,
+        config,
+        reapply,
+        profiles_submenu,
+        profile_items,
+        sensitivity_items,
+        custom_sensitivity,
+        colour_switch,
+        dark_mode,
+        light_mode,
+        auto_colour,
+        custom_colour,
+        quit,
+    })
+}
+
+// Swap in a new tray menu when the informational items to show have changed
+fn rebuild_menu_if_changed(tray_icon: &TrayIcon, state: &BatteryState, settings: &Settings) {
+    let wanted = shown_info_items(settings, state);
+    let changed = MENU.with(|cell| cell.borrow().as_ref().is_some_and(|h| h.info != wanted));
+    if !changed {
+        return;
+    }
+    match build_menu(settings, state) {
+        Ok(handles) => {
+            eprintln!("[rivalcfg-tray] Rebuilding the tray menu");
+            tray_icon.set_menu(Some(Box::new(handles.menu.clone())));
+            MENU.with(|cell| cell.replace(Some(handles)));
         }
+        Err(e) => eprintln!("[rivalcfg-tray] Warning: Failed to rebuild the tray menu: {}", e),
     }
+}
 
-    generate_tray_icon(&tray_icon);
+// Refresh the device name, percentage and status items that are in the menu
+fn update_info_items(display_name: &str, state: &BatteryState) {
+    MENU.with(|cell| {
+        let Some(handles) = cell.borrow().as_ref().map(|h| (h.device_name.clone(), h.percent.clone(), h.status.clone()))
+        else {
+            return;
+        };
+        if let Some(item) = handles.0 {
+            item.set_text(display_name);
+        }
+        if let Some(item) = handles.1 {
+            item.set_text(battery_menu_text(state));
+        }
+        if let Some(item) = handles.2 {
+            item.set_text(status_menu_text(state));
+        }
+    });
+}
 
-    // Store references for menu event handling
-    let runner_for_ui = runner.clone();
-    let tray_icon_for_config = tray_icon.clone();
-    let tray_icon_for_dark = tray_icon.clone();
-    let tray_icon_for_light = tray_icon.clone();
-    let tray_icon_for_custom = tray_icon.clone();
-    let tray_icon_for_timer = tray_icon.clone();
-    
-    // Get menu item IDs for event handling
-    let quit_button_id = quit_button.id().clone();
-    let config_button_id = config_button.id().clone();
-    let dark_mode_id = dark_mode_item.id().clone();
-    let light_mode_id = light_mode_item.id().clone();
-    let custom_colour_id = custom_colour_item.id().clone();
-    
-    // Handle menu events using glib's idle_add
-    let menu_channel = MenuEvent::receiver();
-    glib::idle_add_local(move || {
-        if let Ok(event) = menu_channel.try_recv() {
-            if event.id == quit_button_id {
-                cleanup_temp_files();
-                gtk::main_quit();
-            } else if event.id == config_button_id {
-                // Handle config dialog
-                open_config_dialog(runner_for_ui.clone(), tray_icon_for_config.clone(), mouse_name.clone());
-            } else if event.id == dark_mode_id {
-                handle_dark_mode(tray_icon_for_dark.clone());
-            } else if event.id == light_mode_id {
-                handle_light_mode(tray_icon_for_light.clone());
-            } else if event.id == custom_colour_id {
-                handle_custom_colour(tray_icon_for_custom.clone());
+// Show `text` in the time remaining item right under the percentage, or take the
+// item out of the menu when there is no estimate
+fn set_remaining_item(text: Option<String>) {
+    MENU.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let Some(handles) = cell.as_mut() else {
+            return;
+        };
+        let position = handles.info.iter().position(|i| *i == InfoItem::TimeEstimate).unwrap_or(0);
+        let Some((item, shown)) = handles.remaining.as_mut() else {
+            return;
+        };
+        match text {
+            Some(text) => {
+                item.set_text(text);
+                if !*shown {
+                    *shown = handles.menu.insert(item, position).is_ok();
+                }
             }
+            None if *shown => {
+                *shown = handles.menu.remove(item).is_err();
+            }
+            None => {}
         }
-        ControlFlow::Continue
     });
+}
 
-    // Update icon every 30 seconds
-    glib::timeout_add_local(Duration::from_secs(30), move || {
-        let (level, _charging) = generate_tray_icon(&tray_icon_for_timer).unwrap_or((0, false));
-        let _ = tray_icon_for_timer.set_tooltip(Some(&format!("Battery: {}%", level)));
-        ControlFlow::Continue
+// Whether a click on the tray icon itself should open the config window: a
+// released left click
+fn opens_config_window(event: &TrayIconEvent) -> bool {
+    matches!(
+        event,
+        TrayIconEvent::Click {
+            button: MouseButton::Left,
+            button_state: MouseButtonState::Up,
+            ..
+        }
+    )
+}
+
+// What a tray menu click asks for
+enum MenuCommand {
+    Quit,
+    Config,
+    ColourSwitch(bool),
+    DarkMode,
+    LightMode,
+    AutoColour,
+    CustomColour,
+    Reapply,
+    Profile(String),
+    CustomSensitivity,
+    SensitivityPreset(u32),
+}
+
+// Look up the clicked item in the current menu. The handlers open dialogs with
+// nested main loops, so MENU is not kept borrowed while they run.
+fn menu_command(id: &tray_icon::menu::MenuId) -> Option<MenuCommand> {
+    MENU.with(|cell| {
+        let cell = cell.borrow();
+        let h = cell.as_ref()?;
+        if id == h.quit.id() {
+            Some(MenuCommand::Quit)
+        } else if id == h.config.id() {
+            Some(MenuCommand::Config)
+        } else if id == h.colour_switch.id() {
+            Some(MenuCommand::ColourSwitch(h.colour_switch.is_checked()))
+        } else if id == h.dark_mode.id() {
+            Some(MenuCommand::DarkMode)
+        } else if id == h.light_mode.id() {
+            Some(MenuCommand::LightMode)
+        } else if id == h.auto_colour.id() {
+            Some(MenuCommand::AutoColour)
+        } else if id == h.custom_colour.id() {
+            Some(MenuCommand::CustomColour)
+        } else if id == h.reapply.id() {
+            Some(MenuCommand::Reapply)
+        } else if let Some(name) = h.profile_items.get(id) {
+            Some(MenuCommand::Profile(name.clone()))
+        } else if id == h.custom_sensitivity.id() {
+            Some(MenuCommand::CustomSensitivity)
+        } else {
+            h.sensitivity_items
+                .iter()
+                .find(|(item, _)| item.id() == id)
+                .map(|(_, value)| MenuCommand::SensitivityPreset(*value))
+        }
+    })
+}
+
+fn set_colour_switch_checked(checked: bool) {
+    MENU.with(|cell| {
+        if let Some(handles) = cell.borrow().as_ref() {
+            handles.colour_switch.set_checked(checked);
+        }
     });
+}
 
-    // Cleanup temp files every 10 minutes
-    glib::timeout_add_local(Duration::from_secs(600), move || {
-        cleanup_temp_files();
+// (Re)create the battery poll timer, destroying the previous one if it still exists
+fn restart_battery_poll(tray_icon: TrayIcon) {
+    // a read stuck on its worker must not keep the new timer from polling
+    POLL_IN_FLIGHT.store(false, Ordering::SeqCst);
+    let new = schedule_battery_poll(tray_icon);
+    if let Some(old) = POLL_SOURCE.with(|s| s.replace(Some(new)))
+        // The stalled source may already be gone; only destroy it if it still exists
+        && let Some(src) = glib::MainContext::default().find_source_by_id(&old)
+    {
+        src.destroy();
+    }
+}
+
+// Start the periodic battery poll that refreshes the icon and menu labels, at the
+// configured interval or one adapted to the last reading (see poll_interval_for).
+// Each read runs on a worker thread with retries, and a tick is skipped while the
+// previous read is still running. Every completed cycle records a heartbeat for the
+// watchdog. After each poll the timer moves to a new interval if the reading or the
+// settings call for one; Apply restarts it right away.
+//
+// The temp-file cleanup runs on its own fixed 10 minute timer. A short poll interval
+// doesn't pile up PNGs in between, since renders of an unchanged state reuse the
+// cached PNG, and cleanup never deletes the one on display.
+fn schedule_battery_poll(tray_icon: TrayIcon) -> glib::SourceId {
+    let last = LAST_BATTERY_STATE.lock().ok().and_then(|s| *s);
+    let interval = poll_interval_for(&load_settings().unwrap_or_default(), &current_mouse_name(), last.as_ref());
+    eprintln!("[rivalcfg-tray] Polling the battery every {}s", interval.as_secs());
+    if let Ok(mut w) = POLL_WATCHDOG.lock() {
+        w.set_interval(interval);
+    }
+    glib::timeout_add_local(interval, move || {
+        if POLL_IN_FLIGHT.swap(true, Ordering::SeqCst) {
+            return ControlFlow::Continue;
+        }
+        let tray_icon_for_next = tray_icon.clone();
+        generate_tray_icon_in_background(tray_icon.clone(), move |state, settings| {
+            POLL_IN_FLIGHT.store(false, Ordering::SeqCst);
+            record_battery_history(settings, &state, SystemTime::now());
+            if let Ok(mut w) = POLL_WATCHDOG.lock() {
+                w.heartbeat(Instant::now());
+            }
+            // Pick up a changed interval: hand over to a new timer, which stops this one
+            if rescheduled_poll_interval(interval, settings, &current_mouse_name(), Some(&state)).is_some() {
+                restart_battery_poll(tray_icon_for_next);
+            }
+        });
         ControlFlow::Continue
-    });
+    })
+}
 
-    gtk::main();
-    
-    // Cleanup temp files on exit
-    cleanup_temp_files();
-    Ok(())
+// After the icon colour settings changed: drop the PNGs rendered in the old colour and
+// force a redraw even if the battery state is unchanged
+fn redraw_in_new_colour(tray_icon: &TrayIcon) {
+    purge_recoloured_pngs();
+    force_icon_refresh();
+    generate_tray_icon(tray_icon);
+}
+
+// Helper function to handle the white/black colour switch toggle
+fn handle_colour_switch(tray_icon: TrayIcon, on: bool) {
+    if on {
+        handle_dark_mode(tray_icon);
+    } else {
+        handle_light_mode(tray_icon);
+    }
 }
 
 // Helper function to handle dark mode selection
@@ -695,13 +4205,18 @@ fn handle_dark_mode(tray_icon: TrayIcon) {
     if let Err(e) = save_settings(&settings) {
         eprintln!("[rivalcfg-tray] Failed to save colour setting: {}", e);
     }
-    // Force regeneration even if battery state is unchanged
-    if let Ok(mut last) = LAST_BATTERY_STATE.lock() {
-        *last = None;
-    }
-    if let Some((level, _charging)) = generate_tray_icon(&tray_icon) {
-        let _ = tray_icon.set_tooltip(Some(&format!("Battery: {}%", level)));
+    redraw_in_new_colour(&tray_icon);
+}
+
+// Helper function to handle the follow-the-theme selection
+fn handle_auto_colour_mode(tray_icon: TrayIcon) {
+    let mut settings = load_settings().unwrap_or_default();
+    settings.colour_mode = Some("auto".to_string());
+    settings.custom_color = None;
+    if let Err(e) = save_settings(&settings) {
+        eprintln!("[rivalcfg-tray] Failed to save colour setting: {}", e);
     }
+    redraw_in_new_colour(&tray_icon);
 }
 
 // Helper function to handle light mode selection
@@ -712,13 +4227,7 @@ fn handle_light_mode(tray_icon: TrayIcon) {
     if let Err(e) = save_settings(&settings) {
         eprintln!("[rivalcfg-tray] Failed to save colour setting: {}", e);
     }
-    // Force regeneration even if battery state is unchanged
-    if let Ok(mut last) = LAST_BATTERY_STATE.lock() {
-        *last = None;
-    }
-    if let Some((level, _charging)) = generate_tray_icon(&tray_icon) {
-        let _ = tray_icon.set_tooltip(Some(&format!("Battery: {}%", level)));
-    }
+    redraw_in_new_colour(&tray_icon);
 }
 
 // Helper function to handle custom colour selection
@@ -750,12 +4259,7 @@ fn handle_custom_colour(tray_icon: TrayIcon) {
             if let Err(e) = save_settings(&settings) {
                 eprintln!("[rivalcfg-tray] Failed to save custom colour: {}", e);
             }
-            if let Ok(mut last) = LAST_BATTERY_STATE.lock() {
-                *last = None;
-            }
-            if let Some((level, _charging)) = generate_tray_icon(&tray_icon) {
-                let _ = tray_icon.set_tooltip(Some(&format!("Battery: {}%", level)));
-            }
+            redraw_in_new_colour(&tray_icon);
         }
         dlg.close();
     });
@@ -763,6 +4267,58 @@ fn handle_custom_colour(tray_icon: TrayIcon) {
     dialog.show_all();
 }
 
+// "X DPI" and "Y DPI" spin buttons for devices that take `--sensitivity X,Y`
+#[derive(Clone)]
+struct DualSensitivityEntry {
+    container: gtk::Box,
+    x_spin: gtk::SpinButton,
+    y_spin: gtk::SpinButton,
+}
+
+impl DualSensitivityEntry {
+    fn new() -> Self {
+        use gtk::prelude::*;
+        let container = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        let spin = || {
+            gtk::SpinButton::new(
+                Some(&gtk::Adjustment::new(800.0, 100.0, 16000.0, 100.0, 500.0, 0.0)),
+                1.0,
+                0,
+            )
+        };
+        let (x_spin, y_spin) = (spin(), spin());
+        container.pack_start(&gtk::Label::new(Some("X DPI:")), false, false, 0);
+        container.pack_start(&x_spin, true, true, 0);
+        container.pack_start(&gtk::Label::new(Some("Y DPI:")), false, false, 0);
+        container.pack_start(&y_spin, true, true, 0);
+        Self { container, x_spin, y_spin }
+    }
+
+    fn widget(&self) -> &gtk::Box {
+        &self.container
+    }
+
+    fn value(&self) -> (u32, u32) {
+        use gtk::prelude::*;
+        (self.x_spin.value_as_int() as u32, self.y_spin.value_as_int() as u32)
+    }
+
+    fn set_value(&self, (x, y): (u32, u32)) {
+        use gtk::prelude::*;
+        self.x_spin.set_value(x as f64);
+        self.y_spin.set_value(y as f64);
+    }
+
+    // `f` runs when either axis changes
+    fn connect_value_changed(&self, f: impl Fn() + Clone + 'static) {
+        use gtk::prelude::*;
+        for spin in [&self.x_spin, &self.y_spin] {
+            let f = f.clone();
+            spin.connect_value_changed(move |_| f());
+        }
+    }
+}
+
 // Helper function to handle config dialog
 fn open_config_dialog(runner: Arc<dyn CommandRunner>, tray_icon: TrayIcon, mouse_name: String) {
         use gtk::prelude::*;
@@ -782,20 +4338,49 @@ fn open_config_dialog(runner: Arc<dyn CommandRunner>, tray_icon: TrayIcon, mouse
         vbox.set_margin_start(10);
         vbox.set_margin_end(10);
 
+        // Layout note: horizontal GtkBox rows mirror pack_start/pack_end automatically in
+        // RTL locales, so rows are packed label-first with pack_start and any explicit
+        // alignment uses Align::Start/End (never absolute left/right) to follow the
+        // widget's text direction. Wrapped labels also need start_justification.
         let title = Label::new(Some("SteelSeries Mouse Configuration"));
         title.set_markup("<span size='large'><b>SteelSeries Mouse Configuration</b></span>");
         vbox.pack_start(&title, false, false, 0);
 
+        // Which mouse this is, and its firmware (selectable so it can be copied into reports)
+        let device_label = Label::new(Some(&device_display_name(&mouse_name, &load_settings().unwrap_or_default())));
+        device_label.set_halign(gtk::Align::Start);
+        vbox.pack_start(&device_label, false, false, 0);
+        let firmware = get_firmware_version_with_runner(runner.as_ref());
+        let firmware_label = Label::new(Some(&firmware_label_text(firmware.as_deref())));
+        firmware_label.set_halign(gtk::Align::Start);
+        firmware_label.set_selectable(true);
+        vbox.pack_start(&firmware_label, false, false, 0);
+
         // Battery level
         let battery_label = Label::new(Some("Battery Level: N/A"));
+        battery_label.set_halign(gtk::Align::Start);
+        // a wired mouse has no battery to report
+        battery_label.set_no_show_all(NO_BATTERY.load(Ordering::SeqCst));
         vbox.pack_start(&battery_label, false, false, 0);
 
         // Sensitivity (DPI)
         let sens_box = GtkBox::new(Orientation::Horizontal, 4);
         sens_box.pack_start(&Label::new(Some("Sensitivity (DPI):")), false, false, 0);
-        let sensitivity_entry = Entry::new();
-        sens_box.pack_start(&sensitivity_entry, true, true, 0);
+        let sensitivity_spin = gtk::SpinButton::new(
+            Some(&gtk::Adjustment::new(800.0, 100.0, 16000.0, 100.0, 500.0, 0.0)),
+            1.0,
+            0,
+        );
+        sens_box.pack_start(&sensitivity_spin, true, true, 0);
         vbox.pack_start(&sens_box, false, false, 0);
+        // per-axis devices get separate X and Y values instead
+        let dual_mode = device_supports_dual_sensitivity();
+        let dual_sensitivity = DualSensitivityEntry::new();
+        vbox.pack_start(dual_sensitivity.widget(), false, false, 0);
+        sens_box.set_no_show_all(dual_mode);
+        dual_sensitivity.widget().set_no_show_all(!dual_mode);
+        // set once the user changes the DPI; the spin button's default is not a choice
+        let sensitivity_edited = Rc::new(std::cell::Cell::new(false));
 
         // Polling rate
         let poll_box = GtkBox::new(Orientation::Horizontal, 4);
@@ -823,6 +4408,154 @@ fn open_config_dialog(runner: Arc<dyn CommandRunner>, tray_icon: TrayIcon, mouse
         dim_box.pack_start(&dim_timer_entry, true, true, 0);
         vbox.pack_start(&dim_box, false, false, 0);
 
+        // Low battery attention threshold
+        let attention_box = GtkBox::new(Orientation::Horizontal, 4);
+        attention_box.pack_start(&Label::new(Some("Low Battery Warning (%):")), false, false, 0);
+        let attention_spin = gtk::SpinButton::with_range(0.0, 50.0, 1.0);
+        attention_spin.set_value(DEFAULT_ATTENTION_THRESHOLD as f64);
+        attention_spin.set_tooltip_text(Some("Show a red tray icon below this level while discharging (0 disables)"));
+        attention_box.pack_start(&attention_spin, true, true, 0);
+        vbox.pack_start(&attention_box, false, false, 0);
+
+        // How often the battery is read; a device override below takes precedence
+        let poll_interval_box = GtkBox::new(Orientation::Horizontal, 4);
+        poll_interval_box.pack_start(&Label::new(Some("Battery Poll Interval (s):")), false, false, 0);
+        let poll_interval_spin = gtk::SpinButton::with_range(
+            MIN_POLL_INTERVAL_SECS as f64,
+            MAX_POLL_INTERVAL_SECS as f64,
+            5.0,
+        );
+        poll_interval_spin.set_value(POLL_INTERVAL.as_secs() as f64);
+        poll_interval_box.pack_start(&poll_interval_spin, true, true, 0);
+        // adaptive polling reads more often when the battery is low or charging
+        let adaptive_poll_check = gtk::CheckButton::with_label("Adapt to battery level");
+        adaptive_poll_check.set_active(true);
+        poll_interval_spin.set_sensitive(false);
+        {
+            let poll_interval_spin = poll_interval_spin.clone();
+            adaptive_poll_check.connect_toggled(move |check| poll_interval_spin.set_sensitive(!check.is_active()));
+        }
+        poll_interval_box.pack_start(&adaptive_poll_check, false, false, 0);
+        vbox.pack_start(&poll_interval_box, false, false, 0);
+
+        // Levels at which the tray icon switches to the warning and empty battery
+        let icon_levels_box = GtkBox::new(Orientation::Horizontal, 4);
+        icon_levels_box.pack_start(&Label::new(Some("Warning Icon At (%):")), false, false, 0);
+        let warning_spin = gtk::SpinButton::with_range(1.0, 99.0, 1.0);
+        warning_spin.set_value(DEFAULT_WARNING_LEVEL as f64);
+        icon_levels_box.pack_start(&warning_spin, true, true, 0);
+        icon_levels_box.pack_start(&Label::new(Some("Critical At (%):")), false, false, 0);
+        let critical_spin = gtk::SpinButton::with_range(1.0, 99.0, 1.0);
+        critical_spin.set_value(DEFAULT_CRITICAL_LEVEL as f64);
+        icon_levels_box.pack_start(&critical_spin, true, true, 0);
+        vbox.pack_start(&icon_levels_box, false, false, 0);
+
+        // Percentage text beside the tray icon (shown by e.g. KDE and Budgie panels)
+        let show_label_check = gtk::CheckButton::with_label("Show percentage next to the tray icon");
+        show_label_check.set_halign(gtk::Align::Start);
+        vbox.pack_start(&show_label_check, false, false, 0);
+
+        // Icon pack: the built-in icons plus any packs found on disk
+        let icon_pack_box = GtkBox::new(Orientation::Horizontal, 4);
+        icon_pack_box.pack_start(&Label::new(Some("Icon Pack:")), false, false, 0);
+        let icon_pack_combo = ComboBoxText::new();
+        icon_pack_combo.append(Some(DEFAULT_ICON_PACK), DEFAULT_ICON_PACK);
+        for pack in discover_icon_packs(&icon_pack_roots()) {
+            icon_pack_combo.append(Some(&pack), &pack);
+        }
+        icon_pack_combo.set_active_id(Some(DEFAULT_ICON_PACK));
+        icon_pack_combo.set_tooltip_text(Some("Icons missing from a pack fall back to the built-in ones"));
+        icon_pack_box.pack_start(&icon_pack_combo, true, true, 0);
+        vbox.pack_start(&icon_pack_box, false, false, 0);
+
+        // Informational items in the tray menu
+        let menu_items_box = GtkBox::new(Orientation::Horizontal, 4);
+        menu_items_box.pack_start(&Label::new(Some("Tray menu shows:")), false, false, 0);
+        let menu_device_check = gtk::CheckButton::with_label("Device name");
+        let menu_percent_check = gtk::CheckButton::with_label("Battery %");
+        let menu_estimate_check = gtk::CheckButton::with_label("Time remaining");
+        let menu_status_check = gtk::CheckButton::with_label("Status");
+        for check in [&menu_device_check, &menu_percent_check, &menu_estimate_check, &menu_status_check] {
+            menu_items_box.pack_start(check, false, false, 0);
+        }
+        vbox.pack_start(&menu_items_box, false, false, 0);
+        let shown = menu_info_items(&load_settings().unwrap_or_default());
+        menu_device_check.set_active(shown.contains(&InfoItem::DeviceName));
+        menu_percent_check.set_active(shown.contains(&InfoItem::BatteryPercent));
+        menu_estimate_check.set_active(shown.contains(&InfoItem::TimeEstimate));
+        menu_status_check.set_active(shown.contains(&InfoItem::Status));
+
+        // Per-device overrides of the monitoring options
+        let device_expander = gtk::Expander::new(Some("Override defaults for this device"));
+        let device_grid = gtk::Grid::new();
+        device_grid.set_row_spacing(4);
+        device_grid.set_column_spacing(8);
+        let device_override_check = gtk::CheckButton::with_label("Use these values for this device");
+        let device_poll_spin = gtk::SpinButton::with_range(
+            MIN_POLL_INTERVAL_SECS as f64,
+            MAX_POLL_INTERVAL_SECS as f64,
+            5.0,
+        );
+        let device_attention_spin = gtk::SpinButton::with_range(0.0, 50.0, 1.0);
+        let device_low_spin = gtk::SpinButton::with_range(0.0, 50.0, 1.0);
+        let device_notify_check = gtk::CheckButton::with_label("Low battery notifications");
+        device_grid.attach(&device_override_check, 0, 0, 2, 1);
+        device_grid.attach(&Label::new(Some("Poll Interval (s):")), 0, 1, 1, 1);
+        device_grid.attach(&device_poll_spin, 1, 1, 1, 1);
+        device_grid.attach(&Label::new(Some("Red Icon Below (%):")), 0, 2, 1, 1);
+        device_grid.attach(&device_attention_spin, 1, 2, 1, 1);
+        // Notification controls only when notifications are compiled in
+        if cfg!(feature = "notifications") {
+            device_grid.attach(&Label::new(Some("Notify Below (%):")), 0, 3, 1, 1);
+            device_grid.attach(&device_low_spin, 1, 3, 1, 1);
+            device_grid.attach(&device_notify_check, 0, 4, 2, 1);
+        }
+        device_expander.add(&device_grid);
+        vbox.pack_start(&device_expander, false, false, 0);
+        {
+            let s = load_settings().unwrap_or_default();
+            let effective = effective_device_settings(&s, &mouse_name);
+            device_poll_spin.set_value(effective.poll_interval.as_secs() as f64);
+            device_attention_spin.set_value(effective.attention_threshold as f64);
+            device_low_spin.set_value(effective.low_battery_threshold as f64);
+            device_notify_check.set_active(effective.notifications);
+            let has_override = s.devices.as_ref().is_some_and(|d| d.contains_key(&mouse_name));
+            device_override_check.set_active(has_override);
+            device_expander.set_expanded(has_override);
+        }
+
+        // Button mapping (rivalcfg --buttons expression)
+        let buttons_box = GtkBox::new(Orientation::Vertical, 4);
+        let buttons_label = Label::new(Some("Button Mapping:"));
+        buttons_label.set_halign(gtk::Align::Start);
+        buttons_box.pack_start(&buttons_label, false, false, 0);
+        let buttons_view = gtk::TextView::new();
+        buttons_view.set_wrap_mode(gtk::WrapMode::WordChar);
+        let buttons_scroll = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        buttons_scroll.set_min_content_height(60);
+        buttons_scroll.set_shadow_type(gtk::ShadowType::In);
+        buttons_scroll.add(&buttons_view);
+        buttons_box.pack_start(&buttons_scroll, true, true, 0);
+        vbox.pack_start(&buttons_box, false, false, 0);
+
+        // Extra rivalcfg arguments for options without dedicated UI (e.g. --rotation)
+        let extra_args_box = GtkBox::new(Orientation::Horizontal, 4);
+        extra_args_box.pack_start(&Label::new(Some("Advanced arguments:")), false, false, 0);
+        let extra_args_entry = Entry::new();
+        extra_args_entry.set_placeholder_text(Some("e.g. --rotation 90"));
+        extra_args_entry.set_tooltip_text(Some("Passed to rivalcfg after the settings above, split on spaces"));
+        extra_args_box.pack_start(&extra_args_entry, true, true, 0);
+        vbox.pack_start(&extra_args_box, false, false, 0);
+        let startup_apply_check = gtk::CheckButton::with_label("Apply these settings when the tray starts");
+        startup_apply_check.set_tooltip_text(Some("Turn off if another tool manages the mouse configuration"));
+        startup_apply_check.set_halign(gtk::Align::Start);
+        startup_apply_check.set_active(true);
+        vbox.pack_start(&startup_apply_check, false, false, 0);
+        let features_label = Label::new(Some(&format!("Built with: {}", compiled_features().join(", "))));
+        features_label.set_halign(gtk::Align::Start);
+        features_label.style_context().add_class("dim-label");
+        vbox.pack_start(&features_label, false, false, 0);
+
         // Icon colour chooser (inline ColorButton)
         let colour_box = GtkBox::new(Orientation::Horizontal, 4);
         colour_box.pack_start(&Label::new(Some("Icon Colour (Custom):")), false, false, 0);
@@ -838,6 +4571,55 @@ fn open_config_dialog(runner: Arc<dyn CommandRunner>, tray_icon: TrayIcon, mouse
         colour_box.pack_start(&color_button, false, false, 0);
         vbox.pack_start(&colour_box, false, false, 0);
 
+        // Mouse LED colour preview: sends the colour to the mouse without saving anything,
+        // so closing the window (or resetting) reverts to the stored configuration.
+        let led_box = GtkBox::new(Orientation::Horizontal, 4);
+        led_box.pack_start(&Label::new(Some("LED Colour:")), false, false, 0);
+        let led_color_button = gtk::ColorButton::new();
+        led_box.pack_start(&led_color_button, false, false, 0);
+        let preview_btn = Button::with_label("Preview Color");
+        led_box.pack_start(&preview_btn, false, false, 0);
+        vbox.pack_start(&led_box, false, false, 0);
+
+        // LED mode; the colour picker only matters for "Custom"
+        let led_mode_box = GtkBox::new(Orientation::Horizontal, 4);
+        led_mode_box.pack_start(&Label::new(Some("LED Mode:")), false, false, 0);
+        let led_default_radio = gtk::RadioButton::with_label("Default");
+        let led_off_radio = gtk::RadioButton::with_label_from_widget(&led_default_radio, "Off");
+        let led_custom_radio = gtk::RadioButton::with_label_from_widget(&led_default_radio, "Custom");
+        led_mode_box.pack_start(&led_default_radio, false, false, 0);
+        led_mode_box.pack_start(&led_off_radio, false, false, 0);
+        led_mode_box.pack_start(&led_custom_radio, false, false, 0);
+        vbox.pack_start(&led_mode_box, false, false, 0);
+        let fill_led = {
+            let led_default_radio = led_default_radio.clone();
+            let led_off_radio = led_off_radio.clone();
+            let led_custom_radio = led_custom_radio.clone();
+            let led_color_button = led_color_button.clone();
+            move |s: &Settings| {
+                match s.led_mode.as_deref() {
+                    Some(LED_MODE_OFF) => led_off_radio.set_active(true),
+                    Some(LED_MODE_CUSTOM) => led_custom_radio.set_active(true),
+                    _ => led_default_radio.set_active(true),
+                }
+                if let Some(rgba) = s.led_color.as_deref().and_then(rgba_from_hex) {
+                    led_color_button.set_rgba(&rgba);
+                }
+            }
+        };
+        if let Some(s) = load_settings() {
+            fill_led(&s);
+        }
+        let custom_led = led_custom_radio.is_active();
+        led_color_button.set_sensitive(custom_led);
+        preview_btn.set_sensitive(custom_led);
+        let led_color_button_toggle = led_color_button.clone();
+        let preview_btn_toggle = preview_btn.clone();
+        led_custom_radio.connect_toggled(move |radio| {
+            led_color_button_toggle.set_sensitive(radio.is_active());
+            preview_btn_toggle.set_sensitive(radio.is_active());
+        });
+
         // When the ColorButton color changes, save as custom color and regenerate icon
         let tray_icon_cb = tray_icon.clone();
         color_button.connect_color_set(move |btn| {
@@ -849,12 +4631,7 @@ fn open_config_dialog(runner: Arc<dyn CommandRunner>, tray_icon: TrayIcon, mouse
                 if let Err(e) = save_settings(&settings) {
                     eprintln!("[rivalcfg-tray] Failed to save custom colour: {}", e);
                 }
-                if let Ok(mut last) = LAST_BATTERY_STATE.lock() {
-                    *last = None;
-                }
-                if let Some((level, _charging)) = generate_tray_icon(&tray_icon_cb) {
-                    let _ = tray_icon_cb.set_tooltip(Some(&format!("Battery: {}%", level)));
-                }
+                redraw_in_new_colour(&tray_icon_cb);
         });
 
         // Buttons
@@ -863,19 +4640,66 @@ fn open_config_dialog(runner: Arc<dyn CommandRunner>, tray_icon: TrayIcon, mouse
         let reset_btn = Button::with_label("Reset Settings");
         btn_box.pack_start(&apply_btn, true, true, 0);
         btn_box.pack_start(&reset_btn, true, true, 0);
+        // Apply edits as they are made; Apply stays for re-sending unchanged settings
+        let auto_apply_check = gtk::CheckButton::with_label("Apply automatically");
+        auto_apply_check.set_active(load_settings().and_then(|s| s.auto_apply).unwrap_or(false));
+        btn_box.pack_start(&auto_apply_check, false, false, 0);
         vbox.pack_start(&btn_box, false, false, 0);
 
+        // Validation problems from the last Apply, all listed at once
+        let validation_label = Label::new(None);
+        validation_label.set_halign(gtk::Align::Start);
+        validation_label.set_valign(gtk::Align::Start);
+        validation_label.set_line_wrap(true);
+        validation_label.set_justify(start_justification(validation_label.direction()));
+        validation_label.set_selectable(true);
+        let validation_scroll = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        validation_scroll.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        validation_scroll.set_min_content_height(60);
+        validation_scroll.set_max_content_height(120);
+        validation_scroll.add(&validation_label);
+        vbox.pack_start(&validation_scroll, false, false, 0);
+
         let show_btn = Button::with_label("Show Connected Devices");
         vbox.pack_start(&show_btn, false, false, 0);
 
+        // Environment report for bug reports, copied to the clipboard
+        let diagnostics_btn = Button::with_label("Copy Diagnostics");
+        vbox.pack_start(&diagnostics_btn, false, false, 0);
+
+        // The rivalcfg invocation Apply would run for the form as it is now
+        let copy_command_btn = Button::with_label("Copy Command");
+        copy_command_btn.set_tooltip_text(Some("Copy the rivalcfg command for the current settings without applying them"));
+        vbox.pack_start(&copy_command_btn, false, false, 0);
+
+        let save_profile_btn = Button::with_label("Save Settings as Profile…");
+        vbox.pack_start(&save_profile_btn, false, false, 0);
+
+        // Settings backup as a JSON file; imports only fill the form until Apply
+        let file_box = GtkBox::new(Orientation::Horizontal, 8);
+        let export_btn = Button::with_label("Export Settings…");
+        let import_btn = Button::with_label("Import Settings…");
+        file_box.pack_start(&export_btn, true, true, 0);
+        file_box.pack_start(&import_btn, true, true, 0);
+        vbox.pack_start(&file_box, false, false, 0);
+
+        // Outcome of the last Apply, kept until the next one
+        let status_label = Label::new(None);
+        status_label.set_halign(gtk::Align::Start);
+        status_label.set_line_wrap(true);
+        status_label.set_justify(start_justification(status_label.direction()));
+        status_label.set_selectable(true);
+        vbox.pack_start(&status_label, false, false, 0);
+
         win.add(&vbox);
         win.show_all();
+        validation_scroll.hide();
 
         // Helper to update battery label
         let battery_label_rc = Rc::new(battery_label);
-        let win_apply = win.clone();
         let win_reset = win.clone();
         let win_show = win.clone();
+        let win_profile = win.clone();
         let runner_clone = runner.clone();
         let update_battery = {
             let battery_label = battery_label_rc.clone();
@@ -892,98 +4716,176 @@ fn open_config_dialog(runner: Arc<dyn CommandRunner>, tray_icon: TrayIcon, mouse
         update_battery();
 
         // Now fill UI from stored settings (after widgets are created)
-        if let Some(s) = load_settings() {
-            if let Some(ref pr) = s.polling_rate {
-                let idx = match pr.as_str() {
-                    "125" => 0,
-                    "250" => 1,
-                    "500" => 2,
-                    "1000" => 3,
-                    _ => 3,
-                };
-                polling_rate_combo.set_active(Some(idx));
-            }
-            if let Some(ref sens) = s.sensitivity {
-                sensitivity_entry.set_text(sens);
-            }
-            if let Some(ref sleep_t) = s.sleep_timer {
-                sleep_timer_entry.set_text(sleep_t);
-            }
-            if let Some(ref dim_t) = s.dim_timer {
-                dim_timer_entry.set_text(dim_t);
+        let fill_widgets = {
+            let polling_rate_combo = polling_rate_combo.clone();
+            let sensitivity_spin = sensitivity_spin.clone();
+            let dual_sensitivity = dual_sensitivity.clone();
+            let sleep_timer_entry = sleep_timer_entry.clone();
+            let dim_timer_entry = dim_timer_entry.clone();
+            let extra_args_entry = extra_args_entry.clone();
+            let buttons_view = buttons_view.clone();
+            let attention_spin = attention_spin.clone();
+            let poll_interval_spin = poll_interval_spin.clone();
+            let adaptive_poll_check = adaptive_poll_check.clone();
+            let warning_spin = warning_spin.clone();
+            let critical_spin = critical_spin.clone();
+            let show_label_check = show_label_check.clone();
+            let icon_pack_combo = icon_pack_combo.clone();
+            let startup_apply_check = startup_apply_check.clone();
+            move |s: &Settings| {
+                if let Some(ref pr) = s.polling_rate {
+                    let idx = match pr.as_str() {
+                        "125" => 0,
+                        "250" => 1,
+                        "500" => 2,
+                        "1000" => 3,
+                        _ => 3,
+                    };
+                    polling_rate_combo.set_active(Some(idx));
+                }
+                if let Some(dpi) = s.sensitivity.as_deref().and_then(first_sensitivity_stage) {
+                    sensitivity_spin.set_value(dpi as f64);
+                    dual_sensitivity.set_value((dpi, dpi));
+                }
+                if let Some(xy) = s.dual_sensitivity {
+                    dual_sensitivity.set_value(xy);
+                }
+                if let Some(ref sleep_t) = s.sleep_timer {
+                    sleep_timer_entry.set_text(sleep_t);
+                }
+                if let Some(ref dim_t) = s.dim_timer {
+                    dim_timer_entry.set_text(dim_t);
+                }
+                if let Some(ref args) = s.extra_args {
+                    extra_args_entry.set_text(&args.join(" "));
+                }
+                if let Some(ref buttons) = s.buttons
+                    && let Some(buf) = buttons_view.buffer()
+                {
+                    buf.set_text(buttons);
+                }
+                if let Some(threshold) = s.attention_threshold {
+                    attention_spin.set_value(threshold as f64);
+                }
+                if let Some(secs) = s.poll_interval {
+                    poll_interval_spin.set_value(secs as f64);
+                }
+                adaptive_poll_check.set_active(s.poll_interval.is_none());
+                if let Some(level) = s.warning_level {
+                    warning_spin.set_value(level as f64);
+                }
+                if let Some(level) = s.critical_level {
+                    critical_spin.set_value(level as f64);
+                }
+                show_label_check.set_active(s.show_label.unwrap_or(false));
+                startup_apply_check.set_active(applies_on_startup(s));
+                // a pack that is no longer installed shows as the default
+                let pack = s.icon_pack.as_deref().unwrap_or(DEFAULT_ICON_PACK);
+                if !icon_pack_combo.set_active_id(Some(pack)) {
+                    icon_pack_combo.set_active_id(Some(DEFAULT_ICON_PACK));
+                }
+                fill_led(s);
             }
+        };
+        if let Some(s) = load_settings() {
+            fill_widgets(&s);
         }
 
+        // Device options as entered in the form, over the saved settings; shared by
+        // Apply and Copy Command
+        let form_device_options = {
+            let sensitivity_spin = sensitivity_spin.clone();
+            let dual_sensitivity = dual_sensitivity.clone();
+            let sensitivity_edited = sensitivity_edited.clone();
+            let polling_rate_combo = polling_rate_combo.clone();
+            let sleep_timer_entry = sleep_timer_entry.clone();
+            let dim_timer_entry = dim_timer_entry.clone();
+            let buttons_view = buttons_view.clone();
+            let extra_args_entry = extra_args_entry.clone();
+            let led_off_radio = led_off_radio.clone();
+            let led_custom_radio = led_custom_radio.clone();
+            let led_color_button = led_color_button.clone();
+            Rc::new(move || {
+                let saved = load_settings().unwrap_or_default();
+                let edited = sensitivity_edited.get();
+                let sensitivity =
+                    sensitivity_from_spin(sensitivity_spin.value_as_int(), edited, saved.sensitivity.as_deref());
+                let has_saved_dpi = saved.sensitivity.is_some() || saved.dual_sensitivity.is_some();
+                let sleep_timer = sleep_timer_entry.text().to_string();
+                let dim_timer = dim_timer_entry.text().to_string();
+                let buttons = buttons_view
+                    .buffer()
+                    .and_then(|buf| buf.text(&buf.start_iter(), &buf.end_iter(), false))
+                    .map(|t| t.trim().to_string())
+                    .unwrap_or_default();
+                let extra_args: Vec<String> = extra_args_entry
+                    .text()
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect();
+                Settings {
+                    sensitivity,
+                    dual_sensitivity: (dual_mode && (edited || has_saved_dpi)).then(|| dual_sensitivity.value()),
+                    polling_rate: polling_rate_combo.active_text().map(|s| s.to_string()),
+                    sleep_timer: if sleep_timer.is_empty() { None } else { Some(sleep_timer) },
+                    dim_timer: if dim_timer.is_empty() { None } else { Some(dim_timer) },
+                    buttons: if buttons.is_empty() { None } else { Some(buttons) },
+                    extra_args: if extra_args.is_empty() { None } else { Some(extra_args) },
+                    led_mode: Some(
+                        if led_custom_radio.is_active() {
+                            LED_MODE_CUSTOM
+                        } else if led_off_radio.is_active() {
+                            LED_MODE_OFF
+                        } else {
+                            LED_MODE_DEFAULT
+                        }
+                        .to_string(),
+                    ),
+                    led_color: Some(hex_from_rgba(&led_color_button.rgba())),
+                    ..saved
+                }
+            })
+        };
+
         // Apply button logic
         let battery_label_apply = battery_label_rc.clone();
-        let win_apply_clone = win_apply.clone();
-        let sensitivity_entry_apply = sensitivity_entry.clone();
-        let polling_rate_combo_apply = polling_rate_combo.clone();
-        let sleep_timer_entry_apply = sleep_timer_entry.clone();
-        let dim_timer_entry_apply = dim_timer_entry.clone();
+        let status_label_apply = status_label.clone();
+        let form_device_options_apply = form_device_options.clone();
+        let attention_spin_apply = attention_spin.clone();
+        let poll_interval_apply = poll_interval_spin.clone();
+        let adaptive_poll_apply = adaptive_poll_check.clone();
+        let warning_spin_apply = warning_spin.clone();
+        let critical_spin_apply = critical_spin.clone();
+        let show_label_apply = show_label_check.clone();
+        let startup_apply_apply = startup_apply_check.clone();
+        let icon_pack_apply = icon_pack_combo.clone();
+        let menu_device_apply = menu_device_check.clone();
+        let menu_percent_apply = menu_percent_check.clone();
+        let menu_estimate_apply = menu_estimate_check.clone();
+        let menu_status_apply = menu_status_check.clone();
+        let device_override_apply = device_override_check.clone();
+        let device_poll_apply = device_poll_spin.clone();
+        let device_attention_apply = device_attention_spin.clone();
+        let device_low_apply = device_low_spin.clone();
+        let device_notify_apply = device_notify_check.clone();
+        let mouse_name_apply = mouse_name.clone();
+        let validation_label_apply = validation_label.clone();
+        let validation_scroll_apply = validation_scroll.clone();
+        let tray_icon_apply = tray_icon.clone();
         let runner_apply = runner.clone();
 
-        apply_btn.connect_clicked(move |_| {
-            let sensitivity = sensitivity_entry_apply.text().to_string();
+        apply_btn.connect_clicked(move |btn| {
+            use std::sync::mpsc::{self, TryRecvError};
 
-            // Validate fields before proceeding
-            if let Err(msg) = validate_sensitivity(&sensitivity) {
-                let dialog = MessageDialog::new(
-                    Some(&*win_apply_clone),
-                    DialogFlags::MODAL,
-                    MessageType::Error,
-                    ButtonsType::Ok,
-                    &msg,
-                );
-                dialog.run();
-                unsafe { dialog.destroy(); }
-                return;
-            }
-            // sensitivity will be saved in Settings and applied below via runner
-            let polling_rate = polling_rate_combo_apply.active_text().map(|s| s.to_string());
-            if let Some(ref prate) = polling_rate {
-                if let Err(msg) = validate_polling_rate(prate) {
-                    let dialog = MessageDialog::new(
-                        Some(&*win_apply_clone),
-                        DialogFlags::MODAL,
-                        MessageType::Error,
-                        ButtonsType::Ok,
-                        &msg,
-                    );
-                    dialog.run();
-                    unsafe { dialog.destroy(); }
-                    return;
-                }
-            }
-            // polling_rate will be saved in Settings and applied below via runner
-            let sleep_timer = sleep_timer_entry_apply.text().to_string();
-            if let Err(msg) = validate_timer(&sleep_timer, "Sleep Timer") {
-                let dialog = MessageDialog::new(
-                    Some(&*win_apply_clone),
-                    DialogFlags::MODAL,
-                    MessageType::Error,
-                    ButtonsType::Ok,
-                    &msg,
-                );
-                dialog.run();
-                unsafe { dialog.destroy(); }
-                return;
-            }
-            // sleep_timer will be saved in Settings and applied below via runner
-            let dim_timer = dim_timer_entry_apply.text().to_string();
-            if let Err(msg) = validate_timer(&dim_timer, "Dim Timer") {
-                let dialog = MessageDialog::new(
-                    Some(&*win_apply_clone),
-                    DialogFlags::MODAL,
-                    MessageType::Error,
-                    ButtonsType::Ok,
-                    &msg,
-                );
-                dialog.run();
-                unsafe { dialog.destroy(); }
+            // Ignore clicks while the last apply is still running
+            let Some(guard) = ApplyGuard::acquire(&APPLY_BUTTON_BUSY) else {
                 return;
-            }
-            // dim_timer will be saved in Settings and applied below via runner
+            };
+            let set_busy = |btn: &Button, busy: bool| {
+                btn.set_sensitive(!busy);
+                btn.set_label(if busy { "Applying…" } else { "Apply Settings" });
+            };
+            set_busy(btn, true);
             // Update battery using runner
             let out = runner_apply.run("rivalcfg", &["--battery-level"]);
             let text = if out.success {
@@ -992,44 +4894,199 @@ fn open_config_dialog(runner: Arc<dyn CommandRunner>, tray_icon: TrayIcon, mouse
                 "Battery Level: N/A".to_string()
             };
             battery_label_apply.set_text(&text);
+            // Per-device overrides: store them, or drop this device's block
+            let mut devices = load_settings().and_then(|s| s.devices).unwrap_or_default();
+            if device_override_apply.is_active() {
+                devices.insert(
+                    mouse_name_apply.clone(),
+                    DeviceSettings {
+                        poll_interval: Some(device_poll_apply.value_as_int() as u32),
+                        attention_threshold: Some(device_attention_apply.value_as_int().clamp(0, 50) as u8),
+                        low_battery_threshold: Some(device_low_apply.value_as_int().clamp(0, 50) as u8),
+                        notifications: Some(device_notify_apply.is_active()),
+                    },
+                );
+            } else {
+                devices.remove(&mouse_name_apply);
+            }
             // Save settings to disk
-            let settings = Settings {
-                sensitivity: if sensitivity.is_empty() { None } else { Some(sensitivity) },
-                polling_rate: polling_rate.clone(),
-                sleep_timer: if sleep_timer.is_empty() { None } else { Some(sleep_timer) },
-                dim_timer: if dim_timer.is_empty() { None } else { Some(dim_timer) },
+            let settings = normalize_settings(Settings {
+                devices: if devices.is_empty() { None } else { Some(devices) },
+                attention_threshold: Some(attention_spin_apply.value_as_int().clamp(0, 50) as u8),
+                poll_interval: (!adaptive_poll_apply.is_active()).then(|| poll_interval_apply.value_as_int() as u32),
+                warning_level: Some(warning_spin_apply.value_as_int().clamp(1, 99) as u8),
+                critical_level: Some(critical_spin_apply.value_as_int().clamp(1, 99) as u8),
+                show_label: Some(show_label_apply.is_active()),
+                auto_apply_on_startup: Some(startup_apply_apply.is_active()),
+                icon_pack: icon_pack_apply
+                    .active_id()
+                    .map(|id| id.to_string())
+                    .filter(|id| id != DEFAULT_ICON_PACK),
+                menu: Some(MenuSettings {
+                    device_name: Some(menu_device_apply.is_active()),
+                    battery_percent: Some(menu_percent_apply.is_active()),
+                    time_estimate: Some(menu_estimate_apply.is_active()),
+                    status: Some(menu_status_apply.is_active()),
+                }),
                 colour_mode: None,
                 custom_color: None,
-            };
+                // keep settings that are not edited in this window
+                ..form_device_options_apply()
+            });
+            // Validate every field before saving or applying, and list all problems at once
+            let errors = validate_settings(&settings);
+            if !errors.is_empty() {
+                for e in &errors {
+                    eprintln!("[rivalcfg-tray] Invalid {}: {}", e.field, e.message);
+                }
+                let text = errors.iter().map(|e| format!("• {}", e.message)).collect::<Vec<_>>().join("\n");
+                validation_label_apply.set_text(&text);
+                validation_scroll_apply.show_all();
+                status_label_apply.set_text("");
+                set_busy(btn, false);
+                return;
+            }
+            validation_scroll_apply.hide();
+            let previous = load_settings().unwrap_or_default();
+            match save_settings(&settings) {
+                // Redraw with e.g. a new attention threshold or icon theme right away
+                Ok(()) if icon_settings_changed(&previous, &settings) => {
+                    force_icon_refresh();
+                    generate_tray_icon_in_background(tray_icon_apply.clone(), |_, _| {});
+                }
+                Ok(()) => {}
+                Err(e) => eprintln!("[rivalcfg-tray] Failed to save settings: {}", e),
+            }
+            // A new poll interval takes effect now rather than at the old timer's next tick
+            let last = LAST_BATTERY_STATE.lock().ok().and_then(|s| *s);
+            let scheduled = poll_interval_for(&previous, &mouse_name_apply, last.as_ref());
+            if rescheduled_poll_interval(scheduled, &settings, &mouse_name_apply, last.as_ref()).is_some() {
+                restart_battery_poll(tray_icon_apply.clone());
+            }
+            // Apply settings via runner, off the main loop so the window stays responsive
+            let summary = applied_settings_summary(&settings);
+            let (tx, rx) = mpsc::channel();
+            let runner = runner_apply.clone();
+            let refresh = tray_refresh_sender();
+            std::thread::spawn(move || {
+                let _ = tx.send(apply_settings_and_refresh(&settings, runner.as_ref(), refresh.as_ref()));
+            });
+            let btn = btn.clone();
+            let status_label = status_label_apply.clone();
+            let mut guard = Some(guard);
+            glib::timeout_add_local(Duration::from_millis(100), move || {
+                let result = match rx.try_recv() {
+                    Ok(result) => result,
+                    Err(TryRecvError::Empty) => return ControlFlow::Continue,
+                    Err(TryRecvError::Disconnected) => Ok(()),
+                };
+                drop(guard.take());
+                set_busy(&btn, false);
+                status_label.set_markup(&apply_status_markup(&result, &summary));
+                ControlFlow::Break
+            });
+        });
+
+        // Auto-apply: every edit restarts the delay, then Apply runs with its usual
+        // validation, so invalid input in between never reaches rivalcfg
+        auto_apply_check.connect_toggled(|check| {
+            let mut settings = load_settings().unwrap_or_default();
+            settings.auto_apply = Some(check.is_active());
             if let Err(e) = save_settings(&settings) {
                 eprintln!("[rivalcfg-tray] Failed to save settings: {}", e);
             }
-            // Apply settings via runner
-            let args = build_rivalcfg_args(&settings);
-            if !args.is_empty() {
-                let slices = args.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
-                let out = runner_apply.run("rivalcfg", &slices);
-                if !out.success {
-                    let dialog = MessageDialog::new(
-                        Some(&*win_apply_clone),
-                        DialogFlags::MODAL,
-                        MessageType::Error,
-                        ButtonsType::Ok,
-                        &format!("Error running the command: {}", out.stderr),
-                    );
-                    dialog.run();
-                    unsafe {
-                        dialog.destroy();
+        });
+        let auto_apply_debounce = Rc::new(std::cell::RefCell::new(ApplyDebounce::new(AUTO_APPLY_DELAY)));
+        let schedule_auto_apply = {
+            let check = auto_apply_check.clone();
+            let apply_btn = apply_btn.clone();
+            let debounce = auto_apply_debounce.clone();
+            Rc::new(move || {
+                if !check.is_active() {
+                    return;
+                }
+                let was_pending = debounce.borrow().pending();
+                debounce.borrow_mut().changed(Instant::now());
+                if was_pending {
+                    return;
+                }
+                let apply_btn = apply_btn.clone();
+                let debounce = debounce.clone();
+                glib::timeout_add_local(Duration::from_millis(100), move || {
+                    // wait for a running apply; it may have been started before the edit
+                    if APPLY_BUTTON_BUSY.load(Ordering::SeqCst) {
+                        return ControlFlow::Continue;
+                    }
+                    if !debounce.borrow_mut().take_due(Instant::now()) {
+                        return ControlFlow::Continue;
+                    }
+                    apply_btn.clicked();
+                    ControlFlow::Break
+                });
+            })
+        };
+        let on_change = schedule_auto_apply.clone();
+        let edited = sensitivity_edited.clone();
+        sensitivity_spin.connect_value_changed(move |_| {
+            edited.set(true);
+            on_change();
+        });
+        let on_change = schedule_auto_apply.clone();
+        let edited = sensitivity_edited.clone();
+        dual_sensitivity.connect_value_changed(move || {
+            edited.set(true);
+            on_change();
+        });
+        let on_change = schedule_auto_apply.clone();
+        polling_rate_combo.connect_changed(move |_| on_change());
+        for entry in [&sleep_timer_entry, &dim_timer_entry, &extra_args_entry] {
+            let on_change = schedule_auto_apply.clone();
+            entry.connect_changed(move |_| on_change());
+        }
+        if let Some(buf) = buttons_view.buffer() {
+            let on_change = schedule_auto_apply.clone();
+            buf.connect_changed(move |_| on_change());
+        }
+
+        // Preview color button logic
+        let runner_preview = runner.clone();
+        let win_preview = win.clone();
+        preview_btn.connect_clicked(move |_| {
+            let hex = hex_from_rgba(&led_color_button.rgba());
+            let error = match validate_hex_color(&hex) {
+                Err(msg) => Some(msg),
+                Ok(()) => {
+                    let out = apply_color_only(runner_preview.as_ref(), &hex);
+                    if out.success {
+                        None
+                    } else {
+                        Some(format!("Error running the command: {}", out.stderr))
                     }
                 }
+            };
+            if let Some(msg) = error {
+                let dialog = MessageDialog::new(
+                    Some(&*win_preview),
+                    DialogFlags::MODAL,
+                    MessageType::Error,
+                    ButtonsType::Ok,
+                    &msg,
+                );
+                dialog.run();
+                unsafe {
+                    dialog.destroy();
+                }
             }
         });
 
         // Reset button logic
+        let runner_reset = runner.clone();
         reset_btn.connect_clicked(move |_| {
-            let result = std::process::Command::new("rivalcfg").arg("-r").output();
-            if let Ok(out) = result {
-                let msg = String::from_utf8_lossy(&out.stdout).to_string();
+            let Some(out) = reset_if_confirmed(runner_reset.as_ref(), || confirm_reset(&win_reset)) else {
+                return;
+            };
+            if out.success {
+                let msg = out.stdout;
                 let dialog = MessageDialog::new(
                     Some(&*win_reset),
                     DialogFlags::MODAL,
@@ -1056,21 +5113,289 @@ fn open_config_dialog(runner: Arc<dyn CommandRunner>, tray_icon: TrayIcon, mouse
             }
         });
 
+        // Copy diagnostics button logic
+        let runner_diagnostics = runner.clone();
+        diagnostics_btn.connect_clicked(move |btn| {
+            let report = collect_diagnostics(runner_diagnostics.as_ref());
+            gtk::Clipboard::get(&gtk::gdk::SELECTION_CLIPBOARD).set_text(&report);
+            btn.set_label("Copied Diagnostics");
+        });
+
+        // Copy command button logic: no validation, so a half-edited form can be inspected too
+        copy_command_btn.connect_clicked(move |btn| {
+            let settings = normalize_settings(form_device_options());
+            let command = format_command_string(&build_rivalcfg_args(&settings));
+            gtk::Clipboard::get(&gtk::gdk::SELECTION_CLIPBOARD).set_text(&command);
+            btn.set_label("Copied Command");
+        });
+
         // Show devices button logic
+        let runner_show = runner.clone();
         show_btn.connect_clicked(move |_| {
+            let settings = load_settings().unwrap_or_default();
+            let devices: Vec<DeviceInfo> = get_all_device_info_with_runner(runner_show.as_ref())
+                .into_iter()
+                .filter(|d| d.connected)
+                .collect();
+            let text = if devices.is_empty() {
+                connected_device_row(&mouse_name, None, &settings)
+            } else {
+                devices
+                    .iter()
+                    .map(|d| connected_device_row(&d.name, Some(&d.usb_id), &settings))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            };
             let dialog = MessageDialog::new(
                 Some(&*win_show),
                 DialogFlags::MODAL,
                 MessageType::Info,
                 ButtonsType::Ok,
-                &mouse_name,
+                &text,
             );
-            dialog.run();
+            dialog.add_button("Rename…", gtk::ResponseType::Other(RENAME_RESPONSE));
+            let resp = dialog.run();
             unsafe {
                 dialog.destroy();
             }
+            if resp == gtk::ResponseType::Other(RENAME_RESPONSE) {
+                open_rename_dialog(&win_show, &mouse_name);
+            }
+        });
+
+        // Save profile button logic
+        let win_export = win_profile.clone();
+        let win_import = win_profile.clone();
+        save_profile_btn.connect_clicked(move |_| {
+            open_save_profile_dialog(&win_profile);
+        });
+
+        export_btn.connect_clicked(move |_| {
+            let Some(path) = choose_settings_file(&win_export, gtk::FileChooserAction::Save) else {
+                return;
+            };
+            if let Err(e) = export_settings_to_path(&load_settings().unwrap_or_default(), &path) {
+                eprintln!("[rivalcfg-tray] Failed to export settings to {}: {}", path.display(), e);
+                show_error_dialog(&format!("Could not export the settings: {}", e));
+            }
+        });
+
+        import_btn.connect_clicked(move |_| {
+            let Some(path) = choose_settings_file(&win_import, gtk::FileChooserAction::Open) else {
+                return;
+            };
+            match import_settings_from_path(&path) {
+                // Only the form changes; nothing is saved or sent until Apply
+                Ok(settings) => fill_widgets(&settings),
+                Err(e) => {
+                    eprintln!("[rivalcfg-tray] Failed to import settings from {}: {}", path.display(), e);
+                    show_error_dialog(&format!("Could not import the settings: {}", e));
+                }
+            }
         });
 }
 
+// Reset the device to factory settings only once `confirm` agrees; None when cancelled
+fn reset_if_confirmed(runner: &dyn CommandRunner, confirm: impl FnOnce() -> bool) -> Option<CommandOutput> {
+    if !confirm() {
+        eprintln!("[rivalcfg-tray] Device reset cancelled");
+        return None;
+    }
+    Some(reset_device_with_runner(runner))
+}
+
+// Yes/No warning shown before the Reset Settings button runs `rivalcfg -r`
+fn confirm_reset(parent: &gtk::Window) -> bool {
+    use gtk::prelude::*;
+
+    let dialog = gtk::MessageDialog::new(
+        Some(parent),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Warning,
+        gtk::ButtonsType::YesNo,
+        "Reset the mouse to its factory settings?\n\nThis overwrites the sensitivity, polling rate, \
+         timers, button mapping and LED colour on the device.",
+    );
+    dialog.set_default_response(gtk::ResponseType::No);
+    let confirmed = dialog.run() == gtk::ResponseType::Yes;
+    unsafe {
+        dialog.destroy();
+    }
+    confirmed
+}
+
+// Response of the missing tools dialog's "Retry" button
+const RETRY_RESPONSE: gtk::ResponseType = gtk::ResponseType::Other(1);
+
+// Probe rivalcfg and rsvg-convert. While one is missing, show what to install and
+// let the user retry after installing it or continue without it.
+fn check_dependencies(runner: &dyn CommandRunner) -> AppCapabilities {
+    use gtk::prelude::*;
+
+    let distro = distro_from_os_release(&fs::read_to_string("/etc/os-release").unwrap_or_default());
+    loop {
+        let caps = AppCapabilities::probe(runner);
+        let missing = caps.missing();
+        if missing.is_empty() {
+            return caps;
+        }
+        eprintln!("[rivalcfg-tray] Missing tools: {}", missing.join(", "));
+        let dialog = gtk::MessageDialog::new(
+            None::<&gtk::Window>,
+            gtk::DialogFlags::MODAL,
+            gtk::MessageType::Warning,
+            gtk::ButtonsType::None,
+            "Some tools Rivalcfg Tray needs were not found",
+        );
+        dialog.set_secondary_text(Some(&missing_tools_message(&caps, distro)));
+        dialog.add_button("Retry", RETRY_RESPONSE);
+        let continue_label = if caps.can_poll_battery() { "Continue" } else { "Continue without battery" };
+        dialog.add_button(continue_label, gtk::ResponseType::Accept);
+        dialog.set_default_response(RETRY_RESPONSE);
+        let response = dialog.run();
+        unsafe {
+            dialog.destroy();
+        }
+        if response != RETRY_RESPONSE {
+            return caps;
+        }
+    }
+}
+
+// Ask for a settings JSON file to export to (`Save`) or import from (`Open`)
+fn choose_settings_file(parent: &gtk::Window, action: gtk::FileChooserAction) -> Option<PathBuf> {
+    use gtk::prelude::*;
+    use gtk::{FileChooserDialog, ResponseType};
+
+    let (title, accept) = match action {
+        gtk::FileChooserAction::Save => ("Export Settings", "Export"),
+        _ => ("Import Settings", "Import"),
+    };
+    let dialog = FileChooserDialog::with_buttons(
+        Some(title),
+        Some(parent),
+        action,
+        &[("Cancel", ResponseType::Cancel), (accept, ResponseType::Accept)],
+    );
+    let filter = gtk::FileFilter::new();
+    filter.set_name(Some("JSON files"));
+    filter.add_pattern("*.json");
+    dialog.add_filter(filter);
+    if action == gtk::FileChooserAction::Save {
+        dialog.set_do_overwrite_confirmation(true);
+        dialog.set_current_name("rivalcfg-tray-settings.json");
+    }
+    let path = (dialog.run() == ResponseType::Accept).then(|| dialog.filename()).flatten();
+    unsafe {
+        dialog.destroy();
+    }
+    path
+}
+
+// One line of the connected devices dialog, e.g. "Desk mouse (SteelSeries Rival 3) — 1038:1824"
+fn connected_device_row(raw_name: &str, usb_id: Option<&str>, settings: &Settings) -> String {
+    let display_name = device_display_name(raw_name, settings);
+    let name = if display_name != raw_name {
+        format!("{} ({})", display_name, raw_name)
+    } else {
+        display_name
+    };
+    match usb_id {
+        Some(id) => format!("{} — {}", name, id),
+        None => name,
+    }
+}
+
+const RENAME_RESPONSE: u16 = 1;
+
+// Small modal dialog to edit the nickname of `raw_name`. An empty entry clears it.
+fn open_rename_dialog(parent: &gtk::Window, raw_name: &str) {
+    use gtk::prelude::*;
+    use gtk::{Dialog, DialogFlags, Entry, Label, ResponseType};
+
+    let dialog = Dialog::with_buttons(
+        Some("Rename Device"),
+        Some(parent),
+        DialogFlags::MODAL,
+        &[("Cancel", ResponseType::Cancel), ("Save", ResponseType::Accept)],
+    );
+    dialog.set_default_response(ResponseType::Accept);
+
+    let settings = load_settings().unwrap_or_default();
+    let content = dialog.content_area();
+    content.set_spacing(6);
+    content.pack_start(&Label::new(Some(&format!("Nickname for {}:", raw_name))), false, false, 0);
+    let entry = Entry::new();
+    if let Some(nick) = settings.nicknames.as_ref().and_then(|n| n.get(raw_name)) {
+        entry.set_text(nick);
+    }
+    entry.set_activates_default(true);
+    content.pack_start(&entry, false, false, 0);
+    dialog.show_all();
+
+    if dialog.run() == ResponseType::Accept {
+        let nickname = entry.text();
+        if let Err(msg) = validate_spawn_arg(nickname.trim(), "Nickname", MAX_NICKNAME_LEN) {
+            let error = gtk::MessageDialog::new(
+                Some(parent),
+                DialogFlags::MODAL,
+                gtk::MessageType::Error,
+                gtk::ButtonsType::Ok,
+                &msg,
+            );
+            error.run();
+            unsafe {
+                error.destroy();
+                dialog.destroy();
+            }
+            return;
+        }
+        let mut settings = load_settings().unwrap_or_default();
+        set_device_nickname(&mut settings, raw_name, &nickname);
+        if let Err(e) = save_settings(&settings) {
+            eprintln!("[rivalcfg-tray] Failed to save device nickname: {}", e);
+        }
+    }
+    unsafe {
+        dialog.destroy();
+    }
+}
+
+// Ask for a name and store the saved settings' device options as a profile
+fn open_save_profile_dialog(parent: &gtk::Window) {
+    use gtk::prelude::*;
+    use gtk::{Dialog, DialogFlags, Entry, Label, ResponseType};
+
+    let dialog = Dialog::with_buttons(
+        Some("Save Profile"),
+        Some(parent),
+        DialogFlags::MODAL,
+        &[("Cancel", ResponseType::Cancel), ("Save", ResponseType::Accept)],
+    );
+    dialog.set_default_response(ResponseType::Accept);
+
+    let content = dialog.content_area();
+    content.set_spacing(6);
+    content.pack_start(&Label::new(Some("Profile name (saves the applied settings):")), false, false, 0);
+    let entry = Entry::new();
+    entry.set_activates_default(true);
+    content.pack_start(&entry, false, false, 0);
+    dialog.show_all();
+
+    if dialog.run() == ResponseType::Accept {
+        match save_profile(&entry.text(), &load_settings().unwrap_or_default()) {
+            Ok(()) => {
+                if let Ok(mut changed) = PROFILES_CHANGED.lock() {
+                    *changed = true;
+                }
+            }
+            Err(e) => eprintln!("[rivalcfg-tray] Failed to save profile: {}", e),
+        }
+    }
+    unsafe {
+        dialog.destroy();
+    }
+}
+
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::actions::TrayAction;
+#[cfg(feature = "notifications")]
+use crate::cmd::CommandRunner;
+
+/// Longest summary sent; many notification daemons cut the title to one line
+pub const MAX_SUMMARY_LEN: usize = 48;
+
+/// Something the tray tells the user about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotifyEvent {
+    LowBattery { device: String, level: u8 },
+    ChargeComplete { device: String, took: Option<Duration> },
+    /// Applying settings failed; `token` keys the full error text kept by the tray
+    ApplyFailed { error: String, token: u64 },
+    /// Writes to `target` kept failing and are skipped for `minutes`
+    WritesSuspended { target: String, minutes: u64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Normal,
+    Critical,
+}
+
+/// A notification as shown: a one-line summary, optional detail, and the action
+/// clicking it runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub summary: String,
+    pub body: Option<String>,
+    pub urgency: Urgency,
+    pub click: TrayAction,
+}
+
+/// Shows notifications; the tray uses `NotifySendNotifier`
+pub trait Notifier {
+    fn notify(&self, notification: Notification);
+}
+
+// "1h 05m", "42m" or "under a minute"
+pub fn format_charge_duration(d: Duration) -> String {
+    let minutes = d.as_secs() / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, 0) => "under a minute".to_string(),
+        (0, m) => format!("{}m", m),
+        (h, m) => format!("{}h {:02}m", h, m),
+    }
+}
+
+// First line of `s`, cut to `max` characters with an ellipsis
+fn one_line(s: &str, max: usize) -> String {
+    let line = s.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("");
+    if line.chars().count() <= max {
+        return line.to_string();
+    }
+    let mut cut: String = line.chars().take(max.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+// Last path component of `path`, or all of it
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').find(|part| !part.is_empty()).unwrap_or(path)
+}
+
+/// The summary, body and click action for `event`. Battery alerts open the
+/// configuration window; failures open a dialog with the full error.
+pub fn notification_for(event: &NotifyEvent) -> Notification {
+    match event {
+        NotifyEvent::LowBattery { device, level } => Notification {
+            summary: one_line(&format!("{}: Low Battery ({}%)", device, level), MAX_SUMMARY_LEN),
+            body: Some("Plug in the mouse soon. Click to open the settings.".to_string()),
+            urgency: Urgency::Critical,
+            click: TrayAction::OpenConfig,
+        },
+        NotifyEvent::ChargeComplete { device, took } => Notification {
+            summary: one_line(&format!("{}: Fully Charged", device), MAX_SUMMARY_LEN),
+            body: Some(match took {
+                Some(took) => format!("Charged in {}. You can unplug the cable.", format_charge_duration(*took)),
+                None => "You can unplug the cable.".to_string(),
+            }),
+            urgency: Urgency::Normal,
+            click: TrayAction::OpenConfig,
+        },
+        NotifyEvent::ApplyFailed { error, token } => Notification {
+            summary: "Settings not applied".to_string(),
+            body: Some(one_line(error, 200)).filter(|b| !b.is_empty()),
+            urgency: Urgency::Normal,
+            click: TrayAction::ShowError(*token),
+        },
+        NotifyEvent::WritesSuspended { target, minutes } => Notification {
+            summary: one_line(&format!("Can't write {}", file_name(target)), MAX_SUMMARY_LEN),
+            body: Some(format!(
+                "Writing {} keeps failing, so it is skipped for {} minutes. Check free space and permissions.",
+                target, minutes
+            )),
+            urgency: Urgency::Normal,
+            click: TrayAction::OpenConfig,
+        },
+    }
+}
+
+/// What a notification click opens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClickTarget {
+    Action(TrayAction),
+    ErrorDialog(String),
+}
+
+/// Resolve a clicked notification's action. Error tokens from before the tray
+/// restarted are no longer known and fall back to the configuration window.
+pub fn resolve_click(action: TrayAction, errors: &HashMap<u64, String>) -> ClickTarget {
+    match action {
+        TrayAction::ShowError(token) => match errors.get(&token) {
+            Some(error) => ClickTarget::ErrorDialog(error.clone()),
+            None => ClickTarget::Action(TrayAction::OpenConfig),
+        },
+        other => ClickTarget::Action(other),
+    }
+}
+
+/// notify-send arguments for `n`. With `with_click` notify-send waits for the
+/// notification to close and prints "default" if it was clicked.
+#[cfg(feature = "notifications")]
+pub fn notify_send_args(n: &Notification, with_click: bool) -> Vec<String> {
+    let mut args = Vec::new();
+    if n.urgency == Urgency::Critical {
+        args.extend(["-u".to_string(), "critical".to_string()]);
+    }
+    args.extend(["-a".to_string(), "rivalcfg-tray".to_string()]);
+    if with_click {
+        args.extend(["-A".to_string(), "default=Open".to_string(), "--wait".to_string()]);
+    }
+    args.push(n.summary.clone());
+    args.extend(n.body.clone());
+    args
+}
+
+/// Show `n` and wait for it to close. Returns the click action if the user clicked
+/// it. notify-send versions without action support get a plain notification.
+#[cfg(feature = "notifications")]
+pub fn deliver(runner: &dyn CommandRunner, n: &Notification) -> Option<TrayAction> {
+    let run = |with_click| {
+        let args = notify_send_args(n, with_click);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        runner.run("notify-send", &args)
+    };
+    let out = run(true);
+    if out.success {
+        return (out.stdout.trim() == "default").then(|| n.click.clone());
+    }
+    let out = run(false);
+    if !out.success {
+        eprintln!("[rivalcfg-tray] Failed to send notification: {}", out.stderr);
+    }
+    None
+}
+
+/// Sends notifications with notify-send on a worker thread and hands clicks to the
+/// running tray through its action socket, like `rivalcfg-tray --action`.
+#[cfg(feature = "notifications")]
+#[derive(Debug)]
+pub struct NotifySendNotifier {
+    // None when the tray has no action socket; clicks are then dropped
+    socket: Option<std::path::PathBuf>,
+}
+
+#[cfg(feature = "notifications")]
+impl NotifySendNotifier {
+    pub fn new(socket: Option<std::path::PathBuf>) -> Self {
+        Self { socket }
+    }
+}
+
+#[cfg(feature = "notifications")]
+impl Notifier for NotifySendNotifier {
+    fn notify(&self, notification: Notification) {
+        let socket = self.socket.clone();
+        std::thread::spawn(move || {
+            // only notify-send is run here, so the rivalcfg path doesn't matter
+            let runner = crate::cmd::RealCommandRunner::new("rivalcfg".to_string());
+            if let Some(action) = deliver(&runner, &notification)
+                && let Some(socket) = socket
+                && let Err(e) = crate::actions::forward_action(&socket, &action.id())
+            {
+                eprintln!("[rivalcfg-tray] Warning: Failed to forward notification click: {}", e);
+            }
+        });
+    }
+}
+
+/// Stands in for `NotifySendNotifier` when the notifications feature is compiled out
+#[cfg(not(feature = "notifications"))]
+#[derive(Debug, Default)]
+pub struct DisabledNotifier;
+
+#[cfg(not(feature = "notifications"))]
+impl Notifier for DisabledNotifier {
+    fn notify(&self, _notification: Notification) {}
+}
@@ -0,0 +1,219 @@
+// Pure parsers for rivalcfg output. Kept free of any other crate module so the
+// fuzz targets under `fuzz/` can include this file directly.
+
+/// Charge state reported by `rivalcfg --battery-level`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChargeStatus {
+    Discharging,
+    Charging,
+    // on the charger and full ("Charged"/"Full", or charging at 100%)
+    Charged,
+    Unknown,
+}
+
+/// A successfully parsed battery reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatteryReading {
+    pub percent: u8,
+    pub status: ChargeStatus,
+}
+
+/// A device line from `rivalcfg --list`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceEntry {
+    pub name: String,
+    // USB "vendor:product" id, e.g. "1038:1836"
+    pub usb_id: String,
+}
+
+/// What `rivalcfg --help` says about the connected device.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HelpCapabilities {
+    // from the "<device name> Options:" heading; None when no device is connected
+    pub device_name: Option<String>,
+    // long option flags listed for the device, e.g. "--sensitivity"
+    pub options: Vec<String>,
+    // upper bound of --dim-timer in seconds, from its "(from 0 s to 1200 s, ...)" help
+    pub max_dim_timer: Option<u32>,
+    // --sensitivity takes separate "X,Y" values per axis (e.g. Rival 310)
+    pub dual_sensitivity: bool,
+}
+
+impl HelpCapabilities {
+    pub fn supports(&self, flag: &str) -> bool {
+        self.options.iter().any(|o| o == flag)
+    }
+
+    /// Whether the sensitivity is set per axis as `--sensitivity X,Y`
+    pub fn supports_dual_sensitivity(&self) -> bool {
+        self.dual_sensitivity && self.supports("--sensitivity")
+    }
+}
+
+pub fn parse_charge_status(stdout: &str) -> ChargeStatus {
+    if stdout.contains("Discharging") {
+        ChargeStatus::Discharging
+    } else if stdout.contains("Charged") || stdout.contains("Full") {
+        ChargeStatus::Charged
+    } else if stdout.contains("Charging") {
+        ChargeStatus::Charging
+    } else {
+        ChargeStatus::Unknown
+    }
+}
+
+/// Parse `rivalcfg --battery-level` output such as "Discharging [=====     ] 45 %"
+/// or "Mouse battery: 75% Charging". Percentages above 100 are rejected.
+pub fn parse_battery(stdout: &str) -> Option<BatteryReading> {
+    let mut status = parse_charge_status(stdout);
+    if status == ChargeStatus::Unknown {
+        return None;
+    }
+    // The percentage is the last numeric token, with or without a trailing '%'
+    let percent = stdout
+        .split_whitespace()
+        .rev()
+        .filter_map(|word| word.trim_end_matches('%').parse::<u8>().ok())
+        .next()?;
+    if percent > 100 {
+        return None;
+    }
+    // Some firmwares keep reporting "Charging" once full
+    if status == ChargeStatus::Charging && percent >= 100 {
+        status = ChargeStatus::Charged;
+    }
+    Some(BatteryReading { percent, status })
+}
+
+fn is_usb_id(token: &str) -> bool {
+    let mut parts = token.split(':');
+    let (Some(vendor), Some(product), None) = (parts.next(), parts.next(), parts.next()) else {
+        return false;
+    };
+    [vendor, product]
+        .iter()
+        .all(|p| p.len() == 4 && p.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Parse `rivalcfg --list` output. Every line carrying a "vvvv:pppp" USB id is a
+/// device; the remaining text on the line (minus separators) is its name.
+pub fn parse_device_list(stdout: &str) -> Vec<DeviceEntry> {
+    let mut devices = Vec::new();
+    for line in stdout.lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some(id_pos) = tokens.iter().position(|t| is_usb_id(t)) else {
+            continue;
+        };
+        let name = tokens
+            .iter()
+            .enumerate()
+            .filter(|(i, t)| *i != id_pos && !matches!(**t, "|" | "-" | "—"))
+            .map(|(_, t)| *t)
+            .collect::<Vec<_>>()
+            .join(" ");
+        if name.is_empty() {
+            continue;
+        }
+        devices.push(DeviceEntry {
+            name,
+            usb_id: tokens[id_pos].to_lowercase(),
+        });
+    }
+    devices
+}
+
+/// Parse `rivalcfg --help`: the device heading ("<name> Options:") and the long
+/// flags listed underneath it.
+pub fn parse_help_capabilities(stdout: &str) -> HelpCapabilities {
+    let mut caps = HelpCapabilities::default();
+    let mut lines = stdout.lines();
+    for line in lines.by_ref() {
+        if let Some(name) = line.trim_end().strip_suffix("Options:") {
+            let name = name.trim();
+            if !name.is_empty() {
+                caps.device_name = Some(name.to_string());
+                break;
+            }
+        }
+    }
+    if caps.device_name.is_none() {
+        return caps;
+    }
+    // help text of --dim-timer and --sensitivity, which may wrap over several lines
+    let mut dim_help = String::new();
+    let mut sens_help = String::new();
+    let mut in_dim_help = false;
+    let mut in_sens_help = false;
+    for line in lines {
+        if line.trim_start().starts_with('-') {
+            in_dim_help = line.contains("--dim-timer");
+            in_sens_help = line.contains("--sensitivity ") || line.trim_end().ends_with("--sensitivity");
+        }
+        if in_dim_help {
+            dim_help.push_str(line);
+            dim_help.push(' ');
+        }
+        if in_sens_help {
+            sens_help.push_str(line);
+            sens_help.push(' ');
+        }
+        for token in line.split(|c: char| c.is_whitespace() || c == ',') {
+            if let Some(flag) = token.strip_prefix("--")
+                && !flag.is_empty()
+                && flag.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            {
+                let flag = format!("--{}", flag);
+                if !caps.options.contains(&flag) {
+                    caps.options.push(flag);
+                }
+            }
+        }
+    }
+    caps.max_dim_timer = range_max(&dim_help);
+    // per-axis devices describe the value as "X,Y" (spacing varies)
+    let sens_help: String = sens_help.split_whitespace().collect::<String>().to_ascii_lowercase();
+    caps.dual_sensitivity = sens_help.contains("x,y");
+    caps
+}
+
+// N from a "(from 0 s to N s, ...)" range in option help
+fn range_max(help: &str) -> Option<u32> {
+    let words: Vec<&str> = help.split_whitespace().collect();
+    words
+        .windows(2)
+        .filter(|w| w[0] == "to")
+        .find_map(|w| w[1].trim_end_matches([',', ')']).parse().ok())
+}
+
+/// Parse `rivalcfg --firmware-version` output such as
+/// "SteelSeries Rival 3 (firmware v0.37.0.0)" or a bare "1.2". Numbers without a
+/// "v" prefix or a dot (like the "3" in a device name) are not versions.
+pub fn parse_firmware_version(stdout: &str) -> Option<String> {
+    stdout
+        .split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | ',' | ':'))
+        .filter_map(|token| {
+            // a version may end a sentence
+            let token = token.trim_end_matches('.');
+            let (number, prefixed) = match token.strip_prefix(['v', 'V']) {
+                Some(rest) => (rest, true),
+                None => (token, false),
+            };
+            let is_version = number.starts_with(|c: char| c.is_ascii_digit())
+                && number.chars().all(|c| c.is_ascii_digit() || c == '.')
+                && (prefixed || number.contains('.'));
+            is_version.then(|| number.to_string())
+        })
+        .last()
+}
+
+/// Whether `rivalcfg --battery-level` output says the device has no battery, e.g.
+/// "rivalcfg: error: unrecognized arguments: --battery-level" from a wired mouse
+/// whose options don't include it.
+pub fn reports_no_battery(output: &str) -> bool {
+    let text = output.to_ascii_lowercase();
+    text.contains("unrecognized arguments: --battery-level")
+        || ["no battery", "battery level is not supported", "does not support battery"]
+            .iter()
+            .any(|marker| text.contains(marker))
+}
@@ -0,0 +1,48 @@
+use std::path::{Path, PathBuf};
+
+/// Name of the generated systemd user unit
+pub const SERVICE_UNIT_NAME: &str = "rivalcfg-tray.service";
+
+/// Where `--install-service` writes the unit, under the user's config dir
+pub fn service_unit_path(config_dir: &Path) -> PathBuf {
+    config_dir.join("systemd").join("user").join(SERVICE_UNIT_NAME)
+}
+
+// `path` as a single ExecStart word: quoted when it contains whitespace or quotes,
+// with `%` doubled so systemd doesn't read it as a specifier
+fn exec_word(path: &str) -> String {
+    let escaped = path.replace('%', "%%");
+    if escaped.chars().any(|c| c.is_whitespace() || c == '"' || c == '\'' || c == '\\') {
+        format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        escaped
+    }
+}
+
+/// A systemd user unit that starts the tray at `exec_path` with the graphical
+/// session and restarts it if it crashes
+pub fn render_service_unit(exec_path: &str) -> String {
+    format!(
+        "[Unit]\n\
+         Description=RivalCfg Tray App\n\
+         PartOf=graphical-session.target\n\
+         After=graphical-session.target\n\
+         \n\
+         [Service]\n\
+         ExecStart={}\n\
+         Restart=on-failure\n\
+         \n\
+         [Install]\n\
+         WantedBy=graphical-session.target\n",
+        exec_word(exec_path)
+    )
+}
+
+/// Remove the unit at `path`. Returns whether there was one.
+pub fn remove_service_unit(path: &Path) -> std::io::Result<bool> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
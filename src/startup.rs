@@ -0,0 +1,93 @@
+use std::time::{Duration, Instant};
+
+// Bus name owned by the StatusNotifier host that displays tray icons
+pub const STATUS_NOTIFIER_WATCHER: &str = "org.kde.StatusNotifierWatcher";
+// Probes run once more this long after startup to correct early misdetections
+pub const REPROBE_AFTER: Duration = Duration::from_secs(60);
+
+/// Answers whether a well-known D-Bus name currently has an owner.
+pub trait BusNameChecker {
+    fn has_owner(&self, name: &str) -> bool;
+}
+
+/// Asks the session bus via org.freedesktop.DBus.NameHasOwner.
+#[derive(Debug, Default)]
+pub struct SessionBusChecker {}
+
+impl BusNameChecker for SessionBusChecker {
+    fn has_owner(&self, name: &str) -> bool {
+        use gio::prelude::*;
+
+        let Ok(conn) = gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE) else {
+            return false;
+        };
+        conn.call_sync(
+            Some("org.freedesktop.DBus"),
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "NameHasOwner",
+            Some(&(name,).to_variant()),
+            Some(glib::VariantTy::new("(b)").expect("valid variant type")),
+            gio::DBusCallFlags::NONE,
+            1000,
+            gio::Cancellable::NONE,
+        )
+        .ok()
+        .and_then(|reply| reply.get::<(bool,)>())
+        .is_some_and(|(owned,)| owned)
+    }
+}
+
+/// What the caller should do after `StartupGate::poll`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum GateAction {
+    // keep showing the neutral state and poll again later
+    Wait,
+    // the session looks ready (or the grace period ran out): run the probes
+    Probe,
+    // the one deferred re-run of the probes
+    Reprobe,
+    // nothing left to do; stop polling
+    Done,
+}
+
+/// Defers device/capability probes after an early autostart until the tray host is
+/// up or `grace` has passed, then allows exactly one re-probe at REPROBE_AFTER.
+/// Time is passed in so the logic can be tested without sleeping.
+#[derive(Debug)]
+pub struct StartupGate {
+    started: Instant,
+    grace: Duration,
+    probed: bool,
+    reprobed: bool,
+}
+
+impl StartupGate {
+    pub fn new(grace: Duration, now: Instant) -> Self {
+        Self {
+            started: now,
+            grace,
+            probed: false,
+            reprobed: false,
+        }
+    }
+
+    pub fn poll(&mut self, now: Instant, bus: &dyn BusNameChecker) -> GateAction {
+        let elapsed = now.saturating_duration_since(self.started);
+        if !self.probed {
+            if elapsed >= self.grace || bus.has_owner(STATUS_NOTIFIER_WATCHER) {
+                self.probed = true;
+                return GateAction::Probe;
+            }
+            return GateAction::Wait;
+        }
+        if !self.reprobed {
+            if elapsed >= REPROBE_AFTER {
+                self.reprobed = true;
+                return GateAction::Reprobe;
+            }
+            return GateAction::Wait;
+        }
+        GateAction::Done
+    }
+}
@@ -0,0 +1,154 @@
+// Recolouring of the tray's SVG icons: custom icon colours, the attention red and
+// the monochrome icon colours all go through here.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use xmltree::{Element, XMLNode};
+
+// Presentation properties that carry a paint colour, as attributes or in CSS
+const COLOR_PROPERTIES: [&str; 4] = ["fill", "stroke", "color", "stop-color"];
+
+// Lowercase `#rrggbb` for hex colours (short forms expanded, alpha dropped) so
+// "#FF0", "#ffff00" and "#ffff00aa" compare equal; other values are only lowercased
+fn normalize_color(value: &str) -> String {
+    let v = value.trim().to_ascii_lowercase();
+    if let Some(hex) = v.strip_prefix('#')
+        && hex.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        match hex.len() {
+            3 | 4 => return format!("#{}", hex[..3].chars().flat_map(|c| [c, c]).collect::<String>()),
+            6 | 8 => return format!("#{}", &hex[..6]),
+            _ => {}
+        }
+    }
+    v
+}
+
+struct Recolor {
+    color: String,
+    preserve: Vec<String>,
+}
+
+impl Recolor {
+    // The replacement for a paint `value`, or None to leave it alone: no paint,
+    // gradient/pattern references and preserved colours are kept
+    fn paint(&self, value: &str) -> Option<&str> {
+        let v = value.trim();
+        let keep = v.is_empty()
+            || ["none", "transparent", "inherit"].iter().any(|k| v.eq_ignore_ascii_case(k))
+            || v.starts_with("url(")
+            || self.preserve.contains(&normalize_color(v));
+        (!keep).then_some(self.color.as_str())
+    }
+
+    // Rewrite the colour properties of a CSS declaration list ("fill:#000;stroke:#fff")
+    fn declarations(&self, css: &str) -> String {
+        css.split(';')
+            .filter(|d| !d.trim().is_empty())
+            .map(|d| {
+                let Some((key, value)) = d.split_once(':') else {
+                    return d.to_string();
+                };
+                let name = key.trim().to_ascii_lowercase();
+                if !COLOR_PROPERTIES.contains(&name.as_str()) {
+                    return d.to_string();
+                }
+                let (value, important) = match value.find('!') {
+                    Some(i) => (&value[..i], &value[i..]),
+                    None => (value, ""),
+                };
+                match self.paint(value) {
+                    Some(color) if important.is_empty() => format!("{}:{}", name, color),
+                    Some(color) => format!("{}:{} {}", name, color, important.trim()),
+                    None => d.to_string(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+
+    // Rewrite the declaration blocks of a <style> sheet, leaving selectors untouched
+    fn stylesheet(&self, css: &str) -> String {
+        let mut out = String::new();
+        let mut rest = css;
+        while let Some(open) = rest.find('{') {
+            let Some(len) = rest[open..].find('}') else {
+                break;
+            };
+            out.push_str(&rest[..=open]);
+            out.push_str(&self.declarations(&rest[open + 1..open + len]));
+            out.push('}');
+            rest = &rest[open + len + 1..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    fn element(&self, elem: &mut Element) {
+        for property in COLOR_PROPERTIES {
+            if let Some(value) = elem.attributes.get_mut(property)
+                && let Some(color) = self.paint(value)
+            {
+                *value = color.to_string();
+            }
+        }
+        if let Some(style) = elem.attributes.get_mut("style") {
+            *style = self.declarations(style);
+        }
+        let is_stylesheet = elem.name.eq_ignore_ascii_case("style");
+        for child in elem.children.iter_mut() {
+            match child {
+                XMLNode::Element(e) => self.element(e),
+                XMLNode::Text(css) | XMLNode::CData(css) if is_stylesheet => *css = self.stylesheet(css),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Set every fill/stroke/color in `data` to `color`, in attributes, inline `style`
+/// declarations and `<style>` sheets, on any element including groups. `currentColor`
+/// counts as a colour; `none`, `url(...)` references and colours matching `preserve`
+/// (compared ignoring case and alpha) are kept. Shapes with no fill anywhere would
+/// render black, so a root without a fill gets `color` as its fill.
+pub fn recolor_svg_data(data: &str, color: &str, preserve: &[&str]) -> Option<Vec<u8>> {
+    let mut root = Element::parse(data.as_bytes()).ok()?;
+    let recolor = Recolor {
+        color: color.to_string(),
+        preserve: preserve.iter().map(|c| normalize_color(c)).collect(),
+    };
+    let root_has_fill = root.attributes.contains_key("fill")
+        || root.attributes.get("style").is_some_and(|s| {
+            s.split(';')
+                .any(|d| d.split_once(':').is_some_and(|(k, _)| k.trim().eq_ignore_ascii_case("fill")))
+        });
+    if !root_has_fill {
+        root.attributes.insert("fill".to_string(), color.to_string());
+    }
+    recolor.element(&mut root);
+
+    let mut buf: Vec<u8> = Vec::new();
+    root.write(&mut buf).ok()?;
+    Some(buf)
+}
+
+/// Recolour `original` (see `recolor_svg_data`) into a file in `dir` and return its
+/// path. The name is derived from the source, colour and preserved colours, and the
+/// file is only rewritten when its content changes, so repeated calls reuse it.
+pub fn recolor_svg_to_temp(original: &Path, color: &str, preserve: &[&str], dir: &Path) -> Option<PathBuf> {
+    let data = std::fs::read_to_string(original).ok()?;
+    let buf = recolor_svg_data(&data, color, preserve)?;
+
+    let mut hasher = DefaultHasher::new();
+    (original, color, preserve).hash(&mut hasher);
+    let path = dir.join(format!("rivalcfg-recolor-{:016x}.svg", hasher.finish()));
+    if std::fs::read(&path).ok().as_deref() != Some(buf.as_slice())
+        && let Err(e) = std::fs::create_dir_all(dir).and_then(|()| std::fs::write(&path, &buf))
+    {
+        eprintln!("[rivalcfg-tray] Failed to write recolored SVG to {}: {}", path.display(), e);
+        return None;
+    }
+    Some(path)
+}
@@ -0,0 +1,106 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use tempfile::NamedTempFile;
+
+/// Upper bound on rendered PNGs kept on disk at once
+pub const MAX_LIVE_TEMP_FILES: usize = 16;
+
+/// Keeps a temp file alive for as long as the guard exists; dropping the guard
+/// deletes the file.
+#[derive(Debug)]
+pub struct TempFileGuard {
+    file: NamedTempFile,
+    path: PathBuf,
+}
+
+impl TempFileGuard {
+    pub fn new(file: NamedTempFile) -> Self {
+        let path = file.path().to_path_buf();
+        Self { file, path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Delete the file now, reporting failures other than it already being gone
+    fn close(self) {
+        if let Err(e) = self.file.close()
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            eprintln!("[rivalcfg-tray] Warning: Failed to cleanup temp file {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Owns the temp files (rendered PNGs) this process created. Each entry is a
+/// `TempFileGuard`, so dropping the entry deletes the file; eviction, removal and
+/// clearing are therefore deterministic. Entries are kept in least recently used
+/// order, oldest first.
+#[derive(Debug)]
+pub struct TempFileRegistry {
+    cap: usize,
+    entries: VecDeque<TempFileGuard>,
+}
+
+impl TempFileRegistry {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            cap: cap.max(1),
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Take ownership of `file` as the most recently used entry, then evict the
+    /// least recently used files beyond the cap. `keep` (the PNG on display) is
+    /// never evicted. Returns the path of the registered file.
+    pub fn insert(&mut self, file: TempFileGuard, keep: Option<&Path>) -> PathBuf {
+        let path = file.path().to_path_buf();
+        self.entries.retain(|p| p.path() != path.as_path());
+        self.entries.push_back(file);
+        while self.entries.len() > self.cap {
+            let Some(pos) = self
+                .entries
+                .iter()
+                .position(|p| p.path() != path.as_path() && Some(p.path()) != keep)
+            else {
+                break;
+            };
+            if let Some(evicted) = self.entries.remove(pos) {
+                evicted.close();
+            }
+        }
+        path
+    }
+
+    /// Mark `path` as just used, e.g. on a cache hit
+    pub fn touch(&mut self, path: &Path) {
+        if let Some(pos) = self.entries.iter().position(|p| p.path() == path)
+            && let Some(entry) = self.entries.remove(pos)
+        {
+            self.entries.push_back(entry);
+        }
+    }
+
+    /// Delete `path` if it is registered
+    pub fn remove(&mut self, path: &Path) {
+        if let Some(pos) = self.entries.iter().position(|p| p.path() == path)
+            && let Some(entry) = self.entries.remove(pos)
+        {
+            entry.close();
+        }
+    }
+
+    /// Delete every registered file except `keep`, which stays registered
+    pub fn retain_only(&mut self, keep: Option<&Path>) {
+        let (kept, dropped): (VecDeque<_>, VecDeque<_>) =
+            self.entries.drain(..).partition(|p| Some(p.path()) == keep);
+        self.entries = kept;
+        dropped.into_iter().for_each(TempFileGuard::close);
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.entries.iter().any(|p| p.path() == path)
+    }
+}
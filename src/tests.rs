@@ -1,40 +1,102 @@
 use super::*;
-use crate::cmd::{CommandOutput, get_battery_level_with_runner, get_mouse_name_with_runner, build_rivalcfg_args};
-use std::collections::HashMap;
+use crate::cmd::{CommandOutput, ChargeStatus, get_battery_level_with_runner, get_mouse_name_with_runner, build_rivalcfg_args, reset_device_with_runner, apply_color_only, apply_settings, apply_settings_with_runner, get_all_device_info_with_runner, DeviceInfo, RivalError};
+use std::collections::{HashMap, VecDeque};
 use std::sync::Mutex;
 use std::fs;
+use crate::actions::desktop_actions_section;
+use crate::svgcolor::recolor_svg_data;
+use crate::notify::{NotifyEvent, Notification, Notifier, Urgency};
+#[cfg(feature = "notifications")]
+use crate::notify::{deliver, notify_send_args};
 
 #[derive(Debug, Default)]
 struct MockCommandRunner {
-    responses: Mutex<HashMap<String, CommandOutput>>,
+    responses: HashMap<String, CommandOutput>,
+    queued: Mutex<HashMap<String, VecDeque<CommandOutput>>>,
     calls: Mutex<Vec<(String, Vec<String>)>>,
 }
 
-impl MockCommandRunner {
-    fn new() -> Self {
-        Self {
-            responses: Mutex::new(HashMap::new()),
+fn mock_key(program: &str, args: &[&str]) -> String {
+    format!("{}|{}", program, args.join("|"))
+}
+
+// Fluent setup for MockCommandRunner: MockCommandRunner::new().with(...).build()
+struct MockCommandRunnerBuilder {
+    responses: HashMap<String, CommandOutput>,
+    queued: HashMap<String, VecDeque<CommandOutput>>,
+}
+
+fn mock_output(stdout: &str, success: bool) -> CommandOutput {
+    CommandOutput {
+        stdout: stdout.to_string(),
+        stderr: String::new(),
+        success,
+        _code: Some(if success { 0 } else { 1 }),
+    }
+}
+
+impl MockCommandRunnerBuilder {
+    fn with(mut self, program: &str, args: &[&str], stdout: &str, success: bool) -> Self {
+        self.responses.insert(mock_key(program, args), mock_output(stdout, success));
+        self
+    }
+
+    // Answers the next call only, ahead of any `with` response; repeat for a sequence
+    fn with_once(mut self, program: &str, args: &[&str], stdout: &str, success: bool) -> Self {
+        self.queued
+            .entry(mock_key(program, args))
+            .or_default()
+            .push_back(mock_output(stdout, success));
+        self
+    }
+
+    fn build(self) -> MockCommandRunner {
+        MockCommandRunner {
+            responses: self.responses,
+            queued: Mutex::new(self.queued),
             calls: Mutex::new(Vec::new()),
         }
     }
+}
 
-    fn set_response(&self, program: &str, args: &[&str], out: CommandOutput) {
-        let key = format!("{}|{}", program, args.join("|"));
-        self.responses.lock().unwrap().insert(key, out);
+impl MockCommandRunner {
+    #[allow(clippy::new_ret_no_self)]
+    fn new() -> MockCommandRunnerBuilder {
+        MockCommandRunnerBuilder {
+            responses: HashMap::new(),
+            queued: HashMap::new(),
+        }
     }
 
-    #[allow(dead_code)]
     fn get_calls(&self) -> Vec<(String, Vec<String>)> {
         self.calls.lock().unwrap().clone()
     }
+
+    // Panics unless exactly one call to `program args` was recorded
+    fn assert_called_once(&self, program: &str, args: &[&str]) {
+        let count = self
+            .get_calls()
+            .iter()
+            .filter(|(p, a)| p == program && a.iter().map(String::as_str).eq(args.iter().copied()))
+            .count();
+        assert_eq!(count, 1, "expected one call to {} {:?}, calls: {:?}", program, args, self.get_calls());
+    }
 }
 
 impl crate::cmd::CommandRunner for MockCommandRunner {
     fn run(&self, program: &str, args: &[&str]) -> CommandOutput {
         let args_vec = args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
         self.calls.lock().unwrap().push((program.to_string(), args_vec.clone()));
-        let key = format!("{}|{}", program, args.join("|"));
-        if let Some(out) = self.responses.lock().unwrap().get(&key) {
+        if let Some(out) = self
+            .queued
+            .lock()
+            .unwrap()
+            .get_mut(&mock_key(program, args))
+            .and_then(VecDeque::pop_front)
+        {
+            return out;
+        }
+        if let Some(out) = self.responses.get(&mock_key(program, args)) {
             return out.clone();
         }
         CommandOutput {
@@ -46,6 +108,28 @@ impl crate::cmd::CommandRunner for MockCommandRunner {
     }
 }
 
+// Resolves every icon name to "/mock/<name>" and records what was asked for
+struct MockIconResolver {
+    requested: Mutex<Vec<String>>,
+}
+
+impl MockIconResolver {
+    fn new() -> Self {
+        Self { requested: Mutex::new(Vec::new()) }
+    }
+
+    fn requested(&self) -> Vec<String> {
+        self.requested.lock().unwrap().clone()
+    }
+}
+
+impl crate::icons::IconResolver for MockIconResolver {
+    fn resolve(&self, name: &str) -> Option<PathBuf> {
+        self.requested.lock().unwrap().push(name.to_string());
+        Some(PathBuf::from("/mock").join(name))
+    }
+}
+
 #[test]
 fn test_validate_sensitivity() {
     assert!(validate_sensitivity("").is_ok());
@@ -53,9 +137,28 @@ fn test_validate_sensitivity() {
     assert!(validate_sensitivity("100").is_ok());
     assert!(validate_sensitivity("16000").is_ok());
     assert!(validate_sensitivity("99").is_err());
+    assert!(validate_sensitivity("16001").is_err());
     assert!(validate_sensitivity("abc").is_err());
 }
 
+#[test]
+fn test_validate_sensitivity_lists() {
+    assert!(validate_sensitivity("400,800,1600").is_ok());
+    assert!(validate_sensitivity("400, 800").is_ok());
+    assert!(validate_sensitivity("100,200,400,800,1600").is_ok());
+    // too many stages
+    assert!(validate_sensitivity("100,200,400,800,1600,3200").is_err());
+    // duplicates
+    assert!(validate_sensitivity("800,800").is_err());
+    // each entry is range checked
+    assert!(validate_sensitivity("400,50").is_err());
+    assert!(validate_sensitivity("400,20000").is_err());
+    // empty or malformed entries
+    assert!(validate_sensitivity("400,").is_err());
+    assert!(validate_sensitivity(",400").is_err());
+    assert!(validate_sensitivity("400,abc").is_err());
+}
+
 #[test]
 fn test_validate_polling_rate() {
     assert!(validate_polling_rate("").is_ok());
@@ -66,99 +169,377 @@ fn test_validate_polling_rate() {
     assert!(validate_polling_rate("42").is_err());
 }
 
+#[test]
+fn validate_settings_accepts_valid_and_empty() {
+    assert!(validate_settings(&Settings::default()).is_empty());
+    let s = Settings {
+        sensitivity: Some("400,800".to_string()),
+        polling_rate: Some("1000".to_string()),
+        sleep_timer: Some("10".to_string()),
+        dim_timer: Some("5".to_string()),
+        buttons: Some("buttons(button1=button1)".to_string()),
+        custom_color: Some("#abc".to_string()),
+        led_mode: Some(LED_MODE_CUSTOM.to_string()),
+        led_color: Some("#ff0000".to_string()),
+        ..Default::default()
+    };
+    assert!(validate_settings(&s).is_empty());
+}
+
+#[test]
+fn validate_settings_collects_every_error() {
+    let s = Settings {
+        sensitivity: Some("50".to_string()),
+        polling_rate: Some("42".to_string()),
+        sleep_timer: Some("soon".to_string()),
+        dim_timer: Some("later".to_string()),
+        buttons: Some("buttons(".to_string()),
+        custom_color: Some("blue".to_string()),
+        led_mode: Some(LED_MODE_CUSTOM.to_string()),
+        led_color: Some("#12".to_string()),
+        ..Default::default()
+    };
+    let errors = validate_settings(&s);
+    let fields: Vec<&str> = errors.iter().map(|e| e.field).collect();
+    assert_eq!(
+        fields,
+        ["sensitivity", "polling_rate", "sleep_timer", "dim_timer", "buttons", "custom_color", "led_color"]
+    );
+    assert!(errors[2].message.contains("Sleep Timer"));
+    assert!(errors[3].message.contains("Dim Timer"));
+    assert_eq!(errors[1].message, validate_polling_rate("42").unwrap_err());
+}
+
+#[test]
+fn validate_settings_skips_led_color_unless_custom() {
+    let mut s = Settings {
+        led_mode: Some(LED_MODE_OFF.to_string()),
+        led_color: Some("nope".to_string()),
+        ..Default::default()
+    };
+    assert!(validate_settings(&s).is_empty());
+    s.led_mode = Some(LED_MODE_CUSTOM.to_string());
+    let errors = validate_settings(&s);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].field, "led_color");
+    // build_rivalcfg_args drops a colour without '#', so validation rejects it too
+    s.led_color = Some("ff0000".to_string());
+    assert_eq!(validate_settings(&s)[0].field, "led_color");
+    s.led_color = Some("#ff0000".to_string());
+    assert!(validate_settings(&s).is_empty());
+}
+
+#[test]
+fn validate_settings_reports_only_bad_fields() {
+    let s = Settings {
+        sensitivity: Some("800".to_string()),
+        sleep_timer: Some("x".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        validate_settings(&s),
+        vec![SettingsValidationError {
+            field: "sleep_timer",
+            message: "Sleep Timer must be an integer value (minutes)".to_string(),
+        }]
+    );
+}
+
 #[test]
 fn test_validate_timer() {
-    assert!(validate_timer("", "Sleep Timer").is_ok());
-    assert!(validate_timer("10", "Sleep Timer").is_ok());
-    assert!(validate_timer("abc", "Dim Timer").is_err());
+    assert!(validate_timer("", "Sleep Timer", "minutes", 0, SLEEP_TIMER_MAX_MINUTES).is_ok());
+    assert!(validate_timer("10", "Sleep Timer", "minutes", 0, SLEEP_TIMER_MAX_MINUTES).is_ok());
+    assert!(validate_dim_timer("abc", DEFAULT_MAX_DIM_TIMER_SECS).is_err());
+    assert!(validate_timer("1200", "Sleep Timer", "minutes", 0, SLEEP_TIMER_MAX_MINUTES).is_ok());
+    assert!(validate_dim_timer("0", DEFAULT_MAX_DIM_TIMER_SECS).is_ok());
+    assert_eq!(
+        validate_timer("99999", "Sleep Timer", "minutes", 0, SLEEP_TIMER_MAX_MINUTES).unwrap_err(),
+        "Sleep Timer must be between 0 and 1200 minutes"
+    );
+    assert_eq!(
+        validate_dim_timer("1201", 1200).unwrap_err(),
+        "Dim Timer must be between 0 and 1200 seconds"
+    );
+    assert!(validate_timer("5", "Dim Timer", "seconds", 10, 20).is_err(), "below the minimum");
+    // without a known device limit the dim timer is capped at the Rival 650's 60 s
+    assert!(validate_dim_timer("60", DEFAULT_MAX_DIM_TIMER_SECS).is_ok());
+    assert!(validate_dim_timer("61", DEFAULT_MAX_DIM_TIMER_SECS).is_err());
+    assert!(validate_dim_timer("61", 1200).is_ok());
 }
 
 #[test]
 fn settings_serde_roundtrip() {
     let s = Settings {
+        version: None,
         sensitivity: Some("800".to_string()),
+        dual_sensitivity: None,
         polling_rate: Some("1000".to_string()),
         sleep_timer: Some("15".to_string()),
         dim_timer: Some("5".to_string()),
         colour_mode: Some("custom".to_string()),
         custom_color: Some("#ff8800".to_string()),
+        icon_size: Some(32),
+        nicknames: None,
+        buttons: None,
+        attention_threshold: None,
+        monochrome_icons: None,
+        low_battery_threshold: None,
+        profiles: None,
+        startup_grace: None,
+        led_mode: None,
+        led_color: None,
+        poll_interval: None,
+        poll_bands: None,
+        notifications: None,
+        devices: None,
+        battery_hysteresis: None,
+        disconnect_after: None,
+        charge_debounce_polls: None,
+        extra_args: None,
+        charge_complete_notifications: None,
+        battery_history: None,
+        show_label: None,
+        menu: None,
+        warning_level: None,
+        critical_level: None,
+        auto_apply: None,
+        auto_apply_on_startup: None,
+        desktop_actions: None,
+        rivalcfg_path: None,
+        icon_theme: None,
+        icon_pack: None,
     };
     let json = serde_json::to_string(&s).expect("serialize");
     let parsed: Settings = serde_json::from_str(&json).expect("deserialize");
     assert_eq!(parsed.colour_mode, s.colour_mode);
     assert_eq!(parsed.custom_color, s.custom_color);
+    assert_eq!(parsed.icon_size, s.icon_size);
 }
 
 #[test]
-fn test_get_battery_level_with_mock_runner_charging() {
-    let mock = MockCommandRunner::new();
-    let stdout = "SteelSeries Rival Options:\nMouse battery: 75% Charging\n".to_string();
-    mock.set_response(
-        "rivalcfg",
-        &["--battery-level"],
-        CommandOutput {
-            stdout: stdout.clone(),
-            stderr: String::new(),
-            success: true,
-            _code: Some(0),
-        },
-    );
+fn test_effective_icon_size_defaults_and_clamps() {
+    assert_eq!(effective_icon_size(None), DEFAULT_ICON_SIZE);
+    assert_eq!(effective_icon_size(Some(48)), 48);
+    assert_eq!(effective_icon_size(Some(4)), 16);
+    assert_eq!(effective_icon_size(Some(4096)), 256);
+    // every supported size is kept; anything else snaps to the nearest one
+    for size in ICON_SIZES {
+        assert_eq!(effective_icon_size(Some(size)), size);
+    }
+    assert_eq!(effective_icon_size(Some(23)), 24);
+    assert_eq!(effective_icon_size(Some(100)), 128);
+    assert_eq!(effective_icon_size(Some(40)), 48);
+}
+
+#[test]
+fn migrate_legacy_colour_switch_to_custom_mode() {
+    let raw = serde_json::json!({
+        "sensitivity": "800",
+        "colour_switch": true,
+    });
+    let s = migrate(raw);
+    assert_eq!(s.version, Some(SETTINGS_VERSION));
+    assert_eq!(s.sensitivity.as_deref(), Some("800"));
+    assert_eq!(s.colour_mode.as_deref(), Some("custom"));
+}
+
+#[test]
+fn migrate_legacy_colour_switch_off_leaves_mode_unset() {
+    let raw = serde_json::json!({ "colour_switch": false });
+    let s = migrate(raw);
+    assert_eq!(s.colour_mode, None);
+}
 
+#[test]
+fn migrate_legacy_numeric_fields_to_strings() {
+    let raw = serde_json::json!({
+        "sensitivity": 1600,
+        "polling_rate": 500,
+        "sleep_timer": 10,
+    });
+    let s = migrate(raw);
+    assert_eq!(s.sensitivity.as_deref(), Some("1600"));
+    assert_eq!(s.polling_rate.as_deref(), Some("500"));
+    assert_eq!(s.sleep_timer.as_deref(), Some("10"));
+}
+
+#[test]
+fn migrate_current_version_is_passthrough() {
+    let raw = serde_json::json!({
+        "version": SETTINGS_VERSION,
+        "colour_mode": "dark",
+        "icon_size": 48,
+    });
+    let s = migrate(raw);
+    assert_eq!(s.colour_mode.as_deref(), Some("dark"));
+    assert_eq!(s.icon_size, Some(48));
+}
+
+#[test]
+fn test_get_battery_level_with_mock_runner_charging() {
+    let mock = MockCommandRunner::new()
+        .with("rivalcfg", &["--battery-level"], "SteelSeries Rival Options:\nMouse battery: 75% Charging\n", true)
+        .build();
     let res = get_battery_level_with_runner(&mock);
-    assert!(res.is_some());
-    let (percent, charging) = res.unwrap();
+    assert!(res.is_ok());
+    let (percent, status) = res.unwrap();
     assert_eq!(percent, 75);
-    assert!(charging);
+    assert_eq!(status, ChargeStatus::Charging);
+    mock.assert_called_once("rivalcfg", &["--battery-level"]);
 }
 
 #[test]
 fn test_get_battery_level_with_mock_runner_discharging() {
-    let mock = MockCommandRunner::new();
-    let stdout = "Mouse battery: 12% Discharging\n".to_string();
-    mock.set_response(
-        "rivalcfg",
-        &["--battery-level"],
-        CommandOutput {
-            stdout: stdout.clone(),
-            stderr: String::new(),
-            success: true,
-            _code: Some(0),
-        },
-    );
+    let mock = MockCommandRunner::new()
+        .with("rivalcfg", &["--battery-level"], "Mouse battery: 12% Discharging\n", true)
+        .build();
     let res = get_battery_level_with_runner(&mock);
-    assert!(res.is_some());
-    let (percent, charging) = res.unwrap();
+    assert!(res.is_ok());
+    let (percent, status) = res.unwrap();
     assert_eq!(percent, 12);
-    assert!(!charging);
+    assert_eq!(status, ChargeStatus::Discharging);
+    mock.assert_called_once("rivalcfg", &["--battery-level"]);
 }
 
 #[test]
-fn test_get_mouse_name_with_mock_runner() {
-    let mock = MockCommandRunner::new();
-    let stdout = "Some header\nMyMouse Options:\n more text\n".to_string();
-    mock.set_response(
-        "rivalcfg",
-        &["--help"],
-        CommandOutput {
-            stdout: stdout.clone(),
-            stderr: String::new(),
-            success: true,
-            _code: Some(0),
-        },
+fn test_get_battery_level_with_mock_runner_charged() {
+    let mock = MockCommandRunner::new()
+        .with("rivalcfg", &["--battery-level"], "Mouse battery: 100% Charged\n", true)
+        .build();
+    let (percent, status) = get_battery_level_with_runner(&mock).unwrap();
+    assert_eq!(percent, 100);
+    assert_eq!(status, ChargeStatus::Charged);
+}
+
+#[test]
+fn test_get_battery_level_charging_at_full_is_charged() {
+    let mock = MockCommandRunner::new()
+        .with("rivalcfg", &["--battery-level"], "Mouse battery: 100% Charging\n", true)
+        .build();
+    let (_, status) = get_battery_level_with_runner(&mock).unwrap();
+    assert_eq!(status, ChargeStatus::Charged);
+}
+
+#[test]
+fn get_battery_level_reports_each_failure() {
+    let failed = MockCommandRunner::new()
+        .with("rivalcfg", &["--battery-level"], "", false)
+        .build();
+    assert!(matches!(get_battery_level_with_runner(&failed), Err(RivalError::CommandFailed(_))));
+
+    let no_device = MockCommandRunner::new()
+        .with("rivalcfg", &["--battery-level"], "E: No supported device found\n", false)
+        .build();
+    assert_eq!(get_battery_level_with_runner(&no_device), Err(RivalError::NoDevice));
+
+    // nothing answered: rivalcfg couldn't be started
+    let missing = MockCommandRunner::new().build();
+    assert!(matches!(get_battery_level_with_runner(&missing), Err(RivalError::SpawnFailed(_))));
+
+    // a wired mouse doesn't list --battery-level, so argparse rejects it
+    let no_battery = MockCommandRunner::new()
+        .with(
+            "rivalcfg",
+            &["--battery-level"],
+            "usage: rivalcfg [-h] [--list]\nrivalcfg: error: unrecognized arguments: --battery-level\n",
+            false,
+        )
+        .build();
+    assert_eq!(get_battery_level_with_runner(&no_battery), Err(RivalError::NoBattery));
+
+    let garbage = MockCommandRunner::new()
+        .with("rivalcfg", &["--battery-level"], "Mouse battery: ???\n", true)
+        .build();
+    assert_eq!(
+        get_battery_level_with_runner(&garbage),
+        Err(RivalError::ParseFailed("Mouse battery: ???\n".to_string()))
     );
+}
+
+#[test]
+fn get_mouse_name_reports_each_failure() {
+    let failed = MockCommandRunner::new().build();
+    assert!(matches!(get_mouse_name_with_runner(&failed), Err(RivalError::CommandFailed(_))));
+
+    // without a device rivalcfg's help has no "<name> Options:" section
+    let no_device = MockCommandRunner::new()
+        .with("rivalcfg", &["--help"], "usage: rivalcfg [-h] [--list]\n", true)
+        .build();
+    assert_eq!(get_mouse_name_with_runner(&no_device), Err(RivalError::NoDevice));
+}
+
+#[test]
+fn rival_error_messages_name_the_failure() {
+    assert_eq!(RivalError::CommandFailed("boom\n".to_string()).to_string(), "rivalcfg command failed: boom");
+    assert_eq!(
+        RivalError::SpawnFailed("No such file or directory".to_string()).to_string(),
+        "could not run rivalcfg: No such file or directory"
+    );
+    assert_eq!(RivalError::NoDevice.to_string(), "no supported device found");
+    assert_eq!(RivalError::NoBattery.to_string(), "the device has no battery");
+    assert_eq!(RivalError::ParseFailed("??".to_string()).to_string(), "could not parse rivalcfg output: ??");
+}
+
+#[test]
+fn test_parse_charge_status_variants() {
+    assert_eq!(crate::parse::parse_charge_status("Mouse battery: 40% Discharging"), ChargeStatus::Discharging);
+    assert_eq!(crate::parse::parse_charge_status("Mouse battery: 40% Charging"), ChargeStatus::Charging);
+    assert_eq!(crate::parse::parse_charge_status("Mouse battery: 100% Charged"), ChargeStatus::Charged);
+    assert_eq!(crate::parse::parse_charge_status("Battery Full"), ChargeStatus::Charged);
+    assert_eq!(crate::parse::parse_charge_status("garbage"), ChargeStatus::Unknown);
+}
+
+#[test]
+fn test_get_mouse_name_with_mock_runner() {
+    let mock = MockCommandRunner::new()
+        .with("rivalcfg", &["--help"], "Some header\nMyMouse Options:\n more text\n", true)
+        .build();
     let res = get_mouse_name_with_runner(&mock);
     assert_eq!(res.unwrap(), "MyMouse");
+    mock.assert_called_once("rivalcfg", &["--help"]);
 }
 
 #[test]
 fn test_build_rivalcfg_args_variations() {
     let s = Settings {
+        version: None,
         sensitivity: Some("800".to_string()),
+        dual_sensitivity: None,
         polling_rate: Some("500".to_string()),
         sleep_timer: Some("10".to_string()),
         dim_timer: Some("3".to_string()),
         colour_mode: None,
         custom_color: None,
+        icon_size: None,
+        nicknames: None,
+        buttons: None,
+        attention_threshold: None,
+        monochrome_icons: None,
+        low_battery_threshold: None,
+        profiles: None,
+        startup_grace: None,
+        led_mode: None,
+        led_color: None,
+        poll_interval: None,
+        poll_bands: None,
+        notifications: None,
+        devices: None,
+        battery_hysteresis: None,
+        disconnect_after: None,
+        charge_debounce_polls: None,
+        extra_args: None,
+        charge_complete_notifications: None,
+        battery_history: None,
+        show_label: None,
+        menu: None,
+        warning_level: None,
+        critical_level: None,
+        auto_apply: None,
+        auto_apply_on_startup: None,
+        desktop_actions: None,
+        rivalcfg_path: None,
+        icon_theme: None,
+        icon_pack: None,
     };
     let args = build_rivalcfg_args(&s);
     assert_eq!(args, vec![
@@ -179,15 +560,3430 @@ fn recolor_svg_temp_creates_file_and_contains_color() {
     let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10">
 <rect width="10" height="10" fill="#000"/>
 </svg>"##;
-    let tmp = std::env::temp_dir().join("rivalcfg-test-input.svg");
-    fs::write(&tmp, svg).expect("write sample svg");
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.svg");
+    fs::write(&input, svg).expect("write sample svg");
 
-    let out = recolor_svg_to_temp(&tmp, "#ff8800");
-    assert!(out.is_some(), "recolor_svg_to_temp returned None");
-    let path = out.unwrap();
+    let path = recolor_svg_to_temp(&input, "#ff8800", &[], dir.path()).expect("recolor_svg_to_temp returned None");
     let data = fs::read_to_string(&path).expect("read recolored svg");
     assert!(data.contains("#ff8800"), "recolored svg should contain the new color");
-    // cleanup
-    let _ = fs::remove_file(tmp);
-    let _ = fs::remove_file(path);
+    assert!(!data.contains("#000"));
+
+    // the same request reuses the file; another colour gets its own
+    assert_eq!(recolor_svg_to_temp(&input, "#ff8800", &[], dir.path()), Some(path.clone()));
+    assert_ne!(recolor_svg_to_temp(&input, "#00ff00", &[], dir.path()), Some(path));
+}
+
+#[test]
+fn recolor_svg_rewrites_inline_css_and_keeps_other_declarations() {
+    let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" fill="#000"><path style="fill:#000;stroke:#FFF ; stroke-width:2;opacity:0.5"/><rect style="fill: none; stroke: url(#grad) !important"/><circle style="FILL:#123 !important"/></svg>"##;
+    let out = String::from_utf8(recolor_svg_data(svg, "#ff8800", &[]).expect("recolor")).unwrap();
+    assert!(out.contains(r#"style="fill:#ff8800;stroke:#ff8800; stroke-width:2;opacity:0.5""#), "{}", out);
+    assert!(out.contains(r#"style="fill: none; stroke: url(#grad) !important""#), "{}", out);
+    assert!(out.contains(r#"style="fill:#ff8800 !important""#), "{}", out);
+}
+
+#[test]
+fn recolor_svg_handles_nested_groups_stylesheets_and_current_color() {
+    let svg = r##"<svg xmlns="http://www.w3.org/2000/svg"><style>.a { fill: #000; stroke-width: 1 } .b{stroke:#fff}</style><g fill="#111" stroke="currentColor"><g color="#222"><path fill="currentColor"/><stop stop-color="#333"/></g></g></svg>"##;
+    let out = String::from_utf8(recolor_svg_data(svg, "#ff8800", &[]).expect("recolor")).unwrap();
+    for old in ["#000", "#111", "#222", "#333", "#fff", "currentColor"] {
+        assert!(!out.contains(old), "{} left in {}", old, out);
+    }
+    assert!(out.contains(".a {fill:#ff8800; stroke-width: 1 } .b{stroke:#ff8800}"), "{}", out);
+    // without a fill on the root, unfilled shapes would have rendered black
+    let root_tag = out.split("<style>").next().unwrap();
+    assert!(root_tag.contains(r##"fill="#ff8800""##), "{}", out);
+}
+
+#[test]
+fn recolor_svg_keeps_preserved_colours() {
+    // the charging bolt keeps its yellow (with or without alpha) while the outline changes
+    let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" fill="#000000"><path d="M1"/><g fill="#FFFF00AA" stroke="#000000aa"><path style="fill:#ff0"/></g></svg>"##;
+    let out = String::from_utf8(recolor_svg_data(svg, "#ff8800", &PRESERVED_ICON_COLORS).expect("recolor")).unwrap();
+    assert!(out.contains(r##"fill="#FFFF00AA""##), "{}", out);
+    assert!(out.contains("fill:#ff0"), "{}", out);
+    assert!(out.contains(r##"stroke="#ff8800""##), "{}", out);
+    assert!(!out.contains("#000000"), "{}", out);
+}
+
+#[test]
+fn battery_state_from_reading() {
+    assert_eq!(
+        BatteryState::from_reading(Some((42, ChargeStatus::Charging))),
+        BatteryState::Connected { level: 42, status: ChargeStatus::Charging }
+    );
+    assert_eq!(BatteryState::from_reading(None), BatteryState::Disconnected);
+}
+
+#[test]
+fn battery_state_transitions_connected_disconnected_connected() {
+    let mut last = None;
+    let connected = BatteryState::Connected { level: 80, status: ChargeStatus::Discharging };
+
+    // first reading always triggers a redraw
+    assert!(record_battery_state(&mut last, connected));
+    // unchanged reading is skipped
+    assert!(!record_battery_state(&mut last, connected));
+    // losing the device must redraw, and only once
+    assert!(record_battery_state(&mut last, BatteryState::Disconnected));
+    assert!(!record_battery_state(&mut last, BatteryState::Disconnected));
+    // reconnecting at the same level must redraw as well
+    assert!(record_battery_state(&mut last, connected));
+    assert_eq!(last, Some(connected));
+}
+
+#[test]
+fn battery_menu_text_for_each_state() {
+    let charging = BatteryState::Connected { level: 55, status: ChargeStatus::Charging };
+    assert_eq!(battery_menu_text(&charging), "Battery: 55%");
+    assert_eq!(status_menu_text(&charging), "Status: Charging");
+    let full = BatteryState::Connected { level: 100, status: ChargeStatus::Charged };
+    assert_eq!(status_menu_text(&full), "Status: Fully charged");
+    assert_eq!(battery_menu_text(&BatteryState::Disconnected), "Battery: not connected");
+    assert_eq!(status_menu_text(&BatteryState::Disconnected), "Status: Disconnected");
+}
+
+#[test]
+fn device_display_name_prefers_nickname() {
+    let raw = "SteelSeries Aerox 3 Wireless Wired Mode";
+    let mut s = Settings::default();
+    assert_eq!(device_display_name(raw, &s), raw);
+
+    set_device_nickname(&mut s, raw, "  Aerox  ");
+    assert_eq!(device_display_name(raw, &s), "Aerox");
+    // keyed on the raw name, so other devices are unaffected
+    assert_eq!(device_display_name("SteelSeries Rival 3", &s), "SteelSeries Rival 3");
+    assert!(s.nicknames.as_ref().unwrap().contains_key(raw));
+}
+
+#[test]
+fn device_display_name_cleans_raw_name() {
+    let s = Settings::default();
+    assert_eq!(device_display_name("  SteelSeries   Rival 3 ", &s), "SteelSeries Rival 3");
+    assert_eq!(device_display_name("   ", &s), DEFAULT_DEVICE_NAME);
+}
+
+#[test]
+fn blank_nickname_clears_entry() {
+    let raw = "SteelSeries Rival 3";
+    let mut s = Settings::default();
+    set_device_nickname(&mut s, raw, "Rival");
+    set_device_nickname(&mut s, raw, "   ");
+    assert!(s.nicknames.is_none());
+    assert_eq!(device_display_name(raw, &s), raw);
+}
+
+#[test]
+fn format_indicator_title_variants() {
+    assert_eq!(format_indicator_title("Rival 3", 75, true), "Rival 3 — 75% (Charging)");
+    assert_eq!(format_indicator_title("Rival 3", 40, false), "Rival 3 — 40%");
+}
+
+#[test]
+fn tray_tooltip_text_covers_all_states() {
+    let full = BatteryState::Connected { level: 100, status: ChargeStatus::Charged };
+    assert_eq!(tray_tooltip_text("Aerox", &full), "Aerox — 100% (Fully charged)");
+    let low = BatteryState::Connected { level: 8, status: ChargeStatus::Discharging };
+    assert_eq!(tray_tooltip_text("Aerox", &low), "Aerox — 8%");
+    assert_eq!(tray_tooltip_text("Aerox", &BatteryState::Disconnected), "Aerox — not connected");
+}
+
+#[test]
+fn load_settings_from_missing_file_returns_defaults() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("default-config.toml");
+    let s = load_settings_from(&path);
+    assert!(s.sensitivity.is_none());
+    assert!(!settings_backup_path(&path).exists());
+}
+
+#[test]
+fn load_settings_from_malformed_file_backs_up_and_returns_defaults() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("default-config.toml");
+    let bad = "sensitivity = \"800\"\npolling_rate = ";
+    fs::write(&path, bad).expect("write bad settings");
+
+    let s = load_settings_from(&path);
+    assert!(s.sensitivity.is_none());
+
+    let backup = dir.path().join("default-config.toml.bak");
+    assert_eq!(settings_backup_path(&path), backup);
+    assert_eq!(fs::read_to_string(&backup).expect("read backup"), bad);
+}
+
+#[test]
+fn load_settings_from_keeps_valid_fields_next_to_a_bad_one() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("default-config.toml");
+    let file = "version = 1\nicon_size = \"32\"\nsensitivity = 800\npolling_rate = \"500\"\n";
+    fs::write(&path, file).expect("write settings");
+
+    let s = load_settings_from(&path);
+    // the string icon size is dropped, the numeric sensitivity is coerced, the rest is kept
+    assert_eq!(s.icon_size, None);
+    assert_eq!(s.sensitivity.as_deref(), Some("800"));
+    assert_eq!(s.polling_rate.as_deref(), Some("500"));
+    assert_eq!(fs::read_to_string(settings_backup_path(&path)).expect("read backup"), file);
+}
+
+#[test]
+fn load_settings_from_valid_file() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("default-config.toml");
+    fs::write(&path, "sensitivity = \"1600\"\n").expect("write settings");
+    let s = load_settings_from(&path);
+    assert_eq!(s.sensitivity.as_deref(), Some("1600"));
+}
+
+#[test]
+fn legacy_json_settings_are_moved_to_the_confy_file_once() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("default-config.toml");
+    let legacy = dir.path().join(LEGACY_SETTINGS_FILE);
+    // an unversioned settings.json, migrated on the way
+    fs::write(&legacy, r#"{ "colour_switch": true, "sensitivity": 1200 }"#).expect("write legacy settings");
+
+    import_legacy_settings(&path);
+    let s = load_settings_from(&path);
+    assert_eq!(s.colour_mode.as_deref(), Some("custom"));
+    assert_eq!(s.sensitivity.as_deref(), Some("1200"));
+    assert_eq!(s.version, Some(SETTINGS_VERSION));
+    assert!(legacy.exists(), "the old file is left in place");
+
+    // a malformed settings.json is backed up like any other settings file
+    let broken = tempfile::tempdir().expect("tempdir");
+    fs::write(broken.path().join(LEGACY_SETTINGS_FILE), "{ nope").expect("write legacy settings");
+    import_legacy_settings(&broken.path().join("default-config.toml"));
+    assert!(settings_backup_path(&broken.path().join(LEGACY_SETTINGS_FILE)).exists());
+}
+
+#[test]
+fn save_settings_to_replaces_the_file_atomically() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("rivalcfg-tray").join("default-config.toml");
+    let first = Settings {
+        sensitivity: Some("800".to_string()),
+        ..Default::default()
+    };
+    save_settings_to(&first, &path).expect("save into a new directory");
+    let second = Settings {
+        sensitivity: Some("1600".to_string()),
+        ..Default::default()
+    };
+    save_settings_to(&second, &path).expect("save over the old file");
+
+    let loaded = load_settings_from(&path);
+    assert_eq!(loaded.sensitivity.as_deref(), Some("1600"));
+    assert_eq!(loaded.version, Some(SETTINGS_VERSION));
+    // the temp file was renamed into place, nothing is left beside it
+    let entries: Vec<_> = fs::read_dir(path.parent().unwrap()).unwrap().flatten().collect();
+    assert_eq!(entries.len(), 1);
+}
+
+#[test]
+fn watchdog_detects_stale_heartbeat() {
+    let start = std::time::Instant::now();
+    let interval = std::time::Duration::from_secs(30);
+    let mut w = crate::watchdog::PollWatchdog::new(interval, start);
+    assert!(!w.is_stale(start + interval * 3));
+    assert!(w.is_stale(start + interval * 3 + std::time::Duration::from_secs(1)));
+
+    w.heartbeat(start + interval * 3);
+    assert!(!w.is_stale(start + interval * 4));
+}
+
+#[test]
+fn watchdog_restarts_once_per_stale_window() {
+    let start = std::time::Instant::now();
+    let interval = std::time::Duration::from_secs(30);
+    let mut w = crate::watchdog::PollWatchdog::new(interval, start);
+
+    let first = start + std::time::Duration::from_secs(300);
+    assert!(w.check(first));
+    assert_eq!(w.restarts(), 1);
+    // still stale shortly after, but a restart was just issued
+    assert!(!w.check(first + std::time::Duration::from_secs(60)));
+    assert_eq!(w.restarts(), 1);
+    // a whole stale window later with no heartbeat, try again
+    assert!(w.check(first + w.stale_after()));
+    assert_eq!(w.restarts(), 2);
+
+    // once the loop is alive again, no restarts
+    let alive = first + std::time::Duration::from_secs(600);
+    w.heartbeat(alive);
+    assert!(!w.check(alive + interval));
+    assert_eq!(w.restarts(), 2);
+}
+
+#[test]
+fn test_build_rivalcfg_args_buttons() {
+    let s = Settings {
+        buttons: Some("buttons(button1=button1; button2=disabled)".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(build_rivalcfg_args(&s), vec![
+        "--buttons".to_string(),
+        "buttons(button1=button1; button2=disabled)".to_string(),
+    ]);
+
+    let empty = Settings {
+        buttons: Some(String::new()),
+        ..Default::default()
+    };
+    assert!(build_rivalcfg_args(&empty).is_empty());
+}
+
+#[test]
+fn test_validate_buttons() {
+    assert!(validate_buttons("").is_ok());
+    assert!(validate_buttons("buttons(button1=button1; button2=button2)").is_ok());
+    assert!(validate_buttons("buttons(layout={a:[1,2]})").is_ok());
+    assert!(validate_buttons("buttons(button1=button1").is_err());
+    assert!(validate_buttons("buttons)button1(").is_err());
+    assert!(validate_buttons("buttons(layout={a:[1,2})]").is_err());
+}
+
+#[test]
+fn start_justification_follows_text_direction() {
+    assert_eq!(start_justification(gtk::TextDirection::Ltr), gtk::Justification::Left);
+    assert_eq!(start_justification(gtk::TextDirection::Rtl), gtk::Justification::Right);
+    // an unset direction lays out like the LTR default
+    assert_eq!(start_justification(gtk::TextDirection::None), gtk::Justification::Left);
+}
+
+#[test]
+fn battery_icon_is_mirrored_only_for_rtl() {
+    let mut img = image::RgbaImage::new(2, 1);
+    img.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+    let original = img.clone();
+
+    mirror_icon_for_direction(&mut img, false);
+    assert_eq!(img, original);
+
+    mirror_icon_for_direction(&mut img, true);
+    assert_eq!(img.get_pixel(0, 0).0, [0, 0, 0, 0]);
+    assert_eq!(img.get_pixel(1, 0).0, [255, 0, 0, 255]);
+}
+
+#[test]
+fn parse_cli_args_force_rtl() {
+    assert_eq!(parse_cli_args(Vec::<String>::new()), CliOptions::default());
+    let opts = parse_cli_args(vec!["--force-rtl".to_string()]);
+    assert!(opts.force_rtl);
+    // unknown arguments are ignored rather than fatal
+    let opts = parse_cli_args(vec!["--bogus".to_string()]);
+    assert!(!opts.force_rtl);
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn parse_cli_args_battery() {
+    let opts = parse_cli_args(vec!["--battery".to_string()]);
+    assert!(opts.battery && !opts.json);
+    let opts = parse_cli_args(vec!["--battery".to_string(), "--json".to_string()]);
+    assert!(opts.battery && opts.json);
+    // --json alone implies --battery
+    let opts = parse_cli_args(vec!["--json".to_string()]);
+    assert!(opts.battery && opts.json);
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn battery_status_line_formats() {
+    assert_eq!(battery_status_line(Some((45, ChargeStatus::Discharging)), false).as_deref(), Some("45 Discharging"));
+    assert_eq!(battery_status_line(Some((80, ChargeStatus::Charging)), false).as_deref(), Some("80 Charging"));
+    assert_eq!(
+        battery_status_line(Some((45, ChargeStatus::Discharging)), true).as_deref(),
+        Some(r#"{"percent":45,"charging":false}"#)
+    );
+    let json: serde_json::Value =
+        serde_json::from_str(&battery_status_line(Some((80, ChargeStatus::Charging)), true).unwrap()).unwrap();
+    assert_eq!(json["percent"], 80);
+    assert_eq!(json["charging"], true);
+    assert_eq!(battery_status_line(None, false), None);
+    assert_eq!(battery_status_line(None, true), None);
+}
+
+#[test]
+fn battery_state_file_roundtrip() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("state.json");
+    assert_eq!(load_battery_state_from(&path), None);
+
+    let state = BatteryState::Connected { level: 64, status: ChargeStatus::Charging };
+    let data = serialize_battery_state(&state, "Rival 3", &Settings::default()).expect("serialize state");
+    fs::write(&path, data).expect("write state");
+    assert_eq!(load_battery_state_from(&path), Some(state));
+
+    // files from before the device names were stored still load
+    fs::write(&path, r#"{"level":50,"status":"discharging"}"#).expect("write state");
+    assert_eq!(
+        load_battery_state_from(&path),
+        Some(BatteryState::Connected { level: 50, status: ChargeStatus::Discharging })
+    );
+}
+
+#[test]
+fn battery_state_file_carries_raw_name_and_nickname() {
+    let raw = "SteelSeries Aerox 3 Wireless Wired Mode";
+    let settings = Settings {
+        nicknames: Some(HashMap::from([(raw.to_string(), "Aerox".to_string())])),
+        ..Default::default()
+    };
+    let state = BatteryState::Connected { level: 80, status: ChargeStatus::Discharging };
+    let data = serialize_battery_state(&state, raw, &settings).expect("serialize state");
+    let persisted: PersistedBattery = serde_json::from_str(&data).expect("parse state");
+    assert_eq!(
+        persisted,
+        PersistedBattery {
+            level: 80,
+            status: ChargeStatus::Discharging,
+            device: raw.to_string(),
+            nickname: "Aerox".to_string(),
+        }
+    );
+}
+
+#[test]
+fn battery_state_file_ignores_disconnected() {
+    // a disconnect must not overwrite the last real reading
+    assert_eq!(serialize_battery_state(&BatteryState::Disconnected, "Rival 3", &Settings::default()), None);
+}
+
+#[test]
+fn icon_recolor_color_follows_colour_switch() {
+    let mut s = Settings::default();
+    assert_eq!(icon_recolor_color(&s), None);
+    s.colour_mode = Some("dark".to_string());
+    assert_eq!(icon_recolor_color(&s).as_deref(), Some(DARK_MODE_COLOR));
+    s.colour_mode = Some("light".to_string());
+    assert_eq!(icon_recolor_color(&s).as_deref(), Some(LIGHT_MODE_COLOR));
+    // a custom colour always wins
+    s.custom_color = Some("#ff8800".to_string());
+    assert_eq!(icon_recolor_color(&s).as_deref(), Some("#ff8800"));
+}
+
+#[test]
+fn failure_tracker_suspends_after_repeated_failures() {
+    use crate::file_writer::{FailureOutcome, FailureTracker};
+    let target = std::path::Path::new("/tmp/rivalcfg-status.json");
+    let start = std::time::Instant::now();
+    let suspend = std::time::Duration::from_secs(900);
+    let mut t = FailureTracker::new(3, suspend);
+
+    assert!(!t.should_skip(target, start));
+    assert_eq!(t.record_failure(target, start), FailureOutcome::Retry);
+    assert_eq!(t.record_failure(target, start), FailureOutcome::Retry);
+    assert_eq!(t.record_failure(target, start), FailureOutcome::Suspended);
+    assert!(t.should_skip(target, start + std::time::Duration::from_secs(60)));
+    // other targets are unaffected
+    assert!(!t.should_skip(std::path::Path::new("/tmp/other"), start));
+    // suspension expires
+    assert!(!t.should_skip(target, start + suspend));
+}
+
+#[test]
+fn failure_tracker_success_clears_state() {
+    use crate::file_writer::FailureTracker;
+    let target = std::path::Path::new("/tmp/rivalcfg-status.json");
+    let now = std::time::Instant::now();
+    let mut t = FailureTracker::new(1, std::time::Duration::from_secs(900));
+    t.record_failure(target, now);
+    assert!(t.should_skip(target, now));
+    assert!(t.record_success(target), "recovery should be reported");
+    assert!(!t.should_skip(target, now));
+    // nothing to recover the second time
+    assert!(!t.record_success(target));
+}
+
+#[test]
+fn file_writer_submit_does_not_block_when_backed_up() {
+    use crate::file_writer::{FailureTracker, FileWriter};
+    use std::sync::mpsc;
+
+    // The worker blocks on the first write until released
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+    let release_rx = Mutex::new(release_rx);
+    let (done_tx, done_rx) = mpsc::channel::<std::path::PathBuf>();
+    let writer = FileWriter::spawn_with(
+        1,
+        FailureTracker::default(),
+        move |path, _| {
+            let _ = release_rx.lock().unwrap().recv();
+            let _ = done_tx.send(path.to_path_buf());
+            Ok(())
+        },
+        |_, _| {},
+    );
+
+    let start = std::time::Instant::now();
+    let mut accepted = 0;
+    for i in 0..5 {
+        if writer.submit(std::path::PathBuf::from(format!("/tmp/target-{}", i)), Vec::new()) {
+            accepted += 1;
+        }
+    }
+    assert!(start.elapsed() < std::time::Duration::from_secs(1), "submit must not block");
+    assert!(accepted < 5, "excess writes should be dropped");
+
+    for _ in 0..accepted {
+        release_tx.send(()).unwrap();
+    }
+    for _ in 0..accepted {
+        done_rx.recv_timeout(std::time::Duration::from_secs(5)).expect("write completes");
+    }
+}
+
+#[test]
+fn file_writer_reports_each_suspension_once() {
+    use crate::file_writer::{FailureTracker, FileWriter};
+    use std::sync::mpsc;
+
+    let suspend = std::time::Duration::from_secs(900);
+    let (attempt_tx, attempt_rx) = mpsc::channel::<()>();
+    let (suspended_tx, suspended_rx) = mpsc::channel::<(std::path::PathBuf, std::time::Duration)>();
+    let writer = FileWriter::spawn_with(
+        8,
+        FailureTracker::new(2, suspend),
+        move |_, _| {
+            let _ = attempt_tx.send(());
+            Err(std::io::Error::other("disk full"))
+        },
+        move |path, for_| {
+            let _ = suspended_tx.send((path.to_path_buf(), for_));
+        },
+    );
+
+    let target = std::path::PathBuf::from("/tmp/rivalcfg-status.json");
+    for _ in 0..4 {
+        assert!(writer.submit(target.clone(), Vec::new()));
+    }
+    let (path, for_) = suspended_rx.recv_timeout(std::time::Duration::from_secs(5)).expect("suspension reported");
+    assert_eq!(path, target);
+    assert_eq!(for_, suspend);
+    // the writes after the suspension are skipped without another report
+    attempt_rx.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+    attempt_rx.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+    drop(writer);
+    assert!(attempt_rx.recv_timeout(std::time::Duration::from_secs(1)).is_err());
+    assert!(suspended_rx.try_recv().is_err());
+}
+
+#[test]
+fn test_reset_device_runs_rivalcfg_reset() {
+    let mock = MockCommandRunner::new()
+        .with("rivalcfg", &["-r"], "Settings reset\n", true)
+        .build();
+    let out = reset_device_with_runner(&mock);
+    assert!(out.success);
+    mock.assert_called_once("rivalcfg", &["-r"]);
+    assert_eq!(mock.get_calls().len(), 1);
+}
+
+#[test]
+fn reset_runs_only_after_confirmation() {
+    let mock = MockCommandRunner::new()
+        .with("rivalcfg", &["-r"], "Settings reset\n", true)
+        .build();
+    assert!(reset_if_confirmed(&mock, || false).is_none());
+    assert!(mock.get_calls().is_empty());
+
+    let out = reset_if_confirmed(&mock, || true).expect("confirmed reset runs");
+    assert!(out.success);
+    mock.assert_called_once("rivalcfg", &["-r"]);
+}
+
+#[test]
+fn attention_state_hysteresis() {
+    let reading = |level| BatteryState::Connected { level, status: ChargeStatus::Discharging };
+    let threshold = 10;
+
+    let mut active = false;
+    active = next_attention_state(active, &reading(11), threshold);
+    assert!(!active);
+    active = next_attention_state(active, &reading(9), threshold);
+    assert!(active, "drops below threshold");
+    // wobbling just above the threshold must not clear it
+    active = next_attention_state(active, &reading(10), threshold);
+    assert!(active);
+    active = next_attention_state(active, &reading(14), threshold);
+    assert!(active);
+    active = next_attention_state(active, &reading(15), threshold);
+    assert!(!active, "clears at threshold + hysteresis");
+    // and once cleared, 10..15 doesn't re-enter
+    active = next_attention_state(active, &reading(12), threshold);
+    assert!(!active);
+}
+
+#[test]
+fn attention_state_clears_when_charging_or_disconnected() {
+    let low = BatteryState::Connected { level: 3, status: ChargeStatus::Discharging };
+    assert!(next_attention_state(false, &low, 10));
+    let charging = BatteryState::Connected { level: 3, status: ChargeStatus::Charging };
+    assert!(!next_attention_state(true, &charging, 10));
+    assert!(!next_attention_state(true, &BatteryState::Disconnected, 10));
+    // threshold 0 disables the attention state entirely
+    assert!(!next_attention_state(false, &low, 0));
+}
+
+#[test]
+fn test_apply_color_only_runs_color_command() {
+    let mock = MockCommandRunner::new()
+        .with("rivalcfg", &["--color", "#ff8800"], "", true)
+        .build();
+    assert!(apply_color_only(&mock, "#ff8800").success);
+    mock.assert_called_once("rivalcfg", &["--color", "#ff8800"]);
+    assert_eq!(mock.get_calls().len(), 1);
+}
+
+#[test]
+fn parse_cli_args_version() {
+    assert!(parse_cli_args(vec!["--version".to_string()]).version);
+    assert!(parse_cli_args(vec!["-V".to_string()]).version);
+    assert!(!parse_cli_args(vec!["--force-rtl".to_string()]).version);
+}
+
+#[test]
+fn crate_version_matches_manifest() {
+    assert_eq!(crate_version(), env!("CARGO_PKG_VERSION"));
+    assert!(!crate_version().is_empty());
+}
+
+fn corpus_dir() -> std::path::PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("corpus")
+}
+
+fn read_corpus(kind: &str, name: &str) -> String {
+    let data = fs::read(corpus_dir().join(kind).join(name)).expect("read corpus file");
+    String::from_utf8_lossy(&data).to_string()
+}
+
+#[test]
+fn corpus_every_parser_handles_every_file() {
+    use crate::parse::{parse_battery, parse_device_list, parse_help_capabilities};
+    let mut count = 0;
+    for kind in ["battery", "help", "list"] {
+        for entry in fs::read_dir(corpus_dir().join(kind)).expect("read corpus dir").flatten() {
+            let data = fs::read(entry.path()).expect("read corpus file");
+            let text = String::from_utf8_lossy(&data);
+            // must not panic on any captured output, whatever command produced it
+            let _ = parse_battery(&text);
+            let _ = parse_device_list(&text);
+            let _ = parse_help_capabilities(&text);
+            count += 1;
+        }
+    }
+    assert!(count > 0, "corpus should not be empty");
+}
+
+#[test]
+fn corpus_battery_outputs() {
+    use crate::parse::{BatteryReading, parse_battery};
+    let reading = |percent, status| Some(BatteryReading { percent, status });
+    assert_eq!(parse_battery(&read_corpus("battery", "discharging-bar.txt")), reading(85, ChargeStatus::Discharging));
+    assert_eq!(parse_battery(&read_corpus("battery", "charging-bar.txt")), reading(27, ChargeStatus::Charging));
+    assert_eq!(parse_battery(&read_corpus("battery", "charging-full.txt")), reading(100, ChargeStatus::Charged));
+    assert_eq!(parse_battery(&read_corpus("battery", "charged.txt")), reading(100, ChargeStatus::Charged));
+    assert_eq!(parse_battery(&read_corpus("battery", "discharging-inline.txt")), reading(12, ChargeStatus::Discharging));
+    assert_eq!(parse_battery(&read_corpus("battery", "unsupported.txt")), None);
+    assert_eq!(parse_battery(&read_corpus("battery", "missing-percent.txt")), None);
+    assert_eq!(parse_battery(&read_corpus("battery", "out-of-range.txt")), None);
+}
+
+#[test]
+fn corpus_help_outputs() {
+    use crate::parse::parse_help_capabilities;
+    let caps = parse_help_capabilities(&read_corpus("help", "aerox3-wireless.txt"));
+    assert_eq!(caps.device_name.as_deref(), Some("SteelSeries Aerox 3 Wireless (2.4 GHz wireless mode)"));
+    for flag in ["--sensitivity", "--polling-rate", "--sleep-timer", "--dim-timer", "--buttons", "--battery-level"] {
+        assert!(caps.supports(flag), "missing {}", flag);
+    }
+    // generic options listed before the device section are not device capabilities
+    assert!(!caps.supports("--list"));
+
+    assert_eq!(caps.max_dim_timer, Some(1200));
+
+    let caps = parse_help_capabilities(&read_corpus("help", "rival3-wired.txt"));
+    assert_eq!(caps.device_name.as_deref(), Some("SteelSeries Rival 3"));
+    assert_eq!(caps.max_dim_timer, None);
+    assert!(caps.supports("--color"));
+    assert!(!caps.supports("--battery-level"));
+
+    assert!(!caps.supports_dual_sensitivity());
+
+    let caps = parse_help_capabilities(&read_corpus("help", "rival310-dual.txt"));
+    assert_eq!(caps.device_name.as_deref(), Some("SteelSeries Rival 310"));
+    assert!(caps.supports_dual_sensitivity());
+
+    let caps = parse_help_capabilities(&read_corpus("help", "no-device.txt"));
+    assert_eq!(caps.device_name, None);
+    assert!(caps.options.is_empty());
+}
+
+#[test]
+fn corpus_list_outputs() {
+    use crate::parse::parse_device_list;
+    let devices = parse_device_list(&read_corpus("list", "supported.txt"));
+    assert_eq!(devices.len(), 4);
+    assert_eq!(devices[0].name, "SteelSeries Aerox 3 Wireless Wired Mode");
+    assert_eq!(devices[0].usb_id, "1038:1836");
+
+    let devices = parse_device_list(&read_corpus("list", "piped.txt"));
+    assert_eq!(devices.len(), 2);
+    assert_eq!(devices[1].name, "SteelSeries Sensei 310");
+
+    assert!(parse_device_list(&read_corpus("list", "garbage.txt")).is_empty());
+}
+
+#[test]
+fn parsers_never_panic_on_pseudo_random_input() {
+    use crate::parse::{parse_battery, parse_device_list, parse_help_capabilities};
+    // Cheap deterministic stand-in for the cargo-fuzz targets so plain `cargo test`
+    // still exercises odd input (multi-byte chars, huge numbers, stray separators).
+    let alphabet: Vec<char> = "0123456789%:|-—é€ \n\tabcdefABCDEF[]=OptionsChargingDischargedFull".chars().collect();
+    let mut seed: u64 = 0x2545F4914F6CDD1D;
+    for _ in 0..2000 {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        let len = (seed % 64) as usize;
+        let input: String = (0..len)
+            .map(|i| alphabet[((seed >> (i % 48)) as usize + i) % alphabet.len()])
+            .collect();
+        let _ = parse_battery(&input);
+        let _ = parse_device_list(&input);
+        let _ = parse_help_capabilities(&input);
+    }
+    assert_eq!(parse_battery("Charging 99999999999999999999 %"), None);
+}
+
+#[test]
+fn rgba_from_hex_rejects_multibyte_input() {
+    // six bytes but not six hex digits; byte slicing used to panic here
+    assert!(rgba_from_hex("#a€bc").is_none());
+    assert!(rgba_from_hex("€€").is_none());
+}
+
+#[test]
+fn monochrome_mode_follows_desktop_unless_overridden() {
+    assert!(monochrome_mode_enabled(None, Some("GNOME")));
+    assert!(monochrome_mode_enabled(None, Some("ubuntu:GNOME")));
+    assert!(!monochrome_mode_enabled(None, Some("KDE")));
+    assert!(!monochrome_mode_enabled(None, None));
+    assert!(!monochrome_mode_enabled(Some(false), Some("GNOME")));
+    assert!(monochrome_mode_enabled(Some(true), Some("KDE")));
+}
+
+#[test]
+fn icon_theme_picks_variant_and_foreground() {
+    let with_theme = |theme: &str| Settings {
+        icon_theme: Some(theme.to_string()),
+        ..Default::default()
+    };
+    let light = with_theme("monochrome-light");
+    let dark = with_theme("monochrome-dark");
+    let color = with_theme("color");
+    assert_eq!(icon_theme(&light, None), IconTheme::MonochromeLight);
+    assert_eq!(icon_theme(&dark, None), IconTheme::MonochromeDark);
+    // an explicit theme beats the GNOME default
+    assert_eq!(icon_theme(&color, Some("GNOME")), IconTheme::Color);
+    // values written before the rename still work
+    assert_eq!(icon_theme(&with_theme("symbolic-light"), None), IconTheme::MonochromeLight);
+    assert_eq!(icon_theme(&with_theme("symbolic-dark"), None), IconTheme::MonochromeDark);
+
+    // monochrome themes render the icon tinted to their foreground
+    assert_eq!(icon_color_override(IconTheme::MonochromeLight, false, &light).as_deref(), Some(DARK_MODE_COLOR));
+    assert_eq!(icon_color_override(IconTheme::MonochromeDark, false, &dark).as_deref(), Some(LIGHT_MODE_COLOR));
+    // coloured icons are left to the colour switch; attention red always wins
+    assert_eq!(icon_color_override(IconTheme::Color, false, &color), None);
+    assert_eq!(icon_color_override(IconTheme::MonochromeDark, true, &dark).as_deref(), Some(ATTENTION_COLOR));
+
+    // a custom icon colour tints monochrome icons too
+    let custom = Settings {
+        custom_color: Some("#ff8800".to_string()),
+        ..dark
+    };
+    assert_eq!(icon_color_override(IconTheme::MonochromeDark, false, &custom).as_deref(), Some("#ff8800"));
+}
+
+#[test]
+fn icon_theme_falls_back_to_monochrome_icons_and_colour_switch() {
+    let mut s = Settings::default();
+    assert_eq!(icon_theme(&s, Some("KDE")), IconTheme::Color);
+    assert_eq!(icon_theme(&s, Some("GNOME")), IconTheme::MonochromeLight);
+    s.colour_mode = Some("light".to_string());
+    assert_eq!(icon_theme(&s, Some("GNOME")), IconTheme::MonochromeDark);
+    s.monochrome_icons = Some(false);
+    assert_eq!(icon_theme(&s, Some("GNOME")), IconTheme::Color);
+    s.icon_theme = Some("neon".to_string());
+    assert_eq!(icon_theme(&s, Some("GNOME")), IconTheme::Color);
+}
+
+#[test]
+fn recolor_svg_data_replaces_every_paint() {
+    let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" fill="#000000"><path fill="#123456" stroke="none" style="fill:#abcdef"/></svg>"##;
+    let out = String::from_utf8(recolor_svg_data(svg, "currentColor", &[]).expect("recolor")).unwrap();
+    assert!(!out.contains("#000000"));
+    assert!(!out.contains("#123456"));
+    assert!(out.contains("fill:currentColor"));
+    assert!(out.contains("stroke=\"none\""));
+}
+
+#[test]
+fn mock_runner_reports_unmocked_commands_as_failures() {
+    let mock = MockCommandRunner::new()
+        .with("rivalcfg", &["--battery-level"], "Mouse battery: 50% Discharging\n", true)
+        .with("rivalcfg", &["-r"], "", false)
+        .build();
+    assert!(!reset_device_with_runner(&mock).success);
+    assert!(matches!(get_mouse_name_with_runner(&mock), Err(RivalError::CommandFailed(_))));
+    mock.assert_called_once("rivalcfg", &["-r"]);
+    mock.assert_called_once("rivalcfg", &["--help"]);
+}
+
+#[test]
+fn xdg_icon_dirs_order_and_defaults() {
+    let dirs = xdg_icon_dirs(Some("/data/home"), Some("/opt/share:/usr/share"), Some(PathBuf::from("/home/u")));
+    assert_eq!(
+        dirs,
+        vec![
+            PathBuf::from("/data/home/icons/hicolor/scalable/apps"),
+            PathBuf::from("/data/home/icons/rivalcfgtray"),
+            PathBuf::from("/opt/share/icons/hicolor/scalable/apps"),
+            PathBuf::from("/opt/share/icons/rivalcfgtray"),
+            PathBuf::from("/usr/share/icons/hicolor/scalable/apps"),
+            PathBuf::from("/usr/share/icons/rivalcfgtray"),
+        ]
+    );
+    let defaults = xdg_icon_dirs(None, None, Some(PathBuf::from("/home/u")));
+    assert_eq!(defaults[0], PathBuf::from("/home/u/.local/share/icons/hicolor/scalable/apps"));
+    assert!(defaults.contains(&PathBuf::from("/usr/local/share/icons/rivalcfgtray")));
+}
+
+#[test]
+fn locate_icon_prefers_override_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let icon = dir.path().join("rivalcfg-test-override-icon.svg");
+    std::fs::write(&icon, "<svg/>").unwrap();
+    // the same icon in an XDG data dir loses to the override
+    let data_home = tempfile::tempdir().unwrap();
+    let xdg_icons = data_home.path().join("icons").join("rivalcfgtray");
+    std::fs::create_dir_all(&xdg_icons).unwrap();
+    std::fs::write(xdg_icons.join("rivalcfg-test-override-icon.svg"), "<svg/>").unwrap();
+    let xdg = xdg_icon_dirs(data_home.path().to_str(), Some("/nonexistent"), None);
+
+    assert_eq!(
+        locate_icon_in("rivalcfg-test-override-icon.svg", Some(dir.path()), &xdg),
+        Some((icon, IconSource::OverrideDir))
+    );
+    assert_eq!(
+        locate_icon_in("rivalcfg-test-override-icon.svg", None, &xdg),
+        Some((xdg_icons.join("rivalcfg-test-override-icon.svg"), IconSource::XdgDataDir))
+    );
+}
+
+#[test]
+fn reapply_availability() {
+    let s = Settings {
+        sensitivity: Some("800".to_string()),
+        ..Default::default()
+    };
+    assert!(reapply_available(Some(&s), false));
+    assert!(!reapply_available(Some(&s), true), "disabled while a run is in flight");
+    assert!(!reapply_available(None, false), "disabled without saved settings");
+    // colour-only settings have nothing to push to the mouse
+    let icon_only = Settings {
+        colour_mode: Some("dark".to_string()),
+        ..Default::default()
+    };
+    assert!(!reapply_available(Some(&icon_only), false));
+}
+
+#[test]
+fn apply_settings_runs_one_merged_command() {
+    let s = Settings {
+        sensitivity: Some("800,1600".to_string()),
+        polling_rate: Some("1000".to_string()),
+        dim_timer: Some("5".to_string()),
+        ..Default::default()
+    };
+    let args = ["--sensitivity", "800,1600", "--polling-rate", "1000", "--dim-timer", "5"];
+    let mock = MockCommandRunner::new().with("rivalcfg", &args, "", true).build();
+    assert!(apply_settings(&mock, &s).expect("something to apply").success);
+    mock.assert_called_once("rivalcfg", &args);
+    assert_eq!(mock.get_calls().len(), 1);
+
+    let empty = MockCommandRunner::new().build();
+    assert!(apply_settings(&empty, &Settings::default()).is_none());
+    assert!(empty.get_calls().is_empty());
+}
+
+#[test]
+fn apply_settings_with_runner_passes_args_and_propagates_errors() {
+    let s = Settings {
+        sensitivity: Some("1600".to_string()),
+        polling_rate: Some("500".to_string()),
+        ..Default::default()
+    };
+    let args = ["--sensitivity", "1600", "--polling-rate", "500"];
+    let ok = MockCommandRunner::new().with("rivalcfg", &args, "", true).build();
+    assert_eq!(apply_settings_with_runner(&s, &ok), Ok(()));
+    ok.assert_called_once("rivalcfg", &args);
+
+    // unmocked commands fail with a "No mock response" stderr, which is passed on
+    let failing = MockCommandRunner::new().build();
+    assert_eq!(
+        apply_settings_with_runner(&s, &failing),
+        Err(format!("No mock response for rivalcfg {:?}", args))
+    );
+
+    // nothing to apply is not an error and runs nothing
+    let empty = MockCommandRunner::new().build();
+    assert_eq!(apply_settings_with_runner(&Settings::default(), &empty), Ok(()));
+    assert!(empty.get_calls().is_empty());
+}
+
+#[test]
+fn locate_icon_walks_xdg_data_dirs() {
+    let home = tempfile::tempdir().unwrap();
+    let system = tempfile::tempdir().unwrap();
+    let home_icons = home.path().join("icons").join("rivalcfgtray");
+    let system_icons = system.path().join("icons").join("hicolor").join("scalable").join("apps");
+    std::fs::create_dir_all(&home_icons).unwrap();
+    std::fs::create_dir_all(&system_icons).unwrap();
+    std::fs::write(home_icons.join("rivalcfg-test-xdg-both.svg"), "<svg/>").unwrap();
+    std::fs::write(system_icons.join("rivalcfg-test-xdg-both.svg"), "<svg/>").unwrap();
+    std::fs::write(system_icons.join("rivalcfg-test-xdg-system.svg"), "<svg/>").unwrap();
+
+    let data_dirs = format!("/nonexistent:{}", system.path().display());
+    let dirs = xdg_icon_dirs(home.path().to_str(), Some(&data_dirs), None);
+
+    // $XDG_DATA_HOME wins over $XDG_DATA_DIRS
+    assert_eq!(
+        locate_icon_in("rivalcfg-test-xdg-both.svg", None, &dirs),
+        Some((home_icons.join("rivalcfg-test-xdg-both.svg"), IconSource::XdgDataDir))
+    );
+    assert_eq!(
+        locate_icon_in("rivalcfg-test-xdg-system.svg", None, &dirs),
+        Some((system_icons.join("rivalcfg-test-xdg-system.svg"), IconSource::XdgDataDir))
+    );
+    assert_eq!(find_icon_in_dirs("rivalcfg-test-xdg-missing.svg", &dirs), None);
+}
+
+// Collects notifications instead of showing them
+#[derive(Default)]
+struct RecordingNotifier {
+    sent: Mutex<Vec<Notification>>,
+}
+
+impl Notifier for RecordingNotifier {
+    fn notify(&self, notification: Notification) {
+        self.sent.lock().unwrap().push(notification);
+    }
+}
+
+impl RecordingNotifier {
+    fn summaries(&self) -> Vec<String> {
+        self.sent.lock().unwrap().iter().map(|n| n.summary.clone()).collect()
+    }
+}
+
+#[test]
+fn low_battery_notification_fires_once_per_drop() {
+    let reading = |level| BatteryState::Connected { level, status: ChargeStatus::Discharging };
+    let notifier = RecordingNotifier::default();
+    let mut notified = false;
+
+    for level in [20, 16, 15] {
+        assert!(!maybe_notify_low_battery(&notifier, &mut notified, "Rival 3", &reading(level), 15));
+    }
+    assert!(maybe_notify_low_battery(&notifier, &mut notified, "Rival 3", &reading(14), 15));
+    // staying low, or losing the mouse for a poll, does not repeat it
+    assert!(!maybe_notify_low_battery(&notifier, &mut notified, "Rival 3", &reading(12), 15));
+    assert!(!maybe_notify_low_battery(&notifier, &mut notified, "Rival 3", &BatteryState::Disconnected, 15));
+    assert_eq!(notifier.summaries(), ["Rival 3: Low Battery (14%)"]);
+    assert_eq!(notifier.sent.lock().unwrap()[0].urgency, Urgency::Critical);
+
+    // back above the threshold re-arms it
+    assert!(!maybe_notify_low_battery(&notifier, &mut notified, "Rival 3", &reading(16), 15));
+    assert!(maybe_notify_low_battery(&notifier, &mut notified, "Rival 3", &reading(14), 15));
+    assert_eq!(notifier.summaries().len(), 2);
+}
+
+#[test]
+fn low_battery_notification_skips_charging_and_zero_threshold() {
+    let mut notified = false;
+    let charging = BatteryState::Connected { level: 5, status: ChargeStatus::Charging };
+    assert_eq!(low_battery_notification(&mut notified, &charging, 15), None);
+    let low = BatteryState::Connected { level: 5, status: ChargeStatus::Discharging };
+    assert_eq!(low_battery_notification(&mut notified, &low, 0), None);
+    assert_eq!(low_battery_notification(&mut notified, &low, 15), Some(5));
+}
+
+#[test]
+fn low_battery_notification_rearms_when_charging_starts() {
+    let reading = |level, status| BatteryState::Connected { level, status };
+    let mut notified = false;
+    assert_eq!(low_battery_notification(&mut notified, &reading(10, ChargeStatus::Discharging), 15), Some(10));
+    assert_eq!(low_battery_notification(&mut notified, &reading(9, ChargeStatus::Discharging), 15), None);
+    // plugged in briefly without getting back above the threshold
+    assert_eq!(low_battery_notification(&mut notified, &reading(11, ChargeStatus::Charging), 15), None);
+    assert!(!notified);
+    // the next discharge cycle announces again
+    assert_eq!(low_battery_notification(&mut notified, &reading(11, ChargeStatus::Discharging), 15), Some(11));
+    assert_eq!(low_battery_notification(&mut notified, &reading(10, ChargeStatus::Discharging), 15), None);
+    // an unknown status doesn't count as charging
+    assert_eq!(low_battery_notification(&mut notified, &reading(10, ChargeStatus::Unknown), 15), None);
+    assert!(notified);
+}
+
+#[test]
+fn notifications_setting_disables_low_battery_alerts() {
+    assert!(effective_device_settings(&Settings::default(), "Rival 3").notifications);
+    let off = Settings { notifications: Some(false), ..Default::default() };
+    assert!(!effective_device_settings(&off, "Rival 3").notifications);
+    let s: Settings = serde_json::from_str(r#"{"notifications": false}"#).unwrap();
+    assert_eq!(s.notifications, Some(false));
+}
+
+#[test]
+fn profile_store_roundtrip() {
+    let mut store = Settings {
+        colour_mode: Some("dark".to_string()),
+        ..Default::default()
+    };
+    let fps = Settings {
+        sensitivity: Some("1600,3200".to_string()),
+        polling_rate: Some("1000".to_string()),
+        // icon preferences are not part of a profile
+        custom_color: Some("#ff0000".to_string()),
+        ..Default::default()
+    };
+    let office = Settings {
+        sensitivity: Some("800".to_string()),
+        polling_rate: Some("250".to_string()),
+        sleep_timer: Some("5".to_string()),
+        ..Default::default()
+    };
+    store_profile(&mut store, " FPS ", &fps).unwrap();
+    store_profile(&mut store, "Office", &office).unwrap();
+    assert!(store_profile(&mut store, "  ", &office).is_err());
+
+    let json = serde_json::to_string(&store).expect("serialize");
+    let parsed: Settings = serde_json::from_str(&json).expect("deserialize");
+    assert_eq!(profile_names(&parsed), vec!["FPS".to_string(), "Office".to_string()]);
+    let fps_loaded = &parsed.profiles.as_ref().unwrap()["FPS"];
+    assert_eq!(fps_loaded.sensitivity.as_deref(), Some("1600,3200"));
+    assert_eq!(fps_loaded.polling_rate.as_deref(), Some("1000"));
+    assert_eq!(fps_loaded.custom_color, None);
+    assert_eq!(parsed.colour_mode.as_deref(), Some("dark"));
+
+    // migrating a file with profiles keeps them
+    let migrated = migrate(serde_json::from_str(&json).unwrap());
+    assert_eq!(profile_names(&migrated), vec!["FPS".to_string(), "Office".to_string()]);
+}
+
+#[test]
+fn applying_profile_only_switches_device_options() {
+    let active = Settings {
+        sensitivity: Some("400".to_string()),
+        dim_timer: Some("3".to_string()),
+        colour_mode: Some("light".to_string()),
+        attention_threshold: Some(20),
+        ..Default::default()
+    };
+    let profile = Settings {
+        sensitivity: Some("1600".to_string()),
+        polling_rate: Some("1000".to_string()),
+        ..Default::default()
+    };
+    let merged = with_profile_applied(&active, &profile);
+    assert_eq!(merged.sensitivity.as_deref(), Some("1600"));
+    assert_eq!(merged.polling_rate.as_deref(), Some("1000"));
+    assert_eq!(merged.dim_timer, None);
+    assert_eq!(merged.colour_mode.as_deref(), Some("light"));
+    assert_eq!(merged.attention_threshold, Some(20));
+}
+
+#[test]
+fn battery_icon_for_level() {
+    let resolver = MockIconResolver::new();
+    let cases = [
+        (100, "battery-100.svg"),
+        (91, "battery-100.svg"),
+        (90, "battery-75.svg"),
+        (75, "battery-75.svg"),
+        (74, "battery-50.svg"),
+        (50, "battery-50.svg"),
+        (49, "battery-25.svg"),
+        (25, "battery-25.svg"),
+        (24, "battery-warn.svg"),
+        (10, "battery-warn.svg"),
+        (9, "battery-0.svg"),
+        (0, "battery-0.svg"),
+    ];
+    for (level, name) in cases {
+        assert_eq!(
+            battery_icon_path_with_resolver(&resolver, level, IconThresholds::default()),
+            PathBuf::from("/mock").join(name),
+            "level {}",
+            level
+        );
+    }
+}
+
+#[test]
+fn icon_selection_per_state() {
+    let resolver = MockIconResolver::new();
+    let connected = |level, status| BatteryState::Connected { level, status };
+    assert_eq!(
+        select_icon_path(&resolver, &BatteryState::Disconnected, false, IconThresholds::default()),
+        PathBuf::from("/mock/mouse-disconnected.svg")
+    );
+    assert_eq!(
+        select_icon_path(&resolver, &BatteryState::NoBattery, false, IconThresholds::default()),
+        PathBuf::from("/mock/mouse.svg")
+    );
+    assert_eq!(
+        select_icon_path(&resolver, &connected(100, ChargeStatus::Charged), false, IconThresholds::default()),
+        PathBuf::from("/mock/battery-full.svg")
+    );
+    assert_eq!(
+        select_icon_path(&resolver, &connected(60, ChargeStatus::Discharging), false, IconThresholds::default()),
+        PathBuf::from("/mock/battery-50.svg")
+    );
+    assert_eq!(
+        select_icon_path(&resolver, &connected(60, ChargeStatus::Charging), true, IconThresholds::default()),
+        PathBuf::from("/mock/battery-charging.svg")
+    );
+    // the mock files don't exist, so compositing falls back to the plain level icon
+    assert_eq!(
+        select_icon_path(&resolver, &connected(60, ChargeStatus::Charging), false, IconThresholds::default()),
+        PathBuf::from("/mock/battery-50.svg")
+    );
+    assert!(resolver.requested().contains(&"charging.svg".to_string()));
+}
+
+#[test]
+fn missing_icons_fall_back_to_development_path() {
+    struct NoIcons;
+    impl crate::icons::IconResolver for NoIcons {
+        fn resolve(&self, _name: &str) -> Option<PathBuf> {
+            None
+        }
+    }
+    assert_eq!(battery_icon_path_with_resolver(&NoIcons, 30, IconThresholds::default()), PathBuf::from("icons/battery-25.svg"));
+}
+
+#[test]
+fn normalize_settings_trims_each_field() {
+    let mut nicknames = HashMap::new();
+    nicknames.insert("Rival 3".to_string(), "  Desk  ".to_string());
+    nicknames.insert("Aerox 3".to_string(), "\t".to_string());
+    let mut profiles = HashMap::new();
+    profiles.insert(
+        " FPS ".to_string(),
+        Settings {
+            sensitivity: Some(" 1600 ".to_string()),
+            ..Default::default()
+        },
+    );
+    let s = normalize_settings(Settings {
+        sensitivity: Some(" 800 ".to_string()),
+        polling_rate: Some("\t1000".to_string()),
+        sleep_timer: Some(" ".to_string()),
+        dim_timer: Some("5\n".to_string()),
+        colour_mode: Some(" dark".to_string()),
+        custom_color: Some("\t\t".to_string()),
+        buttons: Some(" buttons(button1=button1) ".to_string()),
+        nicknames: Some(nicknames),
+        profiles: Some(profiles),
+        attention_threshold: Some(12),
+        ..Default::default()
+    });
+    assert_eq!(s.sensitivity.as_deref(), Some("800"));
+    assert_eq!(s.polling_rate.as_deref(), Some("1000"));
+    assert_eq!(s.sleep_timer, None);
+    assert_eq!(s.dim_timer.as_deref(), Some("5"));
+    assert_eq!(s.colour_mode.as_deref(), Some("dark"));
+    assert_eq!(s.custom_color, None);
+    assert_eq!(s.buttons.as_deref(), Some("buttons(button1=button1)"));
+    let nicknames = s.nicknames.unwrap();
+    assert_eq!(nicknames.get("Rival 3").map(String::as_str), Some("Desk"));
+    assert!(!nicknames.contains_key("Aerox 3"));
+    let profiles = s.profiles.unwrap();
+    assert_eq!(profiles["FPS"].sensitivity.as_deref(), Some("1600"));
+    assert_eq!(s.attention_threshold, Some(12));
+}
+
+#[test]
+fn whitespace_values_validate_and_build_consistently() {
+    // the validators accept padded input ...
+    assert!(validate_sensitivity(" 800 ").is_ok());
+    assert!(validate_polling_rate("\t1000").is_ok());
+    // ... and after normalization the arg builder passes the same values on trimmed
+    let s = normalize_settings(Settings {
+        sensitivity: Some(" 800 ".to_string()),
+        polling_rate: Some("\t1000".to_string()),
+        sleep_timer: Some(" ".to_string()),
+        ..Default::default()
+    });
+    assert_eq!(build_rivalcfg_args(&s), vec!["--sensitivity", "800", "--polling-rate", "1000"]);
+}
+
+#[test]
+fn migrate_normalizes_loaded_settings() {
+    let raw = serde_json::json!({ "version": 1, "sensitivity": " 800 ", "dim_timer": " " });
+    let s = migrate(raw);
+    assert_eq!(s.sensitivity.as_deref(), Some("800"));
+    assert_eq!(s.dim_timer, None);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "normalized")]
+fn build_rivalcfg_args_rejects_untrimmed_values() {
+    let s = Settings {
+        sensitivity: Some(" 800 ".to_string()),
+        ..Default::default()
+    };
+    build_rivalcfg_args(&s);
+}
+
+#[test]
+fn validators_treat_whitespace_like_normalization() {
+    assert!(validate_sensitivity(" ").is_ok());
+    assert!(validate_polling_rate(" 500 ").is_ok());
+    assert!(validate_polling_rate("\t").is_ok());
+    assert!(validate_timer(" 10 ", "Sleep Timer", "minutes", 0, SLEEP_TIMER_MAX_MINUTES).is_ok());
+    assert!(validate_timer("\t", "Sleep Timer", "minutes", 0, SLEEP_TIMER_MAX_MINUTES).is_ok());
+    assert!(validate_timer(" x ", "Sleep Timer", "minutes", 0, SLEEP_TIMER_MAX_MINUTES).is_err());
+}
+
+#[test]
+fn migrate_v0_to_v1() {
+    let v0 = serde_json::json!({
+        "colour_switch": true,
+        "custom_color": "#00ff00",
+        "sensitivity": 1200,
+    });
+    let s = migrate(v0);
+    assert_eq!(s.version, Some(SETTINGS_VERSION));
+    assert_eq!(s.colour_mode.as_deref(), Some("custom"));
+    assert_eq!(s.custom_color.as_deref(), Some("#00ff00"));
+    assert_eq!(s.sensitivity.as_deref(), Some("1200"));
+    // fields the old schema never had come back as defaults
+    assert_eq!(s.polling_rate, None);
+    assert_eq!(s.icon_size, None);
+    assert!(s.profiles.is_none());
+
+    // the v0 steps are skipped for files that are already v1
+    let v1 = serde_json::json!({ "version": 1, "colour_switch": true });
+    assert_eq!(migrate(v1).colour_mode, None);
+}
+
+#[test]
+fn migrate_detects_version_from_file() {
+    let unversioned = serde_json::json!({ "colour_switch": true });
+    assert_eq!(migrate(unversioned).colour_mode.as_deref(), Some("custom"));
+    // an out-of-range version is treated as unversioned rather than wrapping
+    let bogus = serde_json::json!({ "version": u64::MAX, "sleep_timer": 5 });
+    assert_eq!(migrate(bogus).sleep_timer.as_deref(), Some("5"));
+}
+
+#[test]
+fn forget_missing_pngs_drops_only_entries_without_a_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let shown = dir.path().join("shown.png");
+    std::fs::write(&shown, b"png").unwrap();
+    let shown = shown.to_string_lossy().to_string();
+    let gone = dir.path().join("gone.png").to_string_lossy().to_string();
+    let now = SystemTime::now();
+    let mut cache = HashMap::new();
+    cache.insert("battery-50.svg@64".to_string(), (shown.clone(), now));
+    cache.insert("battery-25.svg@64".to_string(), (gone, now));
+
+    forget_missing_pngs(&mut cache);
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache["battery-50.svg@64"].0, shown);
+}
+
+struct MockBus {
+    watcher_up: std::cell::Cell<bool>,
+    queries: std::cell::Cell<u32>,
+}
+
+impl crate::startup::BusNameChecker for MockBus {
+    fn has_owner(&self, name: &str) -> bool {
+        assert_eq!(name, crate::startup::STATUS_NOTIFIER_WATCHER);
+        self.queries.set(self.queries.get() + 1);
+        self.watcher_up.get()
+    }
+}
+
+#[test]
+fn startup_gate_waits_for_watcher_then_reprobes_once() {
+    use crate::startup::REPROBE_AFTER;
+
+    let bus = MockBus { watcher_up: std::cell::Cell::new(false), queries: std::cell::Cell::new(0) };
+    let t0 = Instant::now();
+    let mut gate = StartupGate::new(Duration::from_secs(10), t0);
+    assert_eq!(gate.poll(t0, &bus), GateAction::Wait);
+    assert_eq!(gate.poll(t0 + Duration::from_secs(3), &bus), GateAction::Wait);
+    bus.watcher_up.set(true);
+    assert_eq!(gate.poll(t0 + Duration::from_secs(4), &bus), GateAction::Probe);
+    let queries = bus.queries.get();
+    // no further bus queries once probed
+    assert_eq!(gate.poll(t0 + Duration::from_secs(30), &bus), GateAction::Wait);
+    assert_eq!(bus.queries.get(), queries);
+    assert_eq!(gate.poll(t0 + REPROBE_AFTER, &bus), GateAction::Reprobe);
+    assert_eq!(gate.poll(t0 + REPROBE_AFTER * 2, &bus), GateAction::Done);
+}
+
+#[test]
+fn startup_gate_times_out_without_watcher() {
+    let bus = MockBus { watcher_up: std::cell::Cell::new(false), queries: std::cell::Cell::new(0) };
+    let t0 = Instant::now();
+    let mut gate = StartupGate::new(Duration::from_secs(10), t0);
+    assert_eq!(gate.poll(t0 + Duration::from_secs(9), &bus), GateAction::Wait);
+    assert_eq!(gate.poll(t0 + Duration::from_secs(10), &bus), GateAction::Probe);
+
+    // a zero grace probes immediately without asking the bus
+    let mut gate = StartupGate::new(Duration::ZERO, t0);
+    let before = bus.queries.get();
+    assert_eq!(gate.poll(t0, &bus), GateAction::Probe);
+    assert_eq!(bus.queries.get(), before);
+}
+
+#[test]
+fn test_build_rivalcfg_args_led_modes() {
+    let with_mode = |mode: Option<&str>| Settings {
+        led_mode: mode.map(str::to_string),
+        led_color: Some("#ff8800".to_string()),
+        ..Default::default()
+    };
+    assert!(build_rivalcfg_args(&with_mode(None)).is_empty());
+    assert!(build_rivalcfg_args(&with_mode(Some("default"))).is_empty());
+    assert_eq!(build_rivalcfg_args(&with_mode(Some("off"))), vec!["--color", "#000000"]);
+    assert_eq!(build_rivalcfg_args(&with_mode(Some("custom"))), vec!["--color", "#ff8800"]);
+    // custom without a colour has nothing to send
+    let no_color = Settings {
+        led_mode: Some("custom".to_string()),
+        ..Default::default()
+    };
+    assert!(build_rivalcfg_args(&no_color).is_empty());
+}
+
+#[test]
+fn test_validate_hex_color() {
+    assert!(validate_hex_color("#ff8800").is_ok());
+    assert!(validate_hex_color("#FF8800").is_ok());
+    assert!(validate_hex_color("#f80").is_ok());
+    assert!(validate_hex_color("ff8800").is_err());
+    assert!(validate_hex_color("#ff880").is_err());
+    assert!(validate_hex_color("#gg8800").is_err());
+    assert!(validate_hex_color("#").is_err());
+    assert!(validate_hex_color("").is_err());
+    assert!(validate_hex_color("#ff8800 ").is_err());
+}
+
+#[test]
+fn test_build_rivalcfg_args_custom_colour_mode() {
+    let mut s = Settings {
+        colour_mode: Some("custom".to_string()),
+        custom_color: Some("#00ff00".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(build_rivalcfg_args(&s), vec!["--color", "#00ff00"]);
+    // only a valid custom colour is sent
+    s.custom_color = Some("00ff00".to_string());
+    assert!(build_rivalcfg_args(&s).is_empty());
+    s.custom_color = None;
+    assert!(build_rivalcfg_args(&s).is_empty());
+    // other modes only colour the icon
+    s.custom_color = Some("#00ff00".to_string());
+    s.colour_mode = Some("dark".to_string());
+    assert!(build_rivalcfg_args(&s).is_empty());
+    // an LED mode takes over from colour_mode
+    s.colour_mode = Some("custom".to_string());
+    s.led_mode = Some(LED_MODE_DEFAULT.to_string());
+    assert!(build_rivalcfg_args(&s).is_empty());
+    s.led_mode = Some(LED_MODE_OFF.to_string());
+    assert_eq!(build_rivalcfg_args(&s), vec!["--color", "#000000"]);
+    s.led_mode = Some(LED_MODE_CUSTOM.to_string());
+    s.led_color = Some("#123456".to_string());
+    assert_eq!(build_rivalcfg_args(&s), vec!["--color", "#123456"]);
+    // ...and an invalid LED colour is not sent
+    s.led_color = Some("#12345".to_string());
+    assert!(build_rivalcfg_args(&s).is_empty());
+}
+
+// Tests that clean the global TEMP_FILES registry hold this so they don't delete
+// each other's files
+static TEMP_FILES_TEST_LOCK: Mutex<()> = Mutex::new(());
+
+#[test]
+fn kept_temp_files_are_registered_and_cleaned() {
+    let _guard = TEMP_FILES_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = tempfile::tempdir().unwrap();
+    let make = || {
+        tempfile::Builder::new()
+            .prefix("rivalcfg-tray-")
+            .suffix(".png")
+            .tempfile_in(dir.path())
+            .unwrap()
+    };
+    let shown = keep_temp_file(make()).expect("kept");
+    let old = keep_temp_file(make()).expect("kept");
+    assert!(shown.exists() && old.exists(), "files outlive their handles");
+    {
+        let files = TEMP_FILES.lock().unwrap();
+        assert!(files.contains(&shown));
+        assert!(files.contains(&old));
+    }
+
+    remove_registered_temp_files(Some(shown.to_str().unwrap()));
+    assert!(shown.exists());
+    assert!(!old.exists());
+    let files = TEMP_FILES.lock().unwrap();
+    assert!(files.contains(&shown));
+    assert!(!files.contains(&old));
+}
+
+#[test]
+fn stale_temp_pngs_are_removed_on_startup() {
+    let dir = tempfile::tempdir().unwrap();
+    let stale = dir.path().join("rivalcfg-tray-abc123.png");
+    let other = dir.path().join("unrelated.png");
+    let svg = dir.path().join("rivalcfg-tray-abc123.svg");
+    for p in [&stale, &other, &svg] {
+        std::fs::write(p, b"x").unwrap();
+    }
+    remove_stale_temp_pngs(&[dir.path().to_path_buf(), dir.path().join("missing")]);
+    assert!(!stale.exists());
+    assert!(other.exists());
+    assert!(svg.exists());
+}
+
+const RIVAL3_HELP: &str = "usage: rivalcfg [-h]\n\nSteelSeries Rival 3 Options:\n  -s SENSITIVITY, --sensitivity SENSITIVITY\n";
+
+#[test]
+fn device_info_with_no_devices() {
+    let mock = MockCommandRunner::new()
+        .with("rivalcfg", &["--list"], "", true)
+        .with("rivalcfg", &["--help"], "usage: rivalcfg [-h]\n", true)
+        .build();
+    assert!(get_all_device_info_with_runner(&mock).is_empty());
+
+    let failing = MockCommandRunner::new().build();
+    assert!(get_all_device_info_with_runner(&failing).is_empty());
+    failing.assert_called_once("rivalcfg", &["--list"]);
+    assert_eq!(failing.get_calls().len(), 1, "no --help probe after --list fails");
+}
+
+#[test]
+fn device_info_with_one_device() {
+    let mock = MockCommandRunner::new()
+        .with("rivalcfg", &["--list"], "SteelSeries Rival 3    1038:1824\n", true)
+        .with("rivalcfg", &["--help"], RIVAL3_HELP, true)
+        .build();
+    assert_eq!(
+        get_all_device_info_with_runner(&mock),
+        vec![DeviceInfo { name: "SteelSeries Rival 3".to_string(), usb_id: "1038:1824".to_string(), connected: true }]
+    );
+}
+
+#[test]
+fn device_info_with_multiple_devices() {
+    let list = "SteelSeries Rival 3    1038:1824\nSteelSeries Rival 3    1038:184C\nSteelSeries Rival 110  1038:1729\n";
+    let mock = MockCommandRunner::new()
+        .with("rivalcfg", &["--list"], list, true)
+        .with("rivalcfg", &["--help"], RIVAL3_HELP, true)
+        .build();
+    let devices = get_all_device_info_with_runner(&mock);
+    assert_eq!(devices.len(), 3);
+    assert_eq!(devices.iter().filter(|d| d.connected).count(), 2);
+    assert_eq!(devices[1].usb_id, "1038:184c");
+    assert!(!devices[2].connected);
+}
+
+#[test]
+fn connected_device_rows() {
+    let mut settings = Settings::default();
+    assert_eq!(connected_device_row("Rival 3", Some("1038:1824"), &settings), "Rival 3 — 1038:1824");
+    set_device_nickname(&mut settings, "Rival 3", "Desk");
+    assert_eq!(connected_device_row("Rival 3", None, &settings), "Desk (Rival 3)");
+}
+
+#[test]
+fn effective_device_settings_resolution() {
+    let builtin = effective_device_settings(&Settings::default(), "Rival 3");
+    assert_eq!(
+        builtin,
+        EffectiveDeviceSettings {
+            poll_interval: POLL_INTERVAL,
+            adaptive_poll: true,
+            attention_threshold: DEFAULT_ATTENTION_THRESHOLD,
+            low_battery_threshold: DEFAULT_LOW_BATTERY_THRESHOLD,
+            notifications: true,
+        }
+    );
+
+    let mut devices = HashMap::new();
+    devices.insert(
+        "Arctis 7".to_string(),
+        DeviceSettings {
+            poll_interval: Some(600),
+            low_battery_threshold: Some(5),
+            notifications: Some(false),
+            ..Default::default()
+        },
+    );
+    let s = Settings {
+        poll_interval: Some(60),
+        attention_threshold: Some(20),
+        low_battery_threshold: Some(25),
+        devices: Some(devices),
+        ..Default::default()
+    };
+    // globals apply to devices without an override block
+    let mouse = effective_device_settings(&s, "Rival 3");
+    assert_eq!(mouse.poll_interval, Duration::from_secs(60));
+    assert!(!mouse.adaptive_poll);
+    assert_eq!(mouse.attention_threshold, 20);
+    assert_eq!(mouse.low_battery_threshold, 25);
+    assert!(mouse.notifications);
+    // overrides win field by field, the rest falls back to the globals
+    let headset = effective_device_settings(&s, "Arctis 7");
+    assert_eq!(headset.poll_interval, Duration::from_secs(600));
+    assert_eq!(headset.attention_threshold, 20);
+    assert_eq!(headset.low_battery_threshold, 5);
+    assert!(!headset.notifications);
+
+    // out-of-range intervals are clamped
+    let fast = Settings { poll_interval: Some(1), ..Default::default() };
+    assert_eq!(effective_device_settings(&fast, "x").poll_interval, Duration::from_secs(5));
+}
+
+#[test]
+fn device_settings_roundtrip() {
+    let json = r#"{"devices": {"Arctis 7": {"poll_interval": 600, "notifications": false}}}"#;
+    let s = migrate(serde_json::from_str(json).unwrap());
+    let device = &s.devices.as_ref().unwrap()["Arctis 7"];
+    assert_eq!(device.poll_interval, Some(600));
+    assert_eq!(device.notifications, Some(false));
+    assert_eq!(device.attention_threshold, None);
+}
+
+#[test]
+fn watchdog_follows_interval_changes() {
+    let t0 = Instant::now();
+    let mut w = PollWatchdog::new(Duration::from_secs(30), t0);
+    w.set_interval(Duration::from_secs(600));
+    assert_eq!(w.stale_after(), Duration::from_secs(1800));
+    assert!(!w.check(t0 + Duration::from_secs(300)));
+}
+
+#[test]
+fn temp_registry_evicts_least_recently_used() {
+    let dir = tempfile::tempdir().unwrap();
+    let make = || TempFileGuard::new(tempfile::NamedTempFile::new_in(dir.path()).unwrap());
+    let mut reg = TempFileRegistry::new(2);
+    let a = reg.insert(make(), None);
+    let b = reg.insert(make(), None);
+    reg.touch(&a);
+    let c = reg.insert(make(), None);
+    assert!(!b.exists(), "least recently used file is deleted");
+    assert!(!reg.contains(&b));
+    assert!(a.exists() && c.exists());
+
+    // the PNG on display survives eviction even when it is the oldest
+    let d = reg.insert(make(), Some(&a));
+    assert!(a.exists() && d.exists());
+    assert!(!c.exists());
+
+    reg.remove(&d);
+    assert!(!d.exists());
+    reg.retain_only(None);
+    assert!(!a.exists());
+    assert!(!reg.contains(&a));
+}
+
+#[test]
+fn battery_bucket_matches_icon_boundaries() {
+    let resolver = MockIconResolver::new();
+    for level in 0..=100u8 {
+        let bucket = BatteryBucket::from_level(level, IconThresholds::default());
+        assert_eq!(
+            battery_icon_path_with_resolver(&resolver, level, IconThresholds::default()),
+            battery_icon_path_with_resolver(&resolver, bucket.icon_level(IconThresholds::default()), IconThresholds::default()),
+            "level {}",
+            level
+        );
+        assert_eq!(BatteryBucket::from_level(bucket.icon_level(IconThresholds::default()), IconThresholds::default()), bucket);
+    }
+}
+
+#[test]
+fn select_bucket_holds_through_oscillation() {
+    let mut bucket = Some(select_bucket(None, 74, 2, IconThresholds::default()));
+    assert_eq!(bucket, Some(BatteryBucket::Half));
+    for level in [75, 74, 75, 76, 74, 75] {
+        bucket = Some(select_bucket(bucket, level, 2, IconThresholds::default()));
+        assert_eq!(bucket, Some(BatteryBucket::Half), "level {}", level);
+    }
+    // far enough past the boundary
+    bucket = Some(select_bucket(bucket, 77, 2, IconThresholds::default()));
+    assert_eq!(bucket, Some(BatteryBucket::ThreeQuarters));
+    for level in [75, 74, 73, 76] {
+        bucket = Some(select_bucket(bucket, level, 2, IconThresholds::default()));
+        assert_eq!(bucket, Some(BatteryBucket::ThreeQuarters), "level {}", level);
+    }
+    bucket = Some(select_bucket(bucket, 72, 2, IconThresholds::default()));
+    assert_eq!(bucket, Some(BatteryBucket::Half));
+}
+
+#[test]
+fn select_bucket_large_jumps_and_no_hysteresis() {
+    // a charge jump goes straight to the right bucket
+    assert_eq!(select_bucket(Some(BatteryBucket::Warn), 100, 2, IconThresholds::default()), BatteryBucket::Full);
+    assert_eq!(select_bucket(Some(BatteryBucket::Full), 5, 2, IconThresholds::default()), BatteryBucket::Empty);
+    // a jump that ends just past a boundary stops one bucket short
+    assert_eq!(select_bucket(Some(BatteryBucket::Warn), 75, 2, IconThresholds::default()), BatteryBucket::Half);
+    // zero hysteresis follows the level exactly
+    assert_eq!(select_bucket(Some(BatteryBucket::Half), 75, 0, IconThresholds::default()), BatteryBucket::ThreeQuarters);
+    assert_eq!(select_bucket(Some(BatteryBucket::ThreeQuarters), 74, 0, IconThresholds::default()), BatteryBucket::Half);
+    // no history: no hysteresis
+    assert_eq!(select_bucket(None, 75, 2, IconThresholds::default()), BatteryBucket::ThreeQuarters);
+}
+
+#[test]
+fn displayed_battery_state_snaps_level() {
+    let state = BatteryState::Connected { level: 74, status: ChargeStatus::Charging };
+    assert_eq!(
+        displayed_battery_state(state, Some(BatteryBucket::ThreeQuarters), IconThresholds::default()),
+        BatteryState::Connected { level: 75, status: ChargeStatus::Charging }
+    );
+    assert_eq!(displayed_battery_state(state, None, IconThresholds::default()), state);
+    assert_eq!(displayed_battery_state(BatteryState::Disconnected, None, IconThresholds::default()), BatteryState::Disconnected);
+}
+
+#[test]
+fn tray_action_ids_round_trip() {
+    let profiles = vec!["Gaming".to_string(), "Work Mode".to_string()];
+    for action in all_actions(&profiles) {
+        let opts = parse_cli_args(vec!["--action".to_string(), action.id()]);
+        let id = opts.action.expect("action id parsed");
+        assert_eq!(TrayAction::from_id(&id, &profiles), Some(action));
+    }
+    assert_eq!(TrayAction::SwitchProfile("Work Mode".to_string()).id(), "profile-work-mode");
+    assert_eq!(TrayAction::from_id("profile-deleted", &profiles), None);
+    assert_eq!(TrayAction::from_id("bogus", &profiles), None);
+    // a trailing --action without an id is ignored
+    assert_eq!(parse_cli_args(vec!["--action".to_string()]).action, None);
+}
+
+#[test]
+fn all_actions_skips_colliding_profile_ids() {
+    let profiles = vec!["A b".to_string(), "a-b".to_string()];
+    let ids: Vec<String> = all_actions(&profiles).iter().map(|a| a.id()).collect();
+    assert_eq!(ids, ["open-config", "refresh-battery", "profile-a-b"]);
+    assert_eq!(
+        TrayAction::from_id("profile-a-b", &profiles),
+        Some(TrayAction::SwitchProfile("A b".to_string()))
+    );
+}
+
+#[test]
+fn desktop_actions_section_lists_every_action() {
+    let actions = all_actions(&["Gaming".to_string()]);
+    let section = desktop_actions_section("rivalcfg-tray", &actions);
+    assert!(section.starts_with("Actions=open-config;refresh-battery;profile-gaming;\n"));
+    assert!(section.contains("[Desktop Action open-config]\nName=Open Configuration\nExec=rivalcfg-tray --action open-config\n"));
+    assert!(section.contains("[Desktop Action refresh-battery]\nName=Refresh Battery\n"));
+    assert!(section.contains("[Desktop Action profile-gaming]\nName=Switch to Profile Gaming\nExec=rivalcfg-tray --action profile-gaming\n"));
+}
+
+#[test]
+fn render_desktop_file_replaces_old_actions() {
+    let base = "[Desktop Entry]\nType=Application\nName=RivalCfg Tray\nExec=rivalcfg-tray\n";
+    let first = render_desktop_file(base, "rivalcfg-tray", &all_actions(&["Gaming".to_string()]));
+    assert!(first.starts_with(base));
+    assert!(first.contains("profile-gaming"));
+
+    // regenerating from the previous output drops the stale profile
+    let second = render_desktop_file(&first, "rivalcfg-tray", &all_actions(&[]));
+    assert!(!second.contains("profile-gaming"));
+    assert_eq!(second.matches("Actions=").count(), 1);
+    assert_eq!(second, render_desktop_file(base, "rivalcfg-tray", &all_actions(&[])));
+    // stable: rendering the output again changes nothing
+    assert_eq!(render_desktop_file(&second, "rivalcfg-tray", &all_actions(&[])), second);
+}
+
+#[test]
+fn desktop_exec_reads_the_main_entry() {
+    let installed = "[Desktop Entry]\nName=RivalCfg Tray\nExec=/opt/rivalcfg-tray/bin/rivalcfg-tray %U\n";
+    assert_eq!(desktop_exec(installed), Some("/opt/rivalcfg-tray/bin/rivalcfg-tray"));
+    // the Exec lines of action groups don't count
+    let actions_only = "[Desktop Entry]\nName=RivalCfg Tray\n\n[Desktop Action open-config]\nExec=other --action open-config\n";
+    assert_eq!(desktop_exec(actions_only), None);
+}
+
+#[test]
+fn write_if_changed_only_rewrites_on_difference() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("applications").join("rivalcfg-tray.desktop");
+    assert!(write_if_changed(&path, "a").unwrap());
+    assert!(!write_if_changed(&path, "a").unwrap());
+    assert!(write_if_changed(&path, "b").unwrap());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "b");
+}
+
+#[test]
+fn forwarded_actions_reach_the_listener() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket = action_socket_path(dir.path());
+    // no tray running yet
+    assert!(forward_action(&socket, "open-config").is_err());
+
+    // a stale socket file from an earlier run is replaced
+    drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+    let mut listener = ActionListener::bind(&socket).unwrap();
+    assert!(listener.try_recv().is_empty());
+    forward_action(&socket, "refresh-battery").unwrap();
+    forward_action(&socket, "profile-gaming").unwrap();
+    assert_eq!(listener.try_recv(), ["refresh-battery", "profile-gaming"]);
+    drop(listener);
+    assert!(!socket.exists());
+}
+
+#[test]
+fn action_listener_does_not_wait_for_slow_senders() {
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let socket = action_socket_path(dir.path());
+    let mut listener = ActionListener::bind(&socket).unwrap();
+    let start = Instant::now();
+
+    // a sender that connects and says nothing doesn't hold up the others
+    let _idle = std::os::unix::net::UnixStream::connect(&socket).unwrap();
+    let mut slow = std::os::unix::net::UnixStream::connect(&socket).unwrap();
+    slow.write_all(b"refresh-").unwrap();
+    forward_action(&socket, "open-config").unwrap();
+    assert_eq!(listener.try_recv_at(start), ["open-config"]);
+
+    // the rest of a partial id arrives later
+    slow.write_all(b"battery").unwrap();
+    drop(slow);
+    assert_eq!(listener.try_recv_at(start), ["refresh-battery"]);
+
+    // the idle connection is given up on after the timeout, without an id
+    assert!(listener.try_recv_at(start + Duration::from_secs(60)).is_empty());
+}
+
+#[test]
+fn private_dir_in_is_only_accessible_to_the_user() {
+    use std::os::unix::fs::PermissionsExt;
+    let base = tempfile::tempdir().unwrap();
+    let dir = private_dir_in(base.path()).unwrap();
+    assert_eq!(dir, base.path().join("rivalcfg-tray"));
+    assert_eq!(fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+
+    // a directory left world-readable is tightened
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+    private_dir_in(base.path()).unwrap();
+    assert_eq!(fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+
+    // a symlink in its place is refused rather than followed
+    let elsewhere = tempfile::tempdir().unwrap();
+    let planted = tempfile::tempdir().unwrap();
+    std::os::unix::fs::symlink(elsewhere.path(), planted.path().join("rivalcfg-tray")).unwrap();
+    assert!(private_dir_in(planted.path()).is_err());
+}
+
+#[test]
+fn debounce_disconnect_waits_for_consecutive_failures() {
+    let connected = BatteryState::Connected { level: 60, status: ChargeStatus::Discharging };
+    let mut failures = 0;
+    // one failed read keeps the last connected state
+    assert_eq!(
+        debounce_disconnect(&mut failures, BatteryState::Disconnected, Some(connected), 2),
+        (connected, false)
+    );
+    // the second one in a row shows Disconnected
+    assert_eq!(
+        debounce_disconnect(&mut failures, BatteryState::Disconnected, Some(connected), 2),
+        (BatteryState::Disconnected, false)
+    );
+    assert_eq!(
+        debounce_disconnect(&mut failures, BatteryState::Disconnected, Some(BatteryState::Disconnected), 2),
+        (BatteryState::Disconnected, false)
+    );
+    // the device coming back is reported so the icon gets refreshed
+    assert_eq!(debounce_disconnect(&mut failures, connected, Some(BatteryState::Disconnected), 2), (connected, true));
+    assert_eq!(failures, 0);
+}
+
+#[test]
+fn debounce_disconnect_single_failure_is_not_a_reconnect() {
+    let connected = BatteryState::Connected { level: 60, status: ChargeStatus::Discharging };
+    let mut failures = 0;
+    debounce_disconnect(&mut failures, BatteryState::Disconnected, Some(connected), 2);
+    assert_eq!(debounce_disconnect(&mut failures, connected, Some(connected), 2), (connected, false));
+    // nothing known yet: show Disconnected right away
+    let mut failures = 0;
+    assert_eq!(
+        debounce_disconnect(&mut failures, BatteryState::Disconnected, None, 2),
+        (BatteryState::Disconnected, false)
+    );
+    // a limit of 1 (or 0) disconnects on the first failure
+    let mut failures = 0;
+    assert_eq!(
+        debounce_disconnect(&mut failures, BatteryState::Disconnected, Some(connected), 1),
+        (BatteryState::Disconnected, false)
+    );
+    let mut failures = 0;
+    assert_eq!(
+        debounce_disconnect(&mut failures, BatteryState::Disconnected, Some(connected), 0),
+        (BatteryState::Disconnected, false)
+    );
+    assert_eq!(debounce_disconnect(&mut failures, connected, None, 0), (connected, true));
+}
+
+#[test]
+fn charge_debouncer_ignores_flapping() {
+    use ChargeStatus::{Charging, Discharging};
+    let mut d = ChargeDebouncer::default();
+    assert_eq!(d.observe(Discharging, 2), Discharging);
+    // alternating every poll never commits
+    for status in [Charging, Discharging, Charging, Discharging, Charging] {
+        assert_eq!(d.observe(status, 2), Discharging);
+    }
+    // two in a row do
+    assert_eq!(d.observe(Charging, 2), Charging);
+    assert_eq!(d.observe(Discharging, 2), Charging);
+    assert_eq!(d.observe(Charging, 2), Charging);
+    assert_eq!(d.observe(Discharging, 2), Charging);
+    assert_eq!(d.observe(Discharging, 2), Discharging);
+}
+
+#[test]
+fn charge_debouncer_confirm_counts_and_reset() {
+    use ChargeStatus::{Charged, Charging, Discharging};
+    let mut d = ChargeDebouncer::default();
+    d.observe(Discharging, 3);
+    assert_eq!(d.observe(Charging, 3), Discharging);
+    assert_eq!(d.observe(Charging, 3), Discharging);
+    assert_eq!(d.observe(Charging, 3), Charging);
+    // a different new status restarts the count
+    assert_eq!(d.observe(Discharging, 3), Charging);
+    assert_eq!(d.observe(Charged, 3), Charging);
+    assert_eq!(d.observe(Charged, 3), Charging);
+    assert_eq!(d.observe(Charged, 3), Charged);
+    // 0 or 1 disables debouncing
+    assert_eq!(d.observe(Discharging, 1), Discharging);
+    assert_eq!(d.observe(Charging, 0), Charging);
+    // after a reset the next status is taken as is
+    d.reset();
+    assert_eq!(d.observe(Discharging, 5), Discharging);
+}
+
+#[test]
+fn charge_debounce_does_not_hold_back_low_battery() {
+    let mut d = ChargeDebouncer::default();
+    let charging = BatteryState::Connected { level: 12, status: ChargeStatus::Charging };
+    let low = BatteryState::Connected { level: 12, status: ChargeStatus::Discharging };
+    assert_eq!(debounce_charge_state(&mut d, charging, 5, 15), charging);
+    // first discharging poll is held back, the second is shown despite confirm_after 5
+    assert_eq!(debounce_charge_state(&mut d, low, 5, 15), charging);
+    assert_eq!(debounce_charge_state(&mut d, low, 5, 15), low);
+    let mut notified = false;
+    assert_eq!(low_battery_notification(&mut notified, &low, 15), Some(12));
+
+    // above the threshold the full count applies
+    let mut d = ChargeDebouncer::default();
+    let high = BatteryState::Connected { level: 80, status: ChargeStatus::Discharging };
+    let high_charging = BatteryState::Connected { level: 80, status: ChargeStatus::Charging };
+    debounce_charge_state(&mut d, high_charging, 3, 15);
+    assert_eq!(debounce_charge_state(&mut d, high, 3, 15), high_charging);
+    assert_eq!(debounce_charge_state(&mut d, high, 3, 15), high_charging);
+    assert_eq!(debounce_charge_state(&mut d, high, 3, 15), high);
+    // disconnecting resets the history
+    assert_eq!(debounce_charge_state(&mut d, BatteryState::Disconnected, 3, 15), BatteryState::Disconnected);
+    assert_eq!(debounce_charge_state(&mut d, high_charging, 3, 15), high_charging);
+}
+
+#[test]
+fn temp_file_guard_keeps_file_until_cleanup() {
+    let _guard = TEMP_FILES_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = tempfile::tempdir().unwrap();
+    let file = tempfile::Builder::new()
+        .prefix("rivalcfg-tray-")
+        .suffix(".png")
+        .tempfile_in(dir.path())
+        .unwrap();
+    let path = keep_temp_file(file).expect("stored");
+    assert!(path.exists(), "file outlives the NamedTempFile handle while stored");
+    assert!(TEMP_FILES.lock().unwrap().contains(&path));
+
+    // what cleanup_temp_files does with nothing on display
+    remove_registered_temp_files(None);
+    assert!(!path.exists());
+    assert!(!TEMP_FILES.lock().unwrap().contains(&path));
+}
+
+#[test]
+fn validate_spawn_arg_rejects_nul_control_and_overlong() {
+    assert!(validate_spawn_arg("buttons(button1=button2)", "Button mapping", 64).is_ok());
+    assert!(validate_spawn_arg("", "Button mapping", 64).is_ok());
+    let err = validate_spawn_arg("a\0b", "Button mapping", 64).unwrap_err();
+    assert!(err.contains("NUL"), "{}", err);
+    let err = validate_spawn_arg("a\nb", "Button mapping", 64).unwrap_err();
+    assert!(err.contains("control characters") && err.contains("\\n"), "{}", err);
+    assert!(validate_spawn_arg("\u{1b}[31m", "Nickname", 64).is_err());
+    let err = validate_spawn_arg(&"x".repeat(65), "Nickname", 64).unwrap_err();
+    assert!(err.contains("too long") && err.contains("64"), "{}", err);
+    // the cap counts characters, not bytes
+    assert!(validate_spawn_arg(&"é".repeat(64), "Nickname", 64).is_ok());
+}
+
+#[test]
+fn field_validators_apply_spawn_checks() {
+    assert!(validate_buttons("buttons(\nbutton1=button2)").unwrap_err().contains("Button mapping"));
+    assert!(validate_buttons(&format!("buttons({})", "x".repeat(MAX_BUTTONS_LEN))).unwrap_err().contains("too long"));
+    assert!(validate_sensitivity("800\u{7}").unwrap_err().contains("control characters"));
+    assert!(validate_polling_rate("1000\0").unwrap_err().contains("NUL"));
+    assert!(validate_dim_timer("1\r0", DEFAULT_MAX_DIM_TIMER_SECS).unwrap_err().starts_with("Dim Timer"));
+    assert!(validate_hex_color("#ff\n0000").unwrap_err().contains("control characters"));
+    // invalid colours are echoed with escapes, not raw
+    assert!(validate_hex_color("red\u{1b}").unwrap_err().contains("Colour"));
+}
+
+#[test]
+fn validate_settings_checks_nicknames() {
+    let mut nicknames = HashMap::new();
+    nicknames.insert("Rival 3".to_string(), "Work\nMouse".to_string());
+    nicknames.insert("Aerox 3".to_string(), "Fine".to_string());
+    let s = Settings { nicknames: Some(nicknames), ..Default::default() };
+    let errors = validate_settings(&s);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].field, "nicknames");
+}
+
+#[test]
+fn escape_for_display_shows_non_printables() {
+    assert_eq!(escape_for_display("Rival 3"), "Rival 3");
+    assert_eq!(escape_for_display("a\nb\0c\u{1b}"), "a\\nb\\u{0}c\\u{1b}");
+    assert!(is_spawn_safe(&escape_for_display("x\ty")));
+    assert!(!is_spawn_safe("x\ty"));
+}
+
+#[test]
+fn unsafe_values_from_disk_never_reach_a_spawn() {
+    let raw = serde_json::json!({
+        "sensitivity": "800",
+        "buttons": "buttons(button1=button2)\nrm",
+        "led_mode": "custom",
+        "led_color": "#ff0000\0",
+        "nicknames": {"Rival 3": "bad\u{1b}name", "Aerox 3": "ok"},
+        "profiles": {"Gaming": {"buttons": "x\ty"}}
+    });
+    let s = migrate(raw);
+    assert_eq!(s.sensitivity.as_deref(), Some("800"));
+    assert_eq!(s.buttons, None);
+    assert_eq!(s.led_color, None);
+    let nicks = s.nicknames.as_ref().unwrap();
+    assert_eq!(nicks.len(), 1);
+    assert_eq!(nicks["Aerox 3"], "ok");
+    assert_eq!(s.profiles.as_ref().unwrap()["Gaming"].buttons, None);
+
+    let runner = MockCommandRunner::new().with("rivalcfg", &["--sensitivity", "800"], "", true).build();
+    apply_settings(&runner, &s).expect("something to apply");
+    for (_, args) in runner.get_calls() {
+        assert!(args.iter().all(|a| is_spawn_safe(a)));
+    }
+}
+
+#[test]
+fn low_battery_notification_escapes_device_name() {
+    let notifier = RecordingNotifier::default();
+    let mut notified = false;
+    let state = BatteryState::Connected { level: 5, status: ChargeStatus::Discharging };
+    assert!(maybe_notify_low_battery(&notifier, &mut notified, "Rival\u{1b}3", &state, 15));
+    assert_eq!(notifier.summaries(), ["Rival\\u{1b}3: Low Battery (5%)"]);
+}
+
+#[test]
+fn test_build_rivalcfg_args_extra_args_appended_in_order() {
+    let s = Settings {
+        sensitivity: Some("800".to_string()),
+        led_mode: Some(LED_MODE_OFF.to_string()),
+        extra_args: Some(vec!["--rotation".to_string(), "90".to_string(), "--default-lift".to_string(), "2".to_string()]),
+        ..Default::default()
+    };
+    assert_eq!(
+        build_rivalcfg_args(&s),
+        vec!["--sensitivity", "800", "--color", "#000000", "--rotation", "90", "--default-lift", "2"]
+    );
+    let only_extra = Settings { extra_args: Some(vec!["--rotation".to_string(), "90".to_string()]), ..Default::default() };
+    assert_eq!(build_rivalcfg_args(&only_extra), vec!["--rotation", "90"]);
+}
+
+#[test]
+fn test_validate_extra_args() {
+    let args = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert!(validate_extra_args(&args(&["--rotation", "90"])).is_ok());
+    assert!(validate_extra_args(&[]).is_ok());
+    assert!(validate_extra_args(&args(&["--rotation", ""])).unwrap_err().contains("empty"));
+    assert!(validate_extra_args(&args(&["  "])).is_err());
+    assert!(validate_extra_args(&args(&["--x\n"])).unwrap_err().contains("control characters"));
+    assert!(validate_extra_args(&vec!["-x".to_string(); MAX_EXTRA_ARGS + 1]).is_err());
+
+    let s = Settings { extra_args: Some(args(&["--rotation", ""])), ..Default::default() };
+    let errors = validate_settings(&s);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].field, "extra_args");
+}
+
+#[test]
+fn extra_args_load_and_profile_roundtrip() {
+    let s = migrate(serde_json::json!({"extra_args": ["--rotation", "90"]}));
+    assert_eq!(s.extra_args, Some(vec!["--rotation".to_string(), "90".to_string()]));
+    assert_eq!(profile_from_settings(&s).extra_args, s.extra_args);
+    let applied = with_profile_applied(&Settings::default(), &s);
+    assert_eq!(applied.extra_args, s.extra_args);
+    // empty lists are dropped, invalid ones are not sent
+    assert_eq!(migrate(serde_json::json!({"extra_args": []})).extra_args, None);
+    assert_eq!(migrate(serde_json::json!({"extra_args": ["--x", ""]})).extra_args, None);
+}
+
+#[test]
+fn battery_icon_name_boundaries() {
+    for (level, name) in [
+        (100, "battery-100.svg"),
+        (91, "battery-100.svg"),
+        (90, "battery-75.svg"),
+        (75, "battery-75.svg"),
+        (74, "battery-50.svg"),
+        (50, "battery-50.svg"),
+        (49, "battery-25.svg"),
+        (25, "battery-25.svg"),
+        (24, "battery-warn.svg"),
+        (10, "battery-warn.svg"),
+        (9, "battery-0.svg"),
+        (0, "battery-0.svg"),
+    ] {
+        assert_eq!(battery_icon_name(level, IconThresholds::default()), name, "level {}", level);
+    }
+}
+
+#[test]
+fn battery_icon_name_uses_configured_thresholds() {
+    let t = IconThresholds { warning: 35, critical: 15 };
+    for (level, name) in [
+        (36, "battery-25.svg"),
+        (35, "battery-warn.svg"),
+        (16, "battery-warn.svg"),
+        (15, "battery-0.svg"),
+    ] {
+        assert_eq!(battery_icon_name(level, t), name, "level {}", level);
+    }
+    // a warning level above a bucket boundary takes over the buckets below it
+    let high = IconThresholds { warning: 60, critical: 30 };
+    assert_eq!(battery_icon_name(61, high), "battery-50.svg");
+    assert_eq!(battery_icon_name(60, high), "battery-warn.svg");
+    for level in 0..=100u8 {
+        let bucket = BatteryBucket::from_level(level, high);
+        assert_eq!(battery_icon_name(bucket.icon_level(high), high), battery_icon_name(level, high), "level {}", level);
+    }
+}
+
+#[test]
+fn icon_threshold_validation_boundaries() {
+    let check = |warning, critical| validate_icon_thresholds(IconThresholds { warning, critical });
+    assert!(check(99, 1).is_ok());
+    assert!(check(2, 1).is_ok());
+    assert!(check(100, 1).is_err());
+    assert!(check(10, 0).is_err());
+    assert!(check(10, 10).is_err(), "warning must be above critical");
+    assert!(check(9, 10).is_err());
+
+    let s = Settings {
+        warning_level: Some(5),
+        ..Default::default()
+    };
+    // the critical level defaults to 9, which is above 5
+    let errors = validate_settings(&s);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].field, "warning_level");
+    // invalid saved values fall back to the defaults
+    assert_eq!(icon_thresholds(&s), IconThresholds::default());
+    let ok = Settings {
+        warning_level: Some(30),
+        critical_level: Some(12),
+        ..Default::default()
+    };
+    assert!(validate_settings(&ok).is_empty());
+    assert_eq!(icon_thresholds(&ok), IconThresholds { warning: 30, critical: 12 });
+}
+
+#[test]
+fn battery_icon_path_resolves_the_pure_name() {
+    let resolver = MockIconResolver::new();
+    battery_icon_path_with_resolver(&resolver, 74, IconThresholds::default());
+    // the thresholds come from the caller, not the settings file
+    battery_icon_path_with_resolver(&resolver, 28, IconThresholds { warning: 30, critical: 12 });
+    assert_eq!(resolver.requested(), vec!["battery-50.svg".to_string(), "battery-warn.svg".to_string()]);
+}
+
+#[test]
+fn svg_inner_content_keeps_any_first_child() {
+    let g = r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 24 24"><g fill="red"><path d="M0 0h1"/></g></svg>"#;
+    assert_eq!(svg_inner_content(g), Some(r#"<g fill="red"><path d="M0 0h1"/></g>"#));
+    let circle = "<svg viewBox=\"0 0 24 24\">\n  <circle cx=\"12\" cy=\"12\" r=\"4\"/>\n  <path d=\"M1 1\"/>\n</svg>\n";
+    assert_eq!(svg_inner_content(circle), Some("\n  <circle cx=\"12\" cy=\"12\" r=\"4\"/>\n  <path d=\"M1 1\"/>\n"));
+    let rect = r#"<svg width="24" height="24"><rect x="1" y="1" width="4" height="4"/></svg>"#;
+    assert_eq!(svg_inner_content(rect), Some(r#"<rect x="1" y="1" width="4" height="4"/>"#));
+}
+
+#[test]
+fn svg_inner_content_skips_prolog_and_balances_nesting() {
+    let src = r#"<?xml version="1.0"?>
+<!-- charging bolt -->
+<svg data-x="a>b" viewBox="0 0 1 1"><svg x="0"><rect/></svg><circle r="1"/></svg>"#;
+    assert_eq!(svg_inner_content(src), Some(r#"<svg x="0"><rect/></svg><circle r="1"/>"#));
+    assert_eq!(svg_inner_content(r#"<svg viewBox="0 0 1 1"/>"#), Some(""));
+    assert_eq!(svg_inner_content("<svgx></svgx>"), None);
+    assert_eq!(svg_inner_content("<svg><g>"), None);
+    assert_eq!(svg_inner_content("not svg"), None);
+}
+
+#[test]
+fn composite_charging_svg_includes_non_path_overlay() {
+    let dir = tempfile::tempdir().unwrap();
+    let battery = dir.path().join("battery-composite-test-50.svg");
+    let charging = dir.path().join("charging.svg");
+    fs::write(&battery, r#"<svg viewBox="0 0 24 24"><path d="M2 2"/></svg>"#).unwrap();
+    fs::write(&charging, r#"<svg viewBox="0 0 24 24"><g id="bolt"><circle r="2"/></g></svg>"#).unwrap();
+    let out = composite_battery_charging_svg(&battery, &charging).expect("composited");
+    let composite = fs::read_to_string(&out).unwrap();
+    let _ = fs::remove_file(&out);
+    assert!(composite.contains(r#"<path d="M2 2"/><g id="bolt"><circle r="2"/></g>"#), "{}", composite);
+    assert!(composite.trim_end().ends_with("</svg>"));
+    assert_eq!(composite.matches("<svg").count(), 1);
+}
+
+#[test]
+fn charge_complete_fires_once_per_session_with_duration() {
+    let t0 = Instant::now();
+    let at = |mins: u64| t0 + Duration::from_secs(mins * 60);
+    let reading = |level, status| BatteryState::Connected { level, status };
+    let mut tracker = ChargeCompleteTracker::default();
+
+    assert_eq!(charge_complete_notification(&mut tracker, &reading(30, ChargeStatus::Discharging), at(0)), None);
+    assert_eq!(charge_complete_notification(&mut tracker, &reading(31, ChargeStatus::Charging), at(1)), None);
+    assert_eq!(charge_complete_notification(&mut tracker, &reading(80, ChargeStatus::Charging), at(40)), None);
+    assert_eq!(
+        charge_complete_notification(&mut tracker, &reading(100, ChargeStatus::Charging), at(66)),
+        Some(ChargeComplete { took: Some(Duration::from_secs(65 * 60)) })
+    );
+    // staying on the charger, reporting Charged, or dipping to 99% doesn't repeat it
+    for (level, status) in [(100, ChargeStatus::Charged), (99, ChargeStatus::Charging), (100, ChargeStatus::Charged)] {
+        assert_eq!(charge_complete_notification(&mut tracker, &reading(level, status), at(70)), None);
+    }
+    // a lost poll doesn't end the session
+    assert_eq!(charge_complete_notification(&mut tracker, &BatteryState::Disconnected, at(71)), None);
+    assert_eq!(charge_complete_notification(&mut tracker, &reading(100, ChargeStatus::Charged), at(72)), None);
+
+    // unplug, plug in again: a new session
+    charge_complete_notification(&mut tracker, &reading(95, ChargeStatus::Discharging), at(100));
+    charge_complete_notification(&mut tracker, &reading(95, ChargeStatus::Charging), at(101));
+    assert_eq!(
+        charge_complete_notification(&mut tracker, &reading(99, ChargeStatus::Charged), at(111)),
+        Some(ChargeComplete { took: Some(Duration::from_secs(10 * 60)) })
+    );
+}
+
+#[test]
+fn charge_complete_edge_cases() {
+    let now = Instant::now();
+    let reading = |level, status| BatteryState::Connected { level, status };
+    // already full when first seen: nothing to announce
+    let mut tracker = ChargeCompleteTracker::default();
+    assert_eq!(charge_complete_notification(&mut tracker, &reading(100, ChargeStatus::Charged), now), None);
+    assert_eq!(charge_complete_notification(&mut tracker, &reading(100, ChargeStatus::Charged), now), None);
+
+    // charging already when first seen: fires, but without a duration
+    let mut tracker = ChargeCompleteTracker::default();
+    charge_complete_notification(&mut tracker, &reading(60, ChargeStatus::Charging), now);
+    assert_eq!(
+        charge_complete_notification(&mut tracker, &reading(100, ChargeStatus::Charging), now),
+        Some(ChargeComplete { took: None })
+    );
+
+    // flapping Charging/Discharging below full never fires
+    let mut tracker = ChargeCompleteTracker::default();
+    for status in [ChargeStatus::Charging, ChargeStatus::Discharging, ChargeStatus::Charging, ChargeStatus::Discharging] {
+        assert_eq!(charge_complete_notification(&mut tracker, &reading(50, status), now), None);
+    }
+}
+
+#[test]
+fn charge_complete_notification_is_sent_once() {
+    let t0 = Instant::now();
+    let notifier = RecordingNotifier::default();
+    let mut tracker = ChargeCompleteTracker::default();
+    let reading = |level, status| BatteryState::Connected { level, status };
+    maybe_notify_charge_complete(&notifier, &mut tracker, "Rival 3", &reading(20, ChargeStatus::Discharging), t0);
+    maybe_notify_charge_complete(&notifier, &mut tracker, "Rival 3", &reading(21, ChargeStatus::Charging), t0);
+    let done = t0 + Duration::from_secs(65 * 60);
+    assert!(maybe_notify_charge_complete(&notifier, &mut tracker, "Rival 3", &reading(100, ChargeStatus::Charged), done));
+    assert!(!maybe_notify_charge_complete(&notifier, &mut tracker, "Rival 3", &reading(100, ChargeStatus::Charged), done));
+    let sent = notifier.sent.lock().unwrap();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].summary, "Rival 3: Fully Charged");
+    assert_eq!(sent[0].body.as_deref(), Some("Charged in 1h 05m. You can unplug the cable."));
+}
+
+#[test]
+fn format_charge_duration_variants() {
+    assert_eq!(format_charge_duration(Duration::from_secs(30)), "under a minute");
+    assert_eq!(format_charge_duration(Duration::from_secs(42 * 60 + 10)), "42m");
+    assert_eq!(format_charge_duration(Duration::from_secs(65 * 60)), "1h 05m");
+    assert_eq!(format_charge_duration(Duration::from_secs(3 * 3600)), "3h 00m");
+}
+
+#[test]
+fn notification_for_keeps_summaries_to_one_short_line() {
+    let long = notification_for(&NotifyEvent::LowBattery { device: "A".repeat(80), level: 9 });
+    assert_eq!(long.summary.chars().count(), crate::notify::MAX_SUMMARY_LEN);
+    assert!(long.summary.ends_with('…'));
+    assert_eq!(long.click, TrayAction::OpenConfig);
+
+    let failed = notification_for(&NotifyEvent::ApplyFailed { error: "\nbad value\nusage: ...".to_string(), token: 3 });
+    assert_eq!(failed.summary, "Settings not applied");
+    assert_eq!(failed.body.as_deref(), Some("bad value"));
+    assert_eq!(failed.click, TrayAction::ShowError(3));
+    assert_eq!(notification_for(&NotifyEvent::ApplyFailed { error: String::new(), token: 1 }).body, None);
+}
+
+#[test]
+fn writes_suspended_notification_names_the_file() {
+    let n = notification_for(&NotifyEvent::WritesSuspended {
+        target: "/home/me/.local/state/rivalcfg-tray/history.csv".to_string(),
+        minutes: 15,
+    });
+    assert_eq!(n.summary, "Can't write history.csv");
+    let body = n.body.expect("body");
+    assert!(body.contains("/home/me/.local/state/rivalcfg-tray/history.csv"));
+    assert!(body.contains("15 minutes"));
+    assert_eq!(n.urgency, crate::notify::Urgency::Normal);
+    assert_eq!(n.click, TrayAction::OpenConfig);
+}
+
+#[test]
+fn notification_click_with_stale_error_token_opens_config() {
+    let mut errors = HashMap::new();
+    let token = remember_error(&mut errors, "full error");
+    assert_eq!(resolve_click(TrayAction::ShowError(token), &errors), ClickTarget::ErrorDialog("full error".to_string()));
+    // e.g. clicked after the tray restarted
+    assert_eq!(resolve_click(TrayAction::ShowError(token + 1), &errors), ClickTarget::Action(TrayAction::OpenConfig));
+    assert_eq!(resolve_click(TrayAction::RefreshBattery, &errors), ClickTarget::Action(TrayAction::RefreshBattery));
+    // the id survives the trip through the action socket
+    assert_eq!(TrayAction::from_id(&TrayAction::ShowError(7).id(), &[]), Some(TrayAction::ShowError(7)));
+    assert_eq!(TrayAction::from_id("show-error-x", &[]), None);
+}
+
+#[test]
+fn remember_error_keeps_only_recent_errors() {
+    let mut errors = HashMap::new();
+    let tokens: Vec<u64> = (0..MAX_NOTIFIED_ERRORS + 2).map(|i| remember_error(&mut errors, &i.to_string())).collect();
+    assert_eq!(errors.len(), MAX_NOTIFIED_ERRORS);
+    assert!(!errors.contains_key(&tokens[0]));
+    assert!(errors.contains_key(tokens.last().unwrap()));
+}
+
+#[cfg(feature = "notifications")]
+#[test]
+fn deliver_reports_click_and_falls_back_without_actions() {
+    let n = notification_for(&NotifyEvent::ChargeComplete { device: "Rival 3".to_string(), took: None });
+    let with_click = notify_send_args(&n, true);
+    let with_click: Vec<&str> = with_click.iter().map(String::as_str).collect();
+    assert_eq!(
+        with_click,
+        ["-a", "rivalcfg-tray", "-A", "default=Open", "--wait", "Rival 3: Fully Charged", "You can unplug the cable."]
+    );
+    let clicked = MockCommandRunner::new().with("notify-send", &with_click, "default\n", true).build();
+    assert_eq!(deliver(&clicked, &n), Some(TrayAction::OpenConfig));
+    let dismissed = MockCommandRunner::new().with("notify-send", &with_click, "", true).build();
+    assert_eq!(deliver(&dismissed, &n), None);
+
+    // an old notify-send without -A gets a plain notification
+    let plain = notify_send_args(&n, false);
+    let plain: Vec<&str> = plain.iter().map(String::as_str).collect();
+    let old = MockCommandRunner::new()
+        .with("notify-send", &with_click, "", false)
+        .with("notify-send", &plain, "", true)
+        .build();
+    assert_eq!(deliver(&old, &n), None);
+    old.assert_called_once("notify-send", &plain);
+}
+
+#[test]
+fn test_build_rivalcfg_args_sensitivity_as_integer() {
+    let s = Settings { sensitivity: Some("800.0".to_string()), ..Default::default() };
+    assert_eq!(build_rivalcfg_args(&s), ["--sensitivity", "800"]);
+    let s = Settings { sensitivity: Some("400,1600".to_string()), ..Default::default() };
+    assert_eq!(build_rivalcfg_args(&s), ["--sensitivity", "400,1600"]);
+}
+
+#[test]
+fn sensitivity_from_spin_keeps_saved_stage_list() {
+    assert_eq!(first_sensitivity_stage("400,800"), Some(400));
+    assert_eq!(first_sensitivity_stage(""), None);
+    assert_eq!(sensitivity_from_spin(1200, true, None).as_deref(), Some("1200"));
+    assert_eq!(sensitivity_from_spin(1200, true, Some("800")).as_deref(), Some("1200"));
+    // the list survives until its first stage is changed in the window
+    assert_eq!(sensitivity_from_spin(400, true, Some("400,800,1600")).as_deref(), Some("400,800,1600"));
+    assert_eq!(sensitivity_from_spin(500, true, Some("400,800,1600")).as_deref(), Some("500"));
+}
+
+#[test]
+fn untouched_sensitivity_spin_sends_no_sensitivity() {
+    // 800 is the spin button's default, not something the user picked
+    let s = Settings { sensitivity: sensitivity_from_spin(800, false, None), ..Default::default() };
+    assert!(!build_rivalcfg_args(&s).contains(&"--sensitivity".to_string()));
+    // a saved value is still sent when the spin button was left alone
+    assert_eq!(sensitivity_from_spin(1600, false, Some("1600")).as_deref(), Some("1600"));
+}
+
+#[test]
+fn battery_history_appends_rows_with_header() {
+    use crate::battery_history::{HISTORY_HEADER, append_with_rotation, history_row};
+    let dir = tempfile::tempdir().unwrap();
+    // the state directory doesn't exist yet
+    let path = dir.path().join("rivalcfg-tray").join("battery-history.csv");
+    append_with_rotation(&path, history_row(1700000000, 45, false).as_bytes(), 1024).unwrap();
+    append_with_rotation(&path, history_row(1700000030, 46, true).as_bytes(), 1024).unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        format!("{}1700000000,45,false\n1700000030,46,true\n", HISTORY_HEADER)
+    );
+}
+
+#[test]
+fn battery_history_rotates_at_size_limit() {
+    use crate::battery_history::{HISTORY_HEADER, append_with_rotation, history_row};
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("battery-history.csv");
+    let row = history_row(1700000000, 45, false);
+    // room for the header and two rows
+    let max = (HISTORY_HEADER.len() + 2 * row.len()) as u64;
+    for _ in 0..3 {
+        append_with_rotation(&path, row.as_bytes(), max).unwrap();
+    }
+    let rotated = dir.path().join("battery-history.csv.1");
+    assert_eq!(fs::read_to_string(&rotated).unwrap(), format!("{}{}{}", HISTORY_HEADER, row, row));
+    assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}{}", HISTORY_HEADER, row));
+    // the next rotation replaces the old file
+    for _ in 0..2 {
+        append_with_rotation(&path, row.as_bytes(), max).unwrap();
+    }
+    assert_eq!(fs::read_to_string(&rotated).unwrap(), format!("{}{}{}", HISTORY_HEADER, row, row));
+}
+
+#[test]
+fn battery_history_write_failure_is_an_error_not_a_panic() {
+    use crate::battery_history::append_with_rotation;
+    let dir = tempfile::tempdir().unwrap();
+    let blocker = dir.path().join("not-a-dir");
+    fs::write(&blocker, "").unwrap();
+    assert!(append_with_rotation(&blocker.join("battery-history.csv"), b"1,2,false\n", 1024).is_err());
+}
+
+#[test]
+fn insert_svg_overlay_handles_casing_and_self_closing_roots() {
+    assert_eq!(
+        insert_svg_overlay(r#"<SVG viewBox="0 0 1 1"><PATH d="M0"/></SVG >"#, "<g/>"),
+        Some("<SVG viewBox=\"0 0 1 1\"><PATH d=\"M0\"/><g/>\n</SVG >".to_string())
+    );
+    assert_eq!(
+        insert_svg_overlay("<svg viewBox=\"0 0 1 1\" />\n", "<g/>"),
+        Some("<svg viewBox=\"0 0 1 1\"><g/>\n</svg>\n".to_string())
+    );
+    // only the root's closing tag gets the overlay
+    assert_eq!(
+        insert_svg_overlay("<svg><svg><rect/></svg></svg>", "<g/>"),
+        Some("<svg><svg><rect/></svg><g/>\n</svg>".to_string())
+    );
+    assert_eq!(insert_svg_overlay("<svg><g>", "<g/>"), None);
+    assert_eq!(insert_svg_overlay("<g></g>", "<g/>"), None);
+}
+
+#[test]
+fn composite_charging_svg_with_uppercase_tags_and_no_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let battery = dir.path().join("battery-composite-upper-75.svg");
+    let charging = dir.path().join("charging.svg");
+    fs::write(&battery, "<?xml version=\"1.0\"?>\n<SVG viewBox=\"0 0 24 24\"><RECT width=\"4\"/></SVG>\n").unwrap();
+    fs::write(&charging, r#"<SVG viewBox="0 0 24 24"><polygon points="1,1 2,2 1,2"/></SVG>"#).unwrap();
+    let out = composite_battery_charging_svg(&battery, &charging).expect("composited");
+    let composite = fs::read_to_string(&out).unwrap();
+    let _ = fs::remove_file(&out);
+    assert_eq!(
+        composite,
+        "<?xml version=\"1.0\"?>\n<SVG viewBox=\"0 0 24 24\"><RECT width=\"4\"/><polygon points=\"1,1 2,2 1,2\"/>\n</SVG>\n"
+    );
+}
+
+#[test]
+fn compiled_features_lists_tray_first() {
+    let features = compiled_features();
+    assert_eq!(features[0], "tray");
+    assert_eq!(features.contains(&"cli"), cfg!(feature = "cli"));
+    assert_eq!(features.contains(&"notifications"), cfg!(feature = "notifications"));
+}
+
+#[test]
+fn rivalcfg_program_uses_configured_path() {
+    assert_eq!(rivalcfg_program(&Settings::default()), "rivalcfg");
+    let s = Settings { rivalcfg_path: Some("/home/u/.local/bin/rivalcfg".to_string()), ..Default::default() };
+    assert_eq!(rivalcfg_program(&s), "/home/u/.local/bin/rivalcfg");
+    // blank values are dropped when settings are normalized
+    let s = normalize_settings(Settings { rivalcfg_path: Some("  ".to_string()), ..Default::default() });
+    assert_eq!(rivalcfg_program(&s), "rivalcfg");
+}
+
+#[test]
+fn real_runner_spawns_the_program_it_was_created_with() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = tempfile::tempdir().unwrap();
+    let exe = dir.path().join("my-rivalcfg");
+    fs::write(&exe, "#!/bin/sh\necho \"custom $1\"\n").unwrap();
+    fs::set_permissions(&exe, fs::Permissions::from_mode(0o755)).unwrap();
+    let runner = RealCommandRunner::new(exe.to_string_lossy().to_string());
+    let out = runner.run("rivalcfg", &["--version"]);
+    assert!(out.success);
+    assert_eq!(out.stdout, "custom --version\n");
+    // other programs are spawned as named
+    assert_eq!(runner.run("echo", &["hi"]).stdout, "hi\n");
+}
+
+#[test]
+fn check_rivalcfg_path_requires_an_executable_file() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = tempfile::tempdir().unwrap();
+    let exe = dir.path().join("rivalcfg");
+    fs::write(&exe, "#!/bin/sh\n").unwrap();
+    fs::set_permissions(&exe, fs::Permissions::from_mode(0o644)).unwrap();
+    assert!(check_rivalcfg_path(&exe).unwrap_err().contains("not executable"));
+    fs::set_permissions(&exe, fs::Permissions::from_mode(0o755)).unwrap();
+    assert_eq!(check_rivalcfg_path(&exe), Ok(()));
+    assert!(check_rivalcfg_path(dir.path()).unwrap_err().contains("not a file"));
+    assert!(check_rivalcfg_path(&dir.path().join("missing")).is_err());
+}
+
+#[test]
+fn validate_settings_rejects_unsafe_rivalcfg_path() {
+    let s = Settings { rivalcfg_path: Some("/bin/riv\u{0}alcfg".to_string()), ..Default::default() };
+    assert!(validate_settings(&s).iter().any(|e| e.field == "rivalcfg_path"));
+}
+
+#[test]
+fn estimate_remaining_needs_enough_falling_samples() {
+    use crate::battery_estimate::estimate_remaining;
+    let t0 = Instant::now();
+    let at = |mins: u64| t0 + Duration::from_secs(mins * 60);
+    // flat: the level isn't falling yet
+    let flat: Vec<(Instant, u8)> = (0..10).map(|i| (at(i), 80)).collect();
+    assert_eq!(estimate_remaining(&flat), None);
+    // 1% every 5 minutes from 60% leaves 55% * 5m = 4h 35m
+    let steady: Vec<(Instant, u8)> = (0..6).map(|i| (at(i * 5), 60 - i as u8)).collect();
+    let est = estimate_remaining(&steady).unwrap();
+    assert_eq!(est.as_secs() / 60, 275);
+    assert_eq!(estimate_remaining(&steady[..5]), None);
+}
+
+#[test]
+fn estimate_remaining_smooths_noise() {
+    use crate::battery_estimate::estimate_remaining;
+    let t0 = Instant::now();
+    // roughly 1% per 10 minutes with ±1% jitter
+    let levels = [70u8, 70, 68, 68, 67, 65, 66, 64, 63, 62, 62, 60];
+    let noisy: Vec<(Instant, u8)> = levels
+        .iter()
+        .enumerate()
+        .map(|(i, l)| (t0 + Duration::from_secs(i as u64 * 600), *l))
+        .collect();
+    // a rise inside the series (66 after 65) restarts it, so only the end counts
+    let est = estimate_remaining(&noisy).unwrap();
+    let hours = est.as_secs_f64() / 3600.0;
+    assert!((7.0..12.0).contains(&hours), "{}", hours);
+}
+
+#[test]
+fn estimate_remaining_ignores_samples_before_charging() {
+    use crate::battery_estimate::{MAX_ESTIMATE, estimate_remaining};
+    let t0 = Instant::now();
+    let at = |mins: u64| t0 + Duration::from_secs(mins * 60);
+    let mut series: Vec<(Instant, u8)> = (0..10).map(|i| (at(i), 50 - i as u8)).collect();
+    // charged back up, then only three discharging readings
+    series.extend([(at(60), 90), (at(61), 90), (at(62), 89)]);
+    assert_eq!(estimate_remaining(&series), None);
+    // an absurdly slow drain gives no estimate rather than weeks
+    let slow: Vec<(Instant, u8)> = (0..6).map(|i| (t0 + Duration::from_secs(i * 24 * 3600), 99 - i as u8)).collect();
+    assert!(MAX_ESTIMATE < Duration::from_secs(94 * 24 * 3600));
+    assert_eq!(estimate_remaining(&slow), None);
+}
+
+#[test]
+fn record_battery_sample_resets_on_charging() {
+    use crate::battery_estimate::BatterySamples;
+    let t0 = Instant::now();
+    let mut samples = BatterySamples::default();
+    let discharging = |level| BatteryState::Connected { level, status: ChargeStatus::Discharging };
+    for i in 0..6u8 {
+        let est = record_battery_sample(&mut samples, &discharging(60 - i), t0 + Duration::from_secs(i as u64 * 300));
+        assert_eq!(est.is_some(), i == 5);
+    }
+    let charging = BatteryState::Connected { level: 55, status: ChargeStatus::Charging };
+    assert_eq!(record_battery_sample(&mut samples, &charging, t0 + Duration::from_secs(1800)), None);
+    assert_eq!(record_battery_sample(&mut samples, &discharging(55), t0 + Duration::from_secs(2100)), None);
+    assert_eq!(remaining_menu_text(Some(Duration::from_secs(380 * 60))).as_deref(), Some("≈ 6h 20m remaining"));
+    assert_eq!(remaining_menu_text(None), None);
+}
+
+#[test]
+fn cached_battery_poller_reuses_fresh_readings() {
+    use crate::battery_poller::CachedBatteryPoller;
+    let mock = Arc::new(
+        MockCommandRunner::new()
+            .with("rivalcfg", &["--battery-level"], "Mouse battery: 40% Discharging\n", true)
+            .build(),
+    );
+    let poller = CachedBatteryPoller::new(mock.clone(), Duration::from_secs(5));
+    let t0 = Instant::now();
+    assert_eq!(poller.poll_at(t0), Ok((40, ChargeStatus::Discharging)));
+    assert_eq!(poller.poll_at(t0 + Duration::from_secs(4)), Ok((40, ChargeStatus::Discharging)));
+    assert_eq!(mock.get_calls().len(), 1);
+    // stale after the interval
+    let _ = poller.poll_at(t0 + Duration::from_secs(5));
+    assert_eq!(mock.get_calls().len(), 2);
+    poller.invalidate();
+    let _ = poller.poll_at(t0 + Duration::from_secs(6));
+    assert_eq!(mock.get_calls().len(), 3);
+}
+
+#[test]
+fn cached_battery_poller_does_not_cache_failures() {
+    use crate::battery_poller::CachedBatteryPoller;
+    // a device that isn't there isn't retried, so each poll is one call
+    let mock = Arc::new(
+        MockCommandRunner::new()
+            .with("rivalcfg", &["--battery-level"], "E: No supported device found\n", false)
+            .build(),
+    );
+    let poller = CachedBatteryPoller::new(mock.clone(), Duration::from_secs(5));
+    let t0 = Instant::now();
+    assert!(poller.poll_at(t0).is_err());
+    assert!(poller.poll_at(t0 + Duration::from_secs(1)).is_err());
+    assert_eq!(mock.get_calls().len(), 2);
+}
+
+#[test]
+fn cached_battery_poller_does_not_retry_on_the_main_loop() {
+    use crate::battery_poller::CachedBatteryPoller;
+    let mock = Arc::new(
+        MockCommandRunner::new()
+            .with_once("rivalcfg", &["--battery-level"], "", false)
+            .with("rivalcfg", &["--battery-level"], "Mouse battery: 55% Discharging\n", true)
+            .build(),
+    );
+    let poller = CachedBatteryPoller::new(mock.clone(), Duration::from_secs(5));
+    // a transient failure comes back at once; the next poll reads again
+    let t0 = Instant::now();
+    assert!(matches!(poller.poll_at(t0), Err(RivalError::CommandFailed(_))));
+    assert_eq!(mock.get_calls().len(), 1);
+    assert_eq!(poller.poll_at(t0), Ok((55, ChargeStatus::Discharging)));
+}
+
+#[test]
+fn battery_read_retries_a_transient_failure() {
+    use crate::cmd::get_battery_level_retrying;
+    let mock = MockCommandRunner::new()
+        .with_once("rivalcfg", &["--battery-level"], "", false)
+        .with("rivalcfg", &["--battery-level"], "Mouse battery: 55% Discharging\n", true)
+        .build();
+    let delays = [Duration::ZERO; 2];
+    assert_eq!(get_battery_level_retrying(&mock, &delays), Ok((55, ChargeStatus::Discharging)));
+    assert_eq!(mock.get_calls().len(), 2);
+
+    // gives up after the last delay
+    let failing = MockCommandRunner::new().with("rivalcfg", &["--battery-level"], "", false).build();
+    assert!(matches!(get_battery_level_retrying(&failing, &delays), Err(RivalError::CommandFailed(_))));
+    assert_eq!(failing.get_calls().len(), 3);
+}
+
+#[test]
+fn battery_read_does_not_retry_a_spawn_failure() {
+    use crate::cmd::get_battery_level_retrying;
+    let missing = MockCommandRunner::new().build();
+    assert!(matches!(
+        get_battery_level_retrying(&missing, &[Duration::ZERO; 2]),
+        Err(RivalError::SpawnFailed(_))
+    ));
+    assert_eq!(missing.get_calls().len(), 1);
+}
+
+#[test]
+fn set_sensitivity_runs_rivalcfg() {
+    let mock = MockCommandRunner::new().with("rivalcfg", &["--sensitivity", "1600"], "", true).build();
+    assert!(crate::cmd::set_sensitivity(&mock, 1600).success);
+    mock.assert_called_once("rivalcfg", &["--sensitivity", "1600"]);
+}
+
+#[test]
+fn active_sensitivity_preset_only_matches_single_presets() {
+    let with = |sens: &str| Settings { sensitivity: Some(sens.to_string()), ..Default::default() };
+    assert_eq!(active_sensitivity_preset(&with("800")), Some(800));
+    assert_eq!(active_sensitivity_preset(&with("1200")), None);
+    assert_eq!(active_sensitivity_preset(&with("400,800")), None);
+    assert_eq!(active_sensitivity_preset(&Settings::default()), None);
+}
+
+#[test]
+fn icon_search_report_marks_found_and_missing() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("battery-50.svg"), "<svg/>").unwrap();
+    let report = icon_search_report_with(&["battery-50.svg", "charging.svg"], |name| {
+        vec![dir.path().join(name), PathBuf::from("/nonexistent").join(name)]
+    });
+    let d = dir.path().display();
+    assert_eq!(
+        report,
+        format!(
+            "battery-50.svg:\n  [FOUND] {d}/battery-50.svg\n  [MISSING] /nonexistent/battery-50.svg\n\
+             charging.svg:\n  [MISSING] {d}/charging.svg\n  [MISSING] /nonexistent/charging.svg\n"
+        )
+    );
+}
+
+#[test]
+fn list_icon_search_report_covers_known_icons() {
+    let report = list_icon_search_report(&KNOWN_ICON_NAMES);
+    for name in KNOWN_ICON_NAMES {
+        assert!(report.contains(&format!("{}:\n", name)), "{}", report);
+    }
+    // tests run from the crate root, where the development icons are
+    assert!(report.contains("[FOUND] icons/battery-100.svg"), "{}", report);
+    assert!(parse_cli_args(vec!["--list-icons".to_string()]).list_icons);
+}
+
+#[test]
+fn tray_label_text_keeps_a_fixed_width() {
+    let reading = |level| BatteryState::Connected { level, status: ChargeStatus::Discharging };
+    assert_eq!(tray_label_text(&reading(100), true).as_deref(), Some("100%"));
+    assert_eq!(tray_label_text(&reading(75), true).as_deref(), Some("\u{2007}75%"));
+    assert_eq!(tray_label_text(&reading(9), true).as_deref(), Some("\u{2007}\u{2007}9%"));
+    for level in [0, 9, 42, 100] {
+        assert_eq!(tray_label_text(&reading(level), true).unwrap().chars().count(), 4);
+    }
+    // cleared when turned off
+    assert_eq!(tray_label_text(&reading(75), false), None);
+    // says so once the device is treated as gone, instead of a stale percentage,
+    // even with the label turned off
+    assert_eq!(tray_label_text(&BatteryState::Disconnected, true).as_deref(), Some("Mouse disconnected"));
+    assert_eq!(tray_label_text(&BatteryState::Disconnected, false).as_deref(), Some("Mouse disconnected"));
+}
+
+#[test]
+fn menu_info_items_default_to_everything_but_the_device_name() {
+    assert_eq!(
+        menu_info_items(&Settings::default()),
+        vec![InfoItem::BatteryPercent, InfoItem::TimeEstimate, InfoItem::Status]
+    );
+}
+
+#[test]
+fn menu_info_items_follow_the_settings_in_menu_order() {
+    let settings = Settings {
+        menu: Some(MenuSettings {
+            device_name: Some(true),
+            battery_percent: Some(false),
+            time_estimate: None,
+            status: Some(false),
+        }),
+        ..Default::default()
+    };
+    assert_eq!(menu_info_items(&settings), vec![InfoItem::DeviceName, InfoItem::TimeEstimate]);
+
+    let none = Settings {
+        menu: Some(MenuSettings {
+            device_name: Some(false),
+            battery_percent: Some(false),
+            time_estimate: Some(false),
+            status: Some(false),
+        }),
+        ..Default::default()
+    };
+    assert!(menu_info_items(&none).is_empty());
+}
+
+#[test]
+fn apply_guard_rejects_overlapping_applies() {
+    static BUSY: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+    let first = ApplyGuard::acquire(&BUSY).expect("free at first");
+    assert!(ApplyGuard::acquire(&BUSY).is_none(), "a second click is ignored");
+    drop(first);
+    // released on every exit path, e.g. an early return after failed validation
+    let again = ApplyGuard::acquire(&BUSY);
+    assert!(again.is_some());
+    drop(again);
+    assert!(!BUSY.load(std::sync::atomic::Ordering::SeqCst));
+}
+
+#[test]
+fn apply_debounce_fires_once_after_the_last_change() {
+    let t0 = Instant::now();
+    let mut d = ApplyDebounce::new(Duration::from_millis(800));
+    assert!(!d.take_due(t0), "nothing changed yet");
+    d.changed(t0);
+    d.changed(t0 + Duration::from_millis(500));
+    assert!(d.pending());
+    // measured from the last change, not the first
+    assert!(!d.take_due(t0 + Duration::from_millis(1000)));
+    assert!(d.take_due(t0 + Duration::from_millis(1300)));
+    assert!(!d.pending());
+    assert!(!d.take_due(t0 + Duration::from_millis(2000)), "one apply per burst");
+}
+
+#[test]
+fn every_known_icon_is_bundled() {
+    for name in KNOWN_ICON_NAMES {
+        let bytes = crate::icons::bundled_icon(name).unwrap_or_else(|| panic!("{} not bundled", name));
+        assert!(String::from_utf8_lossy(bytes).contains("<svg"), "{}", name);
+    }
+    assert!(crate::icons::bundled_icon("no-such-icon.svg").is_none());
+}
+
+#[test]
+fn bundled_icon_path_writes_and_repairs_the_copy() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = bundled_icon_path("battery-50.svg", dir.path()).unwrap();
+    assert_eq!(path, dir.path().join("battery-50.svg"));
+    let bundled = crate::icons::bundled_icon("battery-50.svg").unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), bundled);
+    // a stale or edited copy is replaced
+    std::fs::write(&path, "<svg/>").unwrap();
+    bundled_icon_path("battery-50.svg", dir.path()).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), bundled);
+    assert!(bundled_icon_path("no-such-icon.svg", dir.path()).is_none());
+}
+
+#[test]
+fn filesystem_icons_win_over_bundled_ones() {
+    // tests run from the crate root, where icons/ holds the same files
+    let (path, source) = locate_icon("battery-50.svg").unwrap();
+    assert_ne!(source, IconSource::Bundled);
+    assert!(path.exists());
+    // names that are neither installed nor bundled stay missing
+    assert!(locate_icon("rivalcfg-test-not-installed.svg").is_none());
+}
+
+#[test]
+fn settings_export_import_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("export.json");
+    let s = Settings {
+        sensitivity: Some("800,1600".to_string()),
+        polling_rate: Some("500".to_string()),
+        warning_level: Some(30),
+        ..Default::default()
+    };
+    export_settings_to_path(&s, &path).unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(text.contains("\n  \"sensitivity\""), "pretty-printed: {}", text);
+    let imported = import_settings_from_path(&path).unwrap();
+    assert_eq!(imported.sensitivity, s.sensitivity);
+    assert_eq!(imported.polling_rate, s.polling_rate);
+    assert_eq!(imported.warning_level, Some(30));
+}
+
+#[test]
+fn settings_import_rejects_bad_files() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(import_settings_from_path(&dir.path().join("missing.json")).is_err());
+
+    let garbage = dir.path().join("garbage.json");
+    std::fs::write(&garbage, "not json").unwrap();
+    assert!(import_settings_from_path(&garbage).is_err());
+
+    let invalid = dir.path().join("invalid.json");
+    std::fs::write(&invalid, r#"{"polling_rate": "123"}"#).unwrap();
+    let err = import_settings_from_path(&invalid).unwrap_err().to_string();
+    assert!(err.to_lowercase().contains("polling"), "{}", err);
+}
+
+#[test]
+fn theme_darkness_from_preference_or_name() {
+    assert!(theme_is_dark(true, "Adwaita"));
+    assert!(theme_is_dark(false, "Adwaita-dark"));
+    assert!(theme_is_dark(false, "Yaru:Dark"));
+    assert!(!theme_is_dark(false, "Adwaita"));
+    assert!(!theme_is_dark(false, ""));
+    // "dark" inside the name is not a variant suffix
+    assert!(!theme_is_dark(false, "Darkly-light"));
+}
+
+#[test]
+fn theme_change_purges_only_recoloured_pngs() {
+    let now = SystemTime::now();
+    let cache: HashMap<String, (String, SystemTime)> = [
+        ("icons/battery-50.svg@64", "/tmp/a.png"),
+        ("icons/battery-50.svg::#ffffff@64", "/tmp/b.png"),
+        ("icons/battery-25.svg::#000000@32", "/tmp/c.png"),
+    ]
+    .into_iter()
+    .map(|(k, p)| (k.to_string(), (p.to_string(), now)))
+    .collect();
+    assert_eq!(
+        recoloured_cache_keys(&cache),
+        vec!["icons/battery-25.svg::#000000@32".to_string(), "icons/battery-50.svg::#ffffff@64".to_string()]
+    );
+}
+
+#[test]
+fn auto_colour_mode_follows_the_theme() {
+    let s = Settings {
+        colour_mode: Some("auto".to_string()),
+        ..Default::default()
+    };
+    // Only this test changes the theme flag
+    THEME_PREFERS_DARK.store(true, std::sync::atomic::Ordering::SeqCst);
+    let dark = icon_recolor_color(&s);
+    THEME_PREFERS_DARK.store(false, std::sync::atomic::Ordering::SeqCst);
+    let light = icon_recolor_color(&s);
+    assert_eq!(dark.as_deref(), Some(DARK_MODE_COLOR));
+    assert_eq!(light.as_deref(), Some(LIGHT_MODE_COLOR));
+}
+
+#[test]
+fn diagnostics_report_gathers_command_output() {
+    let mock = MockCommandRunner::new()
+        .with("rivalcfg", &["--version"], "4.13.0\n", true)
+        .with("rivalcfg", &["--help"], "usage\nSteelSeries Rival 3 Options:\n  --color COLOR\n", true)
+        .with("rivalcfg", &["--battery-level"], "Discharging [=====     ] 45 %\n", true)
+        .build();
+    let report = collect_diagnostics(&mock);
+    assert!(report.starts_with(&format!("rivalcfg-tray {}", env!("CARGO_PKG_VERSION"))), "{}", report);
+    assert!(report.contains("rivalcfg --version: 4.13.0\n"), "{}", report);
+    assert!(report.contains("Mouse: SteelSeries Rival 3\n"), "{}", report);
+    assert!(report.contains("rivalcfg --battery-level: Discharging [=====     ] 45 %\n"), "{}", report);
+    // no test drives the global watchdog, so it has never restarted the poll
+    assert!(report.contains("Poll watchdog restarts: 0\n"), "{}", report);
+    // rsvg-convert isn't mocked, so it reads as missing
+    assert!(report.contains("rsvg-convert: not available\n"), "{}", report);
+    assert!(report.contains("Settings file: "), "{}", report);
+    mock.assert_called_once("rsvg-convert", &["--version"]);
+}
+
+#[test]
+fn diagnostics_report_names_failures() {
+    let report = collect_diagnostics(&MockCommandRunner::new().build());
+    assert!(report.contains("rivalcfg --version: failed: No mock response"), "{}", report);
+    assert!(report.contains("Mouse: not detected (rivalcfg command failed"), "{}", report);
+}
+
+#[test]
+fn successful_apply_requests_a_tray_refresh() {
+    let s = Settings {
+        sensitivity: Some("1600".to_string()),
+        ..Default::default()
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+    let ok = MockCommandRunner::new().with("rivalcfg", &["--sensitivity", "1600"], "", true).build();
+    assert_eq!(apply_settings_and_refresh(&s, &ok, Some(&tx)), Ok(()));
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![TrayRefresh::SettingsApplied]);
+
+    // a failed run leaves the tray alone
+    let failing = MockCommandRunner::new().build();
+    assert!(apply_settings_and_refresh(&s, &failing, Some(&tx)).is_err());
+    assert!(rx.try_recv().is_err());
+
+    // without a main loop to tell, the apply still goes through
+    assert_eq!(apply_settings_and_refresh(&s, &ok, None), Ok(()));
+}
+
+#[test]
+fn service_unit_runs_the_given_executable() {
+    let unit = crate::service::render_service_unit("/home/u/.cargo/bin/rivalcfg-tray");
+    assert!(unit.contains("\nExecStart=/home/u/.cargo/bin/rivalcfg-tray\n"), "{}", unit);
+    assert!(unit.contains("[Install]\nWantedBy=graphical-session.target\n"), "{}", unit);
+    // paths with spaces stay one word and % isn't read as a specifier
+    let unit = crate::service::render_service_unit("/opt/My Apps/100%/rivalcfg-tray");
+    assert!(unit.contains("\nExecStart=\"/opt/My Apps/100%%/rivalcfg-tray\"\n"), "{}", unit);
+    assert_eq!(
+        crate::service::service_unit_path(std::path::Path::new("/home/u/.config")),
+        PathBuf::from("/home/u/.config/systemd/user/rivalcfg-tray.service")
+    );
+}
+
+#[test]
+fn service_flags_and_unit_removal() {
+    assert!(parse_cli_args(vec!["--install-service".to_string()]).install_service);
+    assert!(parse_cli_args(vec!["--uninstall-service".to_string()]).uninstall_service);
+    let dir = tempfile::tempdir().unwrap();
+    let path = crate::service::service_unit_path(dir.path());
+    assert!(!crate::service::remove_service_unit(&path).unwrap());
+    assert!(write_if_changed(&path, &crate::service::render_service_unit("/usr/bin/rivalcfg-tray")).unwrap());
+    assert!(crate::service::remove_service_unit(&path).unwrap());
+    assert!(!path.exists());
+}
+
+#[test]
+fn changing_the_custom_colour_renders_a_new_icon() {
+    let dir = tempfile::tempdir().unwrap();
+    let svg = dir.path().join("battery-50.svg");
+    fs::write(&svg, r##"<svg xmlns="http://www.w3.org/2000/svg" fill="#000000"><path d="M1 1"/></svg>"##).unwrap();
+    let svg_str = svg.to_string_lossy();
+
+    // the same SVG (same mtime) in another colour must miss the cache
+    let orange = png_cache_key(&svg_str, Some("#ff8800"), 64);
+    let blue = png_cache_key(&svg_str, Some("#0000ff"), 64);
+    assert_ne!(orange, blue);
+    assert_ne!(png_cache_key(&svg_str, None, 64), orange);
+    assert_eq!(orange, format!("{}::#ff8800@64", svg_str));
+
+    // and what gets rendered differs in path and content
+    let orange_svg = recolor_svg_to_temp(&svg, "#ff8800", &PRESERVED_ICON_COLORS, dir.path()).unwrap();
+    let blue_svg = recolor_svg_to_temp(&svg, "#0000ff", &PRESERVED_ICON_COLORS, dir.path()).unwrap();
+    assert_ne!(orange_svg, blue_svg);
+    assert_ne!(fs::read(&orange_svg).unwrap(), fs::read(&blue_svg).unwrap());
+
+    // the orange render is one of the entries a colour change drops
+    let now = SystemTime::now();
+    let cache: HashMap<String, (String, SystemTime)> = [
+        (orange.clone(), ("/tmp/orange.png".to_string(), now)),
+        (png_cache_key(&svg_str, None, 64), ("/tmp/plain.png".to_string(), now)),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(recoloured_cache_keys(&cache), vec![orange]);
+}
+
+#[test]
+fn firmware_version_from_rivalcfg() {
+    let mock = MockCommandRunner::new()
+        .with("rivalcfg", &["--firmware-version"], "SteelSeries Rival 3 (firmware v0.37.0.0)\n", true)
+        .build();
+    let version = crate::cmd::get_firmware_version_with_runner(&mock);
+    assert_eq!(version.as_deref(), Some("0.37.0.0"));
+    mock.assert_called_once("rivalcfg", &["--firmware-version"]);
+    assert_eq!(firmware_label_text(version.as_deref()), "Firmware: 0.37.0.0");
+
+    assert_eq!(crate::parse::parse_firmware_version("Firmware version: 1.0.5.\n").as_deref(), Some("1.0.5"));
+    // the digit in a device name is not a version
+    assert_eq!(crate::parse::parse_firmware_version("SteelSeries Rival 3\n"), None);
+}
+
+#[test]
+fn firmware_version_not_available() {
+    // unsupported subcommand, or a device that prints nothing
+    let failing = MockCommandRunner::new().build();
+    assert_eq!(crate::cmd::get_firmware_version_with_runner(&failing), None);
+    let empty = MockCommandRunner::new().with("rivalcfg", &["--firmware-version"], "\n", true).build();
+    assert_eq!(crate::cmd::get_firmware_version_with_runner(&empty), None);
+    assert_eq!(firmware_label_text(None), "Firmware: N/A");
+}
+
+#[test]
+fn force_icon_refresh_defeats_change_detection() {
+    let state = BatteryState::Connected { level: 50, status: ChargeStatus::Discharging };
+    {
+        let mut last = LAST_BATTERY_STATE.lock().unwrap();
+        record_battery_state(&mut last, state);
+        // the same reading again is skipped
+        assert!(!record_battery_state(&mut last, state));
+    }
+    force_icon_refresh();
+    assert!(record_battery_state(&mut LAST_BATTERY_STATE.lock().unwrap(), state));
+}
+
+#[test]
+fn only_icon_settings_force_a_redraw() {
+    let old = Settings::default();
+    let dpi = Settings {
+        sensitivity: Some("1600".to_string()),
+        ..Default::default()
+    };
+    assert!(!icon_settings_changed(&old, &dpi));
+    let theme = Settings {
+        icon_theme: Some("monochrome-dark".to_string()),
+        ..Default::default()
+    };
+    assert!(icon_settings_changed(&old, &theme));
+    let colour = Settings {
+        custom_color: Some("#0000ff".to_string()),
+        ..Default::default()
+    };
+    assert!(icon_settings_changed(&old, &colour));
+    let attention = Settings {
+        attention_threshold: Some(20),
+        ..Default::default()
+    };
+    assert!(icon_settings_changed(&old, &attention));
+    let pack = Settings {
+        icon_pack: Some("mono".to_string()),
+        ..Default::default()
+    };
+    assert!(icon_settings_changed(&old, &pack));
+}
+
+#[test]
+fn discover_icon_packs_lists_pack_dirs_across_roots() {
+    let user = tempfile::tempdir().unwrap();
+    let system = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(user.path().join("mono")).unwrap();
+    std::fs::create_dir_all(user.path().join("retro")).unwrap();
+    std::fs::create_dir_all(system.path().join("mono")).unwrap();
+    std::fs::create_dir_all(system.path().join("default")).unwrap();
+    // stray files are not packs
+    std::fs::write(user.path().join("README.txt"), "packs go here").unwrap();
+
+    let roots = [
+        user.path().to_path_buf(),
+        PathBuf::from("/nonexistent/rivalcfg-packs"),
+        system.path().to_path_buf(),
+    ];
+    assert_eq!(discover_icon_packs(&roots), vec!["mono".to_string(), "retro".to_string()]);
+    assert!(discover_icon_packs(&[]).is_empty());
+}
+
+#[test]
+fn find_icon_in_pack_falls_back_per_icon() {
+    let user = tempfile::tempdir().unwrap();
+    let system = tempfile::tempdir().unwrap();
+    let user_pack = user.path().join("mono");
+    let system_pack = system.path().join("mono");
+    std::fs::create_dir_all(&user_pack).unwrap();
+    std::fs::create_dir_all(&system_pack).unwrap();
+    std::fs::write(user_pack.join("battery-50.svg"), "<svg/>").unwrap();
+    std::fs::write(system_pack.join("battery-50.svg"), "<svg/>").unwrap();
+    std::fs::write(system_pack.join("battery-25.svg"), "<svg/>").unwrap();
+    let roots = [user.path().to_path_buf(), system.path().to_path_buf()];
+
+    // earlier roots win; an icon missing there comes from a later root
+    assert_eq!(find_icon_in_pack("battery-50.svg", "mono", &roots), Some(user_pack.join("battery-50.svg")));
+    assert_eq!(find_icon_in_pack("battery-25.svg", "mono", &roots), Some(system_pack.join("battery-25.svg")));
+    // icons no root has are left to the regular lookup
+    assert_eq!(find_icon_in_pack("battery-100.svg", "mono", &roots), None);
+    // unknown packs, the default pack and path-like names never match
+    assert_eq!(find_icon_in_pack("battery-50.svg", "retro", &roots), None);
+    assert_eq!(find_icon_in_pack("battery-50.svg", DEFAULT_ICON_PACK, &roots), None);
+    assert_eq!(find_icon_in_pack("battery-50.svg", "../mono", &[user.path().join("x")]), None);
+    assert_eq!(find_icon_in_pack("battery-50.svg", "..", &[user_pack.join("x")]), None);
+}
+
+#[test]
+fn opens_config_window_on_left_click_release_only() {
+    let click = |button, button_state| TrayIconEvent::Click {
+        id: tray_icon::TrayIconId::new("tray"),
+        position: tray_icon::dpi::PhysicalPosition::new(0.0, 0.0),
+        rect: tray_icon::Rect {
+            size: tray_icon::dpi::PhysicalSize::new(22, 22),
+            position: tray_icon::dpi::PhysicalPosition::new(0.0, 0.0),
+        },
+        button,
+        button_state,
+    };
+    assert!(opens_config_window(&click(MouseButton::Left, MouseButtonState::Up)));
+    // the press would open a second window when the release follows
+    assert!(!opens_config_window(&click(MouseButton::Left, MouseButtonState::Down)));
+    assert!(!opens_config_window(&click(MouseButton::Right, MouseButtonState::Up)));
+    assert!(!opens_config_window(&click(MouseButton::Middle, MouseButtonState::Up)));
+}
+
+#[test]
+fn reports_no_battery_recognises_wired_mice() {
+    use crate::parse::reports_no_battery;
+    assert!(reports_no_battery(
+        "usage: rivalcfg [-h] [--list]\nrivalcfg: error: unrecognized arguments: --battery-level\n"
+    ));
+    assert!(reports_no_battery("E: This device has no battery\n"));
+    assert!(reports_no_battery("Battery level is not supported by SteelSeries Rival 310"));
+    assert!(!reports_no_battery("Discharging [=====     ] 45 %"));
+    assert!(!reports_no_battery("E: No supported device found"));
+    // another bad argument isn't about the battery
+    assert!(!reports_no_battery("rivalcfg: error: unrecognized arguments: --bogus"));
+    assert!(!reports_no_battery(""));
+}
+
+#[test]
+fn dual_sensitivity_is_sent_as_x_y() {
+    let s = Settings {
+        sensitivity: Some("800".to_string()),
+        dual_sensitivity: Some((800, 1600)),
+        ..Default::default()
+    };
+    assert_eq!(build_rivalcfg_args(&s), vec!["--sensitivity", "800,1600"]);
+    assert_eq!(applied_settings_summary(&s), "DPI 800,1600 (X,Y)");
+    // the single value is used without a per-axis pair
+    let single = Settings { dual_sensitivity: None, ..s };
+    assert_eq!(build_rivalcfg_args(&single), vec!["--sensitivity", "800"]);
+}
+
+#[test]
+fn dual_sensitivity_validates_both_axes() {
+    assert_eq!(validate_dual_sensitivity((800, 1600)), Ok(()));
+    assert_eq!(validate_dual_sensitivity((50, 800)), Err("X DPI must be between 100 and 16000".to_string()));
+    assert_eq!(validate_dual_sensitivity((800, 20000)), Err("Y DPI must be between 100 and 16000".to_string()));
+    let s = Settings { dual_sensitivity: Some((800, 0)), ..Default::default() };
+    assert_eq!(validate_settings(&s).first().map(|e| e.field), Some("dual_sensitivity"));
+}
+
+#[test]
+fn lacks_battery_needs_a_device_listing_options_without_battery_level() {
+    let caps = |options: &[&str]| crate::parse::HelpCapabilities {
+        device_name: Some("SteelSeries Rival 310".to_string()),
+        options: options.iter().map(|o| o.to_string()).collect(),
+        max_dim_timer: None,
+        dual_sensitivity: false,
+    };
+    assert!(lacks_battery(&caps(&["--sensitivity", "--polling-rate"])));
+    assert!(!lacks_battery(&caps(&["--sensitivity", "--battery-level"])));
+    // unparsed options or no device tell nothing
+    assert!(!lacks_battery(&caps(&[])));
+    assert!(!lacks_battery(&crate::parse::HelpCapabilities::default()));
+}
+
+#[test]
+fn no_battery_state_comes_from_its_error_only() {
+    assert_eq!(BatteryState::from_result(Err(RivalError::NoBattery)), BatteryState::NoBattery);
+    assert_eq!(BatteryState::from_result(Err(RivalError::NoDevice)), BatteryState::Disconnected);
+    assert_eq!(
+        BatteryState::from_result(Ok((40, ChargeStatus::Charging))),
+        BatteryState::Connected { level: 40, status: ChargeStatus::Charging }
+    );
+
+    // it's an answer, not a failed read: nothing to debounce
+    let last = Some(BatteryState::Connected { level: 40, status: ChargeStatus::Discharging });
+    let mut failures = 1;
+    assert_eq!(debounce_disconnect(&mut failures, BatteryState::NoBattery, last, 3), (BatteryState::NoBattery, false));
+    assert_eq!(failures, 0);
+}
+
+#[test]
+fn no_battery_state_hides_battery_texts() {
+    assert_eq!(tray_label_text(&BatteryState::NoBattery, true), None);
+    assert_eq!(tray_tooltip_text("Rival 310", &BatteryState::NoBattery), "Rival 310");
+    assert!(!next_attention_state(true, &BatteryState::NoBattery, 20));
+}
+
+#[test]
+fn no_battery_menu_keeps_only_the_device_name() {
+    let connected = BatteryState::Connected { level: 40, status: ChargeStatus::Discharging };
+    let defaults = Settings::default();
+    assert_eq!(shown_info_items(&defaults, &connected), menu_info_items(&defaults));
+    assert_eq!(shown_info_items(&defaults, &BatteryState::Disconnected), menu_info_items(&defaults));
+    assert!(shown_info_items(&defaults, &BatteryState::NoBattery).is_empty());
+
+    let with_name = Settings {
+        menu: Some(MenuSettings {
+            device_name: Some(true),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    assert_eq!(shown_info_items(&with_name, &BatteryState::NoBattery), vec![InfoItem::DeviceName]);
+}
+
+#[test]
+fn apply_status_markup_colours_the_result() {
+    assert_eq!(
+        apply_status_markup(&Ok(()), ""),
+        "<span foreground=\"green\">Settings applied successfully.</span>"
+    );
+    assert_eq!(
+        apply_status_markup(&Ok(()), "DPI 800, 1000Hz"),
+        "<span foreground=\"green\">Settings applied successfully: DPI 800, 1000Hz</span>"
+    );
+    // rivalcfg's stderr is escaped, not interpreted as markup; the summary is not shown
+    assert_eq!(
+        apply_status_markup(&Err("usage: rivalcfg <options> & more\n".to_string()), "DPI 800"),
+        "<span foreground=\"red\">Error: usage: rivalcfg &lt;options&gt; &amp; more</span>"
+    );
+}
+
+#[test]
+fn fallback_png_name_per_level_and_charge_state() {
+    let t = IconThresholds { warning: 24, critical: 9 };
+    let state = |level, status| BatteryState::Connected { level, status };
+    for (level, plain, charging) in [
+        (100, "battery-100.png", "battery-100-charging.png"),
+        (80, "battery-75.png", "battery-75-charging.png"),
+        (60, "battery-50.png", "battery-50-charging.png"),
+        (30, "battery-25.png", "battery-25-charging.png"),
+        (15, "battery-warn.png", "battery-warn-charging.png"),
+        (5, "battery-0.png", "battery-0-charging.png"),
+    ] {
+        assert_eq!(fallback_png_name(&state(level, ChargeStatus::Discharging), t), plain, "level {}", level);
+        assert_eq!(fallback_png_name(&state(level, ChargeStatus::Unknown), t), plain, "level {}", level);
+        assert_eq!(fallback_png_name(&state(level, ChargeStatus::Charging), t), charging, "level {}", level);
+    }
+    assert_eq!(fallback_png_name(&state(100, ChargeStatus::Charged), t), "battery-full.png");
+    assert_eq!(fallback_png_name(&BatteryState::Disconnected, t), "mouse-disconnected.png");
+    assert_eq!(fallback_png_name(&BatteryState::NoBattery, t), "mouse.png");
+}
+
+#[test]
+fn every_fallback_png_is_bundled_at_its_size() {
+    let t = IconThresholds { warning: 24, critical: 9 };
+    let mut states = vec![BatteryState::Disconnected, BatteryState::NoBattery];
+    for level in 0..=100 {
+        for status in [ChargeStatus::Discharging, ChargeStatus::Charging, ChargeStatus::Charged] {
+            states.push(BatteryState::Connected { level, status });
+        }
+    }
+    for state in states {
+        let name = fallback_png_name(&state, t);
+        let bytes = crate::icons::fallback_png(&name).unwrap_or_else(|| panic!("{} not bundled", name));
+        let img = image::load_from_memory(bytes).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        assert_eq!((img.width(), img.height()), (FALLBACK_PNG_SIZE, FALLBACK_PNG_SIZE), "{}", name);
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let state = BatteryState::Connected { level: 60, status: ChargeStatus::Charging };
+    let path = fallback_png_path(&state, t, dir.path()).unwrap();
+    assert_eq!(path, dir.path().join("battery-50-charging.png"));
+    assert!(path.exists());
+}
+
+#[test]
+fn tint_icon_pixels_keeps_alpha_and_coloured_pixels() {
+    let mut img = image::RgbaImage::from_raw(
+        3,
+        1,
+        vec![
+            0, 0, 0, 255, // icon
+            0, 0, 0, 80, // antialiased edge
+            255, 255, 0, 170, // charging bolt
+        ],
+    )
+    .unwrap();
+    tint_icon_pixels(&mut img, rgb_from_hex("#ffffff").unwrap());
+    assert_eq!(img.into_raw(), vec![255, 255, 255, 255, 255, 255, 255, 80, 255, 255, 0, 170]);
+
+    assert_eq!(rgb_from_hex("#FF8000"), Some([255, 128, 0]));
+    assert_eq!(rgb_from_hex("ff8000"), None);
+    assert_eq!(rgb_from_hex("#fff"), None);
+    assert_eq!(rgb_from_hex("#gg0000"), None);
+}
+
+#[test]
+fn applied_settings_summary_lists_what_was_sent() {
+    let s = Settings {
+        sensitivity: Some("800,1600".to_string()),
+        polling_rate: Some("1000".to_string()),
+        sleep_timer: Some("10".to_string()),
+        dim_timer: Some("30".to_string()),
+        led_mode: Some(LED_MODE_CUSTOM.to_string()),
+        led_color: Some("#ff0000".to_string()),
+        extra_args: Some(vec!["--z-axis".to_string(), "off".to_string()]),
+        ..Default::default()
+    };
+    assert_eq!(
+        applied_settings_summary(&s),
+        "DPI 800/1600, 1000Hz, sleep 10m, dim 30s, LED #ff0000, --z-axis off"
+    );
+
+    let off = Settings {
+        sensitivity: Some("800".to_string()),
+        led_mode: Some(LED_MODE_OFF.to_string()),
+        ..Default::default()
+    };
+    assert_eq!(applied_settings_summary(&off), "DPI 800, LED off");
+    // nothing sent, nothing to report
+    assert_eq!(applied_settings_summary(&Settings::default()), "");
+}
+
+#[test]
+fn app_capabilities_probe_each_tool() {
+    use crate::dependencies::{AppCapabilities, Tool};
+    let mock = MockCommandRunner::new()
+        .with("rivalcfg", &["--version"], "rivalcfg 4.13.0\n", true)
+        .with("rsvg-convert", &["--version"], "rsvg-convert version 2.58.0\n", true)
+        .build();
+    let caps = AppCapabilities::probe(&mock);
+    assert_eq!(caps.rivalcfg, Tool::Available { version: Some("4.13.0".to_string()) });
+    assert_eq!(caps.rsvg_convert, Tool::Available { version: Some("2.58.0".to_string()) });
+    assert!(caps.missing().is_empty());
+    assert!(caps.can_poll_battery());
+
+    // rsvg-convert can't be started, rivalcfg is installed but broken
+    let mock = MockCommandRunner::new()
+        .with("rivalcfg", &["--version"], "", false)
+        .build();
+    let caps = AppCapabilities::probe(&mock);
+    assert_eq!(caps.missing(), vec!["rivalcfg", "rsvg-convert"]);
+    assert!(!caps.can_poll_battery());
+}
+
+#[test]
+fn app_capabilities_assume_tools_until_probed() {
+    use crate::dependencies::AppCapabilities;
+    let caps = AppCapabilities::default();
+    assert!(caps.missing().is_empty());
+    assert!(caps.can_poll_battery());
+}
+
+#[test]
+fn parse_tool_version_finds_the_version_number() {
+    use crate::dependencies::parse_tool_version;
+    assert_eq!(parse_tool_version("rivalcfg 4.13.0\n").as_deref(), Some("4.13.0"));
+    assert_eq!(parse_tool_version("rsvg-convert version 2.58.0\n").as_deref(), Some("2.58.0"));
+    assert_eq!(parse_tool_version("tool v1.2, built 2024\n").as_deref(), Some("1.2"));
+    assert_eq!(parse_tool_version("rivalcfg 3\n"), None);
+    assert_eq!(parse_tool_version(""), None);
+}
+
+#[test]
+fn missing_tools_message_has_distro_install_hints() {
+    use crate::dependencies::{AppCapabilities, Distro, Tool, distro_from_os_release, missing_tools_message};
+    assert_eq!(distro_from_os_release("NAME=\"Ubuntu\"\nID=ubuntu\nID_LIKE=debian\n"), Distro::Debian);
+    assert_eq!(distro_from_os_release("ID=\"fedora\"\n"), Distro::Fedora);
+    assert_eq!(distro_from_os_release("ID=endeavouros\nID_LIKE=arch\n"), Distro::Arch);
+    assert_eq!(distro_from_os_release("ID=\"opensuse-tumbleweed\"\nID_LIKE=\"opensuse suse\"\n"), Distro::Suse);
+    assert_eq!(distro_from_os_release(""), Distro::Other);
+
+    let caps = AppCapabilities {
+        rsvg_convert: Tool::Missing,
+        ..Default::default()
+    };
+    let message = missing_tools_message(&caps, Distro::Fedora);
+    assert!(message.starts_with("• rsvg-convert"), "{}", message);
+    assert!(message.contains("sudo dnf install librsvg2-tools"));
+    assert!(!message.contains("rivalcfg"));
+
+    let caps = AppCapabilities {
+        rivalcfg: Tool::Missing,
+        ..Default::default()
+    };
+    assert!(missing_tools_message(&caps, Distro::Debian).contains("pipx install rivalcfg"));
+}
+
+#[test]
+fn format_command_string_quotes_for_the_shell() {
+    let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert_eq!(format_command_string(&[]), "rivalcfg");
+    assert_eq!(
+        format_command_string(&args(&["--sensitivity", "800,1600", "--polling-rate", "1000"])),
+        "rivalcfg --sensitivity 800,1600 --polling-rate 1000"
+    );
+    // "#" would start a shell comment, spaces split words
+    assert_eq!(format_command_string(&args(&["--color", "#ff0000"])), "rivalcfg --color '#ff0000'");
+    assert_eq!(
+        format_command_string(&args(&["--buttons", "buttons(button1=button1; layout=qwerty)"])),
+        "rivalcfg --buttons 'buttons(button1=button1; layout=qwerty)'"
+    );
+    assert_eq!(format_command_string(&args(&["it's"])), "rivalcfg 'it'\\''s'");
+}
+
+#[test]
+fn poll_interval_is_validated() {
+    assert_eq!(validate_poll_interval(5), Ok(()));
+    assert_eq!(validate_poll_interval(3600), Ok(()));
+    assert_eq!(validate_poll_interval(4), Err("Poll interval must be between 5 and 3600 seconds".to_string()));
+    assert!(validate_poll_interval(3601).is_err());
+
+    let s = Settings { poll_interval: Some(1), ..Default::default() };
+    assert_eq!(validate_settings(&s).first().map(|e| e.field), Some("poll_interval"));
+    let mut devices = HashMap::new();
+    devices.insert("Rival 3".to_string(), DeviceSettings { poll_interval: Some(0), ..Default::default() });
+    let s = Settings { devices: Some(devices), ..Default::default() };
+    assert_eq!(validate_settings(&s).first().map(|e| e.field), Some("devices"));
+}
+
+#[test]
+fn poll_timer_is_rescheduled_only_for_a_new_interval() {
+    let secs = Duration::from_secs;
+    let s = Settings { poll_interval: Some(60), ..Default::default() };
+    assert_eq!(rescheduled_poll_interval(secs(60), &s, "Rival 3", None), None);
+    assert_eq!(rescheduled_poll_interval(secs(30), &s, "Rival 3", None), Some(secs(60)));
+    // unset and without a reading means the default, out of range values are clamped
+    assert_eq!(rescheduled_poll_interval(POLL_INTERVAL, &Settings::default(), "Rival 3", None), None);
+    let fast = Settings { poll_interval: Some(1), ..Default::default() };
+    assert_eq!(rescheduled_poll_interval(secs(30), &fast, "Rival 3", None), Some(secs(5)));
+    // a device override wins over the global interval
+    let mut devices = HashMap::new();
+    devices.insert("Rival 3".to_string(), DeviceSettings { poll_interval: Some(120), ..Default::default() });
+    let s = Settings { devices: Some(devices), ..s };
+    assert_eq!(rescheduled_poll_interval(secs(60), &s, "Rival 3", None), Some(secs(120)));
+    assert_eq!(rescheduled_poll_interval(secs(60), &s, "Aerox 3", None), None);
+}
+
+#[test]
+fn next_poll_interval_follows_the_battery_bands() {
+    let secs = Duration::from_secs;
+    let bands = PollBands::default();
+    assert_eq!(next_poll_interval(100, false, &bands), secs(120));
+    assert_eq!(next_poll_interval(51, false, &bands), secs(120));
+    assert_eq!(next_poll_interval(50, false, &bands), secs(60));
+    assert_eq!(next_poll_interval(15, false, &bands), secs(60));
+    assert_eq!(next_poll_interval(14, false, &bands), secs(15));
+    assert_eq!(next_poll_interval(0, false, &bands), secs(15));
+    // charging is watched closely at any level
+    assert_eq!(next_poll_interval(80, true, &bands), secs(15));
+
+    let custom = PollBands {
+        high_level: Some(80),
+        low_level: Some(20),
+        high_secs: Some(300),
+        mid_secs: None,
+        low_secs: Some(1),
+    };
+    assert_eq!(next_poll_interval(81, false, &custom), secs(300));
+    assert_eq!(next_poll_interval(80, false, &custom), secs(60));
+    // clamped to the poll interval range
+    assert_eq!(next_poll_interval(19, false, &custom), secs(5));
+}
+
+#[test]
+fn adaptive_polling_only_without_a_configured_interval() {
+    let secs = Duration::from_secs;
+    let low = BatteryState::Connected { level: 10, status: ChargeStatus::Discharging };
+    let full = BatteryState::Connected { level: 90, status: ChargeStatus::Discharging };
+    let adaptive = Settings::default();
+    assert_eq!(poll_interval_for(&adaptive, "Rival 3", Some(&low)), secs(15));
+    assert_eq!(poll_interval_for(&adaptive, "Rival 3", Some(&full)), secs(120));
+    assert_eq!(poll_interval_for(&adaptive, "Rival 3", Some(&BatteryState::Disconnected)), POLL_INTERVAL);
+    assert_eq!(rescheduled_poll_interval(secs(120), &adaptive, "Rival 3", Some(&low)), Some(secs(15)));
+
+    let fixed = Settings { poll_interval: Some(45), ..Default::default() };
+    assert_eq!(poll_interval_for(&fixed, "Rival 3", Some(&low)), secs(45));
+}
+
+#[test]
+fn poll_bands_are_validated() {
+    assert_eq!(validate_poll_bands(&PollBands::default()), Ok(()));
+    let bad_secs = PollBands { mid_secs: Some(2), ..Default::default() };
+    assert!(validate_poll_bands(&bad_secs).unwrap_err().contains("Poll interval"));
+    let crossed = PollBands { high_level: Some(10), low_level: Some(40), ..Default::default() };
+    assert!(validate_poll_bands(&crossed).is_err());
+    let s = Settings { poll_bands: Some(crossed), ..Default::default() };
+    assert_eq!(validate_settings(&s).first().map(|e| e.field), Some("poll_bands"));
+}
+
+#[test]
+fn startup_apply_is_on_unless_turned_off() {
+    assert!(applies_on_startup(&Settings::default()));
+    let on = Settings { auto_apply_on_startup: Some(true), ..Default::default() };
+    assert!(applies_on_startup(&on));
+    let off = Settings { auto_apply_on_startup: Some(false), ..Default::default() };
+    assert!(!applies_on_startup(&off));
+    // survives a save and reload
+    let json = serde_json::to_value(&off).unwrap();
+    assert!(!applies_on_startup(&migrate(json)));
 }
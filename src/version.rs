@@ -0,0 +1,4 @@
+/// Version of this build, taken from `Cargo.toml` at compile time.
+pub fn crate_version() -> &'static str {
+    env!("CARGO_PKG_VERSION")
+}
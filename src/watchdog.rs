@@ -0,0 +1,67 @@
+use std::time::{Duration, Instant};
+
+// A poll cycle is considered lost once the heartbeat is this many intervals old
+const STALE_INTERVALS: u32 = 3;
+
+/// Tracks the battery poll loop heartbeat so a stalled loop can be detected and
+/// rescheduled. Time is always passed in so the logic can be tested without sleeping.
+#[derive(Debug)]
+pub struct PollWatchdog {
+    interval: Duration,
+    last_heartbeat: Instant,
+    last_restart: Option<Instant>,
+    restarts: u32,
+}
+
+impl PollWatchdog {
+    pub fn new(interval: Duration, now: Instant) -> Self {
+        Self {
+            interval,
+            last_heartbeat: now,
+            last_restart: None,
+            restarts: 0,
+        }
+    }
+
+    /// Follow a changed poll interval; the stale window scales with it.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Record the end of a poll cycle.
+    pub fn heartbeat(&mut self, now: Instant) {
+        self.last_heartbeat = now;
+    }
+
+    pub fn stale_after(&self) -> Duration {
+        self.interval * STALE_INTERVALS
+    }
+
+    pub fn is_stale(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_heartbeat) > self.stale_after()
+    }
+
+    /// Returns true when the poll loop should be recreated. After a restart, another
+    /// one is only allowed once a full stale window has passed, to avoid restart storms.
+    pub fn check(&mut self, now: Instant) -> bool {
+        if !self.is_stale(now) {
+            return false;
+        }
+        if let Some(t) = self.last_restart
+            && now.saturating_duration_since(t) < self.stale_after()
+        {
+            return false;
+        }
+        self.last_restart = Some(now);
+        self.restarts += 1;
+        true
+    }
+
+    pub fn restarts(&self) -> u32 {
+        self.restarts
+    }
+
+    pub fn heartbeat_age(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.last_heartbeat)
+    }
+}